@@ -1,4 +1,10 @@
-use rue_lexer::Token;
+use rue_lexer::{Token, TokenKind};
+
+mod source;
+mod span;
+pub mod visit;
+
+pub use visit::Visitor;
 
 pub type TokenNode = Token;
 
@@ -22,6 +28,8 @@ pub struct FunctionNode {
     pub fn_token: TokenNode,
     pub name: TokenNode,
     pub param_list: ParamListNode,
+    pub arrow: Option<TokenNode>,       // Present along with return_type
+    pub return_type: Option<TokenNode>, // A type name, as in `-> i64`
     pub body: BlockNode,
     pub trivia: Trivia,
 }
@@ -29,11 +37,20 @@ pub struct FunctionNode {
 #[derive(Debug, Clone, PartialEq)]
 pub struct ParamListNode {
     pub open_paren: TokenNode,
-    pub params: Vec<TokenNode>, // Just identifiers for now
+    pub params: Vec<ParamNode>,
+    pub commas: Vec<TokenNode>, // After each param but the last, or every one with a trailing comma
     pub close_paren: TokenNode,
     pub trivia: Trivia,
 }
 
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParamNode {
+    pub name: TokenNode,
+    pub colon: Option<TokenNode>, // Present along with ty, as in `x: i64`
+    pub ty: Option<TokenNode>,    // A type name, which is just an identifier for now
+    pub trivia: Trivia,
+}
+
 #[derive(Debug, Clone, PartialEq)]
 pub struct BlockNode {
     pub open_brace: TokenNode,
@@ -46,8 +63,16 @@ pub struct BlockNode {
 #[derive(Debug, Clone, PartialEq)]
 pub enum StatementNode {
     Let(LetStatementNode),
+    Const(ConstStatementNode),
     Assign(AssignStatementNode),
+    IndexAssign(IndexAssignStatementNode),
     Expression(ExpressionStatementNode),
+    Return(ReturnStatementNode),
+    Break(BreakStatementNode),
+    Continue(ContinueStatementNode),
+    /// A statement that didn't parse, kept as its tokens, of which there's
+    /// at least one
+    Error(ErrorNode),
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -60,8 +85,10 @@ pub struct LetStatementNode {
     pub trivia: Trivia,
 }
 
+/// `const NAME = value;`, a name for a value known at compile time
 #[derive(Debug, Clone, PartialEq)]
-pub struct AssignStatementNode {
+pub struct ConstStatementNode {
+    pub const_token: TokenNode,
     pub name: TokenNode,
     pub equals: TokenNode,
     pub value: ExpressionNode,
@@ -69,6 +96,73 @@ pub struct AssignStatementNode {
     pub trivia: Trivia,
 }
 
+#[derive(Debug, Clone, PartialEq)]
+pub struct AssignStatementNode {
+    pub name: TokenNode,
+    pub operator: TokenNode, // `=`, or a compound assignment like `+=`
+    pub value: ExpressionNode,
+    pub semicolon: TokenNode,
+    pub trivia: Trivia,
+}
+
+impl AssignStatementNode {
+    /// The value this assigns: `value` itself for `=`, or what a compound
+    /// assignment is short for, like `x + value` for `x += value`
+    pub fn assigned_value(&self) -> ExpressionNode {
+        assigned_value(
+            ExpressionNode::Identifier(self.name.clone()),
+            &self.operator,
+            &self.value,
+        )
+    }
+}
+
+/// `array[index] = value;`, or a compound assignment to an element
+#[derive(Debug, Clone, PartialEq)]
+pub struct IndexAssignStatementNode {
+    pub target: IndexExprNode,
+    pub operator: TokenNode, // `=`, or a compound assignment like `+=`
+    pub value: ExpressionNode,
+    pub semicolon: TokenNode,
+    pub trivia: Trivia,
+}
+
+impl IndexAssignStatementNode {
+    /// The value this assigns, as with `AssignStatementNode::assigned_value`
+    pub fn assigned_value(&self) -> ExpressionNode {
+        assigned_value(
+            ExpressionNode::Index(self.target.clone()),
+            &self.operator,
+            &self.value,
+        )
+    }
+}
+
+// What assigning `value` to `target` with `operator` stores
+fn assigned_value(
+    target: ExpressionNode,
+    operator: &TokenNode,
+    value: &ExpressionNode,
+) -> ExpressionNode {
+    let kind = match operator.kind {
+        TokenKind::PlusAssign => TokenKind::Plus,
+        TokenKind::MinusAssign => TokenKind::Minus,
+        TokenKind::StarAssign => TokenKind::Star,
+        TokenKind::SlashAssign => TokenKind::Slash,
+        TokenKind::PercentAssign => TokenKind::Percent,
+        _ => return value.clone(),
+    };
+    ExpressionNode::Binary(BinaryExprNode {
+        left: Box::new(target),
+        operator: Token {
+            kind,
+            span: operator.span,
+        },
+        right: Box::new(value.clone()),
+        trivia: Trivia::default(),
+    })
+}
+
 #[derive(Debug, Clone, PartialEq)]
 pub struct ExpressionStatementNode {
     pub expression: ExpressionNode,
@@ -76,6 +170,28 @@ pub struct ExpressionStatementNode {
     pub trivia: Trivia,
 }
 
+#[derive(Debug, Clone, PartialEq)]
+pub struct ReturnStatementNode {
+    pub return_token: TokenNode,
+    pub value: Option<ExpressionNode>, // None for a bare `return;`
+    pub semicolon: TokenNode,
+    pub trivia: Trivia,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct BreakStatementNode {
+    pub break_token: TokenNode,
+    pub semicolon: TokenNode,
+    pub trivia: Trivia,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ContinueStatementNode {
+    pub continue_token: TokenNode,
+    pub semicolon: TokenNode,
+    pub trivia: Trivia,
+}
+
 #[derive(Debug, Clone, PartialEq)]
 pub struct IfStatementNode {
     pub if_token: TokenNode,
@@ -106,16 +222,56 @@ pub struct WhileStatementNode {
     pub trivia: Trivia,
 }
 
+#[derive(Debug, Clone, PartialEq)]
+pub struct ForStatementNode {
+    pub for_token: TokenNode,
+    pub variable: TokenNode,
+    pub in_token: TokenNode,
+    pub start: ExpressionNode,
+    pub dot_dot: TokenNode,
+    pub end: ExpressionNode,
+    pub body: BlockNode,
+    pub trivia: Trivia,
+}
+
 #[derive(Debug, Clone, PartialEq)]
 pub enum ExpressionNode {
     Binary(BinaryExprNode),
+    Unary(UnaryExprNode),
+    Paren(ParenExprNode),
     Call(CallExprNode),
+    ArrayLit(ArrayLitNode),
+    Index(IndexExprNode),
+    Assign(AssignExprNode),
     If(Box<IfStatementNode>),
     While(Box<WhileStatementNode>),
+    For(Box<ForStatementNode>),
+    Block(Box<BlockNode>),
     Identifier(TokenNode),
     Literal(TokenNode),
 }
 
+impl ExpressionNode {
+    /// The expression's trivia, or None for an identifier or literal, which
+    /// is a bare token with none
+    pub fn trivia_mut(&mut self) -> Option<&mut Trivia> {
+        match self {
+            ExpressionNode::Binary(node) => Some(&mut node.trivia),
+            ExpressionNode::Unary(node) => Some(&mut node.trivia),
+            ExpressionNode::Paren(node) => Some(&mut node.trivia),
+            ExpressionNode::Call(node) => Some(&mut node.trivia),
+            ExpressionNode::ArrayLit(node) => Some(&mut node.trivia),
+            ExpressionNode::Index(node) => Some(&mut node.trivia),
+            ExpressionNode::Assign(node) => Some(&mut node.trivia),
+            ExpressionNode::If(node) => Some(&mut node.trivia),
+            ExpressionNode::While(node) => Some(&mut node.trivia),
+            ExpressionNode::For(node) => Some(&mut node.trivia),
+            ExpressionNode::Block(node) => Some(&mut node.trivia),
+            ExpressionNode::Identifier(_) | ExpressionNode::Literal(_) => None,
+        }
+    }
+}
+
 #[derive(Debug, Clone, PartialEq)]
 pub struct BinaryExprNode {
     pub left: Box<ExpressionNode>,
@@ -124,15 +280,71 @@ pub struct BinaryExprNode {
     pub trivia: Trivia,
 }
 
+#[derive(Debug, Clone, PartialEq)]
+pub struct UnaryExprNode {
+    pub operator: TokenNode, // Minus or Bang
+    pub operand: Box<ExpressionNode>,
+    pub trivia: Trivia,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParenExprNode {
+    pub open_paren: TokenNode,
+    pub inner: Box<ExpressionNode>,
+    pub close_paren: TokenNode,
+    pub trivia: Trivia,
+}
+
 #[derive(Debug, Clone, PartialEq)]
 pub struct CallExprNode {
     pub function: Box<ExpressionNode>,
     pub open_paren: TokenNode,
     pub args: Vec<ExpressionNode>,
+    pub commas: Vec<TokenNode>, // After each arg but the last, or every one with a trailing comma
     pub close_paren: TokenNode,
     pub trivia: Trivia,
 }
 
+/// `x = value` used as an expression, whose value is what's assigned
+#[derive(Debug, Clone, PartialEq)]
+pub struct AssignExprNode {
+    pub name: TokenNode,
+    pub operator: TokenNode, // `=`, or a compound assignment like `+=`
+    pub value: Box<ExpressionNode>,
+    pub trivia: Trivia,
+}
+
+impl AssignExprNode {
+    /// The value this assigns, as with `AssignStatementNode::assigned_value`
+    pub fn assigned_value(&self) -> ExpressionNode {
+        assigned_value(
+            ExpressionNode::Identifier(self.name.clone()),
+            &self.operator,
+            &self.value,
+        )
+    }
+}
+
+/// `[e1, e2, ...]`
+#[derive(Debug, Clone, PartialEq)]
+pub struct ArrayLitNode {
+    pub open_bracket: TokenNode,
+    pub elements: Vec<ExpressionNode>,
+    pub commas: Vec<TokenNode>, // Between elements, one fewer than elements
+    pub close_bracket: TokenNode,
+    pub trivia: Trivia,
+}
+
+/// `array[index]`
+#[derive(Debug, Clone, PartialEq)]
+pub struct IndexExprNode {
+    pub array: Box<ExpressionNode>,
+    pub open_bracket: TokenNode,
+    pub index: Box<ExpressionNode>,
+    pub close_bracket: TokenNode,
+    pub trivia: Trivia,
+}
+
 #[derive(Debug, Clone, PartialEq)]
 pub struct ErrorNode {
     pub tokens: Vec<TokenNode>,
@@ -140,8 +352,12 @@ pub struct ErrorNode {
     pub trivia: Trivia,
 }
 
+/// Comments attached to a node
 #[derive(Debug, Clone, PartialEq, Default)]
 pub struct Trivia {
+    /// Comments before the node, on the lines above it or before it on its
+    /// first line
     pub leading: Vec<TokenNode>,
+    /// A comment after the node, on the same line as its end
     pub trailing: Vec<TokenNode>,
 }
@@ -0,0 +1,236 @@
+use crate::*;
+
+impl CstRoot {
+    /// Source text for the whole tree, its tokens and comments in order
+    ///
+    /// Whitespace isn't kept in the tree, so it's rebuilt from the tokens'
+    /// spans: tokens that were next to each other stay that way, any other
+    /// gap becomes a single space, and a comment is always followed by a
+    /// line break. Literals are spelled as by `TokenKind::text`, and a
+    /// comment the parser found no node for, like one right after an
+    /// operator, is lost. Other source already in that form comes back
+    /// unchanged.
+    pub fn to_source(&self) -> String {
+        let mut tokens = Vec::new();
+        trivia_tokens(&self.trivia, &mut tokens);
+        for item in &self.items {
+            match item {
+                CstNode::Function(func) => function_tokens(func, &mut tokens),
+                CstNode::Statement(stmt) => statement_tokens(stmt, &mut tokens),
+                CstNode::Expression(expr) => expression_tokens(expr, &mut tokens),
+                CstNode::Token(token) => tokens.push(token),
+                CstNode::Error(error) => {
+                    trivia_tokens(&error.trivia, &mut tokens);
+                    tokens.extend(&error.tokens);
+                }
+            }
+        }
+        // Trivia is kept apart from the tokens it's attached to, so the
+        // walk above doesn't quite find everything in order
+        tokens.sort_by_key(|token| token.span.start);
+
+        let mut source = String::new();
+        let mut previous: Option<&TokenNode> = None;
+        for token in tokens {
+            let text = token.kind.text();
+            if text.is_empty() {
+                continue;
+            }
+            if let Some(previous) = previous {
+                if matches!(previous.kind, TokenKind::Comment(_)) {
+                    source.push('\n');
+                } else if previous.span.end < token.span.start {
+                    source.push(' ');
+                }
+            }
+            source.push_str(&text);
+            previous = Some(token);
+        }
+        source
+    }
+}
+
+fn trivia_tokens<'a>(trivia: &'a Trivia, tokens: &mut Vec<&'a TokenNode>) {
+    tokens.extend(&trivia.leading);
+    tokens.extend(&trivia.trailing);
+}
+
+fn function_tokens<'a>(func: &'a FunctionNode, tokens: &mut Vec<&'a TokenNode>) {
+    trivia_tokens(&func.trivia, tokens);
+    tokens.push(&func.fn_token);
+    tokens.push(&func.name);
+
+    let params = &func.param_list;
+    trivia_tokens(&params.trivia, tokens);
+    tokens.push(&params.open_paren);
+    for param in &params.params {
+        trivia_tokens(&param.trivia, tokens);
+        tokens.push(&param.name);
+        tokens.extend(&param.colon);
+        tokens.extend(&param.ty);
+    }
+    tokens.extend(&params.commas);
+    tokens.push(&params.close_paren);
+
+    tokens.extend(&func.arrow);
+    tokens.extend(&func.return_type);
+    block_tokens(&func.body, tokens);
+}
+
+fn block_tokens<'a>(block: &'a BlockNode, tokens: &mut Vec<&'a TokenNode>) {
+    trivia_tokens(&block.trivia, tokens);
+    tokens.push(&block.open_brace);
+    for stmt in &block.statements {
+        statement_tokens(stmt, tokens);
+    }
+    if let Some(final_expr) = &block.final_expr {
+        expression_tokens(final_expr, tokens);
+    }
+    tokens.push(&block.close_brace);
+}
+
+fn statement_tokens<'a>(stmt: &'a StatementNode, tokens: &mut Vec<&'a TokenNode>) {
+    match stmt {
+        StatementNode::Let(let_stmt) => {
+            trivia_tokens(&let_stmt.trivia, tokens);
+            tokens.extend([&let_stmt.let_token, &let_stmt.name, &let_stmt.equals]);
+            expression_tokens(&let_stmt.value, tokens);
+            tokens.push(&let_stmt.semicolon);
+        }
+        StatementNode::Const(const_stmt) => {
+            trivia_tokens(&const_stmt.trivia, tokens);
+            tokens.extend([
+                &const_stmt.const_token,
+                &const_stmt.name,
+                &const_stmt.equals,
+            ]);
+            expression_tokens(&const_stmt.value, tokens);
+            tokens.push(&const_stmt.semicolon);
+        }
+        StatementNode::Assign(assign_stmt) => {
+            trivia_tokens(&assign_stmt.trivia, tokens);
+            tokens.extend([&assign_stmt.name, &assign_stmt.operator]);
+            expression_tokens(&assign_stmt.value, tokens);
+            tokens.push(&assign_stmt.semicolon);
+        }
+        StatementNode::IndexAssign(assign_stmt) => {
+            trivia_tokens(&assign_stmt.trivia, tokens);
+            index_tokens(&assign_stmt.target, tokens);
+            tokens.push(&assign_stmt.operator);
+            expression_tokens(&assign_stmt.value, tokens);
+            tokens.push(&assign_stmt.semicolon);
+        }
+        StatementNode::Expression(expr_stmt) => {
+            trivia_tokens(&expr_stmt.trivia, tokens);
+            expression_tokens(&expr_stmt.expression, tokens);
+            tokens.push(&expr_stmt.semicolon);
+        }
+        StatementNode::Return(return_stmt) => {
+            trivia_tokens(&return_stmt.trivia, tokens);
+            tokens.push(&return_stmt.return_token);
+            if let Some(value) = &return_stmt.value {
+                expression_tokens(value, tokens);
+            }
+            tokens.push(&return_stmt.semicolon);
+        }
+        StatementNode::Break(break_stmt) => {
+            trivia_tokens(&break_stmt.trivia, tokens);
+            tokens.extend([&break_stmt.break_token, &break_stmt.semicolon]);
+        }
+        StatementNode::Continue(continue_stmt) => {
+            trivia_tokens(&continue_stmt.trivia, tokens);
+            tokens.extend([&continue_stmt.continue_token, &continue_stmt.semicolon]);
+        }
+        StatementNode::Error(error) => {
+            trivia_tokens(&error.trivia, tokens);
+            tokens.extend(&error.tokens);
+        }
+    }
+}
+
+fn expression_tokens<'a>(expr: &'a ExpressionNode, tokens: &mut Vec<&'a TokenNode>) {
+    match expr {
+        ExpressionNode::Binary(binary) => {
+            trivia_tokens(&binary.trivia, tokens);
+            expression_tokens(&binary.left, tokens);
+            tokens.push(&binary.operator);
+            expression_tokens(&binary.right, tokens);
+        }
+        ExpressionNode::Unary(unary) => {
+            trivia_tokens(&unary.trivia, tokens);
+            tokens.push(&unary.operator);
+            expression_tokens(&unary.operand, tokens);
+        }
+        ExpressionNode::Paren(paren) => {
+            trivia_tokens(&paren.trivia, tokens);
+            tokens.push(&paren.open_paren);
+            expression_tokens(&paren.inner, tokens);
+            tokens.push(&paren.close_paren);
+        }
+        ExpressionNode::Call(call) => {
+            trivia_tokens(&call.trivia, tokens);
+            expression_tokens(&call.function, tokens);
+            tokens.push(&call.open_paren);
+            for arg in &call.args {
+                expression_tokens(arg, tokens);
+            }
+            tokens.extend(&call.commas);
+            tokens.push(&call.close_paren);
+        }
+        ExpressionNode::ArrayLit(array) => {
+            trivia_tokens(&array.trivia, tokens);
+            tokens.push(&array.open_bracket);
+            for element in &array.elements {
+                expression_tokens(element, tokens);
+            }
+            tokens.extend(&array.commas);
+            tokens.push(&array.close_bracket);
+        }
+        ExpressionNode::Index(index_expr) => index_tokens(index_expr, tokens),
+        ExpressionNode::Assign(assign_expr) => {
+            trivia_tokens(&assign_expr.trivia, tokens);
+            tokens.extend([&assign_expr.name, &assign_expr.operator]);
+            expression_tokens(&assign_expr.value, tokens);
+        }
+        ExpressionNode::If(if_expr) => if_tokens(if_expr, tokens),
+        ExpressionNode::While(while_expr) => {
+            trivia_tokens(&while_expr.trivia, tokens);
+            tokens.push(&while_expr.while_token);
+            expression_tokens(&while_expr.condition, tokens);
+            block_tokens(&while_expr.body, tokens);
+        }
+        ExpressionNode::For(for_expr) => {
+            trivia_tokens(&for_expr.trivia, tokens);
+            tokens.extend([&for_expr.for_token, &for_expr.variable, &for_expr.in_token]);
+            expression_tokens(&for_expr.start, tokens);
+            tokens.push(&for_expr.dot_dot);
+            expression_tokens(&for_expr.end, tokens);
+            block_tokens(&for_expr.body, tokens);
+        }
+        ExpressionNode::Block(block) => block_tokens(block, tokens),
+        ExpressionNode::Identifier(token) | ExpressionNode::Literal(token) => tokens.push(token),
+    }
+}
+
+fn index_tokens<'a>(index_expr: &'a IndexExprNode, tokens: &mut Vec<&'a TokenNode>) {
+    trivia_tokens(&index_expr.trivia, tokens);
+    expression_tokens(&index_expr.array, tokens);
+    tokens.push(&index_expr.open_bracket);
+    expression_tokens(&index_expr.index, tokens);
+    tokens.push(&index_expr.close_bracket);
+}
+
+fn if_tokens<'a>(if_expr: &'a IfStatementNode, tokens: &mut Vec<&'a TokenNode>) {
+    trivia_tokens(&if_expr.trivia, tokens);
+    tokens.push(&if_expr.if_token);
+    expression_tokens(&if_expr.condition, tokens);
+    block_tokens(&if_expr.then_block, tokens);
+    if let Some(else_clause) = &if_expr.else_clause {
+        trivia_tokens(&else_clause.trivia, tokens);
+        tokens.push(&else_clause.else_token);
+        match &else_clause.body {
+            ElseBodyNode::Block(block) => block_tokens(block, tokens),
+            ElseBodyNode::If(nested_if) => if_tokens(nested_if, tokens),
+        }
+    }
+}
@@ -0,0 +1,190 @@
+use crate::*;
+use rue_lexer::Span;
+
+// From the start of `first` to the end of `last`
+fn between(first: Span, last: Span) -> Span {
+    Span {
+        start: first.start,
+        end: last.end,
+    }
+}
+
+// Spans cover a node's own tokens, from its first to its last, and never
+// its trivia, so a comment before a function isn't part of the function.
+
+impl CstRoot {
+    /// From the first item to the last, or empty if there are none
+    pub fn span(&self) -> Span {
+        let item_span = |item: &CstNode| match item {
+            CstNode::Function(func) => Some(func.span()),
+            CstNode::Statement(stmt) => Some(stmt.span()),
+            CstNode::Expression(expr) => Some(expr.span()),
+            CstNode::Token(token) => Some(token.span),
+            CstNode::Error(error) => Some(between(
+                error.tokens.first()?.span,
+                error.tokens.last()?.span,
+            )),
+        };
+        let mut spans = self.items.iter().filter_map(item_span);
+        match (spans.next(), spans.next_back()) {
+            (Some(first), Some(last)) => between(first, last),
+            (Some(only), None) => only,
+            _ => Span { start: 0, end: 0 },
+        }
+    }
+}
+
+impl FunctionNode {
+    /// From `fn` to the body's closing `}`
+    pub fn span(&self) -> Span {
+        between(self.fn_token.span, self.body.span())
+    }
+}
+
+impl ParamListNode {
+    pub fn span(&self) -> Span {
+        between(self.open_paren.span, self.close_paren.span)
+    }
+}
+
+impl ParamNode {
+    pub fn span(&self) -> Span {
+        between(self.name.span, self.ty.as_ref().unwrap_or(&self.name).span)
+    }
+}
+
+impl BlockNode {
+    pub fn span(&self) -> Span {
+        between(self.open_brace.span, self.close_brace.span)
+    }
+}
+
+impl StatementNode {
+    /// Up to and including the statement's `;`
+    pub fn span(&self) -> Span {
+        match self {
+            StatementNode::Let(let_stmt) => {
+                between(let_stmt.let_token.span, let_stmt.semicolon.span)
+            }
+            StatementNode::Const(const_stmt) => {
+                between(const_stmt.const_token.span, const_stmt.semicolon.span)
+            }
+            StatementNode::Assign(assign_stmt) => {
+                between(assign_stmt.name.span, assign_stmt.semicolon.span)
+            }
+            StatementNode::IndexAssign(assign_stmt) => {
+                between(assign_stmt.target.span(), assign_stmt.semicolon.span)
+            }
+            StatementNode::Expression(expr_stmt) => {
+                between(expr_stmt.expression.span(), expr_stmt.semicolon.span)
+            }
+            StatementNode::Return(return_stmt) => {
+                between(return_stmt.return_token.span, return_stmt.semicolon.span)
+            }
+            StatementNode::Break(break_stmt) => {
+                between(break_stmt.break_token.span, break_stmt.semicolon.span)
+            }
+            StatementNode::Continue(continue_stmt) => between(
+                continue_stmt.continue_token.span,
+                continue_stmt.semicolon.span,
+            ),
+            StatementNode::Error(error) => match (error.tokens.first(), error.tokens.last()) {
+                (Some(first), Some(last)) => between(first.span, last.span),
+                _ => Span { start: 0, end: 0 },
+            },
+        }
+    }
+}
+
+impl ExpressionNode {
+    pub fn span(&self) -> Span {
+        match self {
+            ExpressionNode::Binary(binary) => binary.span(),
+            ExpressionNode::Unary(unary) => unary.span(),
+            ExpressionNode::Paren(paren) => paren.span(),
+            ExpressionNode::Call(call) => call.span(),
+            ExpressionNode::ArrayLit(array) => array.span(),
+            ExpressionNode::Index(index_expr) => index_expr.span(),
+            ExpressionNode::Assign(assign_expr) => assign_expr.span(),
+            ExpressionNode::If(if_expr) => if_expr.span(),
+            ExpressionNode::While(while_expr) => while_expr.span(),
+            ExpressionNode::For(for_expr) => for_expr.span(),
+            ExpressionNode::Block(block) => block.span(),
+            ExpressionNode::Identifier(token) | ExpressionNode::Literal(token) => token.span,
+        }
+    }
+}
+
+impl BinaryExprNode {
+    pub fn span(&self) -> Span {
+        between(self.left.span(), self.right.span())
+    }
+}
+
+impl UnaryExprNode {
+    pub fn span(&self) -> Span {
+        between(self.operator.span, self.operand.span())
+    }
+}
+
+impl ParenExprNode {
+    pub fn span(&self) -> Span {
+        between(self.open_paren.span, self.close_paren.span)
+    }
+}
+
+impl CallExprNode {
+    pub fn span(&self) -> Span {
+        between(self.function.span(), self.close_paren.span)
+    }
+}
+
+impl ArrayLitNode {
+    pub fn span(&self) -> Span {
+        between(self.open_bracket.span, self.close_bracket.span)
+    }
+}
+
+impl IndexExprNode {
+    pub fn span(&self) -> Span {
+        between(self.array.span(), self.close_bracket.span)
+    }
+}
+
+impl AssignExprNode {
+    pub fn span(&self) -> Span {
+        between(self.name.span, self.value.span())
+    }
+}
+
+impl IfStatementNode {
+    /// From `if` to the end of the last block, after any `else`s
+    pub fn span(&self) -> Span {
+        match &self.else_clause {
+            Some(else_clause) => between(self.if_token.span, else_clause.span()),
+            None => between(self.if_token.span, self.then_block.span()),
+        }
+    }
+}
+
+impl ElseClauseNode {
+    pub fn span(&self) -> Span {
+        let body = match &self.body {
+            ElseBodyNode::Block(block) => block.span(),
+            ElseBodyNode::If(nested_if) => nested_if.span(),
+        };
+        between(self.else_token.span, body)
+    }
+}
+
+impl WhileStatementNode {
+    pub fn span(&self) -> Span {
+        between(self.while_token.span, self.body.span())
+    }
+}
+
+impl ForStatementNode {
+    pub fn span(&self) -> Span {
+        between(self.for_token.span, self.body.span())
+    }
+}
@@ -0,0 +1,135 @@
+//! Walking a tree without writing out every node
+//!
+//! A pass implements `Visitor`, overriding the methods for the nodes it cares
+//! about, and calls the matching `walk_` function from an override to carry
+//! on into that node's children. Methods it leaves alone just walk.
+
+use crate::{
+    BlockNode, CstNode, CstRoot, ElseBodyNode, ExpressionNode, FunctionNode, IfStatementNode,
+    StatementNode,
+};
+
+/// A pass over a tree, visiting every function, block, statement and
+/// expression in source order
+pub trait Visitor {
+    fn visit_function(&mut self, func: &FunctionNode) {
+        walk_function(self, func);
+    }
+
+    fn visit_block(&mut self, block: &BlockNode) {
+        walk_block(self, block);
+    }
+
+    fn visit_statement(&mut self, stmt: &StatementNode) {
+        walk_statement(self, stmt);
+    }
+
+    fn visit_expression(&mut self, expr: &ExpressionNode) {
+        walk_expression(self, expr);
+    }
+
+    /// An `if`, either as an expression or after an `else`, so an `else if`
+    /// chain can be handled without rebuilding its `ExpressionNode`s
+    fn visit_if(&mut self, if_expr: &IfStatementNode) {
+        walk_if(self, if_expr);
+    }
+}
+
+/// Visit every item in a program
+pub fn walk_root<V: Visitor + ?Sized>(visitor: &mut V, root: &CstRoot) {
+    for item in &root.items {
+        match item {
+            CstNode::Function(func) => visitor.visit_function(func),
+            CstNode::Statement(stmt) => visitor.visit_statement(stmt),
+            CstNode::Expression(expr) => visitor.visit_expression(expr),
+            CstNode::Token(_) | CstNode::Error(_) => {}
+        }
+    }
+}
+
+pub fn walk_function<V: Visitor + ?Sized>(visitor: &mut V, func: &FunctionNode) {
+    visitor.visit_block(&func.body);
+}
+
+pub fn walk_block<V: Visitor + ?Sized>(visitor: &mut V, block: &BlockNode) {
+    for stmt in &block.statements {
+        visitor.visit_statement(stmt);
+    }
+    if let Some(final_expr) = &block.final_expr {
+        visitor.visit_expression(final_expr);
+    }
+}
+
+/// Visit a statement's expressions. An index assignment's target isn't an
+/// `ExpressionNode` of its own, so its array and index are visited instead.
+pub fn walk_statement<V: Visitor + ?Sized>(visitor: &mut V, stmt: &StatementNode) {
+    match stmt {
+        StatementNode::Let(let_stmt) => visitor.visit_expression(&let_stmt.value),
+        StatementNode::Const(const_stmt) => visitor.visit_expression(&const_stmt.value),
+        StatementNode::Assign(assign_stmt) => visitor.visit_expression(&assign_stmt.value),
+        StatementNode::IndexAssign(assign_stmt) => {
+            visitor.visit_expression(&assign_stmt.target.array);
+            visitor.visit_expression(&assign_stmt.target.index);
+            visitor.visit_expression(&assign_stmt.value);
+        }
+        StatementNode::Expression(expr_stmt) => visitor.visit_expression(&expr_stmt.expression),
+        StatementNode::Return(return_stmt) => {
+            if let Some(value) = &return_stmt.value {
+                visitor.visit_expression(value);
+            }
+        }
+        StatementNode::Break(_) | StatementNode::Continue(_) | StatementNode::Error(_) => {}
+    }
+}
+
+/// Visit an expression's children. A call's function is visited before its
+/// arguments, even when it's just the name being called.
+pub fn walk_expression<V: Visitor + ?Sized>(visitor: &mut V, expr: &ExpressionNode) {
+    match expr {
+        ExpressionNode::Binary(binary) => {
+            visitor.visit_expression(&binary.left);
+            visitor.visit_expression(&binary.right);
+        }
+        ExpressionNode::Unary(unary) => visitor.visit_expression(&unary.operand),
+        ExpressionNode::Paren(paren) => visitor.visit_expression(&paren.inner),
+        ExpressionNode::Call(call) => {
+            visitor.visit_expression(&call.function);
+            for arg in &call.args {
+                visitor.visit_expression(arg);
+            }
+        }
+        ExpressionNode::ArrayLit(array) => {
+            for element in &array.elements {
+                visitor.visit_expression(element);
+            }
+        }
+        ExpressionNode::Index(index_expr) => {
+            visitor.visit_expression(&index_expr.array);
+            visitor.visit_expression(&index_expr.index);
+        }
+        ExpressionNode::Assign(assign_expr) => visitor.visit_expression(&assign_expr.value),
+        ExpressionNode::If(if_expr) => visitor.visit_if(if_expr),
+        ExpressionNode::While(while_expr) => {
+            visitor.visit_expression(&while_expr.condition);
+            visitor.visit_block(&while_expr.body);
+        }
+        ExpressionNode::For(for_expr) => {
+            visitor.visit_expression(&for_expr.start);
+            visitor.visit_expression(&for_expr.end);
+            visitor.visit_block(&for_expr.body);
+        }
+        ExpressionNode::Block(block) => visitor.visit_block(block),
+        ExpressionNode::Identifier(_) | ExpressionNode::Literal(_) => {}
+    }
+}
+
+pub fn walk_if<V: Visitor + ?Sized>(visitor: &mut V, if_expr: &IfStatementNode) {
+    visitor.visit_expression(&if_expr.condition);
+    visitor.visit_block(&if_expr.then_block);
+    if let Some(else_clause) = &if_expr.else_clause {
+        match &else_clause.body {
+            ElseBodyNode::Block(block) => visitor.visit_block(block),
+            ElseBodyNode::If(nested_if) => visitor.visit_if(nested_if),
+        }
+    }
+}
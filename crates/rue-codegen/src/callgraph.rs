@@ -0,0 +1,304 @@
+use rue_ast::visit::walk_expression;
+use rue_ast::{BlockNode, CstNode, CstRoot, ElseBodyNode, ExpressionNode, StatementNode, Visitor};
+use rue_lexer::TokenKind;
+use rue_semantic::SemanticWarning;
+use std::collections::{BTreeMap, BTreeSet};
+
+/// Map each function defined in the program to the names it calls
+///
+/// Callees are recorded by name even if they aren't defined in the program,
+/// so anything provided outside the file never gets pruned by mistake.
+pub fn call_graph(ast: &CstRoot) -> BTreeMap<String, BTreeSet<String>> {
+    let mut graph = BTreeMap::new();
+    for item in &ast.items {
+        if let CstNode::Function(func) = item
+            && let TokenKind::Ident(name) = &func.name.kind
+        {
+            let mut calls = Calls::default();
+            calls.visit_block(&func.body);
+            graph.insert(name.clone(), calls.names);
+        }
+    }
+    graph
+}
+
+/// Names of the functions reachable from `root` through calls, including
+/// `root` itself
+pub fn reachable_functions(ast: &CstRoot, root: &str) -> BTreeSet<String> {
+    let graph = call_graph(ast);
+    let mut reachable = BTreeSet::new();
+    let mut worklist = vec![root.to_string()];
+
+    while let Some(name) = worklist.pop() {
+        if !reachable.insert(name.clone()) {
+            continue;
+        }
+        if let Some(callees) = graph.get(&name) {
+            worklist.extend(callees.iter().cloned());
+        }
+    }
+
+    reachable
+}
+
+/// Groups of functions that call each other recursively, either a single
+/// function that calls itself or several mutually recursive functions
+pub fn recursive_functions(ast: &CstRoot) -> Vec<Vec<String>> {
+    rue_semantic::find_cycles(&call_graph(ast))
+}
+
+/// Warn about recursive calls that aren't in tail position, in source order
+///
+/// Each of these calls keeps its caller's frame on the stack until it
+/// returns, so recursing deeply enough, as for a large input, overflows it.
+/// A call is in tail position when its result is what the function returns:
+/// the body's final expression, through any `if` and blocks, or a `return`'s
+/// value.
+pub fn recursion_warnings(ast: &CstRoot) -> Vec<SemanticWarning> {
+    let cycles = recursive_functions(ast);
+    let mut warnings = Vec::new();
+    for item in &ast.items {
+        if let CstNode::Function(func) = item
+            && let TokenKind::Ident(name) = &func.name.kind
+            && let Some(cycle) = cycles.iter().find(|cycle| cycle.contains(name))
+        {
+            block_non_tail_calls(&func.body, true, cycle, &mut warnings);
+        }
+    }
+    warnings
+}
+
+// Warn about calls to the functions in `cycle` in a block, which is in tail
+// position if `tail` is
+fn block_non_tail_calls(
+    block: &BlockNode,
+    tail: bool,
+    cycle: &[String],
+    warnings: &mut Vec<SemanticWarning>,
+) {
+    for stmt in &block.statements {
+        match stmt {
+            StatementNode::Let(let_stmt) => {
+                expression_non_tail_calls(&let_stmt.value, false, cycle, warnings)
+            }
+            StatementNode::Const(const_stmt) => {
+                expression_non_tail_calls(&const_stmt.value, false, cycle, warnings)
+            }
+            StatementNode::Assign(assign_stmt) => {
+                expression_non_tail_calls(&assign_stmt.value, false, cycle, warnings)
+            }
+            StatementNode::IndexAssign(assign_stmt) => {
+                expression_non_tail_calls(&assign_stmt.value, false, cycle, warnings)
+            }
+            StatementNode::Expression(expr_stmt) => {
+                expression_non_tail_calls(&expr_stmt.expression, false, cycle, warnings)
+            }
+            // Wherever it is, a `return`'s value is what the function returns
+            StatementNode::Return(return_stmt) => {
+                if let Some(value) = &return_stmt.value {
+                    expression_non_tail_calls(value, true, cycle, warnings);
+                }
+            }
+            StatementNode::Break(_) | StatementNode::Continue(_) | StatementNode::Error(_) => {}
+        }
+    }
+    if let Some(final_expr) = &block.final_expr {
+        expression_non_tail_calls(final_expr, tail, cycle, warnings);
+    }
+}
+
+fn expression_non_tail_calls(
+    expr: &ExpressionNode,
+    tail: bool,
+    cycle: &[String],
+    warnings: &mut Vec<SemanticWarning>,
+) {
+    match expr {
+        ExpressionNode::Call(call) => {
+            if let ExpressionNode::Identifier(token) = call.function.as_ref()
+                && let TokenKind::Ident(name) = &token.kind
+                && cycle.contains(name)
+                && !tail
+            {
+                warnings.push(SemanticWarning {
+                    message: format!(
+                        "recursive call to {} isn't in tail position, so deep recursion may overflow the stack",
+                        name
+                    ),
+                    span: token.span,
+                });
+            }
+            for arg in &call.args {
+                expression_non_tail_calls(arg, false, cycle, warnings);
+            }
+        }
+        ExpressionNode::Binary(binary) => {
+            expression_non_tail_calls(&binary.left, false, cycle, warnings);
+            expression_non_tail_calls(&binary.right, false, cycle, warnings);
+        }
+        ExpressionNode::Unary(unary) => {
+            expression_non_tail_calls(&unary.operand, false, cycle, warnings)
+        }
+        ExpressionNode::ArrayLit(array) => {
+            for element in &array.elements {
+                expression_non_tail_calls(element, false, cycle, warnings);
+            }
+        }
+        ExpressionNode::Index(index_expr) => {
+            expression_non_tail_calls(&index_expr.array, false, cycle, warnings);
+            expression_non_tail_calls(&index_expr.index, false, cycle, warnings);
+        }
+        ExpressionNode::Assign(assign_expr) => {
+            expression_non_tail_calls(&assign_expr.value, false, cycle, warnings)
+        }
+        ExpressionNode::Paren(paren) => {
+            expression_non_tail_calls(&paren.inner, tail, cycle, warnings)
+        }
+        ExpressionNode::If(if_expr) => {
+            expression_non_tail_calls(&if_expr.condition, false, cycle, warnings);
+            block_non_tail_calls(&if_expr.then_block, tail, cycle, warnings);
+            if let Some(else_clause) = &if_expr.else_clause {
+                match &else_clause.body {
+                    ElseBodyNode::Block(block) => {
+                        block_non_tail_calls(block, tail, cycle, warnings)
+                    }
+                    ElseBodyNode::If(nested_if) => expression_non_tail_calls(
+                        &ExpressionNode::If(nested_if.clone()),
+                        tail,
+                        cycle,
+                        warnings,
+                    ),
+                }
+            }
+        }
+        ExpressionNode::Block(block) => block_non_tail_calls(block, tail, cycle, warnings),
+        // A loop's body runs again after it, so nothing in it is in tail
+        // position except a `return`
+        ExpressionNode::While(while_expr) => {
+            expression_non_tail_calls(&while_expr.condition, false, cycle, warnings);
+            block_non_tail_calls(&while_expr.body, false, cycle, warnings);
+        }
+        ExpressionNode::For(for_expr) => {
+            expression_non_tail_calls(&for_expr.start, false, cycle, warnings);
+            expression_non_tail_calls(&for_expr.end, false, cycle, warnings);
+            block_non_tail_calls(&for_expr.body, false, cycle, warnings);
+        }
+        ExpressionNode::Identifier(_) | ExpressionNode::Literal(_) => {}
+    }
+}
+
+// The names a function's body calls
+#[derive(Default)]
+struct Calls {
+    names: BTreeSet<String>,
+}
+
+impl Visitor for Calls {
+    fn visit_expression(&mut self, expr: &ExpressionNode) {
+        if let ExpressionNode::Call(call) = expr
+            && let ExpressionNode::Identifier(token) = call.function.as_ref()
+            && let TokenKind::Ident(name) = &token.kind
+        {
+            self.names.insert(name.clone());
+        }
+        walk_expression(self, expr);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rue_lexer::Lexer;
+
+    fn parse(source: &str) -> CstRoot {
+        rue_parser::parse(Lexer::new(source).tokenize()).unwrap()
+    }
+
+    fn names(names: &[&str]) -> BTreeSet<String> {
+        names.iter().map(|name| name.to_string()).collect()
+    }
+
+    #[test]
+    fn test_call_graph() {
+        let ast = parse(
+            r#"
+fn leaf() { 1 }
+fn middle(n) { if n > 0 { leaf() } else { middle(n - 1) } }
+fn zero() { 0 }
+fn main() { let x = middle(3); let a = [0]; a[zero()] = x; x }
+"#,
+        );
+        let graph = call_graph(&ast);
+
+        assert_eq!(graph["leaf"], names(&[]));
+        assert_eq!(graph["middle"], names(&["leaf", "middle"]));
+        assert_eq!(graph["main"], names(&["middle", "zero"]));
+    }
+
+    #[test]
+    fn test_recursive_functions() {
+        let ast = parse(
+            r#"
+fn fact(n) { if n < 2 { 1 } else { n * fact(n - 1) } }
+fn is_odd(n) { if n == 0 { 0 } else { is_even(n - 1) } }
+fn is_even(n) { if n == 0 { 1 } else { is_odd(n - 1) } }
+fn main() { fact(3) + is_even(4) }
+"#,
+        );
+
+        assert_eq!(
+            recursive_functions(&ast),
+            vec![
+                vec!["fact".to_string()],
+                vec!["is_even".to_string(), "is_odd".to_string()]
+            ]
+        );
+    }
+
+    #[test]
+    fn test_recursion_warnings() {
+        let source = "fn factorial(n) { if n <= 1 { 1 } else { n * factorial(n - 1) } }";
+        let warnings = recursion_warnings(&parse(source));
+        assert_eq!(
+            warnings,
+            vec![SemanticWarning {
+                message: "recursive call to factorial isn't in tail position, so deep \
+                          recursion may overflow the stack"
+                    .to_string(),
+                span: rue_lexer::Span {
+                    start: source.rfind("factorial").unwrap(),
+                    end: source.rfind("factorial").unwrap() + "factorial".len(),
+                },
+            }]
+        );
+
+        // An accumulator makes the recursive call the last thing done
+        let ast = parse(
+            r#"
+fn factorial(n, acc) { if n <= 1 { acc } else { factorial(n - 1, n * acc) } }
+fn countdown(n) { if n == 0 { return 0; }; return countdown(n - 1); }
+"#,
+        );
+        assert!(recursion_warnings(&ast).is_empty());
+
+        // Calls that aren't recursive are never warned about
+        assert!(recursion_warnings(&parse("fn f(n) { n } fn g(n) { 1 + f(n) }")).is_empty());
+    }
+
+    #[test]
+    fn test_reachable_functions() {
+        let ast = parse(
+            r#"
+fn used() { 1 }
+fn unused() { used() }
+fn main() { used() }
+"#,
+        );
+
+        assert_eq!(reachable_functions(&ast, "main"), names(&["main", "used"]));
+        assert_eq!(
+            reachable_functions(&ast, "unused"),
+            names(&["unused", "used"])
+        );
+    }
+}
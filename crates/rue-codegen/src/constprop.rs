@@ -0,0 +1,40 @@
+use rue_ast::visit::{walk_expression, walk_statement};
+use rue_ast::{BlockNode, ExpressionNode, StatementNode, Visitor};
+use rue_lexer::TokenKind;
+use std::collections::HashSet;
+
+/// Names of all variables assigned anywhere in a block, including nested
+/// blocks. A `let` binding for one of these can't be propagated.
+pub(crate) fn assigned_variables(block: &BlockNode) -> HashSet<String> {
+    let mut assignments = Assignments::default();
+    assignments.visit_block(block);
+    assignments.names
+}
+
+// The variables assigned by everything visited
+#[derive(Default)]
+struct Assignments {
+    names: HashSet<String>,
+}
+
+impl Visitor for Assignments {
+    // An array's elements are never propagated, so assigning one doesn't
+    // count
+    fn visit_statement(&mut self, stmt: &StatementNode) {
+        if let StatementNode::Assign(assign_stmt) = stmt
+            && let TokenKind::Ident(name) = &assign_stmt.name.kind
+        {
+            self.names.insert(name.clone());
+        }
+        walk_statement(self, stmt);
+    }
+
+    fn visit_expression(&mut self, expr: &ExpressionNode) {
+        if let ExpressionNode::Assign(assign_expr) = expr
+            && let TokenKind::Ident(name) = &assign_expr.name.kind
+        {
+            self.names.insert(name.clone());
+        }
+        walk_expression(self, expr);
+    }
+}
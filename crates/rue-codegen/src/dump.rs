@@ -0,0 +1,199 @@
+use crate::{Instruction, LabelId, call_graph, dump_ir};
+use rue_ast::CstRoot;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+
+/// The order `dump_program_ir` prints a program's functions in
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum IrOrder {
+    /// As generated: the entry point, the entry function, then the rest in
+    /// source order
+    #[default]
+    Source,
+    /// Each function after the functions it calls, so the IR reads bottom
+    /// up. Recursive calls can't all be honored; a cycle is entered at the
+    /// function generated first.
+    CallGraph,
+}
+
+/// Render a whole program's IR, as from `dump_ir`, one function at a time
+/// under a `; name` heading, in the given order
+///
+/// `function_labels` maps each function to its label, as returned by
+/// `Codegen::function_labels`, and the call graph is taken from `ast`. Code
+/// before the first function, the program's entry point, is headed
+/// `; _start`.
+pub fn dump_program_ir(
+    ast: &CstRoot,
+    instructions: &[Instruction],
+    function_labels: &HashMap<String, LabelId>,
+    order: IrOrder,
+) -> String {
+    let names: HashMap<LabelId, &str> = function_labels
+        .iter()
+        .map(|(name, label)| (*label, name.as_str()))
+        .collect();
+
+    // Split the instructions at each function's label
+    let mut functions: Vec<(&str, &[Instruction])> = Vec::new();
+    let mut start = 0;
+    let mut name = "_start";
+    for (index, instr) in instructions.iter().enumerate() {
+        if let Instruction::Label(label) = instr
+            && let Some(&next) = names.get(label)
+        {
+            if index > start {
+                functions.push((name, &instructions[start..index]));
+            }
+            start = index;
+            name = next;
+        }
+    }
+    if instructions.len() > start {
+        functions.push((name, &instructions[start..]));
+    }
+
+    if order == IrOrder::CallGraph {
+        functions = callees_first(&functions, &call_graph(ast));
+    }
+
+    functions
+        .iter()
+        .map(|(name, instructions)| format!("; {}\n{}", name, dump_ir(instructions)))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Render a call graph, as from `call_graph`, in Graphviz's DOT format
+///
+/// Every function is a node, even one that makes no calls and isn't called,
+/// and each call an edge from caller to callee, including a recursive call's
+/// edge back to its own function.
+pub fn dump_call_graph(graph: &BTreeMap<String, BTreeSet<String>>) -> String {
+    let mut dot = String::from("digraph calls {\n");
+    for name in graph.keys() {
+        dot.push_str(&format!("    {};\n", dot_id(name)));
+    }
+    for (caller, callees) in graph {
+        for callee in callees {
+            dot.push_str(&format!("    {} -> {};\n", dot_id(caller), dot_id(callee)));
+        }
+    }
+    dot.push_str("}\n");
+    dot
+}
+
+// A function's name as a DOT node ID. Names are already valid IDs, except
+// for DOT's own keywords, which have to be quoted.
+fn dot_id(name: &str) -> String {
+    let keywords = ["digraph", "edge", "graph", "node", "strict", "subgraph"];
+    if keywords.contains(&name.to_lowercase().as_str()) {
+        format!("\"{}\"", name)
+    } else {
+        name.to_string()
+    }
+}
+
+// Reorder functions so each comes after its callees, keeping the given order
+// where calls don't decide it. The entry point calls the entry function
+// without appearing in the call graph, so it always ends up last.
+fn callees_first<'a>(
+    functions: &[(&'a str, &'a [Instruction])],
+    graph: &BTreeMap<String, BTreeSet<String>>,
+) -> Vec<(&'a str, &'a [Instruction])> {
+    fn visit<'a>(
+        name: &str,
+        by_name: &HashMap<&str, (&'a str, &'a [Instruction])>,
+        graph: &BTreeMap<String, BTreeSet<String>>,
+        visited: &mut HashSet<String>,
+        ordered: &mut Vec<(&'a str, &'a [Instruction])>,
+    ) {
+        if !visited.insert(name.to_string()) {
+            return;
+        }
+        for callee in graph.get(name).into_iter().flatten() {
+            visit(callee, by_name, graph, visited, ordered);
+        }
+        if let Some(&function) = by_name.get(name) {
+            ordered.push(function);
+        }
+    }
+
+    let by_name: HashMap<&str, (&str, &[Instruction])> = functions
+        .iter()
+        .map(|&(name, instructions)| (name, (name, instructions)))
+        .collect();
+    let mut visited = HashSet::new();
+    let mut ordered = Vec::new();
+    for (name, _) in functions.iter().filter(|(name, _)| *name != "_start") {
+        visit(name, &by_name, graph, &mut visited, &mut ordered);
+    }
+    ordered.extend(functions.iter().filter(|(name, _)| *name == "_start"));
+    ordered
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Codegen;
+    use rue_lexer::Lexer;
+
+    fn dump(source: &str, order: IrOrder) -> String {
+        let ast = rue_parser::parse(Lexer::new(source).tokenize()).unwrap();
+        let scope = rue_semantic::analyze_cst(&ast).unwrap();
+        let mut codegen = Codegen::new();
+        let instructions = codegen.generate(&ast, &scope).unwrap();
+        dump_program_ir(&ast, &instructions, codegen.function_labels(), order)
+    }
+
+    // Function headings in the order they're printed
+    fn headings(dump: &str) -> Vec<&str> {
+        dump.lines()
+            .filter_map(|line| line.strip_prefix("; "))
+            .collect()
+    }
+
+    #[test]
+    fn test_dump_program_ir_order() {
+        let source = "fn factorial(n) { if n <= 1 { 1 } else { n * factorial(n - 1) } } \
+                      fn main() { factorial(5) }";
+
+        let source_order = dump(source, IrOrder::Source);
+        assert_eq!(headings(&source_order), vec!["_start", "main", "factorial"]);
+
+        let call_graph_order = dump(source, IrOrder::CallGraph);
+        assert_eq!(
+            headings(&call_graph_order),
+            vec!["factorial", "main", "_start"]
+        );
+
+        // Only the order changes
+        let mut lines: Vec<_> = source_order.lines().collect();
+        let mut reordered: Vec<_> = call_graph_order.lines().collect();
+        lines.sort();
+        reordered.sort();
+        assert_eq!(lines, reordered);
+    }
+
+    #[test]
+    fn test_dump_call_graph() {
+        let source = "fn factorial(n) { if n <= 1 { 1 } else { n * factorial(n - 1) } } \
+                      fn node() { 0 } fn main() { factorial(5) }";
+        let ast = rue_parser::parse(Lexer::new(source).tokenize()).unwrap();
+
+        assert_eq!(
+            dump_call_graph(&call_graph(&ast)),
+            "digraph calls {\n    factorial;\n    main;\n    \"node\";\n    \
+             factorial -> factorial;\n    main -> factorial;\n}\n"
+        );
+    }
+
+    #[test]
+    fn test_call_graph_order_puts_shared_callees_first() {
+        let source = "fn a() { 1 } fn b() { a() } fn c() { b() + a() } fn main() { c() }";
+
+        assert_eq!(
+            headings(&dump(source, IrOrder::CallGraph)),
+            vec!["a", "b", "c", "main", "_start"]
+        );
+    }
+}
@@ -0,0 +1,287 @@
+use crate::{ARG_REGISTERS, BinOp, CodegenError, Instruction, LabelId, Register, VReg, Value};
+use std::collections::HashMap;
+
+/// Instructions run before the interpreter gives up on a program, so a
+/// program that never finishes can't hang its caller
+pub const MAX_STEPS: u64 = 10_000_000;
+
+// Once the program starts, the System V ABI puts argc at [rsp]. The
+// interpreter runs programs with no arguments other than their own name.
+const ARGC: i64 = 1;
+
+// A function call in progress
+struct Frame {
+    vregs: HashMap<VReg, i64>,
+    // Where to continue in the caller, and the VReg its result goes to
+    return_to: usize,
+    dest: Option<VReg>,
+}
+
+// The stack slot at [rsp + offset], where the top of the stack is the end of
+// `stack`
+fn slot(stack: &mut [i64], offset: i64) -> Result<&mut i64, CodegenError> {
+    let index = usize::try_from(offset / 8)
+        .ok()
+        .and_then(|slots| stack.len().checked_sub(slots + 1))
+        .filter(|_| offset % 8 == 0)
+        .ok_or_else(|| CodegenError {
+            message: format!("Unsupported access to [rsp + {}]", offset),
+        })?;
+    Ok(&mut stack[index])
+}
+
+// `base` to the power of `exponent`, wrapping on overflow. Done by squaring,
+// since the exponent can be far too big to multiply out one at a time.
+fn wrapping_pow(mut base: i64, mut exponent: u64) -> i64 {
+    let mut result: i64 = 1;
+    while exponent > 0 {
+        if exponent & 1 == 1 {
+            result = result.wrapping_mul(base);
+        }
+        base = base.wrapping_mul(base);
+        exponent >>= 1;
+    }
+    result
+}
+
+/// Run a program's IR without compiling it to machine code, returning the
+/// exit code it passes to the exit syscall
+///
+/// `function_labels` maps each function to its label, as returned by
+/// `Codegen::function_labels`. Each call gets its own set of VRegs, like
+/// the program's stack frames. Arithmetic wraps, as it does in executables
+/// built without overflow checks, and the exit code isn't truncated to a
+/// byte the way a process's is.
+pub fn interpret(
+    instructions: &[Instruction],
+    function_labels: &HashMap<String, LabelId>,
+) -> Result<i64, CodegenError> {
+    interpret_with_limit(instructions, function_labels, MAX_STEPS)
+}
+
+fn interpret_with_limit(
+    instructions: &[Instruction],
+    function_labels: &HashMap<String, LabelId>,
+    max_steps: u64,
+) -> Result<i64, CodegenError> {
+    let labels: HashMap<LabelId, usize> = instructions
+        .iter()
+        .enumerate()
+        .filter_map(|(index, instr)| match instr {
+            Instruction::Label(label) => Some((*label, index)),
+            _ => None,
+        })
+        .collect();
+    let error = |message: String| CodegenError { message };
+    let jump = |label: &LabelId| {
+        labels
+            .get(label)
+            .copied()
+            .ok_or_else(|| error(format!("Jump to undefined label_{}", label.0)))
+    };
+
+    let mut frames = vec![Frame {
+        vregs: HashMap::new(),
+        return_to: instructions.len(),
+        dest: None,
+    }];
+    let mut registers: HashMap<Register, i64> = HashMap::new();
+    let mut stack: Vec<i64> = Vec::new();
+    let mut pc = 0;
+
+    for _ in 0..max_steps {
+        let Some(instr) = instructions.get(pc) else {
+            return Err(error("Program ended without exiting".to_string()));
+        };
+        pc += 1;
+
+        let depth = frames.len();
+        let frame = frames.last_mut().expect("there's always a frame");
+        let read = |vreg: &VReg| {
+            frame
+                .vregs
+                .get(vreg)
+                .copied()
+                .ok_or_else(|| error(format!("Read of undefined {:?}", vreg)))
+        };
+        let value = |value: &Value| match value {
+            Value::VReg(vreg) => read(vreg),
+            Value::Immediate(value) => Ok(*value),
+            Value::PhysicalReg(register) => Ok(registers.get(register).copied().unwrap_or(0)),
+        };
+
+        match instr {
+            Instruction::Copy { dest, src } => {
+                let src = value(src)?;
+                frame.vregs.insert(*dest, src);
+            }
+            Instruction::BinaryOp { dest, lhs, rhs, op } => {
+                let (lhs, rhs) = (value(lhs)?, value(rhs)?);
+                let result = match op {
+                    BinOp::Add => lhs.wrapping_add(rhs),
+                    BinOp::Sub => lhs.wrapping_sub(rhs),
+                    BinOp::Mul => lhs.wrapping_mul(rhs),
+                    BinOp::Div if rhs == 0 => return Err(error("Division by zero".to_string())),
+                    BinOp::Div => lhs.wrapping_div(rhs),
+                    BinOp::Pow if rhs < 0 => return Err(error("Negative exponent".to_string())),
+                    BinOp::Pow => wrapping_pow(lhs, rhs as u64),
+                    BinOp::Lt => (lhs < rhs) as i64,
+                    BinOp::Le => (lhs <= rhs) as i64,
+                    BinOp::Gt => (lhs > rhs) as i64,
+                    BinOp::Ge => (lhs >= rhs) as i64,
+                    BinOp::Eq => (lhs == rhs) as i64,
+                    BinOp::Ne => (lhs != rhs) as i64,
+                };
+                frame.vregs.insert(*dest, result);
+            }
+            Instruction::Load { dest, offset } => {
+                // The prologue loads argc from the top of the stack
+                let loaded = if depth == 1 && *offset == 0 {
+                    ARGC
+                } else {
+                    *slot(&mut stack, *offset)?
+                };
+                frame.vregs.insert(*dest, loaded);
+            }
+            Instruction::Store { src, offset } => {
+                let src = read(src)?;
+                *slot(&mut stack, *offset)? = src;
+            }
+            Instruction::Push { src } => stack.push(read(src)?),
+            Instruction::AllocateStack { bytes } => {
+                stack.extend(std::iter::repeat_n(0, (*bytes / 8) as usize))
+            }
+            Instruction::FreeStack { bytes } => {
+                let len = stack
+                    .len()
+                    .checked_sub((*bytes / 8) as usize)
+                    .ok_or_else(|| error("Freed more stack than was allocated".to_string()))?;
+                stack.truncate(len);
+            }
+            Instruction::Pop { dest } => {
+                let popped = stack
+                    .pop()
+                    .ok_or_else(|| error("Pop from an empty stack".to_string()))?;
+                frame.vregs.insert(*dest, popped);
+            }
+            Instruction::Label(_) => {}
+            Instruction::Jump(label) => pc = jump(label)?,
+            Instruction::Branch {
+                condition,
+                true_label,
+                false_label,
+            } => {
+                pc = if read(condition)? != 0 {
+                    jump(true_label)?
+                } else {
+                    jump(false_label)?
+                };
+            }
+            Instruction::Call {
+                dest,
+                function,
+                args,
+            } => {
+                // Arguments go in registers, as they do in the executable
+                if args.len() > ARG_REGISTERS.len() {
+                    return Err(error(format!(
+                        "Too many arguments for function call (max {} supported)",
+                        ARG_REGISTERS.len()
+                    )));
+                }
+                for (arg, register) in args.iter().zip(ARG_REGISTERS) {
+                    registers.insert(register, read(arg)?);
+                }
+
+                let label = function_labels
+                    .get(function)
+                    .ok_or_else(|| error(format!("Call to undefined function: {}", function)))?;
+                frames.push(Frame {
+                    vregs: HashMap::new(),
+                    return_to: pc,
+                    dest: *dest,
+                });
+                pc = jump(label)?;
+            }
+            Instruction::Return { value } => {
+                let result = match value {
+                    Some(vreg) => read(vreg)?,
+                    None => 0,
+                };
+                let finished = frames.pop().expect("there's always a frame");
+                let Some(caller) = frames.last_mut() else {
+                    return Err(error("Return from the program's entry point".to_string()));
+                };
+                if let Some(dest) = finished.dest {
+                    caller.vregs.insert(dest, result);
+                }
+                pc = finished.return_to;
+            }
+            Instruction::Syscall {
+                syscall_num, args, ..
+            } => match read(syscall_num)? {
+                60 => {
+                    let code = match args.first() {
+                        Some(arg) => read(arg)?,
+                        None => 0,
+                    };
+                    return Ok(code);
+                }
+                other => return Err(error(format!("Unsupported syscall: {}", other))),
+            },
+            Instruction::Trap => return Err(error("Program trapped".to_string())),
+            Instruction::ReadInt { .. } => {
+                return Err(error("Reading stdin isn't supported".to_string()));
+            }
+            // Registers aren't clobbered by calls in the interpreter
+            Instruction::SaveRegisters { .. } | Instruction::RestoreRegisters { .. } => {}
+        }
+    }
+
+    Err(error(format!(
+        "Program didn't finish within {} steps",
+        max_steps
+    )))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Codegen;
+    use rue_lexer::Lexer;
+
+    fn run(source: &str) -> Result<i64, CodegenError> {
+        let ast = rue_parser::parse(Lexer::new(source).tokenize()).unwrap();
+        let scope = rue_semantic::analyze_cst(&ast).unwrap();
+        let mut codegen = Codegen::new();
+        let instructions = codegen.generate(&ast, &scope)?;
+        interpret_with_limit(&instructions, codegen.function_labels(), 10_000)
+    }
+
+    #[test]
+    fn test_interpret_programs() {
+        let factorial = "fn factorial(n) { if n <= 1 { 1 } else { n * factorial(n - 1) } } \
+                         fn main() { factorial(5) }";
+        assert_eq!(run(factorial).unwrap(), 120);
+
+        let early_return = "fn check(x) { if x < 0 { return 0; }; x * 2 } \
+                            fn main() { check(0 - 5) + check(21) }";
+        assert_eq!(run(early_return).unwrap(), 42);
+
+        // Exit codes aren't truncated the way a process's are
+        assert_eq!(run("fn main() { 1000 }").unwrap(), 1000);
+        assert_eq!(run("fn main(argc) { argc }").unwrap(), 1);
+    }
+
+    #[test]
+    fn test_interpret_errors() {
+        let error = run("fn into_one(d) { 1 / d } fn main() { into_one(0) }").unwrap_err();
+        assert_eq!(error.message, "Division by zero");
+
+        let error = run("fn two_to(e) { 2 ** e } fn main() { two_to(0 - 1) }").unwrap_err();
+        assert_eq!(error.message, "Negative exponent");
+
+        let error = run("fn main() { while 1 > 0 { }; 0 }").unwrap_err();
+        assert!(error.message.contains("didn't finish"));
+    }
+}
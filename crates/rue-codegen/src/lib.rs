@@ -1,15 +1,68 @@
-use rue_ast::{CstRoot, ExpressionNode, FunctionNode, StatementNode};
+use rue_ast::visit::walk_expression;
+use rue_ast::{CstRoot, ExpressionNode, FunctionNode, StatementNode, Visitor};
+use rue_lexer::Span;
 use rue_semantic::Scope;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 
+mod callgraph;
+mod constprop;
+mod dump;
+mod interpret;
+mod listing;
 mod regalloc;
-pub use regalloc::RegisterAllocator;
+mod verify;
+pub use callgraph::{call_graph, reachable_functions, recursion_warnings, recursive_functions};
+pub use dump::{IrOrder, dump_call_graph, dump_program_ir};
+pub use interpret::{MAX_STEPS, interpret};
+pub use listing::render_listing;
+pub use regalloc::{RegisterAllocator, SpillEvent, render_spills};
+pub use verify::{verify_branch_conditions, verify_stack_balance};
 
 #[derive(Debug, Clone, PartialEq)]
 pub struct CodegenError {
     pub message: String,
 }
 
+/// Exit code of a program whose `assert` failed, the same as a Rust
+/// program's when it panics
+pub const ASSERT_FAILED_EXIT_CODE: i64 = 101;
+
+/// Options that change the generated machine code
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Default)]
+pub struct CodegenOptions {
+    /// Trap with `ud2` when `+`, `-` or `*` overflows instead of wrapping
+    pub overflow_checks: bool,
+    /// Optimization level. At 1 and above, constant expressions are folded,
+    /// functions that can't be reached from the entry function aren't
+    /// emitted, and `let` bindings with constant values are propagated into
+    /// their uses.
+    pub opt_level: u8,
+    /// Function the program starts in, or None for `main`. It takes no
+    /// parameters or just `argc`, and its result is the exit code.
+    pub entry: Option<String>,
+}
+
+/// What the optimizations turned on by `opt_level` did to a program
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct OptStats {
+    /// Expressions computed at compile time, like `2 * 3`. Literals don't
+    /// count, and neither do expressions inside a folded one.
+    pub constant_folds: usize,
+    /// `let` bindings with constant values that needed no code, since their
+    /// uses became the value
+    pub bindings_propagated: usize,
+    /// Functions left out because the entry function never calls them
+    pub functions_pruned: usize,
+}
+
+impl std::fmt::Display for OptStats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "constant folds: {}", self.constant_folds)?;
+        writeln!(f, "bindings propagated: {}", self.bindings_propagated)?;
+        writeln!(f, "functions pruned: {}", self.functions_pruned)
+    }
+}
+
 /// Virtual register - will be allocated to a physical register or stack slot
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub struct VReg(pub u32);
@@ -29,6 +82,8 @@ pub enum BinOp {
     Sub,
     Mul,
     Div,
+    /// Raise to a power, which has to be positive or zero
+    Pow,
     Lt,
     Le,
     Gt,
@@ -41,6 +96,10 @@ pub enum BinOp {
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub struct LabelId(pub u32);
 
+// The label of `_start`, the program's entry point, which is out of the way of
+// the labels numbered from 0 for everything else
+const START_LABEL: LabelId = LabelId(999);
+
 /// Platform-independent instruction set
 ///
 /// Examples:
@@ -80,6 +139,12 @@ pub enum Instruction {
     Pop {
         dest: VReg,
     }, // Pop from stack to register
+    AllocateStack {
+        bytes: i64,
+    }, // Make room on the stack, as for an array
+    FreeStack {
+        bytes: i64,
+    }, // Give back room made by AllocateStack
 
     // Control flow
     Label(LabelId),
@@ -106,6 +171,10 @@ pub enum Instruction {
         syscall_num: VReg,
         args: Vec<VReg>,
     },
+    Trap, // ud2, for code that must never be reached
+    ReadInt {
+        dest: VReg,
+    }, // Read a decimal integer from stdin
 
     // Register preservation for calling convention
     SaveRegisters {
@@ -136,14 +205,35 @@ pub enum Register {
     R15,
 }
 
+/// Where a function's arguments are passed, in order, following the System V
+/// AMD64 calling convention. R9 would come next, but every argument is in an
+/// allocatable register at the call, and there are only five, so a function
+/// takes at most `rue_semantic::MAX_PARAMS`.
+pub const ARG_REGISTERS: [Register; rue_semantic::MAX_PARAMS] = [
+    Register::Rdi,
+    Register::Rsi,
+    Register::Rdx,
+    Register::Rcx,
+    Register::R8,
+];
+
 // Code generator state
 pub struct Codegen {
     instructions: Vec<Instruction>,
     vreg_counter: u32,
     label_counter: u32,
-    stack_offset: i64,
+    stack_offset: i64,                // Bytes the current function has on the stack
     variables: HashMap<String, VReg>, // Variable -> virtual register
+    arrays: HashMap<String, (i64, usize)>, // Array -> stack_offset at its start, and length
+    constants: HashMap<String, i64>,  // Variable -> propagated constant, at -O1
+    assigned: HashSet<String>,        // Variables assigned in the current function
     function_labels: HashMap<String, LabelId>, // Function name -> label ID
+    opt_level: u8,
+    entry: String,                       // Function called by _start
+    loops: Vec<(LabelId, LabelId, i64)>, // Enclosing loops' `continue` and `break` targets, and stack_offset
+    spans: Vec<Option<Span>>,            // Source span that produced each instruction
+    current_span: Option<Span>,
+    opt_stats: OptStats,
 }
 
 impl Codegen {
@@ -154,10 +244,44 @@ impl Codegen {
             label_counter: 0,
             stack_offset: 0,
             variables: HashMap::new(),
+            arrays: HashMap::new(),
+            constants: HashMap::new(),
+            assigned: HashSet::new(),
             function_labels: HashMap::new(),
+            opt_level: 0,
+            entry: "main".to_string(),
+            loops: Vec::new(),
+            spans: Vec::new(),
+            current_span: None,
+            opt_stats: OptStats::default(),
         }
     }
 
+    pub fn set_opt_level(&mut self, opt_level: u8) {
+        self.opt_level = opt_level;
+    }
+
+    pub fn set_entry(&mut self, entry: &str) {
+        self.entry = entry.to_string();
+    }
+
+    /// The source span each generated instruction came from, in step with
+    /// the instructions. Spans are per statement, so several instructions
+    /// share one; code with no source, like the entry point, has None.
+    pub fn spans(&self) -> &[Option<Span>] {
+        &self.spans
+    }
+
+    /// What optimizing did to the code generated so far
+    pub fn opt_stats(&self) -> OptStats {
+        self.opt_stats
+    }
+
+    /// The label of each generated function
+    pub fn function_labels(&self) -> &HashMap<String, LabelId> {
+        &self.function_labels
+    }
+
     // Generate a unique virtual register
     fn next_vreg(&mut self) -> VReg {
         let vreg = VReg(self.vreg_counter);
@@ -174,7 +298,41 @@ impl Codegen {
 
     // Emit an instruction
     fn emit(&mut self, instr: Instruction) {
+        match instr {
+            Instruction::Push { .. } => self.stack_offset += 8,
+            Instruction::Pop { .. } => self.stack_offset -= 8,
+            Instruction::AllocateStack { bytes } => self.stack_offset += bytes,
+            Instruction::FreeStack { bytes } => self.stack_offset -= bytes,
+            _ => {}
+        }
         self.instructions.push(instr);
+        self.spans.push(self.current_span);
+    }
+
+    // Free whatever has been put on the stack since it was `depth` bytes
+    fn free_stack_to(&mut self, depth: i64) {
+        if self.stack_offset > depth {
+            self.emit(Instruction::FreeStack {
+                bytes: self.stack_offset - depth,
+            });
+        }
+    }
+
+    // Jump out of the current stack depth, to code that expects `depth`,
+    // leaving the depth as it was for the unreachable code that follows
+    fn emit_exit(&mut self, depth: i64, instr: Instruction) {
+        let saved = self.stack_offset;
+        self.free_stack_to(depth);
+        self.emit(instr);
+        self.stack_offset = saved;
+    }
+
+    // Attribute instructions emitted by `f` to `span`
+    fn with_span<T>(&mut self, span: Span, f: impl FnOnce(&mut Self) -> T) -> T {
+        let saved = self.current_span.replace(span);
+        let result = f(self);
+        self.current_span = saved;
+        result
     }
 
     // Generate code for the entire program
@@ -183,36 +341,68 @@ impl Codegen {
         ast: &CstRoot,
         scope: &Scope,
     ) -> Result<Vec<Instruction>, CodegenError> {
-        // Generate program prologue
-        self.emit_prologue();
+        // Replace constant expressions, including ones made of `const`s,
+        // with their values before generating anything
+        let folded;
+        let ast = if self.opt_level >= 1 {
+            let mut program = ast.clone();
+            self.opt_stats.constant_folds +=
+                rue_semantic::fold_program(&mut program, &scope.constants);
+            folded = program;
+            &folded
+        } else {
+            ast
+        };
 
-        // Find and generate main function first
-        let mut main_generated = false;
-        for item in &ast.items {
-            if let rue_ast::CstNode::Function(func) = item {
-                if let rue_lexer::TokenKind::Ident(name) = &func.name.kind {
-                    if name == "main" {
-                        self.generate_function(func, scope)?;
-                        main_generated = true;
-                        break;
-                    }
-                }
+        // Find the entry function, which is generated first
+        let entry = self.entry.clone();
+        let entry_func = ast.items.iter().find_map(|item| match item {
+            rue_ast::CstNode::Function(func)
+                if matches!(&func.name.kind, rue_lexer::TokenKind::Ident(n) if *n == entry) =>
+            {
+                Some(func)
             }
-        }
+            _ => None,
+        });
 
-        if !main_generated {
-            return Err(CodegenError {
-                message: "No main function found".to_string(),
-            });
+        match entry_func {
+            Some(func) if func.param_list.params.len() > 1 => {
+                return Err(CodegenError {
+                    message: format!(
+                        "Entry function '{}' can only take one parameter, argc",
+                        entry
+                    ),
+                });
+            }
+            Some(func) => {
+                // Generate program prologue
+                self.emit_prologue(func.param_list.params.len() == 1);
+                self.generate_function(func, scope)?;
+            }
+            None if entry == "main" => {
+                return Err(CodegenError {
+                    message: "No main function found".to_string(),
+                });
+            }
+            None => {
+                return Err(CodegenError {
+                    message: format!("No entry function found: {}", entry),
+                });
+            }
         }
 
-        // Generate other functions
+        // Generate other functions, skipping ones the entry never calls when
+        // optimizing
+        let reachable = (self.opt_level >= 1).then(|| reachable_functions(ast, &entry));
         for item in &ast.items {
-            if let rue_ast::CstNode::Function(func) = item {
-                if let rue_lexer::TokenKind::Ident(name) = &func.name.kind {
-                    if name != "main" {
-                        self.generate_function(func, scope)?;
-                    }
+            if let rue_ast::CstNode::Function(func) = item
+                && let rue_lexer::TokenKind::Ident(name) = &func.name.kind
+                && *name != entry
+            {
+                if reachable.as_ref().is_none_or(|r| r.contains(name)) {
+                    self.generate_function(func, scope)?;
+                } else {
+                    self.opt_stats.functions_pruned += 1;
                 }
             }
         }
@@ -222,21 +412,59 @@ impl Codegen {
         Ok(self.instructions.clone())
     }
 
+    // Generate code for a single function on its own, without the program
+    // entry point or any other functions
+    pub fn generate_single_function(
+        &mut self,
+        ast: &CstRoot,
+        scope: &Scope,
+        name: &str,
+    ) -> Result<Vec<Instruction>, CodegenError> {
+        let func = ast
+            .items
+            .iter()
+            .find_map(|item| match item {
+                rue_ast::CstNode::Function(func)
+                    if matches!(&func.name.kind, rue_lexer::TokenKind::Ident(n) if n == name) =>
+                {
+                    Some(func)
+                }
+                _ => None,
+            })
+            .ok_or_else(|| CodegenError {
+                message: format!("No function named {}", name),
+            })?;
+
+        self.generate_function(func, scope)?;
+        self.spans.clear();
+        Ok(std::mem::take(&mut self.instructions))
+    }
+
     // Generate program entry point
-    fn emit_prologue(&mut self) {
+    fn emit_prologue(&mut self, pass_argc: bool) {
         // Entry point label (_start)
-        let start_label = LabelId(999); // Reserve special ID for _start
-        self.emit(Instruction::Label(start_label));
+        self.emit(Instruction::Label(START_LABEL));
+
+        // At process startup, the System V ABI puts argc at [rsp]
+        let mut args = vec![];
+        if pass_argc {
+            let argc = self.next_vreg();
+            self.emit(Instruction::Load {
+                dest: argc,
+                offset: 0,
+            });
+            args.push(argc);
+        }
 
-        // Call main function
+        // Call the entry function, normally main
         let main_result = self.next_vreg();
         self.emit(Instruction::Call {
             dest: Some(main_result),
-            function: "main".to_string(),
-            args: vec![],
+            function: self.entry.clone(),
+            args,
         });
 
-        // Exit program with main's return value
+        // Exit program with the entry function's return value
         let exit_code = self.next_vreg();
         self.emit(Instruction::Copy {
             dest: exit_code,
@@ -255,6 +483,10 @@ impl Codegen {
             syscall_num,
             args: vec![exit_code],
         });
+
+        // exit doesn't return, but if it ever did, trap rather than falling
+        // through into the first function
+        self.emit(Instruction::Trap);
     }
 
     fn emit_epilogue(&mut self) {
@@ -267,6 +499,13 @@ impl Codegen {
         func: &FunctionNode,
         scope: &Scope,
     ) -> Result<(), CodegenError> {
+        self.current_span = Some(func.name.span);
+
+        // Only bindings that are never assigned can be propagated
+        if self.opt_level >= 1 {
+            self.assigned = constprop::assigned_variables(&func.body);
+        }
+
         // Function label
         if let rue_lexer::TokenKind::Ident(name) = &func.name.kind {
             // Create a unique label for this function
@@ -277,40 +516,42 @@ impl Codegen {
             self.function_labels.insert(name.clone(), func_label);
         }
 
-        // Handle parameter if exists
-        if let Some(param) = func.param_list.params.first() {
-            if let rue_lexer::TokenKind::Ident(param_name) = &param.kind {
-                // Assign parameter to a new VReg
+        // Move each parameter from its argument register to its own VReg.
+        // These copies have to stay together: the assembler treats them as
+        // one move, so a parameter can't overwrite a later one's register.
+        if func.param_list.params.len() > ARG_REGISTERS.len() {
+            return Err(CodegenError {
+                message: format!(
+                    "Too many parameters for function (max {} supported)",
+                    ARG_REGISTERS.len()
+                ),
+            });
+        }
+        for (param, register) in func.param_list.params.iter().zip(ARG_REGISTERS) {
+            if let rue_lexer::TokenKind::Ident(param_name) = &param.name.kind {
                 let param_vreg = self.next_vreg();
                 self.variables.insert(param_name.clone(), param_vreg);
-
-                // Move first parameter from RDI (calling convention) to parameter VReg
                 self.emit(Instruction::Copy {
                     dest: param_vreg,
-                    src: Value::PhysicalReg(Register::Rdi),
+                    src: Value::PhysicalReg(register),
                 });
             }
         }
 
-        // Generate function body statements
-        for stmt in &func.body.statements {
-            self.generate_statement(stmt, scope)?;
-        }
-
-        // Generate final expression (return value)
-        let return_vreg = if let Some(final_expr) = &func.body.final_expr {
-            let vreg = self.generate_expression(final_expr, scope)?;
-            Some(vreg)
-        } else {
-            None
-        };
+        // Generate function body, whose value (if any) is the return value
+        let return_vreg = self.generate_block(&func.body, scope)?;
 
         // Return instruction
+        self.current_span = Some(func.body.close_brace.span);
         self.emit(Instruction::Return { value: return_vreg });
 
         // Reset state for next function
         self.stack_offset = 0;
         self.variables.clear();
+        self.arrays.clear();
+        self.constants.clear();
+        self.assigned.clear();
+        self.current_span = None;
 
         Ok(())
     }
@@ -323,16 +564,44 @@ impl Codegen {
     ) -> Result<Option<()>, CodegenError> {
         match stmt {
             StatementNode::Expression(expr_stmt) => {
+                // An `if` statement's value is never read, so it can skip
+                // producing one
+                if let ExpressionNode::If(if_expr) = &expr_stmt.expression {
+                    self.generate_if(if_expr, scope, false)?;
+                    return Ok(Some(()));
+                }
+
                 let _result_vreg = self.generate_expression(&expr_stmt.expression, scope)?;
                 // Expression result is discarded for expression statements
                 Ok(Some(()))
             }
             StatementNode::Let(let_stmt) => {
+                // At -O1, a binding that's never assigned and has a constant
+                // value needs no code: its uses become the constant instead
+                if self.opt_level >= 1
+                    && let rue_lexer::TokenKind::Ident(var_name) = &let_stmt.name.kind
+                    && !self.assigned.contains(var_name)
+                    && let Some(value) = rue_semantic::fold(&let_stmt.value, &self.constants)
+                {
+                    self.constants.insert(var_name.clone(), value);
+                    self.opt_stats.bindings_propagated += 1;
+                    if is_computed(&let_stmt.value) {
+                        self.opt_stats.constant_folds += 1;
+                    }
+                    return Ok(None);
+                }
+
+                if let ExpressionNode::ArrayLit(array) = &let_stmt.value {
+                    return self.generate_array_let(&let_stmt.name, array, scope);
+                }
+
                 // Generate the value expression
                 let value_vreg = self.generate_expression(&let_stmt.value, scope)?;
 
-                // Store in variable mapping
+                // Store in variable mapping, shadowing any constant binding
                 if let rue_lexer::TokenKind::Ident(var_name) = &let_stmt.name.kind {
+                    self.constants.remove(var_name);
+                    self.arrays.remove(var_name);
                     self.variables.insert(var_name.clone(), value_vreg);
                 } else {
                     return Err(CodegenError {
@@ -342,81 +611,183 @@ impl Codegen {
                 Ok(None)
             }
             StatementNode::Assign(assign_stmt) => {
-                // Generate the value expression
-                let value_vreg = self.generate_expression(&assign_stmt.value, scope)?;
-
-                // Update existing variable
-                if let rue_lexer::TokenKind::Ident(var_name) = &assign_stmt.name.kind {
-                    if self.variables.contains_key(var_name) {
-                        self.variables.insert(var_name.clone(), value_vreg);
-                    } else {
-                        return Err(CodegenError {
-                            message: format!("Undefined variable in assignment: {}", var_name),
-                        });
-                    }
-                } else {
-                    return Err(CodegenError {
-                        message: "Invalid variable name in assignment".to_string(),
-                    });
-                }
+                self.generate_assignment(&assign_stmt.name, &assign_stmt.assigned_value(), scope)?;
                 Ok(None)
             }
-        }
-    }
-
-    // Helper function to check if an expression contains function calls
-    fn expression_contains_call(&self, expr: &ExpressionNode) -> bool {
-        match expr {
-            ExpressionNode::Call(_) => true,
-            ExpressionNode::Binary(binary_expr) => {
-                self.expression_contains_call(&binary_expr.left)
-                    || self.expression_contains_call(&binary_expr.right)
-            }
-            ExpressionNode::If(if_expr) => {
-                self.expression_contains_call(&if_expr.condition)
-                    || self.block_contains_call(&if_expr.then_block)
-                    || if let Some(else_clause) = &if_expr.else_clause {
-                        match &else_clause.body {
-                            rue_ast::ElseBodyNode::Block(block) => self.block_contains_call(block),
-                            rue_ast::ElseBodyNode::If(nested_if) => self
-                                .expression_contains_call(&ExpressionNode::If(nested_if.clone())),
-                        }
-                    } else {
-                        false
-                    }
+            StatementNode::IndexAssign(assign_stmt) => {
+                let value_vreg = self.generate_expression(&assign_stmt.assigned_value(), scope)?;
+                let offset = self.element_offset(&assign_stmt.target, scope)?;
+                self.emit(Instruction::Store {
+                    src: value_vreg,
+                    offset,
+                });
+                Ok(None)
+            }
+            // Constants need no code of their own: each use is inlined
+            StatementNode::Const(_) => Ok(None),
+            StatementNode::Return(return_stmt) => {
+                let value = match &return_stmt.value {
+                    Some(value) => Some(self.generate_expression(value, scope)?),
+                    None => None,
+                };
+                self.emit_exit(0, Instruction::Return { value });
+                Ok(None)
             }
-            ExpressionNode::While(while_expr) => {
-                self.expression_contains_call(&while_expr.condition)
-                    || self.block_contains_call(&while_expr.body)
+            StatementNode::Break(_) => {
+                let &(_, break_label, depth) = self.loops.last().ok_or_else(|| CodegenError {
+                    message: "break outside of a loop".to_string(),
+                })?;
+                self.emit_exit(depth, Instruction::Jump(break_label));
+                Ok(None)
+            }
+            StatementNode::Continue(_) => {
+                let &(continue_label, _, depth) =
+                    self.loops.last().ok_or_else(|| CodegenError {
+                        message: "continue outside of a loop".to_string(),
+                    })?;
+                self.emit_exit(depth, Instruction::Jump(continue_label));
+                Ok(None)
             }
-            ExpressionNode::Literal(_) | ExpressionNode::Identifier(_) => false,
+            StatementNode::Error(error) => Err(CodegenError {
+                message: format!("Can't generate code that didn't parse: {}", error.message),
+            }),
         }
     }
 
-    // Helper function to check if a block contains function calls
-    fn block_contains_call(&self, block: &rue_ast::BlockNode) -> bool {
-        // Check statements
+    // Generate a block's statements and final expression. Returns the VReg
+    // holding the block's value, or None for a unit block with no final
+    // expression.
+    fn generate_block(
+        &mut self,
+        block: &rue_ast::BlockNode,
+        scope: &Scope,
+    ) -> Result<Option<VReg>, CodegenError> {
+        // Variables and arrays declared in the block go out of scope at its
+        // end. Assigning an outer variable updates its register in place, so
+        // the outer bindings are still right afterwards.
+        let depth = self.stack_offset;
+        let variables = self.variables.clone();
+        let constants = self.constants.clone();
+        let arrays = self.arrays.clone();
+
         for stmt in &block.statements {
-            if self.statement_contains_call(stmt) {
-                return true;
-            }
-        }
-        // Check final expression
-        if let Some(final_expr) = &block.final_expr {
-            return self.expression_contains_call(final_expr);
+            self.with_span(statement_span(stmt), |this| {
+                this.generate_statement(stmt, scope)
+            })?;
         }
-        false
+
+        let value = match &block.final_expr {
+            Some(final_expr) => Some(self.with_span(expression_span(final_expr), |this| {
+                this.generate_expression(final_expr, scope)
+            })?),
+            None => None,
+        };
+
+        self.free_stack_to(depth);
+        self.variables = variables;
+        self.constants = constants;
+        self.arrays = arrays;
+        Ok(value)
     }
 
-    // Helper function to check if a statement contains function calls
-    fn statement_contains_call(&self, stmt: &StatementNode) -> bool {
-        match stmt {
-            StatementNode::Expression(expr_stmt) => {
-                self.expression_contains_call(&expr_stmt.expression)
-            }
-            StatementNode::Let(let_stmt) => self.expression_contains_call(&let_stmt.value),
-            StatementNode::Assign(assign_stmt) => self.expression_contains_call(&assign_stmt.value),
+    // Generate an assignment of `assigned_value` to the variable `name`, which
+    // for a compound assignment like `x += e` is `x + e`. Returns the VReg
+    // holding the assigned value.
+    fn generate_assignment(
+        &mut self,
+        name: &rue_lexer::Token,
+        assigned_value: &ExpressionNode,
+        scope: &Scope,
+    ) -> Result<VReg, CodegenError> {
+        let value_vreg = self.generate_expression(assigned_value, scope)?;
+
+        // Update existing variable in place, so that code run again, like a
+        // loop body, sees the new value
+        let rue_lexer::TokenKind::Ident(var_name) = &name.kind else {
+            return Err(CodegenError {
+                message: "Invalid variable name in assignment".to_string(),
+            });
+        };
+        let &var_vreg = self.variables.get(var_name).ok_or_else(|| CodegenError {
+            message: format!("Undefined variable in assignment: {}", var_name),
+        })?;
+        self.emit(Instruction::Copy {
+            dest: var_vreg,
+            src: Value::VReg(value_vreg),
+        });
+        Ok(value_vreg)
+    }
+
+    // Generate `let name = [...]`, which puts the array's elements on the
+    // stack, the first at the top
+    fn generate_array_let(
+        &mut self,
+        name: &rue_lexer::Token,
+        array: &rue_ast::ArrayLitNode,
+        scope: &Scope,
+    ) -> Result<Option<()>, CodegenError> {
+        let rue_lexer::TokenKind::Ident(var_name) = &name.kind else {
+            return Err(CodegenError {
+                message: "Invalid variable name in let statement".to_string(),
+            });
+        };
+
+        self.emit(Instruction::AllocateStack {
+            bytes: 8 * array.elements.len() as i64,
+        });
+        let base = self.stack_offset;
+        for (i, element) in array.elements.iter().enumerate() {
+            let value_vreg = self.generate_expression(element, scope)?;
+            self.emit(Instruction::Store {
+                src: value_vreg,
+                offset: self.stack_offset - base + 8 * i as i64,
+            });
         }
+
+        self.variables.remove(var_name);
+        self.constants.remove(var_name);
+        self.arrays
+            .insert(var_name.clone(), (base, array.elements.len()));
+        Ok(None)
+    }
+
+    // Where `array[index]` is, as an offset from the top of the stack. Indices
+    // are known at compile time, so there's nothing to compute at runtime.
+    fn element_offset(
+        &self,
+        index_expr: &rue_ast::IndexExprNode,
+        scope: &Scope,
+    ) -> Result<i64, CodegenError> {
+        let error = || CodegenError {
+            message: "Arrays can only be indexed by a constant in bounds".to_string(),
+        };
+
+        let ExpressionNode::Identifier(rue_lexer::Token {
+            kind: rue_lexer::TokenKind::Ident(name),
+            ..
+        }) = index_expr.array.as_ref()
+        else {
+            return Err(error());
+        };
+        let &(base, len) = self.arrays.get(name).ok_or_else(|| CodegenError {
+            message: format!("Undefined array: {}", name),
+        })?;
+
+        // Constants the function shadows don't count
+        let constants: HashMap<String, i64> = scope
+            .constants
+            .iter()
+            .filter(|(name, _)| {
+                !self.variables.contains_key(*name) && !self.arrays.contains_key(*name)
+            })
+            .map(|(name, value)| (name.clone(), *value))
+            .chain(self.constants.clone())
+            .collect();
+        let index = rue_semantic::fold(&index_expr.index, &constants)
+            .and_then(|index| usize::try_from(index).ok())
+            .filter(|&index| index < len)
+            .ok_or_else(error)?;
+        Ok(self.stack_offset - base + 8 * index as i64)
     }
 
     // Generate code for an expression, returns VReg containing result
@@ -425,20 +796,39 @@ impl Codegen {
         expr: &ExpressionNode,
         _scope: &Scope,
     ) -> Result<VReg, CodegenError> {
+        if self.opt_level >= 1
+            && let Some(value) = rue_semantic::fold(expr, &self.constants)
+        {
+            if is_computed(expr) {
+                self.opt_stats.constant_folds += 1;
+            }
+            let dest = self.next_vreg();
+            self.emit(Instruction::Copy {
+                dest,
+                src: Value::Immediate(value),
+            });
+            return Ok(dest);
+        }
+
         match expr {
             ExpressionNode::Literal(token) => {
-                if let rue_lexer::TokenKind::Integer(value) = &token.kind {
-                    let dest = self.next_vreg();
-                    self.emit(Instruction::Copy {
-                        dest,
-                        src: Value::Immediate(*value),
-                    });
-                    Ok(dest)
-                } else {
-                    Err(CodegenError {
-                        message: "Invalid literal token".to_string(),
-                    })
-                }
+                // Booleans are 1 for true and 0 for false
+                let value = match token.kind {
+                    rue_lexer::TokenKind::Integer(value) => value,
+                    rue_lexer::TokenKind::True => 1,
+                    rue_lexer::TokenKind::False => 0,
+                    _ => {
+                        return Err(CodegenError {
+                            message: "Invalid literal token".to_string(),
+                        });
+                    }
+                };
+                let dest = self.next_vreg();
+                self.emit(Instruction::Copy {
+                    dest,
+                    src: Value::Immediate(value),
+                });
+                Ok(dest)
             }
             ExpressionNode::Identifier(token) => {
                 if let rue_lexer::TokenKind::Ident(name) = &token.kind {
@@ -449,6 +839,14 @@ impl Codegen {
                             src: Value::VReg(var_vreg),
                         });
                         Ok(dest)
+                    } else if let Some(&value) = _scope.constants.get(name) {
+                        // Constants are inlined where they're used
+                        let dest = self.next_vreg();
+                        self.emit(Instruction::Copy {
+                            dest,
+                            src: Value::Immediate(value),
+                        });
+                        Ok(dest)
                     } else {
                         Err(CodegenError {
                             message: format!("Undefined variable: {}", name),
@@ -460,6 +858,26 @@ impl Codegen {
                     })
                 }
             }
+            ExpressionNode::Index(index_expr) => {
+                let offset = self.element_offset(index_expr, _scope)?;
+                let dest = self.next_vreg();
+                self.emit(Instruction::Load { dest, offset });
+                Ok(dest)
+            }
+            ExpressionNode::Assign(assign_expr) => {
+                self.generate_assignment(&assign_expr.name, &assign_expr.assigned_value(), _scope)
+            }
+            ExpressionNode::ArrayLit(_) => Err(CodegenError {
+                message: "Array literals can only be the value of a `let`".to_string(),
+            }),
+            ExpressionNode::Binary(binary_expr)
+                if matches!(
+                    binary_expr.operator.kind,
+                    rue_lexer::TokenKind::AmpAmp | rue_lexer::TokenKind::PipePipe
+                ) =>
+            {
+                self.generate_logical(binary_expr, _scope)
+            }
             ExpressionNode::Binary(binary_expr) => {
                 // For operations where the RHS might be a function call (that could modify registers),
                 // we need to preserve the LHS value properly
@@ -468,9 +886,14 @@ impl Codegen {
                     rue_lexer::TokenKind::Plus => BinOp::Add,
                     rue_lexer::TokenKind::Minus => BinOp::Sub,
                     rue_lexer::TokenKind::Star => BinOp::Mul,
+                    rue_lexer::TokenKind::StarStar => BinOp::Pow,
                     rue_lexer::TokenKind::Slash => BinOp::Div,
+                    rue_lexer::TokenKind::Less => BinOp::Lt,
                     rue_lexer::TokenKind::LessEqual => BinOp::Le,
                     rue_lexer::TokenKind::Greater => BinOp::Gt,
+                    rue_lexer::TokenKind::GreaterEqual => BinOp::Ge,
+                    rue_lexer::TokenKind::Equal => BinOp::Eq,
+                    rue_lexer::TokenKind::NotEqual => BinOp::Ne,
                     _ => {
                         return Err(CodegenError {
                             message: format!(
@@ -482,7 +905,7 @@ impl Codegen {
                 };
 
                 // Check if RHS contains a function call that could corrupt registers
-                let rhs_has_call = self.expression_contains_call(&binary_expr.right);
+                let rhs_has_call = contains_call(&binary_expr.right);
 
                 if rhs_has_call {
                     // Strategy: Evaluate LHS, push to stack, evaluate RHS, pop LHS back
@@ -520,7 +943,69 @@ impl Codegen {
 
                 Ok(dest)
             }
+            ExpressionNode::Paren(paren_expr) => {
+                self.generate_expression(&paren_expr.inner, _scope)
+            }
+            ExpressionNode::Unary(unary_expr) => {
+                // Negation is subtraction from zero, and `!` compares with
+                // zero, since false is 0
+                let op = match unary_expr.operator.kind {
+                    rue_lexer::TokenKind::Minus => BinOp::Sub,
+                    rue_lexer::TokenKind::Bang => BinOp::Eq,
+                    _ => {
+                        return Err(CodegenError {
+                            message: format!(
+                                "Unsupported operator: {:?}",
+                                unary_expr.operator.kind
+                            ),
+                        });
+                    }
+                };
+                let operand = self.generate_expression(&unary_expr.operand, _scope)?;
+                let dest = self.next_vreg();
+                self.emit(Instruction::BinaryOp {
+                    dest,
+                    lhs: Value::Immediate(0),
+                    rhs: Value::VReg(operand),
+                    op,
+                });
+                Ok(dest)
+            }
             ExpressionNode::Call(call_expr) => {
+                // `size_of` is evaluated at compile time, unless the program
+                // defines its own
+                if let ExpressionNode::Identifier(func_token) = &*call_expr.function
+                    && matches!(&func_token.kind, rue_lexer::TokenKind::Ident(name) if name == rue_semantic::SIZE_OF)
+                    && !_scope.functions.contains_key(rue_semantic::SIZE_OF)
+                {
+                    let size = rue_semantic::size_of_type(call_expr)
+                        .ok()
+                        .and_then(|ty| ty.size())
+                        .ok_or_else(|| CodegenError {
+                            message: "Invalid size_of argument".to_string(),
+                        })?;
+                    let dest = self.next_vreg();
+                    self.emit(Instruction::Copy {
+                        dest,
+                        src: Value::Immediate(size),
+                    });
+                    return Ok(dest);
+                }
+                if let ExpressionNode::Identifier(func_token) = &*call_expr.function
+                    && matches!(&func_token.kind, rue_lexer::TokenKind::Ident(name) if name == rue_semantic::ASSERT)
+                    && !_scope.functions.contains_key(rue_semantic::ASSERT)
+                {
+                    return self.generate_assert(call_expr, _scope);
+                }
+                if let ExpressionNode::Identifier(func_token) = &*call_expr.function
+                    && matches!(&func_token.kind, rue_lexer::TokenKind::Ident(name) if name == rue_semantic::READ_INT)
+                    && !_scope.functions.contains_key(rue_semantic::READ_INT)
+                {
+                    let dest = self.next_vreg();
+                    self.emit(Instruction::ReadInt { dest });
+                    return Ok(dest);
+                }
+
                 // Generate arguments
                 let mut arg_vregs = Vec::new();
                 for arg in &call_expr.args {
@@ -561,94 +1046,8 @@ impl Codegen {
                 }
             }
             ExpressionNode::If(if_stmt) => {
-                let else_label = self.next_label();
-                let end_label = self.next_label();
-
-                // Create a shared result register that both branches will write to
-                let result_vreg = self.next_vreg();
-
-                // Generate condition
-                let condition_vreg = self.generate_expression(&if_stmt.condition, _scope)?;
-
-                // Generate then block label
-                let then_label = self.next_label();
-
-                // Branch on condition
-                self.emit(Instruction::Branch {
-                    condition: condition_vreg,
-                    true_label: then_label,
-                    false_label: else_label,
-                });
-
-                // Generate then block
-                self.emit(Instruction::Label(then_label));
-
-                // Generate then block statements
-                for stmt in &if_stmt.then_block.statements {
-                    self.generate_statement(stmt, _scope)?;
-                }
-
-                // Generate then block final expression and copy to result
-                let then_result = if let Some(final_expr) = &if_stmt.then_block.final_expr {
-                    self.generate_expression(final_expr, _scope)?
-                } else {
-                    let zero_vreg = self.next_vreg();
-                    self.emit(Instruction::Copy {
-                        dest: zero_vreg,
-                        src: Value::Immediate(0),
-                    });
-                    zero_vreg
-                };
-
-                // Copy then result to shared result register
-                self.emit(Instruction::Copy {
-                    dest: result_vreg,
-                    src: Value::VReg(then_result),
-                });
-
-                self.emit(Instruction::Jump(end_label));
-
-                // Generate else block
-                self.emit(Instruction::Label(else_label));
-                let else_result = if let Some(else_clause) = &if_stmt.else_clause {
-                    match &else_clause.body {
-                        rue_ast::ElseBodyNode::Block(block) => {
-                            for stmt in &block.statements {
-                                self.generate_statement(stmt, _scope)?;
-                            }
-                            if let Some(final_expr) = &block.final_expr {
-                                self.generate_expression(final_expr, _scope)?
-                            } else {
-                                let zero_vreg = self.next_vreg();
-                                self.emit(Instruction::Copy {
-                                    dest: zero_vreg,
-                                    src: Value::Immediate(0),
-                                });
-                                zero_vreg
-                            }
-                        }
-                        rue_ast::ElseBodyNode::If(nested_if) => self
-                            .generate_expression(&ExpressionNode::If(nested_if.clone()), _scope)?,
-                    }
-                } else {
-                    let zero_vreg = self.next_vreg();
-                    self.emit(Instruction::Copy {
-                        dest: zero_vreg,
-                        src: Value::Immediate(0),
-                    });
-                    zero_vreg
-                };
-
-                // Copy else result to shared result register
-                self.emit(Instruction::Copy {
-                    dest: result_vreg,
-                    src: Value::VReg(else_result),
-                });
-
-                self.emit(Instruction::Label(end_label));
-
-                // Return the shared result register
-                Ok(result_vreg)
+                let result = self.generate_if(if_stmt, _scope, true)?;
+                Ok(result.expect("a used if always has a result register"))
             }
             ExpressionNode::While(while_stmt) => {
                 let loop_start = self.next_label();
@@ -673,14 +1072,11 @@ impl Codegen {
                 // Generate loop body
                 self.emit(Instruction::Label(body_label));
 
-                // Generate loop body statements
-                for stmt in &while_stmt.body.statements {
-                    self.generate_statement(stmt, _scope)?;
-                }
-                // Generate loop body final expression (if any) - value is discarded
-                if let Some(final_expr) = &while_stmt.body.final_expr {
-                    let _result = self.generate_expression(final_expr, _scope)?;
-                }
+                // Generate loop body - its value is discarded. `continue`
+                // goes back to the condition check.
+                self.loops.push((loop_start, loop_end, self.stack_offset));
+                self.generate_block(&while_stmt.body, _scope)?;
+                self.loops.pop();
 
                 // Jump back to condition check
                 self.emit(Instruction::Jump(loop_start));
@@ -697,14 +1093,392 @@ impl Codegen {
 
                 Ok(zero_vreg)
             }
+            ExpressionNode::For(for_stmt) => self.generate_for(for_stmt, _scope),
+            ExpressionNode::Block(block) => {
+                let result = self.generate_block(block, _scope)?;
+
+                match result {
+                    Some(result) => Ok(result),
+                    None => {
+                        // A block without a final expression has no value
+                        let zero_vreg = self.next_vreg();
+                        self.emit(Instruction::Copy {
+                            dest: zero_vreg,
+                            src: Value::Immediate(0),
+                        });
+                        Ok(zero_vreg)
+                    }
+                }
+            }
         }
     }
-}
 
-impl Default for Codegen {
-    fn default() -> Self {
-        Self::new()
-    }
+    // Generate an `assert`: continue if the condition holds, and otherwise
+    // exit right away with ASSERT_FAILED_EXIT_CODE
+    fn generate_assert(
+        &mut self,
+        call_expr: &rue_ast::CallExprNode,
+        scope: &Scope,
+    ) -> Result<VReg, CodegenError> {
+        let [condition] = call_expr.args.as_slice() else {
+            return Err(CodegenError {
+                message: "assert expects a single condition".to_string(),
+            });
+        };
+        let condition_vreg = self.generate_expression(condition, scope)?;
+
+        let failed_label = self.next_label();
+        let passed_label = self.next_label();
+        self.emit(Instruction::Branch {
+            condition: condition_vreg,
+            true_label: passed_label,
+            false_label: failed_label,
+        });
+
+        self.emit(Instruction::Label(failed_label));
+        let exit_code = self.next_vreg();
+        self.emit(Instruction::Copy {
+            dest: exit_code,
+            src: Value::Immediate(ASSERT_FAILED_EXIT_CODE),
+        });
+        let syscall_num = self.next_vreg();
+        self.emit(Instruction::Copy {
+            dest: syscall_num,
+            src: Value::Immediate(60), // sys_exit
+        });
+        let syscall_result = self.next_vreg();
+        self.emit(Instruction::Syscall {
+            result: syscall_result,
+            syscall_num,
+            args: vec![exit_code],
+        });
+        self.emit(Instruction::Trap);
+
+        // Asserting has no value, but every expression needs a register
+        self.emit(Instruction::Label(passed_label));
+        let zero_vreg = self.next_vreg();
+        self.emit(Instruction::Copy {
+            dest: zero_vreg,
+            src: Value::Immediate(0),
+        });
+        Ok(zero_vreg)
+    }
+
+    // Generate a `for` loop as the `while` it's short for: the loop variable
+    // starts at the start of the range and is incremented after each pass
+    // through the body, until it reaches the end. The end is evaluated once,
+    // before the loop.
+    fn generate_for(
+        &mut self,
+        for_stmt: &rue_ast::ForStatementNode,
+        scope: &Scope,
+    ) -> Result<VReg, CodegenError> {
+        let rue_lexer::TokenKind::Ident(var_name) = &for_stmt.variable.kind else {
+            return Err(CodegenError {
+                message: "Invalid variable name in for loop".to_string(),
+            });
+        };
+
+        let var_vreg = self.generate_expression(&for_stmt.start, scope)?;
+        let end_vreg = self.generate_expression(&for_stmt.end, scope)?;
+
+        // The loop variable shadows any outer binding until the loop ends
+        let outer_variable = self.variables.insert(var_name.clone(), var_vreg);
+        let outer_constant = self.constants.remove(var_name);
+
+        let loop_start = self.next_label();
+        let body_label = self.next_label();
+        let increment_label = self.next_label();
+        let loop_end = self.next_label();
+
+        self.emit(Instruction::Label(loop_start));
+        let condition_vreg = self.next_vreg();
+        self.emit(Instruction::BinaryOp {
+            dest: condition_vreg,
+            lhs: Value::VReg(var_vreg),
+            rhs: Value::VReg(end_vreg),
+            op: BinOp::Lt,
+        });
+        self.emit(Instruction::Branch {
+            condition: condition_vreg,
+            true_label: body_label,
+            false_label: loop_end,
+        });
+
+        // The body's value is discarded. `continue` skips the rest of it, but
+        // not the increment.
+        self.emit(Instruction::Label(body_label));
+        self.loops
+            .push((increment_label, loop_end, self.stack_offset));
+        self.generate_block(&for_stmt.body, scope)?;
+        self.loops.pop();
+
+        // The assembler only adds registers, so the 1 needs one too. The sum
+        // is copied back to the variable, like an assignment's value.
+        self.emit(Instruction::Label(increment_label));
+        let one_vreg = self.next_vreg();
+        self.emit(Instruction::Copy {
+            dest: one_vreg,
+            src: Value::Immediate(1),
+        });
+        let next_vreg = self.next_vreg();
+        self.emit(Instruction::BinaryOp {
+            dest: next_vreg,
+            lhs: Value::VReg(var_vreg),
+            rhs: Value::VReg(one_vreg),
+            op: BinOp::Add,
+        });
+        self.emit(Instruction::Copy {
+            dest: var_vreg,
+            src: Value::VReg(next_vreg),
+        });
+        self.emit(Instruction::Jump(loop_start));
+        self.emit(Instruction::Label(loop_end));
+
+        match outer_variable {
+            Some(vreg) => self.variables.insert(var_name.clone(), vreg),
+            None => self.variables.remove(var_name),
+        };
+        if let Some(value) = outer_constant {
+            self.constants.insert(var_name.clone(), value);
+        }
+
+        // For expressions always return 0, like while expressions
+        let zero_vreg = self.next_vreg();
+        self.emit(Instruction::Copy {
+            dest: zero_vreg,
+            src: Value::Immediate(0),
+        });
+        Ok(zero_vreg)
+    }
+
+    // Generate `&&` or `||`. The right operand is only evaluated when the
+    // left one doesn't already decide the result, like an `if`, and the
+    // result is written to a register on each path.
+    fn generate_logical(
+        &mut self,
+        binary_expr: &rue_ast::BinaryExprNode,
+        scope: &Scope,
+    ) -> Result<VReg, CodegenError> {
+        let result_vreg = self.next_vreg();
+        let rhs_label = self.next_label();
+        let short_label = self.next_label();
+        let end_label = self.next_label();
+
+        let lhs_vreg = self.generate_expression(&binary_expr.left, scope)?;
+        let (true_label, false_label, short_value) = match binary_expr.operator.kind {
+            rue_lexer::TokenKind::AmpAmp => (rhs_label, short_label, 0),
+            _ => (short_label, rhs_label, 1),
+        };
+        self.emit(Instruction::Branch {
+            condition: lhs_vreg,
+            true_label,
+            false_label,
+        });
+
+        self.emit(Instruction::Label(rhs_label));
+        let rhs_vreg = self.generate_expression(&binary_expr.right, scope)?;
+        self.emit(Instruction::Copy {
+            dest: result_vreg,
+            src: Value::VReg(rhs_vreg),
+        });
+        self.emit(Instruction::Jump(end_label));
+
+        self.emit(Instruction::Label(short_label));
+        self.emit(Instruction::Copy {
+            dest: result_vreg,
+            src: Value::Immediate(short_value),
+        });
+        self.emit(Instruction::Label(end_label));
+        Ok(result_vreg)
+    }
+
+    // Generate an `if`. When its value is used, both branches write it to a
+    // shared result register, which is returned. In statement position
+    // nothing reads the value, so there is no result register, no copies
+    // into it, and without an `else` the condition branches straight past
+    // the then block.
+    fn generate_if(
+        &mut self,
+        if_stmt: &rue_ast::IfStatementNode,
+        scope: &Scope,
+        used: bool,
+    ) -> Result<Option<VReg>, CodegenError> {
+        let (else_label, end_label) = match (&if_stmt.else_clause, used) {
+            (None, false) => {
+                let end_label = self.next_label();
+                (end_label, end_label)
+            }
+            _ => (self.next_label(), self.next_label()),
+        };
+
+        // Create a shared result register that both branches will write to
+        let result_vreg = used.then(|| self.next_vreg());
+
+        // Generate condition
+        let condition_vreg = self.generate_expression(&if_stmt.condition, scope)?;
+
+        // Generate then block label
+        let then_label = self.next_label();
+
+        // Branch on condition
+        self.emit(Instruction::Branch {
+            condition: condition_vreg,
+            true_label: then_label,
+            false_label: else_label,
+        });
+
+        // Generate then block
+        self.emit(Instruction::Label(then_label));
+
+        // Generate then block and copy its value to the result, or 0 if it
+        // has none, so the result is written on every path
+        let then_result = self.generate_block(&if_stmt.then_block, scope)?;
+        if let Some(result_vreg) = result_vreg {
+            self.emit(Instruction::Copy {
+                dest: result_vreg,
+                src: then_result.map_or(Value::Immediate(0), Value::VReg),
+            });
+        }
+
+        if else_label == end_label {
+            self.emit(Instruction::Label(end_label));
+            return Ok(None);
+        }
+
+        self.emit(Instruction::Jump(end_label));
+
+        // Generate else block. Without one, the `if` evaluates to 0.
+        self.emit(Instruction::Label(else_label));
+        let else_result = match if_stmt.else_clause.as_ref().map(|clause| &clause.body) {
+            Some(rue_ast::ElseBodyNode::Block(block)) => self.generate_block(block, scope)?,
+            Some(rue_ast::ElseBodyNode::If(nested_if)) => {
+                self.generate_if(nested_if, scope, used)?
+            }
+            None => None,
+        };
+
+        // Copy else result to shared result register
+        if let Some(result_vreg) = result_vreg {
+            self.emit(Instruction::Copy {
+                dest: result_vreg,
+                src: else_result.map_or(Value::Immediate(0), Value::VReg),
+            });
+        }
+
+        self.emit(Instruction::Label(end_label));
+
+        Ok(result_vreg)
+    }
+}
+
+// Whether folding an expression computes anything, rather than just reading
+// a literal or a name's value
+fn is_computed(expr: &ExpressionNode) -> bool {
+    !matches!(
+        expr,
+        ExpressionNode::Literal(_) | ExpressionNode::Identifier(_)
+    )
+}
+
+// Whether evaluating an expression can call a function, which could
+// overwrite registers
+fn contains_call(expr: &ExpressionNode) -> bool {
+    #[derive(Default)]
+    struct FindCall {
+        found: bool,
+    }
+
+    impl Visitor for FindCall {
+        fn visit_expression(&mut self, expr: &ExpressionNode) {
+            if let ExpressionNode::Call(_) = expr {
+                self.found = true;
+            } else if !self.found {
+                walk_expression(self, expr);
+            }
+        }
+    }
+
+    let mut finder = FindCall::default();
+    finder.visit_expression(expr);
+    finder.found
+}
+
+// The span of the token a statement starts with
+fn statement_span(stmt: &StatementNode) -> Span {
+    match stmt {
+        StatementNode::Let(let_stmt) => let_stmt.let_token.span,
+        StatementNode::Const(const_stmt) => const_stmt.const_token.span,
+        StatementNode::Assign(assign_stmt) => assign_stmt.name.span,
+        StatementNode::IndexAssign(assign_stmt) => expression_span(&assign_stmt.target.array),
+        StatementNode::Expression(expr_stmt) => expression_span(&expr_stmt.expression),
+        StatementNode::Return(return_stmt) => return_stmt.return_token.span,
+        StatementNode::Break(break_stmt) => break_stmt.break_token.span,
+        StatementNode::Continue(continue_stmt) => continue_stmt.continue_token.span,
+        StatementNode::Error(_) => stmt.span(),
+    }
+}
+
+// The span of the token an expression starts with
+fn expression_span(expr: &ExpressionNode) -> Span {
+    match expr {
+        ExpressionNode::Binary(binary_expr) => expression_span(&binary_expr.left),
+        ExpressionNode::Unary(unary_expr) => unary_expr.operator.span,
+        ExpressionNode::Paren(paren_expr) => paren_expr.open_paren.span,
+        ExpressionNode::Call(call_expr) => expression_span(&call_expr.function),
+        ExpressionNode::ArrayLit(array) => array.open_bracket.span,
+        ExpressionNode::Index(index_expr) => expression_span(&index_expr.array),
+        ExpressionNode::Assign(assign_expr) => assign_expr.name.span,
+        ExpressionNode::If(if_expr) => if_expr.if_token.span,
+        ExpressionNode::While(while_expr) => while_expr.while_token.span,
+        ExpressionNode::For(for_expr) => for_expr.for_token.span,
+        ExpressionNode::Block(block) => block.open_brace.span,
+        ExpressionNode::Identifier(token) | ExpressionNode::Literal(token) => token.span,
+    }
+}
+
+// The destination and source of a copy from a physical register
+fn physical_copy(instr: &Instruction) -> Option<(VReg, Register)> {
+    match instr {
+        Instruction::Copy {
+            dest,
+            src: Value::PhysicalReg(src),
+        } => Some((*dest, *src)),
+        _ => None,
+    }
+}
+
+// Whether a register is one of r8-r15, which need a REX bit set to encode
+fn is_extended(reg: &Register) -> bool {
+    matches!(
+        reg,
+        Register::R8
+            | Register::R9
+            | Register::R10
+            | Register::R11
+            | Register::R12
+            | Register::R13
+            | Register::R14
+            | Register::R15
+    )
+}
+
+/// Render instructions as text, one per line, with labels outdented
+pub fn dump_ir(instructions: &[Instruction]) -> String {
+    let mut out = String::new();
+    for instr in instructions {
+        match instr {
+            Instruction::Label(label) => out.push_str(&format!("label_{}:\n", label.0)),
+            _ => out.push_str(&format!("    {:?}\n", instr)),
+        }
+    }
+    out
+}
+
+impl Default for Codegen {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 // ELF executable generator
@@ -713,6 +1487,8 @@ pub struct Assembler {
     symbol_table: HashMap<String, u64>,
     relocations: Vec<Relocation>,
     function_labels: HashMap<String, LabelId>, // Function name -> label mapping
+    overflow_checks: bool,
+    spills: Vec<SpillEvent>, // From the last call to assemble
 }
 
 #[derive(Debug)]
@@ -735,22 +1511,35 @@ impl Assembler {
             symbol_table: HashMap::new(),
             relocations: Vec::new(),
             function_labels: HashMap::new(),
+            overflow_checks: false,
+            spills: Vec::new(),
         }
     }
 
+    /// Registers the last call to `assemble` took from live virtual
+    /// registers; see `SpillEvent`
+    pub fn spills(&self) -> &[SpillEvent] {
+        &self.spills
+    }
+
+    pub fn set_overflow_checks(&mut self, enabled: bool) {
+        self.overflow_checks = enabled;
+    }
+
     pub fn add_function_mapping(&mut self, name: String, label_id: LabelId) {
         self.function_labels.insert(name, label_id);
     }
 
     // Convert TargetIR instructions to machine code with register allocation (single-pass)
     pub fn assemble(&mut self, instructions: Vec<Instruction>) -> Result<Vec<u8>, CodegenError> {
-        // Step 1: Perform register allocation
+        // Step 1: Perform register allocation, which adds code for any
+        // values that have to be spilled to the stack
         let mut regalloc = RegisterAllocator::new();
-
-        // Collect all VRegs used in the instructions
-        for instr in &instructions {
-            self.collect_vregs_for_allocation(instr, &mut regalloc);
-        }
+        let mut function_starts: HashSet<LabelId> =
+            self.function_labels.values().copied().collect();
+        function_starts.insert(START_LABEL);
+        let instructions = regalloc.allocate(&instructions, &function_starts)?;
+        self.spills = regalloc.spills().to_vec();
 
         // Step 2: Single-pass code generation with fixups
         self.code.clear();
@@ -761,7 +1550,7 @@ impl Assembler {
         let mut label_positions: HashMap<LabelId, u64> = HashMap::new();
         let mut forward_refs: Vec<(u64, LabelId, bool)> = Vec::new(); // (position, target_label, is_jump)
 
-        for instr in &instructions {
+        for (index, instr) in instructions.iter().enumerate() {
             let current_pos = self.code.len() as u64;
 
             match instr {
@@ -770,7 +1559,7 @@ impl Assembler {
                     label_positions.insert(*label_id, current_pos);
 
                     // Add to symbol table
-                    if label_id.0 == 999 {
+                    if *label_id == START_LABEL {
                         self.symbol_table.insert("_start".to_string(), current_pos);
                     }
                     self.symbol_table
@@ -835,6 +1624,43 @@ impl Assembler {
                     forward_refs.push((false_fixup_pos, *false_label, true));
                 }
 
+                // A run of copies from physical registers, like a function's
+                // parameters, all happen at once, so the first copy of the
+                // run emits the whole thing
+                Instruction::Copy {
+                    src: Value::PhysicalReg(_),
+                    ..
+                } => {
+                    if index > 0 && physical_copy(&instructions[index - 1]).is_some() {
+                        continue;
+                    }
+                    let mut moves = Vec::new();
+                    for (dest, src) in instructions[index..].iter().map_while(physical_copy) {
+                        let dest_reg = regalloc.get_register(dest).ok_or_else(|| CodegenError {
+                            message: format!("No register allocated for {:?}", dest),
+                        })?;
+                        moves.push((dest_reg, src));
+                    }
+                    self.emit_parallel_move(&moves);
+                }
+
+                // The callee can overwrite any allocatable register, so the
+                // ones holding values needed afterwards are saved around it
+                Instruction::Call { .. } => {
+                    let registers = regalloc.live_across_call(index).to_vec();
+                    self.emit_targetir_instruction(
+                        &Instruction::SaveRegisters {
+                            registers: registers.clone(),
+                        },
+                        &regalloc,
+                    )?;
+                    self.emit_targetir_instruction(instr, &regalloc)?;
+                    self.emit_targetir_instruction(
+                        &Instruction::RestoreRegisters { registers },
+                        &regalloc,
+                    )?;
+                }
+
                 _ => {
                     // Emit other instructions normally
                     self.emit_targetir_instruction(instr, &regalloc)?;
@@ -866,77 +1692,6 @@ impl Assembler {
         Ok(self.code.clone())
     }
 
-    // Helper to collect VRegs that need allocation
-    fn collect_vregs_for_allocation(&self, instr: &Instruction, regalloc: &mut RegisterAllocator) {
-        match instr {
-            Instruction::Copy { dest, src } => {
-                regalloc.allocate(*dest);
-                if let Value::VReg(src_vreg) = src {
-                    regalloc.allocate(*src_vreg);
-                }
-            }
-            Instruction::BinaryOp { dest, lhs, rhs, .. } => {
-                regalloc.allocate(*dest);
-                if let Value::VReg(lhs_vreg) = lhs {
-                    regalloc.allocate(*lhs_vreg);
-                }
-                if let Value::VReg(rhs_vreg) = rhs {
-                    regalloc.allocate(*rhs_vreg);
-                }
-            }
-            Instruction::Return {
-                value: Some(return_vreg),
-            } => {
-                regalloc.allocate(*return_vreg);
-            }
-            Instruction::Return { value: None } => {
-                // No register allocation needed for void return
-            }
-            Instruction::Branch { condition, .. } => {
-                regalloc.allocate(*condition);
-            }
-            Instruction::Call { dest, args, .. } => {
-                if let Some(dest_vreg) = dest {
-                    regalloc.allocate(*dest_vreg);
-                }
-                for arg in args {
-                    regalloc.allocate(*arg);
-                }
-            }
-            Instruction::Syscall {
-                result,
-                syscall_num,
-                args,
-            } => {
-                regalloc.allocate(*result);
-                regalloc.allocate(*syscall_num);
-                for arg in args {
-                    regalloc.allocate(*arg);
-                }
-            }
-            Instruction::Load { dest, .. } => {
-                regalloc.allocate(*dest);
-            }
-            Instruction::Store { src, .. } => {
-                regalloc.allocate(*src);
-            }
-            Instruction::SaveRegisters { .. } => {
-                // No VReg allocation needed for physical register operations
-            }
-            Instruction::RestoreRegisters { .. } => {
-                // No VReg allocation needed for physical register operations
-            }
-            Instruction::Push { src } => {
-                regalloc.allocate(*src);
-            }
-            Instruction::Pop { dest } => {
-                regalloc.allocate(*dest);
-            }
-            // Labels and jumps don't need register allocation
-            Instruction::Label(_) | Instruction::Jump(_) => {}
-        }
-    }
-
     fn emit_targetir_instruction(
         &mut self,
         instr: &Instruction,
@@ -972,13 +1727,7 @@ impl Assembler {
                         );
                     }
                     Value::PhysicalReg(src_reg) => {
-                        // mov dst, src = 48 89 ModR/M (from physical register)
-                        self.code.push(0x48); // REX.W prefix  
-                        self.code.push(0x89);
-                        self.code.push(
-                            0xc0 | (self.register_code(src_reg) << 3)
-                                | self.register_code(&dest_reg),
-                        );
+                        self.emit_parallel_move(&[(dest_reg, *src_reg)]);
                     }
                 }
             }
@@ -1042,6 +1791,7 @@ impl Assembler {
                                     0xc0 | (self.register_code(&rhs_reg) << 3)
                                         | self.register_code(&dest_reg),
                                 );
+                                self.emit_overflow_check();
                             }
                             Value::Immediate(_) => {
                                 // TODO: Handle immediate addition
@@ -1077,6 +1827,7 @@ impl Assembler {
                                     0xc0 | (self.register_code(&rhs_reg) << 3)
                                         | self.register_code(&dest_reg),
                                 );
+                                self.emit_overflow_check();
                             }
                             Value::Immediate(_) => {
                                 return Err(CodegenError {
@@ -1112,6 +1863,7 @@ impl Assembler {
                                     0xc0 | (self.register_code(&dest_reg) << 3)
                                         | self.register_code(&rhs_reg),
                                 );
+                                self.emit_overflow_check();
                             }
                             Value::Immediate(_) => {
                                 return Err(CodegenError {
@@ -1127,6 +1879,25 @@ impl Assembler {
                             }
                         }
                     }
+                    BinOp::Pow => match rhs {
+                        Value::VReg(rhs_vreg) => {
+                            let rhs_reg =
+                                regalloc
+                                    .get_register(*rhs_vreg)
+                                    .ok_or_else(|| CodegenError {
+                                        message: format!(
+                                            "No register allocated for {:?}",
+                                            rhs_vreg
+                                        ),
+                                    })?;
+                            self.emit_pow(&dest_reg, &rhs_reg);
+                        }
+                        _ => {
+                            return Err(CodegenError {
+                                message: "Exponents must be in a register".to_string(),
+                            });
+                        }
+                    },
                     BinOp::Div => {
                         // Division requires specific register usage (dividend in rax, quotient in rax)
                         // For now, return error
@@ -1134,7 +1905,7 @@ impl Assembler {
                             message: "Division not yet implemented in TargetIR backend".to_string(),
                         });
                     }
-                    BinOp::Le => {
+                    BinOp::Lt | BinOp::Le | BinOp::Gt | BinOp::Ge | BinOp::Eq | BinOp::Ne => {
                         // Comparison operations set flags, we need to generate a boolean result
                         match rhs {
                             Value::VReg(rhs_vreg) => {
@@ -1156,56 +1927,17 @@ impl Assembler {
                                         | self.register_code(&dest_reg),
                                 );
 
-                                // setle al (set if less or equal)
-                                self.code.push(0x0f);
-                                self.code.push(0x9e);
-                                self.code.push(0xc0); // al register
-
-                                // movzx dest, al (zero extend to full register)
-                                self.code.push(0x48);
-                                self.code.push(0x0f);
-                                self.code.push(0xb6);
-                                self.code.push(0xc0 | (self.register_code(&dest_reg) << 3));
-                            }
-                            Value::Immediate(_) => {
-                                return Err(CodegenError {
-                                    message: "Immediate operands not yet supported for comparisons"
-                                        .to_string(),
-                                });
-                            }
-                            Value::PhysicalReg(_) => {
-                                return Err(CodegenError {
-                                    message: "PhysicalReg not supported in binary operations"
-                                        .to_string(),
-                                });
-                            }
-                        }
-                    }
-                    BinOp::Gt => {
-                        // Greater than comparison
-                        match rhs {
-                            Value::VReg(rhs_vreg) => {
-                                let rhs_reg =
-                                    regalloc.get_register(*rhs_vreg).ok_or_else(|| {
-                                        CodegenError {
-                                            message: format!(
-                                                "No register allocated for {:?}",
-                                                rhs_vreg
-                                            ),
-                                        }
-                                    })?;
-
-                                // cmp lhs, rhs (note: lhs is already in dest)
-                                self.code.push(0x48);
-                                self.code.push(0x39);
-                                self.code.push(
-                                    0xc0 | (self.register_code(&rhs_reg) << 3)
-                                        | self.register_code(&dest_reg),
-                                );
-
-                                // setg al (set if greater)
+                                // setcc al
+                                let setcc = match op {
+                                    BinOp::Lt => 0x9c, // setl
+                                    BinOp::Le => 0x9e, // setle
+                                    BinOp::Gt => 0x9f, // setg
+                                    BinOp::Ge => 0x9d, // setge
+                                    BinOp::Eq => 0x94, // sete
+                                    _ => 0x95,         // setne
+                                };
                                 self.code.push(0x0f);
-                                self.code.push(0x9f);
+                                self.code.push(setcc);
                                 self.code.push(0xc0); // al register
 
                                 // movzx dest, al (zero extend to full register)
@@ -1228,11 +1960,6 @@ impl Assembler {
                             }
                         }
                     }
-                    _ => {
-                        return Err(CodegenError {
-                            message: format!("Binary operation {:?} not yet implemented", op),
-                        });
-                    }
                 }
             }
             Instruction::Branch {
@@ -1292,6 +2019,11 @@ impl Assembler {
                                 | self.register_code(&Register::Rax),
                         );
                     }
+                } else {
+                    // A unit function returns 0 rather than whatever was
+                    // left in rax: xor eax, eax
+                    self.code.push(0x31);
+                    self.code.push(0xc0);
                 }
 
                 // ret instruction
@@ -1302,36 +2034,26 @@ impl Assembler {
                 function,
                 args,
             } => {
-                // System V AMD64 calling convention: first arg in RDI, second in RSI, etc.
-                // Note: Only using the first 4 registers for now (R8, R9 not defined in Register enum)
-                let arg_registers = [Register::Rdi, Register::Rsi, Register::Rdx, Register::Rcx];
-
-                // Move arguments to calling convention registers
-                for (i, arg_vreg) in args.iter().enumerate() {
-                    if i >= arg_registers.len() {
-                        return Err(CodegenError {
-                            message: "Too many arguments for function call (max 4 supported)"
-                                .to_string(),
-                        });
-                    }
-
+                // Move arguments to calling convention registers, all at
+                // once, since an argument can be in another's register
+                if args.len() > ARG_REGISTERS.len() {
+                    return Err(CodegenError {
+                        message: format!(
+                            "Too many arguments for function call (max {} supported)",
+                            ARG_REGISTERS.len()
+                        ),
+                    });
+                }
+                let mut moves = Vec::new();
+                for (arg_vreg, dest_reg) in args.iter().zip(ARG_REGISTERS) {
                     let src_reg = regalloc
                         .get_register(*arg_vreg)
                         .ok_or_else(|| CodegenError {
                             message: format!("No register allocated for argument {:?}", arg_vreg),
                         })?;
-                    let dest_reg = &arg_registers[i];
-
-                    if src_reg != *dest_reg {
-                        // mov dest_reg, src_reg
-                        self.code.push(0x48); // REX.W
-                        self.code.push(0x89);
-                        self.code.push(
-                            0xc0 | (self.register_code(&src_reg) << 3)
-                                | self.register_code(dest_reg),
-                        );
-                    }
+                    moves.push((dest_reg, src_reg));
                 }
+                self.emit_parallel_move(&moves);
 
                 // call function_name
                 self.code.push(0xe8);
@@ -1423,6 +2145,17 @@ impl Assembler {
                     );
                 }
             }
+            Instruction::Trap => {
+                // ud2
+                self.code.push(0x0f);
+                self.code.push(0x0b);
+            }
+            Instruction::ReadInt { dest } => {
+                let dest_reg = regalloc.get_register(*dest).ok_or_else(|| CodegenError {
+                    message: format!("No register allocated for read_int dest {:?}", dest),
+                })?;
+                self.emit_read_int(&dest_reg);
+            }
             Instruction::Load { dest, offset } => {
                 // Load from stack: mov dest, [rsp + offset]
                 let dest_reg = regalloc.get_register(*dest).ok_or_else(|| CodegenError {
@@ -1462,15 +2195,13 @@ impl Assembler {
             Instruction::SaveRegisters { registers } => {
                 // Push caller-saved registers onto stack (64-bit)
                 for reg in registers {
-                    // push reg (64-bit version)
-                    self.code.push(0x50 + self.register_code(reg));
+                    self.emit_push(reg);
                 }
             }
             Instruction::RestoreRegisters { registers } => {
                 // Pop caller-saved registers from stack (in reverse order, 64-bit)
                 for reg in registers.iter().rev() {
-                    // pop reg (64-bit version)
-                    self.code.push(0x58 + self.register_code(reg));
+                    self.emit_pop(reg);
                 }
             }
             Instruction::Push { src } => {
@@ -1479,8 +2210,7 @@ impl Assembler {
                     message: format!("No register allocated for push src {:?}", src),
                 })?;
 
-                // push src_reg (64-bit)
-                self.code.push(0x50 + self.register_code(&src_reg));
+                self.emit_push(&src_reg);
             }
             Instruction::Pop { dest } => {
                 // Pop from stack to VReg
@@ -1488,8 +2218,17 @@ impl Assembler {
                     message: format!("No register allocated for pop dest {:?}", dest),
                 })?;
 
-                // pop dest_reg (64-bit)
-                self.code.push(0x58 + self.register_code(&dest_reg));
+                self.emit_pop(&dest_reg);
+            }
+            Instruction::AllocateStack { bytes } => {
+                // sub rsp, imm32 = 48 81 /5 imm32
+                self.code.extend_from_slice(&[0x48, 0x81, 0xec]);
+                self.code.extend_from_slice(&(*bytes as i32).to_le_bytes());
+            }
+            Instruction::FreeStack { bytes } => {
+                // add rsp, imm32 = 48 81 /0 imm32
+                self.code.extend_from_slice(&[0x48, 0x81, 0xc4]);
+                self.code.extend_from_slice(&(*bytes as i32).to_le_bytes());
             }
             Instruction::Label(_) => {
                 // Labels don't emit code in this simplified version
@@ -1499,14 +2238,214 @@ impl Assembler {
         Ok(())
     }
 
-    fn register_code(&self, reg: &Register) -> u8 {
-        match reg {
-            Register::Rax => 0,
-            Register::Rbx => 3,
-            Register::Rcx => 1,
-            Register::Rdx => 2,
-            Register::Rsp => 4,
-            Register::Rbp => 5,
+    // Trap if the last arithmetic instruction overflowed, like Rust's debug
+    // builds. Does nothing unless overflow checks are enabled.
+    fn emit_overflow_check(&mut self) {
+        if self.overflow_checks {
+            // jno +2 (skip the trap)
+            self.code.push(0x71);
+            self.code.push(0x02);
+            // ud2
+            self.code.push(0x0f);
+            self.code.push(0x0b);
+        }
+    }
+
+    // Copy each `(dest, src)` pair's source to its destination as if all at
+    // once, so that no destination is written before it's read as a source.
+    // Anything more than a single move goes through the stack.
+    fn emit_parallel_move(&mut self, moves: &[(Register, Register)]) {
+        match moves {
+            [] => {}
+            [(dest, src)] => {
+                if dest != src {
+                    // mov dest, src, with REX.R and REX.B for r8-r15
+                    self.code.extend_from_slice(&[
+                        0x48 | (is_extended(src) as u8) << 2 | is_extended(dest) as u8,
+                        0x89,
+                        0xc0 | (self.register_code(src) << 3) | self.register_code(dest),
+                    ]);
+                }
+            }
+            _ => {
+                for (_, src) in moves {
+                    self.emit_push(src);
+                }
+                for (dest, _) in moves.iter().rev() {
+                    self.emit_pop(dest);
+                }
+            }
+        }
+    }
+
+    // push reg, which needs REX.B for r8-r15
+    fn emit_push(&mut self, reg: &Register) {
+        if is_extended(reg) {
+            self.code.push(0x41);
+        }
+        self.code.push(0x50 + self.register_code(reg));
+    }
+
+    // pop reg, which needs REX.B for r8-r15
+    fn emit_pop(&mut self, reg: &Register) {
+        if is_extended(reg) {
+            self.code.push(0x41);
+        }
+        self.code.push(0x58 + self.register_code(reg));
+    }
+
+    // Raise `dest`, which holds the base, to the power in `exponent`, by
+    // multiplying 1 by the base that many times. A negative exponent traps.
+    // The base is kept in r9 and the count of multiplications left in r8,
+    // neither of which are allocated.
+    fn emit_pow(&mut self, dest: &Register, exponent: &Register) {
+        // Patch the rel8 of a short jump at `at` to land on `target`
+        fn patch(code: &mut [u8], at: usize, target: usize) {
+            code[at] = (target as i64 - (at as i64 + 1)) as i8 as u8;
+        }
+
+        let dest_code = self.register_code(dest);
+        self.code.extend_from_slice(&[
+            0x49,
+            0x89,
+            0xc1 | (dest_code << 3), // mov r9, dest
+            0x49,
+            0x89,
+            0xc0 | (self.register_code(exponent) << 3), // mov r8, exponent
+            0x4d,
+            0x85,
+            0xc0, // test r8, r8
+            0x79,
+            0x02, // jns over the trap
+            0x0f,
+            0x0b, // ud2
+            0x48,
+            0xc7,
+            0xc0 | dest_code,
+            1,
+            0,
+            0,
+            0, // mov dest, 1
+        ]);
+
+        let multiply_loop = self.code.len();
+        self.code.extend_from_slice(&[
+            0x4d, 0x85, 0xc0, // test r8, r8
+            0x74, 0, // jz done
+        ]);
+        let jz_done = self.code.len() - 1;
+        // imul dest, r9
+        self.code
+            .extend_from_slice(&[0x49, 0x0f, 0xaf, 0xc1 | (dest_code << 3)]);
+        self.emit_overflow_check();
+        self.code.extend_from_slice(&[
+            0x49, 0xff, 0xc8, // dec r8
+            0xeb, 0, // jmp multiply_loop
+        ]);
+        let jmp_loop = self.code.len() - 1;
+        patch(&mut self.code, jmp_loop, multiply_loop);
+        let done = self.code.len();
+        patch(&mut self.code, jz_done, done);
+    }
+
+    // Read stdin a byte at a time with the read syscall, parsing an optional
+    // `-` and then decimal digits into `dest`, until a byte that isn't a
+    // digit or the end of input. The byte that ends the number is consumed.
+    // The value is accumulated in r8, with r9 set if it's negative and r10
+    // while the first byte hasn't been read, none of which are allocated;
+    // the allocatable registers the syscall uses are saved around it.
+    fn emit_read_int(&mut self, dest: &Register) {
+        // Patch the rel8 of a short jump at `at` to land on `target`
+        fn patch(code: &mut [u8], at: usize, target: usize) {
+            code[at] = (target as i64 - (at as i64 + 1)) as i8 as u8;
+        }
+
+        let mut code = vec![
+            0x51, // push rcx
+            0x52, // push rdx
+            0x56, // push rsi
+            0x57, // push rdi
+            0x49, 0xc7, 0xc0, 0, 0, 0, 0, // mov r8, 0
+            0x49, 0xc7, 0xc1, 0, 0, 0, 0, // mov r9, 0
+            0x49, 0xc7, 0xc2, 1, 0, 0, 0, // mov r10, 1
+            0x48, 0x83, 0xec, 0x08, // sub rsp, 8, for the byte read
+        ];
+
+        let read_loop = code.len();
+        code.extend_from_slice(&[
+            0x48, 0xc7, 0x04, 0x24, 0, 0, 0, 0, // mov qword [rsp], 0
+            0x48, 0xc7, 0xc0, 0, 0, 0, 0, // mov rax, 0 (sys_read)
+            0x48, 0xc7, 0xc7, 0, 0, 0, 0, // mov rdi, 0 (stdin)
+            0x48, 0x89, 0xe6, // mov rsi, rsp
+            0x48, 0xc7, 0xc2, 1, 0, 0, 0, // mov rdx, 1
+            0x0f, 0x05, // syscall
+            0x48, 0x83, 0xf8, 0x01, // cmp rax, 1
+            0x7c, 0, // jl done, at the end of input or on an error
+        ]);
+        let jl_done = code.len() - 1;
+        code.extend_from_slice(&[
+            0x48, 0x8b, 0x04, 0x24, // mov rax, [rsp]
+            0x4d, 0x85, 0xd2, // test r10, r10
+            0x74, 0, // jz digit
+        ]);
+        let jz_digit = code.len() - 1;
+        code.extend_from_slice(&[
+            0x49, 0xc7, 0xc2, 0, 0, 0, 0, // mov r10, 0
+            0x48, 0x83, 0xf8, b'-', // cmp rax, '-'
+            0x75, 0, // jne digit
+        ]);
+        let jne_digit = code.len() - 1;
+        code.extend_from_slice(&[
+            0x49, 0xc7, 0xc1, 1, 0, 0, 0, // mov r9, 1
+            0xeb, 0, // jmp read_loop
+        ]);
+        let jmp_minus = code.len() - 1;
+        patch(&mut code, jmp_minus, read_loop);
+
+        let digit = code.len();
+        patch(&mut code, jz_digit, digit);
+        patch(&mut code, jne_digit, digit);
+        code.extend_from_slice(&[
+            0x48, 0x83, 0xe8, b'0', // sub rax, '0'
+            0x48, 0x83, 0xf8, 0x09, // cmp rax, 9
+            0x77, 0, // ja done, unsigned so bytes below '0' count too
+        ]);
+        let ja_done = code.len() - 1;
+        code.extend_from_slice(&[
+            0x4d, 0x6b, 0xc0, 0x0a, // imul r8, r8, 10
+            0x49, 0x01, 0xc0, // add r8, rax
+            0xeb, 0, // jmp read_loop
+        ]);
+        let jmp_digit = code.len() - 1;
+        patch(&mut code, jmp_digit, read_loop);
+
+        let done = code.len();
+        patch(&mut code, jl_done, done);
+        patch(&mut code, ja_done, done);
+        code.extend_from_slice(&[
+            0x48, 0x83, 0xc4, 0x08, // add rsp, 8
+            0x4d, 0x85, 0xc9, // test r9, r9
+            0x74, 0x03, // jz over the neg
+            0x49, 0xf7, 0xd8, // neg r8
+            0x5f, // pop rdi
+            0x5e, // pop rsi
+            0x5a, // pop rdx
+            0x59, // pop rcx
+        ]);
+
+        // mov dest, r8
+        code.extend_from_slice(&[0x4c, 0x89, 0xc0 | self.register_code(dest)]);
+        self.code.extend_from_slice(&code);
+    }
+
+    fn register_code(&self, reg: &Register) -> u8 {
+        match reg {
+            Register::Rax => 0,
+            Register::Rbx => 3,
+            Register::Rcx => 1,
+            Register::Rdx => 2,
+            Register::Rsp => 4,
+            Register::Rbp => 5,
             Register::Rsi => 6,
             Register::Rdi => 7,
             Register::R8 => 0, // R8-R15 use extended encoding with REX prefix
@@ -1556,11 +2495,24 @@ impl Assembler {
 
     // Generate minimal ELF executable
     pub fn generate_elf(&self, machine_code: &[u8]) -> Vec<u8> {
+        self.generate_elf_with_data(machine_code, &[])
+    }
+
+    /// Generate an ELF executable with a read-only data segment
+    ///
+    /// The code is loaded readable and executable, right after the headers.
+    /// The data, if there is any, gets its own readable-only segment,
+    /// starting at the first page boundary after the code; see
+    /// `data_address`. With no data, only the code segment is emitted.
+    pub fn generate_elf_with_data(&self, machine_code: &[u8], data: &[u8]) -> Vec<u8> {
         let mut elf = Vec::new();
 
+        let phnum: u16 = if data.is_empty() { 1 } else { 2 };
+        let headers_size = elf_headers_size(phnum);
+        let code_size = headers_size + machine_code.len() as u64;
+
         // ELF header
-        let base_addr = 0x400000u64;
-        let entry_point = base_addr + 0x78; // After ELF header + program header
+        let entry_point = ELF_BASE_ADDR + headers_size; // After ELF header + program headers
 
         // ELF identification
         elf.extend_from_slice(&[0x7f, 0x45, 0x4c, 0x46]); // ELF magic
@@ -1580,27 +2532,67 @@ impl Assembler {
         elf.extend_from_slice(&0u32.to_le_bytes()); // Flags
         elf.extend_from_slice(&64u16.to_le_bytes()); // ELF header size
         elf.extend_from_slice(&56u16.to_le_bytes()); // Program header size
-        elf.extend_from_slice(&1u16.to_le_bytes()); // Program header count
+        elf.extend_from_slice(&phnum.to_le_bytes()); // Program header count
         elf.extend_from_slice(&0u16.to_le_bytes()); // Section header size
         elf.extend_from_slice(&0u16.to_le_bytes()); // Section header count
         elf.extend_from_slice(&0u16.to_le_bytes()); // Section name string table index
 
-        // Program header (LOAD segment)
-        elf.extend_from_slice(&1u32.to_le_bytes()); // PT_LOAD
-        elf.extend_from_slice(&5u32.to_le_bytes()); // PF_R | PF_X (readable, executable)
-        elf.extend_from_slice(&0u64.to_le_bytes()); // Offset in file
-        elf.extend_from_slice(&base_addr.to_le_bytes()); // Virtual address
-        elf.extend_from_slice(&base_addr.to_le_bytes()); // Physical address
-        let total_size = 120u64 + machine_code.len() as u64; // ELF header + program header + code
-        elf.extend_from_slice(&total_size.to_le_bytes()); // Size in file
-        elf.extend_from_slice(&total_size.to_le_bytes()); // Size in memory
-        elf.extend_from_slice(&0x1000u64.to_le_bytes()); // Alignment
+        // Program header (LOAD segment for the headers and code)
+        push_load_segment(&mut elf, PF_R | PF_X, 0, code_size);
+
+        // Program header (LOAD segment for the data). Its file offset and
+        // address are both page aligned, so they agree modulo the alignment.
+        let data_offset = code_size.next_multiple_of(ELF_PAGE_SIZE);
+        if !data.is_empty() {
+            push_load_segment(&mut elf, PF_R, data_offset, data.len() as u64);
+        }
 
         // Machine code
         elf.extend_from_slice(machine_code);
 
+        // Data, padded out to its page
+        if !data.is_empty() {
+            elf.resize(data_offset as usize, 0);
+            elf.extend_from_slice(data);
+        }
+
         elf
     }
+
+    /// The virtual address `generate_elf_with_data` loads non-empty data at,
+    /// given the size of the machine code
+    pub fn data_address(&self, code_len: usize) -> u64 {
+        let code_size = elf_headers_size(2) + code_len as u64;
+        ELF_BASE_ADDR + code_size.next_multiple_of(ELF_PAGE_SIZE)
+    }
+}
+
+/// Address the executable is loaded at
+const ELF_BASE_ADDR: u64 = 0x400000;
+/// Alignment of every LOAD segment
+const ELF_PAGE_SIZE: u64 = 0x1000;
+
+// Segment permission flags
+const PF_X: u32 = 1;
+const PF_R: u32 = 4;
+
+// Size of the ELF header plus `phnum` program headers
+fn elf_headers_size(phnum: u16) -> u64 {
+    64 + 56 * phnum as u64
+}
+
+// Append a PT_LOAD program header for `size` bytes at `offset` in the file,
+// loaded at the same offset from the base address
+fn push_load_segment(elf: &mut Vec<u8>, flags: u32, offset: u64, size: u64) {
+    let addr = ELF_BASE_ADDR + offset;
+    elf.extend_from_slice(&1u32.to_le_bytes()); // PT_LOAD
+    elf.extend_from_slice(&flags.to_le_bytes()); // Permissions
+    elf.extend_from_slice(&offset.to_le_bytes()); // Offset in file
+    elf.extend_from_slice(&addr.to_le_bytes()); // Virtual address
+    elf.extend_from_slice(&addr.to_le_bytes()); // Physical address
+    elf.extend_from_slice(&size.to_le_bytes()); // Size in file
+    elf.extend_from_slice(&size.to_le_bytes()); // Size in memory
+    elf.extend_from_slice(&ELF_PAGE_SIZE.to_le_bytes()); // Alignment
 }
 
 impl Default for Assembler {
@@ -1609,26 +2601,127 @@ impl Default for Assembler {
     }
 }
 
+// The virtual registers an instruction uses, destination first, in the
+// order they get registers allocated
+pub(crate) fn instruction_vregs(instr: &Instruction) -> Vec<VReg> {
+    let mut vregs = Vec::new();
+    match instr {
+        Instruction::Copy { dest, src } => {
+            vregs.push(*dest);
+            if let Value::VReg(src_vreg) = src {
+                vregs.push(*src_vreg);
+            }
+        }
+        Instruction::BinaryOp { dest, lhs, rhs, .. } => {
+            vregs.push(*dest);
+            if let Value::VReg(lhs_vreg) = lhs {
+                vregs.push(*lhs_vreg);
+            }
+            if let Value::VReg(rhs_vreg) = rhs {
+                vregs.push(*rhs_vreg);
+            }
+        }
+        Instruction::Return {
+            value: Some(return_vreg),
+        } => {
+            vregs.push(*return_vreg);
+        }
+        Instruction::Return { value: None } => {
+            // No register allocation needed for void return
+        }
+        Instruction::Branch { condition, .. } => {
+            vregs.push(*condition);
+        }
+        Instruction::Call { dest, args, .. } => {
+            if let Some(dest_vreg) = dest {
+                vregs.push(*dest_vreg);
+            }
+            for arg in args {
+                vregs.push(*arg);
+            }
+        }
+        Instruction::Syscall {
+            result,
+            syscall_num,
+            args,
+        } => {
+            vregs.push(*result);
+            vregs.push(*syscall_num);
+            for arg in args {
+                vregs.push(*arg);
+            }
+        }
+        Instruction::Load { dest, .. } | Instruction::ReadInt { dest } => {
+            vregs.push(*dest);
+        }
+        Instruction::Store { src, .. } => {
+            vregs.push(*src);
+        }
+        Instruction::SaveRegisters { .. } => {
+            // No VReg allocation needed for physical register operations
+        }
+        Instruction::RestoreRegisters { .. } => {
+            // No VReg allocation needed for physical register operations
+        }
+        Instruction::Push { src } => {
+            vregs.push(*src);
+        }
+        Instruction::Pop { dest } => {
+            vregs.push(*dest);
+        }
+        // Labels, jumps, traps and stack adjustments don't need register
+        // allocation
+        Instruction::Label(_)
+        | Instruction::Jump(_)
+        | Instruction::Trap
+        | Instruction::AllocateStack { .. }
+        | Instruction::FreeStack { .. } => {}
+    }
+    vregs
+}
+
 // High-level compilation function
 pub fn compile_to_executable(ast: &CstRoot, scope: &Scope) -> Result<Vec<u8>, CodegenError> {
+    compile_to_executable_with_options(ast, scope, &CodegenOptions::default())
+}
+
+pub fn compile_to_executable_with_options(
+    ast: &CstRoot,
+    scope: &Scope,
+    options: &CodegenOptions,
+) -> Result<Vec<u8>, CodegenError> {
+    let machine_code = compile_to_machine_code_with_options(ast, scope, options)?;
+
+    // Generate ELF executable
+    Ok(Assembler::new().generate_elf(&machine_code))
+}
+
+/// Compile to bare machine code, with no ELF wrapper. The code starts with
+/// `_start`, at offset 0.
+pub fn compile_to_machine_code_with_options(
+    ast: &CstRoot,
+    scope: &Scope,
+    options: &CodegenOptions,
+) -> Result<Vec<u8>, CodegenError> {
     // Generate TargetIR instructions
     let mut codegen = Codegen::new();
+    codegen.set_opt_level(options.opt_level);
+    if let Some(entry) = &options.entry {
+        codegen.set_entry(entry);
+    }
     let instructions = codegen.generate(ast, scope)?;
+    verify_stack_balance(&instructions)?;
 
     // Assemble to machine code with register allocation
     let mut assembler = Assembler::new();
+    assembler.set_overflow_checks(options.overflow_checks);
 
     // Pass function labels to assembler
     for (name, label_id) in &codegen.function_labels {
         assembler.add_function_mapping(name.clone(), *label_id);
     }
 
-    let machine_code = assembler.assemble(instructions)?;
-
-    // Generate ELF executable
-    let elf = assembler.generate_elf(&machine_code);
-
-    Ok(elf)
+    assembler.assemble(instructions)
 }
 
 #[cfg(test)]
@@ -1667,11 +2760,11 @@ fn main() {
         let instrs = instructions.unwrap();
 
         // Should have program setup and main function
-        // Look for _start label (ID 999)
+        // Look for _start label
         assert!(
             instrs
                 .iter()
-                .any(|i| matches!(i, Instruction::Label(LabelId(999))))
+                .any(|i| matches!(i, Instruction::Label(START_LABEL)))
         );
         // Should have a Copy instruction with immediate value 42
         assert!(instrs.iter().any(|i| matches!(
@@ -1719,6 +2812,75 @@ fn main() {
         assert!(instructions.is_ok());
     }
 
+    #[test]
+    fn test_function_with_two_parameters() {
+        // Semantic analysis doesn't accept two parameters yet, so this skips
+        // it, which codegen doesn't need here
+        let source = "fn add(a, b) { a + b } fn main() { add(2, 3) }";
+        let ast = rue_parser::parse(Lexer::new(source).tokenize()).unwrap();
+        let mut codegen = Codegen::new();
+        let instrs = codegen
+            .generate(&ast, &rue_semantic::Scope::default())
+            .unwrap();
+
+        let add = codegen.function_labels()["add"];
+        let start = instrs
+            .iter()
+            .position(|i| matches!(i, Instruction::Label(label) if *label == add))
+            .unwrap();
+        assert!(matches!(
+            &instrs[start + 1..start + 3],
+            [
+                Instruction::Copy {
+                    src: Value::PhysicalReg(Register::Rdi),
+                    ..
+                },
+                Instruction::Copy {
+                    src: Value::PhysicalReg(Register::Rsi),
+                    ..
+                },
+            ]
+        ));
+        assert!(instrs.iter().any(|i| matches!(
+            i,
+            Instruction::Call { function, args, .. } if function == "add" && args.len() == 2
+        )));
+        assert_eq!(interpret(&instrs, codegen.function_labels()).unwrap(), 5);
+
+        // Both parameters are moved at once, through the stack: push rdi,
+        // push rsi, then pop them in reverse
+        let code = compile_to_machine_code_with_options(
+            &ast,
+            &rue_semantic::Scope::default(),
+            &CodegenOptions::default(),
+        )
+        .unwrap();
+        assert!(code.windows(2).any(|pair| pair == [0x57, 0x56]));
+    }
+
+    #[test]
+    fn test_fifth_argument_in_r8() {
+        let source = "fn f(a, b, c, d, e) { a + e } fn main() { f(1, 2, 3, 4, 5) }";
+        let ast = rue_parser::parse(Lexer::new(source).tokenize()).unwrap();
+        let scope = rue_semantic::analyze_cst(&ast).unwrap();
+        let mut codegen = Codegen::new();
+        let instrs = codegen.generate(&ast, &scope).unwrap();
+        assert!(instrs.iter().any(|i| matches!(
+            i,
+            Instruction::Copy {
+                src: Value::PhysicalReg(Register::R8),
+                ..
+            }
+        )));
+        assert_eq!(interpret(&instrs, codegen.function_labels()).unwrap(), 6);
+
+        // Pushing and popping r8 needs a REX.B prefix
+        let code =
+            compile_to_machine_code_with_options(&ast, &scope, &CodegenOptions::default()).unwrap();
+        assert!(code.windows(2).any(|pair| pair == [0x41, 0x50]));
+        assert!(code.windows(2).any(|pair| pair == [0x41, 0x58]));
+    }
+
     #[test]
     fn test_assembler_simple() {
         let vreg0 = VReg(0);
@@ -1727,7 +2889,7 @@ fn main() {
         let vreg3 = VReg(3);
 
         let instructions = vec![
-            Instruction::Label(LabelId(999)), // _start
+            Instruction::Label(START_LABEL),
             Instruction::Copy {
                 dest: vreg0,
                 src: Value::Immediate(42),
@@ -1773,6 +2935,48 @@ fn main() {
         assert!(elf.len() > machine_code.len());
     }
 
+    #[test]
+    fn test_elf_data_segment() {
+        let machine_code = vec![0x0f, 0x05]; // syscall
+        let data = b"hello";
+
+        let assembler = Assembler::new();
+        let elf = assembler.generate_elf_with_data(&machine_code, data);
+
+        let u16_at = |at: usize| u16::from_le_bytes(elf[at..at + 2].try_into().unwrap());
+        let u32_at = |at: usize| u32::from_le_bytes(elf[at..at + 4].try_into().unwrap());
+        let u64_at = |at: usize| u64::from_le_bytes(elf[at..at + 8].try_into().unwrap());
+
+        // Two program headers, with the code right after them
+        assert_eq!(u16_at(56), 2);
+        assert_eq!(u64_at(24), 0x400000 + 64 + 2 * 56);
+        assert_eq!(&elf[176..178], &machine_code[..]);
+
+        // Code: readable and executable, covering the headers and code
+        let code = 64;
+        assert_eq!(u32_at(code), 1); // PT_LOAD
+        assert_eq!(u32_at(code + 4), 5); // PF_R | PF_X
+        assert_eq!(u64_at(code + 8), 0);
+        assert_eq!(u64_at(code + 32), 178);
+        assert_eq!(u64_at(code + 48), 0x1000);
+
+        // Data: readable only, on its own page
+        let data_header = code + 56;
+        assert_eq!(u32_at(data_header), 1); // PT_LOAD
+        assert_eq!(u32_at(data_header + 4), 4); // PF_R
+        assert_eq!(u64_at(data_header + 8), 0x1000);
+        assert_eq!(u64_at(data_header + 16), 0x401000);
+        assert_eq!(u64_at(data_header + 16), assembler.data_address(2));
+        assert_eq!(u64_at(data_header + 32), 5);
+        assert_eq!(u64_at(data_header + 48), 0x1000);
+        assert_eq!(&elf[0x1000..], data);
+
+        // Without data there's just the one segment
+        let elf = assembler.generate_elf(&machine_code);
+        assert_eq!(u16::from_le_bytes([elf[56], elf[57]]), 1);
+        assert_eq!(elf.len(), 120 + 2);
+    }
+
     #[test]
     fn test_factorial_compilation() {
         let factorial_source = r#"
@@ -1832,12 +3036,640 @@ fn main() {
         assert!(copy_count >= 3); // At least initial value, assignment, and return loading
     }
 
+    #[test]
+    fn test_compound_assignment() {
+        let source = "fn main() { let x = 5; x += 3; x -= 1; x *= 2; x }";
+        let ast = rue_parser::parse(Lexer::new(source).tokenize()).unwrap();
+        let scope = rue_semantic::analyze_cst(&ast).unwrap();
+        let mut codegen = Codegen::new();
+        let instrs = codegen.generate(&ast, &scope).unwrap();
+        assert_eq!(interpret(&instrs, codegen.function_labels()).unwrap(), 14);
+    }
+
+    #[test]
+    fn test_assignment_expression() {
+        let source =
+            "fn main() { let x = 1; let y = (x = 5); let z = x += 2; x * 100 + y * 10 + z }";
+        let ast = rue_parser::parse(Lexer::new(source).tokenize()).unwrap();
+        let scope = rue_semantic::analyze_cst(&ast).unwrap();
+        for opt_level in [0, 1] {
+            let mut codegen = Codegen::new();
+            codegen.set_opt_level(opt_level);
+            let instrs = codegen.generate(&ast, &scope).unwrap();
+            assert_eq!(interpret(&instrs, codegen.function_labels()).unwrap(), 757);
+        }
+    }
+
+    #[test]
+    fn test_power() {
+        // The parameter keeps the exponent from being folded away
+        let source = "fn two_to(e) { 2 ** e } fn main() { two_to(10) == 1024 }";
+        let ast = rue_parser::parse(Lexer::new(source).tokenize()).unwrap();
+        let scope = rue_semantic::analyze_cst(&ast).unwrap();
+        for opt_level in [0, 1] {
+            let mut codegen = Codegen::new();
+            codegen.set_opt_level(opt_level);
+            let instrs = codegen.generate(&ast, &scope).unwrap();
+            assert_eq!(interpret(&instrs, codegen.function_labels()).unwrap(), 1);
+        }
+    }
+
+    #[test]
+    fn test_logical_operators() {
+        // `&&` skips the division when it would be by zero
+        let source = "fn halves_ten(n) { n != 0 && 10 / n == 5 } \
+                      fn main() { if halves_ten(0) || halves_ten(2) && !false { 1 } else { 2 } }";
+        let ast = rue_parser::parse(Lexer::new(source).tokenize()).unwrap();
+        let scope = rue_semantic::analyze_cst(&ast).unwrap();
+        for opt_level in [0, 1] {
+            let mut codegen = Codegen::new();
+            codegen.set_opt_level(opt_level);
+            let instrs = codegen.generate(&ast, &scope).unwrap();
+            assert_eq!(interpret(&instrs, codegen.function_labels()).unwrap(), 1);
+        }
+    }
+
+    #[test]
+    fn test_array_store_then_load() {
+        let source = "fn double(x) { x * 2 } \
+                      fn main() { let a = [1, 2, 3]; a[1] = 5; a[2] += double(a[0]); a[1] + a[2] }";
+        let ast = rue_parser::parse(Lexer::new(source).tokenize()).unwrap();
+        let scope = rue_semantic::analyze_cst(&ast).unwrap();
+        let mut codegen = Codegen::new();
+        let instrs = codegen.generate(&ast, &scope).unwrap();
+        assert_eq!(interpret(&instrs, codegen.function_labels()).unwrap(), 10);
+
+        // The array's room is given back before returning
+        assert!(
+            instrs
+                .iter()
+                .any(|i| matches!(i, Instruction::AllocateStack { bytes: 24 }))
+        );
+        assert!(
+            instrs
+                .iter()
+                .any(|i| matches!(i, Instruction::FreeStack { bytes: 24 }))
+        );
+        assert!(verify_stack_balance(&instrs).is_ok());
+
+        // Arrays in a loop body live for one iteration, breaking out or not
+        let source = "fn main() { let total = 0; for i in 0..3 { let a = [i, 10]; \
+                      if i == 2 { break; }; total += a[0] + a[1]; }; total }";
+        let ast = rue_parser::parse(Lexer::new(source).tokenize()).unwrap();
+        let scope = rue_semantic::analyze_cst(&ast).unwrap();
+        let mut codegen = Codegen::new();
+        let instrs = codegen.generate(&ast, &scope).unwrap();
+        assert_eq!(interpret(&instrs, codegen.function_labels()).unwrap(), 21);
+        assert!(verify_stack_balance(&instrs).is_ok());
+    }
+
+    #[test]
+    fn test_generated_code_is_stack_balanced() {
+        let instructions = compile_program(
+            r#"
+fn factorial(n) {
+    if n <= 1 {
+        1
+    } else {
+        n * factorial(n - 1)
+    }
+}
+
+fn main() {
+    if 1 < 2 { factorial(3) + factorial(2) } else { 0 }
+}
+"#,
+        )
+        .unwrap();
+        assert!(verify_stack_balance(&instructions).is_ok());
+        assert!(verify_branch_conditions(&instructions).is_empty());
+    }
+
+    #[test]
+    fn test_generate_single_function() {
+        let source = "fn double(x) { x + x } fn main() { double(21) }";
+        let ast = rue_parser::parse(Lexer::new(source).tokenize()).unwrap();
+        let scope = rue_semantic::analyze_cst(&ast).unwrap();
+
+        let instrs = Codegen::new()
+            .generate_single_function(&ast, &scope, "double")
+            .unwrap();
+        // Just the function: no entry point and no call to main
+        assert!(matches!(instrs[0], Instruction::Label(LabelId(0))));
+        assert!(!instrs.iter().any(|i| matches!(i, Instruction::Call { .. })));
+        assert!(
+            instrs
+                .iter()
+                .any(|i| matches!(i, Instruction::BinaryOp { op: BinOp::Add, .. }))
+        );
+
+        let dump = dump_ir(&instrs);
+        assert!(dump.starts_with("label_0:\n    Copy"));
+
+        assert!(
+            Codegen::new()
+                .generate_single_function(&ast, &scope, "missing")
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_opt_level_prunes_unreachable_functions() {
+        let source = r#"
+fn helper(x) { x + 1 }
+fn unused(x) { helper(x) * 2 }
+fn main() { helper(41) }
+"#;
+        let ast = rue_parser::parse(Lexer::new(source).tokenize()).unwrap();
+        let scope = rue_semantic::analyze_cst(&ast).unwrap();
+        let count_labels = |instrs: &[Instruction]| {
+            instrs
+                .iter()
+                .filter(|i| matches!(i, Instruction::Label(_)))
+                .count()
+        };
+
+        let mut unoptimized = Codegen::new();
+        let instrs = unoptimized.generate(&ast, &scope).unwrap();
+        assert!(unoptimized.function_labels.contains_key("unused"));
+        // _start, main, unused and helper
+        assert_eq!(count_labels(&instrs), 4);
+
+        let mut optimized = Codegen::new();
+        optimized.set_opt_level(1);
+        let instrs = optimized.generate(&ast, &scope).unwrap();
+        assert!(!optimized.function_labels.contains_key("unused"));
+        assert!(optimized.function_labels.contains_key("helper"));
+        assert_eq!(count_labels(&instrs), 3);
+        assert!(
+            !instrs
+                .iter()
+                .any(|i| matches!(i, Instruction::BinaryOp { op: BinOp::Mul, .. }))
+        );
+    }
+
+    #[test]
+    fn test_opt_level_folds_constants() {
+        let source = "const N = 4; fn main() { N * 2 + 1 }";
+        let ast = rue_parser::parse(Lexer::new(source).tokenize()).unwrap();
+        let scope = rue_semantic::analyze_cst(&ast).unwrap();
+
+        let mut codegen = Codegen::new();
+        codegen.set_opt_level(1);
+        codegen.generate(&ast, &scope).unwrap();
+        let main = codegen.function_labels["main"];
+        let instrs = codegen.instructions;
+        let start = instrs
+            .iter()
+            .position(|i| matches!(i, Instruction::Label(label) if *label == main))
+            .unwrap();
+
+        // The whole body is folded to its value, `const` and all
+        assert!(matches!(
+            &instrs[start + 1..],
+            [
+                Instruction::Copy {
+                    src: Value::Immediate(9),
+                    ..
+                },
+                Instruction::Return { .. },
+            ]
+        ));
+        assert_eq!(codegen.opt_stats.constant_folds, 1);
+    }
+
+    #[test]
+    fn test_statement_if_has_no_result() {
+        let source = "fn main() { let x = 1; if x < 2 { x + 1 }; x }";
+        let ast = rue_parser::parse(Lexer::new(source).tokenize()).unwrap();
+        let scope = rue_semantic::analyze_cst(&ast).unwrap();
+        let instrs = Codegen::new().generate(&ast, &scope).unwrap();
+
+        // No zero is made up for the missing else
+        assert!(!instrs.iter().any(|i| matches!(
+            i,
+            Instruction::Copy {
+                src: Value::Immediate(0),
+                ..
+            }
+        )));
+
+        // The condition branches straight to the end label, with no copies
+        // of the then block's value before it
+        let (branch, end_label) = instrs
+            .iter()
+            .enumerate()
+            .find_map(|(index, i)| match i {
+                Instruction::Branch { false_label, .. } => Some((index, *false_label)),
+                _ => None,
+            })
+            .unwrap();
+        let end = instrs
+            .iter()
+            .position(|i| matches!(i, Instruction::Label(l) if *l == end_label))
+            .unwrap();
+        assert!(matches!(
+            &instrs[branch + 1..=end],
+            [
+                Instruction::Label(_),
+                Instruction::Copy { .. },
+                Instruction::Copy {
+                    src: Value::Immediate(1),
+                    ..
+                },
+                Instruction::BinaryOp { .. },
+                Instruction::Label(_),
+            ]
+        ));
+    }
+
+    #[test]
+    fn test_valued_if_without_else_is_zero() {
+        // Semantic analysis asks for an `else` here, so this skips it
+        let source = "fn main() { let x = 1; let y = if x < 2 { 5 }; y }";
+        let ast = rue_parser::parse(Lexer::new(source).tokenize()).unwrap();
+        let instrs = Codegen::new().generate(&ast, &Scope::default()).unwrap();
+
+        assert!(instrs.iter().any(|i| matches!(
+            i,
+            Instruction::Copy {
+                src: Value::Immediate(0),
+                ..
+            }
+        )));
+    }
+
+    #[test]
+    fn test_exit_is_followed_by_trap() {
+        let source = "fn main() { 42 }";
+        let ast = rue_parser::parse(Lexer::new(source).tokenize()).unwrap();
+        let scope = rue_semantic::analyze_cst(&ast).unwrap();
+        let instrs = compile_program(source).unwrap();
+
+        // The exit syscall is followed by a trap, then main's label
+        let exit = instrs
+            .iter()
+            .position(|i| matches!(i, Instruction::Syscall { .. }))
+            .unwrap();
+        assert!(matches!(instrs[exit + 1], Instruction::Trap));
+        assert!(matches!(instrs[exit + 2], Instruction::Label(LabelId(0))));
+
+        // In the machine code, ud2 comes right after the syscall and the move
+        // of its result
+        let code =
+            compile_to_machine_code_with_options(&ast, &scope, &CodegenOptions::default()).unwrap();
+        let syscall = code.windows(2).position(|w| w == [0x0f, 0x05]).unwrap();
+        let ud2 = code.windows(2).position(|w| w == [0x0f, 0x0b]).unwrap();
+        assert!(ud2 > syscall && ud2 <= syscall + 5);
+    }
+
+    #[test]
+    fn test_negation() {
+        let instrs = compile_program("fn main() { let x = 5; -x }").unwrap();
+        let main = instrs
+            .iter()
+            .position(|i| matches!(i, Instruction::Label(LabelId(0))))
+            .unwrap();
+        assert!(matches!(
+            &instrs[main + 1..],
+            [
+                Instruction::Copy {
+                    src: Value::Immediate(5),
+                    ..
+                },
+                Instruction::Copy { .. },
+                Instruction::BinaryOp {
+                    lhs: Value::Immediate(0),
+                    op: BinOp::Sub,
+                    ..
+                },
+                Instruction::Return { .. },
+            ]
+        ));
+    }
+
+    #[test]
+    fn test_for_loop() {
+        let run = |source: &str| {
+            let ast = rue_parser::parse(Lexer::new(source).tokenize()).unwrap();
+            let scope = rue_semantic::analyze_cst(&ast).unwrap();
+            let mut codegen = Codegen::new();
+            let instrs = codegen.generate(&ast, &scope).unwrap();
+            interpret(&instrs, codegen.function_labels()).unwrap()
+        };
+
+        let sum = "fn sum(n) { let total = 0; for i in 1..n + 1 { total = total + i; }; total } \
+                   fn main() { sum(10) }";
+        assert_eq!(run(sum), 55);
+
+        // An empty range never runs the body
+        assert_eq!(
+            run("fn main() { let x = 7; for i in 5..5 { x = i; }; x }"),
+            7
+        );
+
+        // The loop variable shadows an outer one only inside the loop
+        let shadow = "fn main() { let i = 100; let last = 0; \
+                      for i in 0..3 { last = i; }; i + last }";
+        assert_eq!(run(shadow), 102);
+    }
+
+    #[test]
+    fn test_unit_return_zeroes_rax() {
+        let source = "fn f(n) { while n > 0 { n - 1 }; } fn main() { f(3) }";
+        let ast = rue_parser::parse(Lexer::new(source).tokenize()).unwrap();
+        let scope = rue_semantic::analyze_cst(&ast).unwrap();
+        let code =
+            compile_to_machine_code_with_options(&ast, &scope, &CodegenOptions::default()).unwrap();
+
+        // xor eax, eax; ret
+        assert!(code.windows(3).any(|w| w == [0x31, 0xc0, 0xc3]));
+    }
+
+    #[test]
+    fn test_custom_entry_function() {
+        let source = "fn answer() { 42 } fn helper(x) { x }";
+        let ast = rue_parser::parse(Lexer::new(source).tokenize()).unwrap();
+        let scope = rue_semantic::analyze_cst(&ast).unwrap();
+
+        let mut codegen = Codegen::new();
+        codegen.set_entry("answer");
+        let instrs = codegen.generate(&ast, &scope).unwrap();
+        assert!(matches!(
+            &instrs[1],
+            Instruction::Call { function, .. } if function == "answer"
+        ));
+
+        // Without a main, the default entry fails
+        let error = Codegen::new().generate(&ast, &scope).unwrap_err();
+        assert_eq!(error.message, "No main function found");
+
+        // An entry function with a parameter gets argc
+        let mut codegen = Codegen::new();
+        codegen.set_entry("helper");
+        let instrs = codegen.generate(&ast, &scope).unwrap();
+        assert!(matches!(
+            &instrs[1..3],
+            [
+                Instruction::Load { dest, offset: 0 },
+                Instruction::Call { function, args, .. },
+            ] if function == "helper" && args == &vec![*dest]
+        ));
+
+        let mut codegen = Codegen::new();
+        codegen.set_entry("missing");
+        let error = codegen.generate(&ast, &scope).unwrap_err();
+        assert_eq!(error.message, "No entry function found: missing");
+    }
+
+    #[test]
+    fn test_constant_propagation() {
+        let main_body = |source: &str, opt_level| {
+            let ast = rue_parser::parse(Lexer::new(source).tokenize()).unwrap();
+            let scope = rue_semantic::analyze_cst(&ast).unwrap();
+            let mut codegen = Codegen::new();
+            codegen.set_opt_level(opt_level);
+            let instrs = codegen.generate(&ast, &scope).unwrap();
+            let main = codegen.function_labels()["main"];
+            let start = instrs
+                .iter()
+                .position(|i| matches!(i, Instruction::Label(l) if *l == main))
+                .unwrap();
+            let end = instrs[start..]
+                .iter()
+                .position(|i| matches!(i, Instruction::Return { .. }))
+                .unwrap();
+            instrs[start + 1..=start + end].to_vec()
+        };
+        let has_binary_op = |instrs: &[Instruction]| {
+            instrs
+                .iter()
+                .any(|i| matches!(i, Instruction::BinaryOp { .. }))
+        };
+
+        let body = main_body("fn main() { let x = 5; x + 3 }", 1);
+        assert!(matches!(
+            body.as_slice(),
+            [
+                Instruction::Copy {
+                    src: Value::Immediate(8),
+                    ..
+                },
+                Instruction::Return { .. }
+            ]
+        ));
+
+        // Without -O1 the add happens at runtime
+        assert!(has_binary_op(&main_body(
+            "fn main() { let x = 5; x + 3 }",
+            0
+        )));
+
+        // A variable that's ever assigned isn't propagated
+        assert!(has_binary_op(&main_body(
+            "fn main() { let x = 5; x = 6; x + 3 }",
+            1
+        )));
+    }
+
+    #[test]
+    fn test_size_of_folds_to_immediate() {
+        let instrs = compile_program("fn main() { size_of(i64) }").unwrap();
+
+        assert!(instrs.iter().any(|i| matches!(
+            i,
+            Instruction::Copy {
+                src: Value::Immediate(8),
+                ..
+            }
+        )));
+        // The only call is _start's call to main
+        let calls = instrs
+            .iter()
+            .filter(|i| matches!(i, Instruction::Call { .. }))
+            .count();
+        assert_eq!(calls, 1);
+    }
+
+    #[test]
+    fn test_block_expression() {
+        let run = |source: &str| {
+            let ast = rue_parser::parse(Lexer::new(source).tokenize()).unwrap();
+            let scope = rue_semantic::analyze_cst(&ast).unwrap();
+            let mut codegen = Codegen::new();
+            let instrs = codegen.generate(&ast, &scope).unwrap();
+            interpret(&instrs, codegen.function_labels()).unwrap()
+        };
+
+        assert_eq!(run("fn main() { let y = { let a = 1; a + 1 }; y }"), 2);
+
+        // A shadowing `let` in the block ends with it, but an assignment
+        // sticks
+        let source = "fn main() { let a = 10; let b = 20; \
+                      let y = { let a = 1; b = 2; a + 1 }; a + b + y }";
+        assert_eq!(run(source), 14);
+
+        // The same goes for the blocks of an `if` and a loop
+        let source = "fn main() { let a = 10; if a > 0 { let a = 1; a } else { 2 }; \
+                      let i = 0; while i < 1 { let a = 3; i = i + 1; }; a }";
+        assert_eq!(run(source), 10);
+    }
+
+    #[test]
+    fn test_break_and_continue() {
+        let run = |source: &str| {
+            let ast = rue_parser::parse(Lexer::new(source).tokenize()).unwrap();
+            let scope = rue_semantic::analyze_cst(&ast).unwrap();
+            let mut codegen = Codegen::new();
+            let instrs = codegen.generate(&ast, &scope).unwrap();
+            interpret(&instrs, codegen.function_labels()).unwrap()
+        };
+
+        let early = "fn main() { let i = 0; while 1 > 0 { if i == 5 { break; }; i = i + 1; }; i }";
+        assert_eq!(run(early), 5);
+
+        // 1 + 2 + 3 + 4 + 5, skipping the rest
+        let skip = "fn main() { let sum = 0; let i = 0; \
+                    while i < 10 { i = i + 1; if i > 5 { continue; }; sum = sum + i; }; sum }";
+        assert_eq!(run(skip), 15);
+
+        // `continue` in a for loop still increments the loop variable, and
+        // `break` only leaves the innermost loop
+        let nested = "fn main() { let sum = 0; \
+                      for i in 0..4 { if i == 2 { continue; }; \
+                      for j in 0..10 { if j == 2 { break; }; sum = sum + 1; }; }; sum }";
+        assert_eq!(run(nested), 6);
+    }
+
+    #[test]
+    fn test_assert() {
+        let run = |source: &str| {
+            let ast = rue_parser::parse(Lexer::new(source).tokenize()).unwrap();
+            let scope = rue_semantic::analyze_cst(&ast).unwrap();
+            let mut codegen = Codegen::new();
+            let instrs = codegen.generate(&ast, &scope).unwrap();
+            interpret(&instrs, codegen.function_labels()).unwrap()
+        };
+
+        assert_eq!(run("fn main() { assert(1 <= 2); 7 }"), 7);
+        assert_eq!(
+            run("fn main() { assert(2 <= 1); 7 }"),
+            ASSERT_FAILED_EXIT_CODE
+        );
+    }
+
+    #[test]
+    fn test_const_is_inlined() {
+        let instrs = compile_program("const MAX = 100; fn main() { MAX }").unwrap();
+        assert!(instrs.iter().any(|instr| matches!(
+            instr,
+            Instruction::Copy {
+                src: Value::Immediate(100),
+                ..
+            }
+        )));
+
+        // A local binding shadows the constant
+        let source = "const N = 10; fn main() { let N = 3; N * 2 }";
+        let ast = rue_parser::parse(Lexer::new(source).tokenize()).unwrap();
+        let scope = rue_semantic::analyze_cst(&ast).unwrap();
+        let mut codegen = Codegen::new();
+        let instrs = codegen.generate(&ast, &scope).unwrap();
+        assert_eq!(interpret(&instrs, codegen.function_labels()).unwrap(), 6);
+    }
+
+    #[test]
+    fn test_return_statement() {
+        let instrs = compile_program("fn f() { return; } fn main() { f(); 7 }").unwrap();
+        let returns: Vec<_> = instrs
+            .iter()
+            .filter_map(|i| match i {
+                Instruction::Return { value } => Some(value.is_some()),
+                _ => None,
+            })
+            .collect();
+        // main's implicit return, then f's bare `return;` and implicit return
+        assert_eq!(returns, vec![true, false, false]);
+    }
+
+    #[test]
+    fn test_unit_if_has_no_zero() {
+        let instructions = compile_program(
+            r#"
+fn main() {
+    if 1 > 2 {};
+    42
+}
+"#,
+        )
+        .unwrap();
+
+        // Neither branch has a value, so no zero is fabricated for them
+        assert!(!instructions.iter().any(|i| matches!(
+            i,
+            Instruction::Copy {
+                src: Value::Immediate(0),
+                ..
+            }
+        )));
+    }
+
+    #[test]
+    fn test_used_unit_if_is_zero() {
+        let run = |source: &str| {
+            let ast = rue_parser::parse(Lexer::new(source).tokenize()).unwrap();
+            let scope = rue_semantic::analyze_cst(&ast).unwrap();
+            let mut codegen = Codegen::new();
+            let instrs = codegen.generate(&ast, &scope).unwrap();
+            interpret(&instrs, codegen.function_labels()).unwrap()
+        };
+
+        // Whichever branch runs writes 0 to the result, rather than leaving
+        // it unset
+        assert_eq!(run("fn main() { let x = if 1 < 2 { } else { }; x }"), 0);
+        assert_eq!(run("fn main() { let x = if 1 > 2 { }; x }"), 0);
+    }
+
+    #[test]
+    fn test_overflow_checks() {
+        let instructions = vec![
+            Instruction::Copy {
+                dest: VReg(0),
+                src: Value::Immediate(i64::MAX),
+            },
+            Instruction::Copy {
+                dest: VReg(1),
+                src: Value::Immediate(1),
+            },
+            Instruction::BinaryOp {
+                dest: VReg(2),
+                lhs: Value::VReg(VReg(0)),
+                rhs: Value::VReg(VReg(1)),
+                op: BinOp::Add,
+            },
+        ];
+        let jno_ud2 = [0x71, 0x02, 0x0f, 0x0b];
+
+        let mut assembler = Assembler::new();
+        let unchecked = assembler.assemble(instructions.clone()).unwrap();
+        assert!(!unchecked.windows(4).any(|w| w == jno_ud2));
+
+        let mut assembler = Assembler::new();
+        assembler.set_overflow_checks(true);
+        let checked = assembler.assemble(instructions).unwrap();
+        assert!(checked.ends_with(&jno_ud2));
+    }
+
     #[test]
     fn test_physical_reg_error_in_binary_ops() {
         let mut assembler = Assembler::new();
         let mut regalloc = RegisterAllocator::new();
         let dest_vreg = VReg(0);
-        regalloc.allocate(dest_vreg);
+        let copy = Instruction::Copy {
+            dest: dest_vreg,
+            src: Value::Immediate(0),
+        };
+        regalloc.allocate(&[copy], &HashSet::new()).unwrap();
 
         // Test that using PhysicalReg in binary operations returns proper error
         let instr = Instruction::BinaryOp {
@@ -0,0 +1,131 @@
+use crate::Instruction;
+use rue_lexer::{SourceMap, Span};
+
+/// Render a listing of source lines, each followed by the instructions
+/// generated for it
+///
+/// `spans` gives the source span of each instruction, as returned by
+/// `Codegen::spans`. Lines are printed as instructions reach them, so a line
+/// that code comes back to (like an `if` after its branches) can appear more
+/// than once. Instructions with no span, like the program's
+/// entry point, are printed under a `<no source>` heading.
+pub fn render_listing(
+    source: &str,
+    instructions: &[Instruction],
+    spans: &[Option<Span>],
+) -> String {
+    let source_map = SourceMap::new(source);
+    let width = source_map.line_count().to_string().len();
+    let indent = " ".repeat(width + 3);
+
+    let mut out = String::new();
+    let mut printed = vec![false; source_map.line_count()];
+    // Line of the last source heading printed, if any
+    let mut current_line = None;
+
+    for (instr, span) in instructions.iter().zip(spans) {
+        let line = span.map(|span| source_map.byte_to_line(span.start));
+        if out.is_empty() || line != current_line {
+            match line {
+                Some(line) => {
+                    // Moving forward, also print the lines skipped over,
+                    // like an `} else {` that produces no code of its own
+                    let first = match current_line {
+                        Some(current) if current < line => current + 1,
+                        _ => line,
+                    };
+                    for (skipped, done) in printed.iter_mut().enumerate().take(line).skip(first) {
+                        if !*done {
+                            push_source_line(&mut out, &source_map, source, width, skipped);
+                            *done = true;
+                        }
+                    }
+                    push_source_line(&mut out, &source_map, source, width, line);
+                    printed[line] = true;
+                }
+                None => out.push_str(&format!("{:>width$} | <no source>\n", "")),
+            }
+            current_line = line;
+        }
+
+        match instr {
+            Instruction::Label(label) => out.push_str(&format!("{}label_{}:\n", indent, label.0)),
+            _ => out.push_str(&format!("{}    {:?}\n", indent, instr)),
+        }
+    }
+
+    out
+}
+
+fn push_source_line(
+    out: &mut String,
+    source_map: &SourceMap,
+    source: &str,
+    width: usize,
+    line: usize,
+) {
+    let text = &source[source_map.line_range(line)];
+    let line = format!("{:>width$} | {}", line + 1, text);
+    out.push_str(line.trim_end());
+    out.push('\n');
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Codegen, LabelId, VReg, Value};
+
+    #[test]
+    fn test_listing_interleaves_source() {
+        let source = "fn main() {\n    let x = 1;\n\n    x\n}\n";
+        let instructions = vec![
+            Instruction::Label(LabelId(0)),
+            Instruction::Copy {
+                dest: VReg(0),
+                src: Value::Immediate(1),
+            },
+            Instruction::Copy {
+                dest: VReg(1),
+                src: Value::VReg(VReg(0)),
+            },
+            Instruction::Return {
+                value: Some(VReg(1)),
+            },
+        ];
+        let offset = |text| {
+            source.find(text).map(|start| Span {
+                start,
+                end: start + 1,
+            })
+        };
+        let spans = vec![offset("main"), offset("let"), offset("x\n"), offset("}")];
+
+        assert_eq!(
+            render_listing(source, &instructions, &spans),
+            "\
+1 | fn main() {
+    label_0:
+2 |     let x = 1;
+        Copy { dest: VReg(0), src: Immediate(1) }
+3 |
+4 |     x
+        Copy { dest: VReg(1), src: VReg(VReg(0)) }
+5 | }
+        Return { value: Some(VReg(1)) }
+"
+        );
+    }
+
+    #[test]
+    fn test_listing_marks_code_without_source() {
+        let source = "fn main() { 42 }";
+        let ast = rue_parser::parse(rue_lexer::Lexer::new(source).tokenize()).unwrap();
+        let scope = rue_semantic::analyze_cst(&ast).unwrap();
+        let mut codegen = Codegen::new();
+        let instructions = codegen.generate(&ast, &scope).unwrap();
+
+        let listing = render_listing(source, &instructions, codegen.spans());
+        assert!(listing.starts_with("  | <no source>\n    label_999:\n"));
+        assert!(listing.contains("1 | fn main() { 42 }\n"));
+    }
+}
@@ -1,46 +1,200 @@
-use crate::{Register, VReg};
-use std::collections::HashMap;
+use crate::verify::stack_depths;
+use crate::{CodegenError, Instruction, LabelId, Register, VReg, Value, instruction_vregs};
+use std::cmp::Reverse;
+use std::collections::{HashMap, HashSet};
+
+/// A virtual register moved out of its physical register and onto the
+/// stack, because no register was free when another one needed it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SpillEvent {
+    /// The virtual register that lost its physical register
+    pub vreg: VReg,
+    /// The physical register it lost
+    pub register: Register,
+    /// Index of the instruction being allocated when it happened
+    pub at_instruction: usize,
+}
 
 /// Simple linear scan register allocator
+///
+/// Each virtual register is live from its first appearance to its last, in
+/// program order, stretched to the end of any loop it's live on entry to,
+/// since the loop's back edge needs it again. A register is free again once
+/// its virtual register is dead. When every register holds a live value,
+/// the one needed furthest away is spilled: it's kept in a stack slot in
+/// its function's frame instead, and loaded into a short-lived virtual
+/// register wherever it's used.
 pub struct RegisterAllocator {
     /// Mapping from virtual registers to physical registers
     allocation: HashMap<VReg, Register>,
     /// Available physical registers (in order of preference)
     available_registers: Vec<Register>,
-    /// Next register to allocate
-    next_register_index: usize,
+    /// Registers holding values that are still needed after each call, by
+    /// the call's index, which the callee is free to overwrite
+    live_across_calls: HashMap<usize, Vec<Register>>,
+    /// Live virtual registers that had their register taken
+    spills: Vec<SpillEvent>,
 }
 
 impl RegisterAllocator {
     pub fn new() -> Self {
+        // Use available x86-64 registers
+        // Reserve rax for return values, rsp/rbp for stack
+        Self::with_registers(vec![
+            Register::Rbx,
+            Register::Rcx,
+            Register::Rdx,
+            Register::Rsi,
+            Register::Rdi,
+        ])
+    }
+
+    /// An allocator drawing from a given pool of registers
+    pub fn with_registers(available_registers: Vec<Register>) -> Self {
         Self {
             allocation: HashMap::new(),
-            // Use available x86-64 registers
-            // Reserve rax for return values, rsp/rbp for stack
-            available_registers: vec![
-                Register::Rbx,
-                Register::Rcx,
-                Register::Rdx,
-                Register::Rsi,
-                Register::Rdi,
-            ],
-            next_register_index: 0,
+            available_registers,
+            live_across_calls: HashMap::new(),
+            spills: Vec::new(),
+        }
+    }
+
+    /// Allocate a physical register for every virtual register in
+    /// `instructions`, and return them with the code to spill the values
+    /// that didn't fit added. The allocation is for the returned
+    /// instructions.
+    ///
+    /// Spill slots are made at the start of each function, which begins at
+    /// one of `function_labels` (or at the first instruction), and freed
+    /// before each of its returns.
+    pub fn allocate(
+        &mut self,
+        instructions: &[Instruction],
+        function_labels: &HashSet<LabelId>,
+    ) -> Result<Vec<Instruction>, CodegenError> {
+        self.spills.clear();
+        let mut spilled = Vec::new();
+        loop {
+            let spill_code = insert_spill_code(instructions, &spilled, function_labels)?;
+            match self.scan(&spill_code.instructions, &spill_code.temporaries)? {
+                None => return Ok(spill_code.instructions),
+                Some(spill) => {
+                    // Spill code gives the value a new home everywhere, so
+                    // start over with it in place
+                    spilled.push(spill.vreg);
+                    self.spills.push(SpillEvent {
+                        at_instruction: spill_code.origins[spill.at_instruction],
+                        ..spill
+                    });
+                }
+            }
         }
     }
 
-    /// Allocate a physical register for a virtual register
-    pub fn allocate(&mut self, vreg: VReg) -> Register {
-        if let Some(&physical_reg) = self.allocation.get(&vreg) {
-            // Already allocated
-            physical_reg
-        } else {
-            // Allocate next available register (simple round-robin)
-            let physical_reg =
-                self.available_registers[self.next_register_index % self.available_registers.len()];
-            self.next_register_index += 1;
+    // Assign registers over `instructions` in one pass, stopping at the
+    // first live value that has to give up its register. `temporaries`
+    // hold spilled values briefly, so spilling them wouldn't help.
+    fn scan(
+        &mut self,
+        instructions: &[Instruction],
+        temporaries: &HashSet<VReg>,
+    ) -> Result<Option<SpillEvent>, CodegenError> {
+        self.allocation.clear();
+        self.live_across_calls.clear();
+        let ranges = live_ranges(instructions);
+        // Virtual registers currently holding a physical register
+        let mut active: Vec<VReg> = Vec::new();
+
+        for (index, instr) in instructions.iter().enumerate() {
+            active.retain(|vreg| ranges[vreg].1 >= index);
+
+            let dest = defined_vreg(instr);
+            let vregs = instruction_vregs(instr);
+            for vreg in used_vregs(instr) {
+                if let Some(spill) =
+                    self.assign(vreg, index, &vregs, &mut active, &ranges, temporaries)?
+                {
+                    return Ok(Some(spill));
+                }
+            }
+            if let Some(dest) = dest {
+                // The destination can reuse the register of a value last
+                // used here, except a binary op's right-hand side, which is
+                // still read after the destination is first written
+                let rhs = match instr {
+                    Instruction::BinaryOp {
+                        lhs,
+                        rhs: Value::VReg(rhs),
+                        ..
+                    } if *lhs != Value::VReg(*rhs) => Some(*rhs),
+                    _ => None,
+                };
+                active.retain(|vreg| ranges[vreg].1 > index || Some(*vreg) == rhs);
+                if let Some(spill) =
+                    self.assign(dest, index, &vregs, &mut active, &ranges, temporaries)?
+                {
+                    return Ok(Some(spill));
+                }
+            }
 
-            self.allocation.insert(vreg, physical_reg);
-            physical_reg
+            if let Instruction::Call { .. } = instr {
+                let live = active
+                    .iter()
+                    .filter(|vreg| ranges[vreg].0 < index && ranges[vreg].1 > index)
+                    .map(|vreg| self.allocation[vreg])
+                    .collect();
+                self.live_across_calls.insert(index, live);
+            }
+        }
+
+        Ok(None)
+    }
+
+    // Give `vreg` a free register, unless it already has one. If none is
+    // free, return the live value that should be spilled to make room: the
+    // one needed furthest away that's not used by this instruction.
+    fn assign(
+        &mut self,
+        vreg: VReg,
+        index: usize,
+        instr_vregs: &[VReg],
+        active: &mut Vec<VReg>,
+        ranges: &HashMap<VReg, (usize, usize)>,
+        temporaries: &HashSet<VReg>,
+    ) -> Result<Option<SpillEvent>, CodegenError> {
+        // Already allocated, as a value defined again in a loop is
+        if self.allocation.contains_key(&vreg) {
+            return Ok(None);
+        }
+        let free = self.available_registers.iter().copied().find(|register| {
+            active
+                .iter()
+                .all(|holder| self.allocation[holder] != *register)
+        });
+
+        match free {
+            Some(register) => {
+                self.allocation.insert(vreg, register);
+                active.push(vreg);
+                Ok(None)
+            }
+            None => {
+                let victim = active
+                    .iter()
+                    .filter(|held| !temporaries.contains(held) && !instr_vregs.contains(held))
+                    .max_by_key(|held| (ranges[held].1, Reverse(held.0)))
+                    .ok_or_else(|| CodegenError {
+                        message: format!(
+                            "Too many values needed at once for {} registers",
+                            self.available_registers.len()
+                        ),
+                    })?;
+                Ok(Some(SpillEvent {
+                    vreg: *victim,
+                    register: self.allocation[victim],
+                    at_instruction: index,
+                }))
+            }
         }
     }
 
@@ -53,6 +207,348 @@ impl RegisterAllocator {
     pub fn get_register(&self, vreg: VReg) -> Option<Register> {
         self.allocation.get(&vreg).copied()
     }
+
+    /// Registers holding values that are needed after the call at `index`,
+    /// and so have to be saved around it
+    pub fn live_across_call(&self, index: usize) -> &[Register] {
+        self.live_across_calls
+            .get(&index)
+            .map_or(&[], |registers| registers.as_slice())
+    }
+
+    /// Every spill, in the order they happened
+    pub fn spills(&self) -> &[SpillEvent] {
+        &self.spills
+    }
+}
+
+// Where each virtual register is live, as the indices of its first and last
+// appearances. A value that's live on entry to a loop, having been defined
+// before it, is kept until the loop's back edge, since the next iteration
+// can still use it.
+fn live_ranges(instructions: &[Instruction]) -> HashMap<VReg, (usize, usize)> {
+    let mut ranges: HashMap<VReg, (usize, usize)> = HashMap::new();
+    let mut label_indices = HashMap::new();
+    let mut back_edges = Vec::new();
+
+    for (index, instr) in instructions.iter().enumerate() {
+        for vreg in instruction_vregs(instr) {
+            ranges
+                .entry(vreg)
+                .and_modify(|range| range.1 = index)
+                .or_insert((index, index));
+        }
+        let targets = match instr {
+            Instruction::Label(label) => {
+                label_indices.insert(*label, index);
+                continue;
+            }
+            Instruction::Jump(target) => vec![*target],
+            Instruction::Branch {
+                true_label,
+                false_label,
+                ..
+            } => vec![*true_label, *false_label],
+            _ => continue,
+        };
+        for target in targets {
+            if let Some(&start) = label_indices.get(&target) {
+                back_edges.push((start, index));
+            }
+        }
+    }
+
+    // Stretching one loop's values can make them live on entry to an
+    // enclosing loop, so repeat until nothing changes
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for &(start, end) in &back_edges {
+            for range in ranges.values_mut() {
+                if range.0 < start && range.1 >= start && range.1 < end {
+                    range.1 = end;
+                    changed = true;
+                }
+            }
+        }
+    }
+
+    ranges
+}
+
+// The virtual register an instruction writes, if any
+fn defined_vreg(instr: &Instruction) -> Option<VReg> {
+    match instr {
+        Instruction::Copy { dest, .. }
+        | Instruction::BinaryOp { dest, .. }
+        | Instruction::Load { dest, .. }
+        | Instruction::Pop { dest }
+        | Instruction::ReadInt { dest }
+        | Instruction::Syscall { result: dest, .. } => Some(*dest),
+        Instruction::Call { dest, .. } => *dest,
+        _ => None,
+    }
+}
+
+// The virtual registers an instruction reads, which include its destination
+// when it's also an operand
+fn used_vregs(instr: &Instruction) -> Vec<VReg> {
+    let mut vregs = instruction_vregs(instr);
+    if let Some(dest) = defined_vreg(instr)
+        && let Some(position) = vregs.iter().position(|vreg| *vreg == dest)
+    {
+        vregs.remove(position);
+    }
+    vregs
+}
+
+// `instr` with its destination replaced by `def`, and every other virtual
+// register by `used`
+fn rename_vregs(
+    instr: &Instruction,
+    def: impl Fn(VReg) -> VReg,
+    used: impl Fn(VReg) -> VReg,
+) -> Instruction {
+    let value = |value: &Value| match value {
+        Value::VReg(vreg) => Value::VReg(used(*vreg)),
+        other => other.clone(),
+    };
+    match instr {
+        Instruction::Copy { dest, src } => Instruction::Copy {
+            dest: def(*dest),
+            src: value(src),
+        },
+        Instruction::BinaryOp { dest, lhs, rhs, op } => Instruction::BinaryOp {
+            dest: def(*dest),
+            lhs: value(lhs),
+            rhs: value(rhs),
+            op: op.clone(),
+        },
+        Instruction::Load { dest, offset } => Instruction::Load {
+            dest: def(*dest),
+            offset: *offset,
+        },
+        Instruction::Store { src, offset } => Instruction::Store {
+            src: used(*src),
+            offset: *offset,
+        },
+        Instruction::Push { src } => Instruction::Push { src: used(*src) },
+        Instruction::Pop { dest } => Instruction::Pop { dest: def(*dest) },
+        Instruction::Branch {
+            condition,
+            true_label,
+            false_label,
+        } => Instruction::Branch {
+            condition: used(*condition),
+            true_label: *true_label,
+            false_label: *false_label,
+        },
+        Instruction::Call {
+            dest,
+            function,
+            args,
+        } => Instruction::Call {
+            dest: dest.map(&def),
+            function: function.clone(),
+            args: args.iter().map(|arg| used(*arg)).collect(),
+        },
+        Instruction::Return { value } => Instruction::Return {
+            value: value.map(&used),
+        },
+        Instruction::Syscall {
+            result,
+            syscall_num,
+            args,
+        } => Instruction::Syscall {
+            result: def(*result),
+            syscall_num: used(*syscall_num),
+            args: args.iter().map(|arg| used(*arg)).collect(),
+        },
+        Instruction::ReadInt { dest } => Instruction::ReadInt { dest: def(*dest) },
+        other => other.clone(),
+    }
+}
+
+// Instructions rewritten to keep some values on the stack
+struct SpillCode {
+    instructions: Vec<Instruction>,
+    // The index of the original instruction each one came from
+    origins: Vec<usize>,
+    // Virtual registers added to hold a spilled value for one instruction
+    temporaries: HashSet<VReg>,
+}
+
+impl SpillCode {
+    fn push(&mut self, instr: Instruction, origin: usize) {
+        self.instructions.push(instr);
+        self.origins.push(origin);
+    }
+}
+
+// Rewrite `instructions` so that each of the `spilled` virtual registers
+// lives in a stack slot: a use loads it into a new temporary first, and a
+// definition writes a new temporary that's then stored. Slots go above
+// everything else the function puts on the stack, so they're found from the
+// stack depth at each instruction.
+fn insert_spill_code(
+    instructions: &[Instruction],
+    spilled: &[VReg],
+    function_labels: &HashSet<LabelId>,
+) -> Result<SpillCode, CodegenError> {
+    let depths = stack_depths(instructions)?;
+    let mut next_vreg = instructions
+        .iter()
+        .flat_map(instruction_vregs)
+        .map(|vreg| vreg.0 + 1)
+        .max()
+        .unwrap_or(0);
+    let mut temporary = || {
+        next_vreg += 1;
+        VReg(next_vreg - 1)
+    };
+
+    let mut code = SpillCode {
+        instructions: Vec::with_capacity(instructions.len()),
+        origins: Vec::with_capacity(instructions.len()),
+        temporaries: HashSet::new(),
+    };
+
+    // Split the instructions into functions
+    let mut starts: Vec<usize> = instructions
+        .iter()
+        .enumerate()
+        .filter(|(_, instr)| matches!(instr, Instruction::Label(label) if function_labels.contains(label)))
+        .map(|(index, _)| index)
+        .collect();
+    if starts.first() != Some(&0) {
+        starts.insert(0, 0);
+    }
+    starts.push(instructions.len());
+
+    for function in starts.windows(2) {
+        let (start, end) = (function[0], function[1]);
+        let body = &instructions[start..end];
+
+        // Each spilled value the function uses gets its own slot
+        let mut slots: HashMap<VReg, i64> = HashMap::new();
+        for vreg in body.iter().flat_map(instruction_vregs) {
+            if spilled.contains(&vreg) && !slots.contains_key(&vreg) {
+                slots.insert(vreg, 8 * slots.len() as i64);
+            }
+        }
+        let frame = 8 * slots.len() as i64;
+
+        let mut index = start;
+        if let Some(label @ Instruction::Label(_)) = body.first() {
+            code.push(label.clone(), index);
+            index += 1;
+        }
+        if frame > 0 {
+            code.push(Instruction::AllocateStack { bytes: frame }, start);
+        }
+
+        // Stores after a run of copies from physical registers wait for the
+        // end of the run, which the assembler emits as a single move
+        let mut pending_stores = Vec::new();
+        for (index, instr) in instructions.iter().enumerate().take(end).skip(index) {
+            let depth = 8 * depths[index].unwrap_or(0);
+
+            let mut loaded = HashMap::new();
+            let dest = defined_vreg(instr);
+            for vreg in used_vregs(instr) {
+                if let Some(&slot) = slots.get(&vreg)
+                    && !loaded.contains_key(&vreg)
+                {
+                    let temp = temporary();
+                    code.temporaries.insert(temp);
+                    loaded.insert(vreg, temp);
+                    code.push(
+                        Instruction::Load {
+                            dest: temp,
+                            offset: depth + slot,
+                        },
+                        index,
+                    );
+                }
+            }
+            let stored = dest.filter(|dest| slots.contains_key(dest)).map(|dest| {
+                let temp = temporary();
+                code.temporaries.insert(temp);
+                (dest, temp)
+            });
+
+            let instr = match instr {
+                // Memory above the function, like argc above _start, is
+                // further away by the size of the slots
+                Instruction::Load { dest, offset } if *offset >= depth => Instruction::Load {
+                    dest: *dest,
+                    offset: offset + frame,
+                },
+                Instruction::Store { src, offset } if *offset >= depth => Instruction::Store {
+                    src: *src,
+                    offset: offset + frame,
+                },
+                _ => instr.clone(),
+            };
+            let instr = rename_vregs(
+                &instr,
+                |vreg| match stored {
+                    Some((dest, temp)) if dest == vreg => temp,
+                    _ => vreg,
+                },
+                |vreg| loaded.get(&vreg).copied().unwrap_or(vreg),
+            );
+            if frame > 0 && matches!(instr, Instruction::Return { .. }) {
+                code.push(Instruction::FreeStack { bytes: frame }, index);
+            }
+            let is_physical_copy = crate::physical_copy(&instr).is_some();
+            code.push(instr, index);
+
+            if let Some((dest, temp)) = stored {
+                // A pop has taken its value off the stack by the time it's
+                // stored
+                let depth = match instructions[index] {
+                    Instruction::Pop { .. } => depth - 8,
+                    _ => depth,
+                };
+                pending_stores.push((
+                    Instruction::Store {
+                        src: temp,
+                        offset: depth + slots[&dest],
+                    },
+                    index,
+                ));
+            }
+            let run_continues = is_physical_copy
+                && instructions
+                    .get(index + 1)
+                    .is_some_and(|next| crate::physical_copy(next).is_some());
+            if !run_continues {
+                for (store, origin) in pending_stores.drain(..) {
+                    code.push(store, origin);
+                }
+            }
+        }
+    }
+
+    Ok(code)
+}
+
+/// Render spill events one per line, along with the instruction each
+/// happened at
+pub fn render_spills(instructions: &[Instruction], spills: &[SpillEvent]) -> String {
+    let mut out = String::new();
+    for spill in spills {
+        out.push_str(&format!(
+            "spill {:?} from {:?} at instruction {}",
+            spill.vreg, spill.register, spill.at_instruction
+        ));
+        if let Some(instr) = instructions.get(spill.at_instruction) {
+            out.push_str(&format!(": {:?}", instr));
+        }
+        out.push('\n');
+    }
+    out
 }
 
 impl Default for RegisterAllocator {
@@ -64,40 +560,208 @@ impl Default for RegisterAllocator {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::BinOp;
+
+    fn copy(dest: u32, imm: i64) -> Instruction {
+        Instruction::Copy {
+            dest: VReg(dest),
+            src: Value::Immediate(imm),
+        }
+    }
+
+    fn add(dest: u32, lhs: u32, rhs: u32) -> Instruction {
+        Instruction::BinaryOp {
+            dest: VReg(dest),
+            lhs: Value::VReg(VReg(lhs)),
+            rhs: Value::VReg(VReg(rhs)),
+            op: BinOp::Add,
+        }
+    }
 
     #[test]
     fn test_register_allocation() {
+        let instructions = vec![copy(1, 1), copy(2, 2), add(3, 1, 2)];
         let mut allocator = RegisterAllocator::new();
+        let allocated = allocator.allocate(&instructions, &HashSet::new()).unwrap();
 
-        let vreg1 = VReg(1);
-        let vreg2 = VReg(2);
+        // Nothing to spill, so the instructions are unchanged
+        assert_eq!(format!("{:?}", allocated), format!("{:?}", instructions));
+        let reg1 = allocator.get_register(VReg(1)).unwrap();
+        let reg2 = allocator.get_register(VReg(2)).unwrap();
+        assert_ne!(reg1, reg2);
 
-        let reg1 = allocator.allocate(vreg1);
-        let reg2 = allocator.allocate(vreg2);
+        // The sum can reuse its left-hand side's register, but not its
+        // right-hand side's, which is read after the sum is first written
+        assert_eq!(allocator.get_register(VReg(3)), Some(reg1));
+    }
 
-        // Should get consistent allocation
-        assert_eq!(allocator.allocate(vreg1), reg1);
-        assert_eq!(allocator.allocate(vreg2), reg2);
+    #[test]
+    fn test_dead_registers_are_reused() {
+        // Ten values, but never more than two live at once
+        let mut instructions = vec![copy(0, 0)];
+        for i in 1..10 {
+            instructions.push(copy(2 * i - 1, 1));
+            instructions.push(add(2 * i, 2 * i - 2, 2 * i - 1));
+        }
+        let mut allocator = RegisterAllocator::with_registers(vec![Register::Rbx, Register::Rcx]);
+        allocator.allocate(&instructions, &HashSet::new()).unwrap();
+        assert!(allocator.spills().is_empty());
+        assert_eq!(allocator.get_allocation().len(), 19);
+    }
 
-        // Should allocate different registers
-        assert_ne!(reg1, reg2);
+    #[test]
+    fn test_loop_carried_values_stay_live() {
+        // v0 is last used near the top of the loop, but the next iteration
+        // needs it again, so v2 can't take its register
+        let instructions = vec![
+            copy(0, 0),
+            Instruction::Label(LabelId(1)),
+            copy(1, 1),
+            add(0, 0, 1),
+            copy(2, 2),
+            Instruction::Branch {
+                condition: VReg(2),
+                true_label: LabelId(1),
+                false_label: LabelId(2),
+            },
+            Instruction::Label(LabelId(2)),
+        ];
+        let ranges = live_ranges(&instructions);
+        assert_eq!(ranges[&VReg(0)], (0, 5));
+        assert_eq!(ranges[&VReg(1)], (2, 3));
+
+        let mut allocator = RegisterAllocator::with_registers(vec![Register::Rbx, Register::Rcx]);
+        allocator.allocate(&instructions, &HashSet::new()).unwrap();
+        assert_ne!(
+            allocator.get_register(VReg(2)),
+            allocator.get_register(VReg(0))
+        );
     }
 
     #[test]
-    fn test_round_robin_allocation() {
+    fn test_live_across_call() {
+        let instructions = vec![
+            copy(0, 1),
+            copy(1, 2),
+            Instruction::Call {
+                dest: Some(VReg(2)),
+                function: "f".to_string(),
+                args: vec![VReg(1)],
+            },
+            add(3, 0, 2),
+        ];
         let mut allocator = RegisterAllocator::new();
+        let allocated = allocator.allocate(&instructions, &HashSet::new()).unwrap();
 
-        // Allocate more VRegs than available physical registers
-        let mut vregs = Vec::new();
-        let mut allocations = Vec::new();
+        // Only v0 is needed after the call; its argument isn't
+        assert_eq!(
+            allocator.live_across_call(2),
+            &[allocator.get_register(VReg(0)).unwrap()]
+        );
+        assert!(allocator.live_across_call(0).is_empty());
+        assert_eq!(allocated.len(), instructions.len());
+    }
+
+    #[test]
+    fn test_spill_events() {
+        // Three values live at once, with only two registers
+        let instructions = vec![
+            copy(0, 1),
+            copy(1, 2),
+            copy(2, 3),
+            add(3, 0, 1),
+            add(4, 3, 2),
+        ];
+        let mut allocator = RegisterAllocator::with_registers(vec![Register::Rbx, Register::Rcx]);
+        let allocated = allocator.allocate(&instructions, &HashSet::new()).unwrap();
 
-        for i in 0..10 {
-            let vreg = VReg(i);
-            vregs.push(vreg);
-            allocations.push(allocator.allocate(vreg));
+        // VReg(2) needs a register while VReg(0) and VReg(1) are still
+        // needed, so VReg(0), the first of the two, goes to the stack
+        assert_eq!(
+            allocator.spills()[0],
+            SpillEvent {
+                vreg: VReg(0),
+                register: Register::Rbx,
+                at_instruction: 2,
+            }
+        );
+        assert_eq!(
+            render_spills(&instructions, &allocator.spills()[..1]),
+            "spill VReg(0) from Rbx at instruction 2: \
+             Copy { dest: VReg(2), src: Immediate(3) }\n"
+        );
+
+        // Spilled values are stored once they're made, and loaded back
+        // before they're used, from slots made for the function
+        assert!(matches!(
+            allocated[0],
+            Instruction::AllocateStack { bytes } if bytes == 8 * allocator.spills().len() as i64
+        ));
+        assert!(matches!(allocated[2], Instruction::Store { offset: 0, .. }));
+        let reload = allocated
+            .iter()
+            .position(|instr| matches!(instr, Instruction::Load { offset: 0, .. }))
+            .unwrap();
+        assert!(reload > 2);
+        for vreg in allocated.iter().flat_map(instruction_vregs) {
+            assert!(allocator.spills().iter().all(|spill| spill.vreg != vreg));
+            assert!(allocator.get_register(vreg).is_some());
         }
+    }
+
+    #[test]
+    fn test_spilled_value_updated_in_place_is_loaded() {
+        // v0 is both read and written by the add, so the add needs its old
+        // value from the slot, and its new one stored back
+        let instructions = vec![copy(0, 1), copy(1, 2), add(0, 0, 1)];
+        let code = insert_spill_code(&instructions, &[VReg(0)], &HashSet::new()).unwrap();
+
+        let add = code
+            .instructions
+            .iter()
+            .position(|instr| matches!(instr, Instruction::BinaryOp { .. }))
+            .unwrap();
+        let Instruction::Load { dest: loaded, .. } = code.instructions[add - 1] else {
+            panic!("expected a load before the add");
+        };
+        let Instruction::BinaryOp { dest, lhs, .. } = &code.instructions[add] else {
+            unreachable!();
+        };
+        assert_eq!(*lhs, Value::VReg(loaded));
+        assert!(matches!(
+            code.instructions[add + 1],
+            Instruction::Store { src, offset: 0 } if src == *dest
+        ));
+    }
+
+    #[test]
+    fn test_spill_slots_are_freed_before_return() {
+        let instructions = vec![
+            Instruction::Label(LabelId(0)),
+            copy(0, 1),
+            copy(1, 2),
+            copy(2, 3),
+            add(3, 0, 1),
+            add(4, 3, 2),
+            Instruction::Return {
+                value: Some(VReg(4)),
+            },
+        ];
+        let mut allocator = RegisterAllocator::with_registers(vec![Register::Rbx, Register::Rcx]);
+        let allocated = allocator
+            .allocate(&instructions, &HashSet::from([LabelId(0)]))
+            .unwrap();
+        let frame = 8 * allocator.spills().len() as i64;
 
-        // Should reuse registers in round-robin fashion
-        assert_eq!(allocations[0], allocations[5]); // Wraparound after 5 registers
+        assert!(matches!(allocated[0], Instruction::Label(LabelId(0))));
+        assert!(matches!(
+            allocated[1],
+            Instruction::AllocateStack { bytes } if bytes == frame
+        ));
+        assert!(matches!(
+            allocated[allocated.len() - 2],
+            Instruction::FreeStack { bytes } if bytes == frame
+        ));
+        assert!(crate::verify_stack_balance(&allocated).is_ok());
     }
 }
@@ -0,0 +1,295 @@
+use crate::{BinOp, CodegenError, Instruction, LabelId, VReg, Value};
+use std::collections::HashMap;
+
+/// Check that every path into a label agrees on how many values are pushed
+///
+/// Codegen preserves values across calls with `Push`/`Pop`, and makes room
+/// for arrays with `AllocateStack`, so if the two branches of an `if` (or a
+/// loop body and its entry) leave different numbers of values on the stack,
+/// loads after the join point read the wrong slots.
+/// Control flow is tracked through `Jump` and `Branch`; a label that's only
+/// reachable from elsewhere, like a function entry after the previous
+/// function's `Return`, starts with an empty stack.
+pub fn verify_stack_balance(instructions: &[Instruction]) -> Result<(), CodegenError> {
+    stack_depths(instructions).map(|_| ())
+}
+
+/// How many values are on the stack before each instruction, counting from
+/// the start of its function, or None where it's unreachable by falling
+/// through. Fails the same way as `verify_stack_balance`.
+pub(crate) fn stack_depths(instructions: &[Instruction]) -> Result<Vec<Option<i64>>, CodegenError> {
+    let mut depths = Vec::with_capacity(instructions.len());
+    // Stack depth expected on entry to each label seen so far
+    let mut label_depths: HashMap<LabelId, i64> = HashMap::new();
+    // Depth at the current instruction, or None if it's unreachable by
+    // falling through
+    let mut depth = Some(0);
+
+    for instr in instructions {
+        if let Instruction::Label(label) = instr {
+            depth = Some(match depth {
+                Some(incoming) => join(&mut label_depths, *label, incoming)?,
+                None => *label_depths.entry(*label).or_insert(0),
+            });
+        }
+        depths.push(depth);
+
+        match instr {
+            Instruction::Jump(target) => {
+                if let Some(current) = depth {
+                    join(&mut label_depths, *target, current)?;
+                }
+                depth = None;
+            }
+            Instruction::Branch {
+                true_label,
+                false_label,
+                ..
+            } => {
+                if let Some(current) = depth {
+                    join(&mut label_depths, *true_label, current)?;
+                    join(&mut label_depths, *false_label, current)?;
+                }
+                depth = None;
+            }
+            Instruction::Push { .. } => depth = depth.map(|d| d + 1),
+            Instruction::Pop { .. } => depth = depth.map(|d| d - 1),
+            Instruction::AllocateStack { bytes } => depth = depth.map(|d| d + bytes / 8),
+            Instruction::FreeStack { bytes } => depth = depth.map(|d| d - bytes / 8),
+            Instruction::Return { .. } | Instruction::Trap => depth = None,
+            _ => {}
+        }
+    }
+
+    Ok(depths)
+}
+
+// A coarse type for what a VReg holds
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ValueKind {
+    // 0 or 1, from a comparison
+    Bool,
+    // Any other integer, like the result of arithmetic
+    Int,
+    // Produced by something the verifier doesn't track, like a `Pop`
+    Unknown,
+}
+
+impl ValueKind {
+    // The kind of a VReg defined in more than one place, as an `if`'s result
+    // is by each branch
+    fn join(self, other: ValueKind) -> ValueKind {
+        match (self, other) {
+            (a, b) if a == b => a,
+            (ValueKind::Unknown, _) | (_, ValueKind::Unknown) => ValueKind::Unknown,
+            _ => ValueKind::Int,
+        }
+    }
+}
+
+/// Warn about each `Branch` whose condition is an arithmetic result rather
+/// than a comparison
+///
+/// Every VReg gets a coarse kind from the instructions that define it:
+/// comparisons make bools, and arithmetic, immediates and calls make other
+/// integers. `Copy` passes its source's kind along. Conditions the verifier
+/// can't see the origin of aren't flagged, so a warning points at a
+/// frontend bug rather than a gap in tracking. This only warns, since rue
+/// doesn't have a bool type to enforce yet.
+pub fn verify_branch_conditions(instructions: &[Instruction]) -> Vec<String> {
+    let mut kinds: HashMap<VReg, ValueKind> = HashMap::new();
+    let mut warnings = Vec::new();
+
+    for instr in instructions {
+        let (dest, kind) = match instr {
+            Instruction::Copy { dest, src } => {
+                let kind = match src {
+                    Value::Immediate(_) => ValueKind::Int,
+                    Value::VReg(src) => kinds.get(src).copied().unwrap_or(ValueKind::Unknown),
+                    Value::PhysicalReg(_) => ValueKind::Unknown,
+                };
+                (*dest, kind)
+            }
+            Instruction::BinaryOp { dest, op, .. } => {
+                let kind = match op {
+                    BinOp::Lt | BinOp::Le | BinOp::Gt | BinOp::Ge | BinOp::Eq | BinOp::Ne => {
+                        ValueKind::Bool
+                    }
+                    BinOp::Add | BinOp::Sub | BinOp::Mul | BinOp::Div | BinOp::Pow => {
+                        ValueKind::Int
+                    }
+                };
+                (*dest, kind)
+            }
+            Instruction::Call {
+                dest: Some(dest), ..
+            }
+            | Instruction::Syscall { result: dest, .. }
+            | Instruction::ReadInt { dest } => (*dest, ValueKind::Int),
+            Instruction::Load { dest, .. } | Instruction::Pop { dest } => {
+                (*dest, ValueKind::Unknown)
+            }
+            Instruction::Branch {
+                condition,
+                true_label,
+                ..
+            } => {
+                if kinds.get(condition) == Some(&ValueKind::Int) {
+                    warnings.push(format!(
+                        "Branch to label_{} on {:?}, which holds an integer rather than a comparison",
+                        true_label.0, condition
+                    ));
+                }
+                continue;
+            }
+            _ => continue,
+        };
+
+        let kind = match kinds.get(&dest) {
+            Some(previous) => previous.join(kind),
+            None => kind,
+        };
+        kinds.insert(dest, kind);
+    }
+
+    warnings
+}
+
+// Record the depth on one path into `label`, checking it against any other
+// path seen so far
+fn join(
+    label_depths: &mut HashMap<LabelId, i64>,
+    label: LabelId,
+    incoming: i64,
+) -> Result<i64, CodegenError> {
+    match label_depths.get(&label) {
+        Some(&expected) if expected != incoming => Err(CodegenError {
+            message: format!(
+                "Unbalanced stack at label_{}: {} value(s) pushed on one path, {} on another",
+                label.0, expected, incoming
+            ),
+        }),
+        _ => {
+            label_depths.insert(label, incoming);
+            Ok(incoming)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{VReg, Value};
+
+    fn if_else(then_body: Vec<Instruction>, else_body: Vec<Instruction>) -> Vec<Instruction> {
+        let (then_label, else_label, end_label) = (LabelId(1), LabelId(2), LabelId(3));
+        let mut instructions = vec![
+            Instruction::Label(LabelId(0)),
+            Instruction::Copy {
+                dest: VReg(0),
+                src: Value::Immediate(1),
+            },
+            Instruction::Branch {
+                condition: VReg(0),
+                true_label: then_label,
+                false_label: else_label,
+            },
+            Instruction::Label(then_label),
+        ];
+        instructions.extend(then_body);
+        instructions.push(Instruction::Jump(end_label));
+        instructions.push(Instruction::Label(else_label));
+        instructions.extend(else_body);
+        instructions.push(Instruction::Label(end_label));
+        instructions.push(Instruction::Return { value: None });
+        instructions
+    }
+
+    #[test]
+    fn test_balanced_branches() {
+        let push_pop = || {
+            vec![
+                Instruction::Push { src: VReg(0) },
+                Instruction::Pop { dest: VReg(1) },
+            ]
+        };
+        assert!(verify_stack_balance(&if_else(push_pop(), vec![])).is_ok());
+        assert!(verify_stack_balance(&if_else(push_pop(), push_pop())).is_ok());
+    }
+
+    #[test]
+    fn test_unbalanced_branches() {
+        let instructions = if_else(vec![Instruction::Push { src: VReg(0) }], vec![]);
+        let error = verify_stack_balance(&instructions).unwrap_err();
+        assert!(error.message.contains("Unbalanced stack at label_3"));
+    }
+
+    #[test]
+    fn test_branch_on_comparison() {
+        let mut instructions = vec![
+            Instruction::Copy {
+                dest: VReg(1),
+                src: Value::Immediate(2),
+            },
+            Instruction::BinaryOp {
+                dest: VReg(0),
+                lhs: Value::VReg(VReg(1)),
+                rhs: Value::Immediate(3),
+                op: BinOp::Lt,
+            },
+        ];
+        instructions.extend(if_else(vec![], vec![]).into_iter().skip(2));
+        assert!(verify_branch_conditions(&instructions).is_empty());
+    }
+
+    #[test]
+    fn test_branch_on_arithmetic() {
+        let instructions = vec![
+            Instruction::Copy {
+                dest: VReg(0),
+                src: Value::Immediate(2),
+            },
+            Instruction::BinaryOp {
+                dest: VReg(1),
+                lhs: Value::VReg(VReg(0)),
+                rhs: Value::Immediate(3),
+                op: BinOp::Add,
+            },
+            // The kind follows copies
+            Instruction::Copy {
+                dest: VReg(2),
+                src: Value::VReg(VReg(1)),
+            },
+            Instruction::Branch {
+                condition: VReg(2),
+                true_label: LabelId(1),
+                false_label: LabelId(2),
+            },
+            // Nothing is known about a popped value
+            Instruction::Pop { dest: VReg(3) },
+            Instruction::Branch {
+                condition: VReg(3),
+                true_label: LabelId(1),
+                false_label: LabelId(2),
+            },
+        ];
+
+        let warnings = verify_branch_conditions(&instructions);
+        assert_eq!(
+            warnings,
+            vec!["Branch to label_1 on VReg(2), which holds an integer rather than a comparison"]
+        );
+    }
+
+    #[test]
+    fn test_unbalanced_loop() {
+        // A loop body that pushes on every iteration
+        let instructions = vec![
+            Instruction::Label(LabelId(0)),
+            Instruction::Label(LabelId(1)),
+            Instruction::Push { src: VReg(0) },
+            Instruction::Jump(LabelId(1)),
+        ];
+        assert!(verify_stack_balance(&instructions).is_err());
+    }
+}
@@ -0,0 +1,122 @@
+use crate::{CompileOptions, STDLIB_SOURCE};
+
+/// The only target rue compiles for
+pub const TARGET: &str = "x86_64-linux";
+
+/// A key for caching a compiled executable across runs of the compiler
+///
+/// The key is a hash of everything the executable depends on: the source,
+/// the target, the options, and the compiler itself. Compilation is
+/// deterministic, so the same key always means the same bytes. It's a hex
+/// string, so it can serve directly as a file name in a cache directory.
+pub fn cache_key(source: &str, options: &CompileOptions) -> String {
+    let CompileOptions {
+        overflow_checks,
+        stdlib,
+        opt_level,
+        entry,
+    } = options;
+
+    let mut hasher = Fnv1a::new();
+    hasher.write_field(env!("CARGO_PKG_VERSION").as_bytes());
+    hasher.write_field(TARGET.as_bytes());
+    hasher.write_field(source.as_bytes());
+    hasher.write_field(&[*overflow_checks as u8]);
+    hasher.write_field(if *stdlib {
+        STDLIB_SOURCE.as_bytes()
+    } else {
+        b""
+    });
+    hasher.write_field(&[*stdlib as u8]);
+    hasher.write_field(&[*opt_level]);
+    match entry {
+        Some(entry) => hasher.write_field(entry.as_bytes()),
+        None => hasher.write_field(b""),
+    }
+    format!("{:032x}", hasher.finish())
+}
+
+// 128-bit FNV-1a. Unlike std's hashers, its output is specified, so it's
+// the same across Rust versions and platforms.
+struct Fnv1a(u128);
+
+impl Fnv1a {
+    const OFFSET_BASIS: u128 = 0x6c62272e07bb014262b821756295c58d;
+    const PRIME: u128 = 0x0000000001000000000000000000013b;
+
+    fn new() -> Self {
+        Fnv1a(Self::OFFSET_BASIS)
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.0 ^= *byte as u128;
+            self.0 = self.0.wrapping_mul(Self::PRIME);
+        }
+    }
+
+    // Length-prefix each field, so that moving bytes from one to the next
+    // changes the hash
+    fn write_field(&mut self, bytes: &[u8]) {
+        self.write(&(bytes.len() as u64).to_le_bytes());
+        self.write(bytes);
+    }
+
+    fn finish(&self) -> u128 {
+        self.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{RueDatabase, SourceFile, compile_file_with_options};
+
+    fn compile(source: &str, options: &CompileOptions) -> Vec<u8> {
+        let db = RueDatabase::default();
+        let file = SourceFile::new(&db, "test.rue".to_string(), source.to_string());
+        compile_file_with_options(&db, file, options.clone())
+            .unwrap()
+            .to_vec()
+    }
+
+    #[test]
+    fn test_identical_inputs_have_identical_keys_and_bytes() {
+        let source = "fn main() { abs(0 - 42) }";
+        let options = CompileOptions::default();
+
+        assert_eq!(cache_key(source, &options), cache_key(source, &options));
+        assert_eq!(compile(source, &options), compile(source, &options));
+    }
+
+    #[test]
+    fn test_keys_depend_on_every_input() {
+        let source = "fn main() { 42 }";
+        let options = CompileOptions::default();
+        let key = cache_key(source, &options);
+        assert_eq!(key.len(), 32);
+
+        assert_ne!(key, cache_key("fn main() { 43 }", &options));
+        let variants = [
+            CompileOptions {
+                overflow_checks: true,
+                ..options.clone()
+            },
+            CompileOptions {
+                stdlib: false,
+                ..options.clone()
+            },
+            CompileOptions {
+                opt_level: 1,
+                ..options.clone()
+            },
+            CompileOptions {
+                entry: Some("main".to_string()),
+                ..options.clone()
+            },
+        ];
+        for variant in &variants {
+            assert_ne!(key, cache_key(source, variant), "{:?}", variant);
+        }
+    }
+}
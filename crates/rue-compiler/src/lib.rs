@@ -1,9 +1,22 @@
 use rue_ast::CstRoot;
-use rue_codegen::compile_to_executable;
+use rue_codegen::{
+    Assembler, Codegen, CodegenOptions, Instruction, IrOrder, OptStats, call_graph,
+    compile_to_executable_with_options, compile_to_machine_code_with_options, dump_call_graph,
+    dump_program_ir, interpret, recursion_warnings, render_listing, render_spills,
+};
+use rue_lexer::Span;
 use rue_parser::ParseError;
-use rue_semantic::{SemanticError, analyze_cst};
+use rue_semantic::{
+    SemanticError, SemanticWarning, analyze_cst, check_program, lint_cst, shadowing_warnings,
+};
+use std::collections::HashSet;
 use std::sync::Arc;
 
+mod cache;
+mod stdlib;
+pub use cache::{TARGET, cache_key};
+pub use stdlib::{STDLIB_SOURCE, with_stdlib};
+
 // Input structs
 #[salsa::input]
 pub struct SourceFile {
@@ -29,6 +42,16 @@ pub fn parse_file(
     }
 }
 
+/// Every syntax error in the file, as found by parsing with recovery, or
+/// none if it parses
+#[salsa::tracked]
+pub fn parse_errors(db: &dyn salsa::Database, file: SourceFile) -> Arc<Vec<CompileError>> {
+    let text = file.text(db);
+    let tokens = rue_lexer::Lexer::new(text.as_str()).tokenize();
+    let (_, errors) = rue_parser::parse_with_recovery(tokens);
+    Arc::new(errors.iter().map(parse_error).collect())
+}
+
 #[salsa::tracked]
 pub fn analyze_file(
     db: &dyn salsa::Database,
@@ -336,12 +359,470 @@ fn main() {
         println!("Executable length: {}", executable.len());
         assert!(executable.len() > 100); // Should be reasonable size
     }
+
+    #[test]
+    fn test_compile_uses_stdlib() {
+        let db = RueDatabase::default();
+
+        let file = SourceFile::new(
+            &db,
+            "test.rue".to_string(),
+            "fn main() { abs(0 - 5) }".to_string(),
+        );
+
+        assert!(compile_file(&db, file).is_ok());
+
+        let without_stdlib = compile_file_with_options(
+            &db,
+            file,
+            CompileOptions {
+                stdlib: false,
+                ..CompileOptions::default()
+            },
+        );
+        let error = without_stdlib.unwrap_err();
+        assert!(error.message.contains("Undefined function: abs"));
+    }
+
+    #[test]
+    fn test_stdlib_max() {
+        let db = RueDatabase::default();
+        let file = SourceFile::new(
+            &db,
+            "test.rue".to_string(),
+            "fn main() { max(3, 7) + max(35, 0 - 2) }".to_string(),
+        );
+        assert_eq!(interpret_file(&db, file, CompileOptions::default()), Ok(42));
+    }
+
+    #[test]
+    fn test_program_can_replace_stdlib_function() {
+        let ast = rue_parser::parse(
+            rue_lexer::Lexer::new("fn abs(x) { x } fn max(a, b) { a } fn main() { abs(1) }")
+                .tokenize(),
+        )
+        .unwrap();
+        let merged = with_stdlib(&ast);
+
+        // Only the program's own `abs` and `max` are kept
+        assert_eq!(merged.items.len(), ast.items.len());
+        assert_eq!(merged, ast);
+    }
+
+    #[test]
+    fn test_compile_with_overflow_checks() {
+        let db = RueDatabase::default();
+
+        let file = SourceFile::new(
+            &db,
+            "test.rue".to_string(),
+            "fn main() { 1 + 2 }".to_string(),
+        );
+
+        let unchecked = compile_file(&db, file).unwrap();
+        let checked = compile_file_with_options(
+            &db,
+            file,
+            CompileOptions {
+                overflow_checks: true,
+                ..CompileOptions::default()
+            },
+        )
+        .unwrap();
+
+        // The checked build adds `jno`/`ud2` pairs after arithmetic
+        let jno_ud2 = [0x71, 0x02, 0x0f, 0x0b];
+        assert!(checked.windows(4).any(|w| w == jno_ud2));
+        assert!(!unchecked.windows(4).any(|w| w == jno_ud2));
+        assert!(Arc::ptr_eq(
+            &unchecked,
+            &compile_file_with_options(&db, file, CompileOptions::default()).unwrap()
+        ));
+    }
+
+    #[test]
+    fn test_opt_level_drops_unused_stdlib_functions() {
+        let db = RueDatabase::default();
+
+        let file = SourceFile::new(&db, "test.rue".to_string(), "fn main() { 42 }".to_string());
+
+        let compile = |opt_level| {
+            compile_file_with_options(
+                &db,
+                file,
+                CompileOptions {
+                    opt_level,
+                    ..CompileOptions::default()
+                },
+            )
+            .unwrap()
+        };
+        let without_stdlib = compile_file_with_options(
+            &db,
+            file,
+            CompileOptions {
+                stdlib: false,
+                ..CompileOptions::default()
+            },
+        )
+        .unwrap();
+
+        // main never calls abs, so -O1 leaves it out entirely
+        assert!(compile(0).len() > without_stdlib.len());
+        assert_eq!(compile(1).len(), without_stdlib.len());
+    }
+
+    #[test]
+    fn test_factorial_listing() {
+        let db = RueDatabase::default();
+
+        let file = SourceFile::new(
+            &db,
+            "factorial.rue".to_string(),
+            r#"fn factorial(n) {
+    if n <= 1 {
+        1
+    } else {
+        n * factorial(n - 1)
+    }
+}
+
+fn main() {
+    factorial(5)
+}
+"#
+            .to_string(),
+        );
+
+        let listing = listing_file(
+            &db,
+            file,
+            CompileOptions {
+                stdlib: false,
+                ..CompileOptions::default()
+            },
+        )
+        .unwrap();
+
+        assert_eq!(
+            listing.as_str(),
+            r#"   | <no source>
+     label_999:
+         Call { dest: Some(VReg(0)), function: "main", args: [] }
+         Copy { dest: VReg(1), src: VReg(VReg(0)) }
+         Copy { dest: VReg(2), src: Immediate(60) }
+         Syscall { result: VReg(3), syscall_num: VReg(2), args: [VReg(1)] }
+         Trap
+ 9 | fn main() {
+     label_0:
+10 |     factorial(5)
+         Copy { dest: VReg(4), src: Immediate(5) }
+         Call { dest: Some(VReg(5)), function: "factorial", args: [VReg(4)] }
+11 | }
+         Return { value: Some(VReg(5)) }
+ 1 | fn factorial(n) {
+     label_1:
+         Copy { dest: VReg(6), src: PhysicalReg(Rdi) }
+ 2 |     if n <= 1 {
+         Copy { dest: VReg(9), src: VReg(VReg(6)) }
+         Copy { dest: VReg(10), src: Immediate(1) }
+         BinaryOp { dest: VReg(8), lhs: VReg(VReg(9)), rhs: VReg(VReg(10)), op: Le }
+         Branch { condition: VReg(8), true_label: LabelId(4), false_label: LabelId(2) }
+     label_4:
+ 3 |         1
+         Copy { dest: VReg(11), src: Immediate(1) }
+ 2 |     if n <= 1 {
+         Copy { dest: VReg(7), src: VReg(VReg(11)) }
+         Jump(LabelId(3))
+     label_2:
+ 4 |     } else {
+ 5 |         n * factorial(n - 1)
+         Copy { dest: VReg(13), src: VReg(VReg(6)) }
+         Push { src: VReg(13) }
+         Copy { dest: VReg(15), src: VReg(VReg(6)) }
+         Copy { dest: VReg(16), src: Immediate(1) }
+         BinaryOp { dest: VReg(14), lhs: VReg(VReg(15)), rhs: VReg(VReg(16)), op: Sub }
+         Call { dest: Some(VReg(17)), function: "factorial", args: [VReg(14)] }
+         Pop { dest: VReg(18) }
+         BinaryOp { dest: VReg(12), lhs: VReg(VReg(18)), rhs: VReg(VReg(17)), op: Mul }
+ 2 |     if n <= 1 {
+         Copy { dest: VReg(7), src: VReg(VReg(12)) }
+     label_3:
+ 6 |     }
+ 7 | }
+         Return { value: Some(VReg(7)) }
+"#
+        );
+    }
+
+    #[test]
+    fn test_interpret_file() {
+        let db = RueDatabase::default();
+        let file = SourceFile::new(
+            &db,
+            "test.rue".to_string(),
+            "fn main() { abs(0 - 40) + 2 }".to_string(),
+        );
+        assert_eq!(interpret_file(&db, file, CompileOptions::default()), Ok(42));
+
+        // Errors from any stage are reported the same way as for compiling
+        let file = SourceFile::new(&db, "test.rue".to_string(), "fn main() { x }".to_string());
+        let error = interpret_file(&db, file, CompileOptions::default()).unwrap_err();
+        assert!(
+            error.message.contains("Undefined variable"),
+            "{}",
+            error.message
+        );
+
+        // The stdlib's functions don't count towards the program's span
+        let file = SourceFile::new(&db, "test.rue".to_string(), "fn f() { 1 }".to_string());
+        let error = interpret_file(&db, file, CompileOptions::default()).unwrap_err();
+        assert_eq!(error.message, "Semantic error: No `main` function found");
+        assert_eq!(error.span, Some(rue_lexer::Span { start: 0, end: 12 }));
+    }
+
+    #[test]
+    fn test_ir_file() {
+        let db = RueDatabase::default();
+        let file = SourceFile::new(
+            &db,
+            "test.rue".to_string(),
+            include_str!("../../../samples/factorial.rue").to_string(),
+        );
+
+        // The stdlib is part of the program, so it's dumped too
+        let ir = ir_file(&db, file, CompileOptions::default(), IrOrder::Source).unwrap();
+        assert!(ir.starts_with("; _start\n"), "{}", ir);
+        assert!(ir.contains("; abs\n"));
+
+        let ir = ir_file(&db, file, CompileOptions::default(), IrOrder::CallGraph).unwrap();
+        let position = |heading: &str| ir.find(heading).unwrap();
+        assert!(position("; factorial\n") < position("; main\n"));
+    }
+
+    #[test]
+    fn test_call_graph_file() {
+        let db = RueDatabase::default();
+        let file = SourceFile::new(
+            &db,
+            "test.rue".to_string(),
+            include_str!("../../../samples/factorial.rue").to_string(),
+        );
+
+        let dot = call_graph_file(&db, file, CompileOptions::default()).unwrap();
+        assert!(dot.starts_with("digraph calls {\n"), "{}", dot);
+        assert!(dot.contains("    factorial -> factorial;\n"), "{}", dot);
+        assert!(dot.contains("    main -> factorial;\n"), "{}", dot);
+        assert!(dot.contains("    abs;\n"), "{}", dot);
+
+        let file = SourceFile::new(&db, "test.rue".to_string(), "fn main() { f() }".to_string());
+        assert!(call_graph_file(&db, file, CompileOptions::default()).is_err());
+    }
+
+    #[test]
+    fn test_regalloc_spills_file() {
+        let db = RueDatabase::default();
+        let options = CompileOptions {
+            stdlib: false,
+            ..CompileOptions::default()
+        };
+
+        // Few enough values that nothing is spilled
+        let file = SourceFile::new(&db, "test.rue".to_string(), "fn main() { 42 }".to_string());
+        let spills = regalloc_spills_file(&db, file, options.clone()).unwrap();
+        assert_eq!(spills.as_str(), "");
+
+        // More variables are live at once than there are registers
+        let file = SourceFile::new(
+            &db,
+            "test.rue".to_string(),
+            "fn main() { let a = 1; let b = 2; let c = 3; let d = 4; let e = 5; let f = 6; \
+             a + b + c + d + e + f }"
+                .to_string(),
+        );
+        let spills = regalloc_spills_file(&db, file, options).unwrap();
+        assert!(spills.starts_with("spill VReg("), "{}", spills);
+    }
+
+    #[test]
+    fn test_opt_report_file() {
+        let db = RueDatabase::default();
+        let source = "fn unused(x) { x + 1 } fn main() { let x = 2 * 3; x + 4 }";
+        let file = SourceFile::new(&db, "test.rue".to_string(), source.to_string());
+        let options = CompileOptions {
+            stdlib: false,
+            opt_level: 1,
+            ..CompileOptions::default()
+        };
+
+        // `2 * 3` and `x + 4`, where `x` is propagated
+        let stats = opt_report_file(&db, file, options.clone()).unwrap();
+        assert_eq!(
+            stats,
+            OptStats {
+                constant_folds: 2,
+                bindings_propagated: 1,
+                functions_pruned: 1,
+            }
+        );
+
+        let unoptimized = CompileOptions {
+            opt_level: 0,
+            ..options
+        };
+        let stats = opt_report_file(&db, file, unoptimized).unwrap();
+        assert_eq!(stats, OptStats::default());
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let db = RueDatabase::default();
+        let file = SourceFile::new(
+            &db,
+            "test.rue".to_string(),
+            "fn main() { 1 + }\nfn f() { let = 2; 0 }".to_string(),
+        );
+        let errors = parse_errors(&db, file);
+        assert_eq!(errors.len(), 2);
+        assert_eq!(
+            errors[0].message,
+            "Parse error: Unexpected token: RightBrace"
+        );
+
+        // Semantic problems aren't parse errors
+        let file = SourceFile::new(&db, "test.rue".to_string(), "fn main() { x }".to_string());
+        assert!(parse_errors(&db, file).is_empty());
+    }
+
+    #[test]
+    fn test_lint_file() {
+        let db = RueDatabase::default();
+        let file = SourceFile::new(
+            &db,
+            "test.rue".to_string(),
+            "fn main() { let x = 1; x == x }".to_string(),
+        );
+        let warnings = lint_file(&db, file);
+        assert_eq!(warnings.len(), 1);
+        assert_eq!(warnings[0].message, "comparison is always true");
+
+        // Warnings don't stop compilation
+        assert!(compile_file(&db, file).is_ok());
+
+        let file = SourceFile::new(&db, "test.rue".to_string(), "fn main( {".to_string());
+        assert!(lint_file(&db, file).is_empty());
+
+        // Including the call graph's warnings, in source order
+        let file = SourceFile::new(
+            &db,
+            "test.rue".to_string(),
+            "fn f(n) { if n == n { 0 } else { 1 + f(n - 1) } }".to_string(),
+        );
+        let warnings = lint_file(&db, file);
+        assert_eq!(warnings.len(), 2);
+        assert_eq!(warnings[0].message, "comparison is always true");
+        assert!(warnings[1].message.starts_with("recursive call to f"));
+    }
+
+    #[test]
+    fn test_shadowing_file() {
+        let db = RueDatabase::default();
+        let file = SourceFile::new(
+            &db,
+            "test.rue".to_string(),
+            "fn main(argc) { let argc = 2; argc }".to_string(),
+        );
+        let warnings = shadowing_file(&db, file);
+        assert_eq!(warnings.len(), 1);
+        assert_eq!(warnings[0].message, "`argc` shadows an existing binding");
+
+        // They're separate from the other warnings
+        assert!(lint_file(&db, file).is_empty());
+        assert!(compile_file(&db, file).is_ok());
+    }
+
+    #[test]
+    fn test_compile_file_to_binary() {
+        let db = RueDatabase::default();
+        let file = SourceFile::new(&db, "test.rue".to_string(), "fn main() { 42 }".to_string());
+
+        // The same code as in the executable, after its 0x78 bytes of headers
+        let binary = compile_file_to_binary(&db, file, CompileOptions::default()).unwrap();
+        let executable = compile_file(&db, file).unwrap();
+        assert_eq!(&binary[..], &executable[0x78..]);
+    }
+
+    #[test]
+    fn test_listing_has_no_source_for_stdlib() {
+        let db = RueDatabase::default();
+
+        let file = SourceFile::new(
+            &db,
+            "test.rue".to_string(),
+            "fn main() {\n    abs(0 - 3)\n}\n".to_string(),
+        );
+
+        let listing = listing_file(&db, file, CompileOptions::default()).unwrap();
+        // abs comes from the stdlib, so its code is listed without source
+        // lines from this file
+        let abs = listing.rfind("<no source>").unwrap();
+        assert!(listing[abs..].contains("op: Lt"));
+        assert!(!listing[abs..].contains(" | fn main"));
+    }
 }
 
 // Simplified compilation error for Salsa
 #[derive(Debug, Clone, PartialEq)]
 pub struct CompileError {
     pub message: String,
+    pub span: Option<Span>,
+    /// A related location to point out alongside `span`
+    pub related: Option<(String, Span)>,
+}
+
+/// Options that affect the generated executable
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct CompileOptions {
+    /// Trap on arithmetic overflow instead of wrapping
+    pub overflow_checks: bool,
+    /// Include the rue-defined standard library
+    pub stdlib: bool,
+    /// Optimization level, as in `-O1`
+    pub opt_level: u8,
+    /// Function to use as the entry point instead of `main`
+    pub entry: Option<String>,
+}
+
+impl Default for CompileOptions {
+    fn default() -> Self {
+        Self {
+            overflow_checks: false,
+            stdlib: true,
+            opt_level: 0,
+            entry: None,
+        }
+    }
+}
+
+impl CompileOptions {
+    // A code generator for inspecting the IR, set up like the one that
+    // produces the executable
+    fn codegen(&self) -> Codegen {
+        let mut codegen = Codegen::new();
+        codegen.set_opt_level(self.opt_level);
+        if let Some(entry) = &self.entry {
+            codegen.set_entry(entry);
+        }
+        codegen
+    }
+
+    fn codegen_options(&self) -> CodegenOptions {
+        CodegenOptions {
+            overflow_checks: self.overflow_checks,
+            opt_level: self.opt_level,
+            entry: self.entry.clone(),
+        }
+    }
 }
 
 #[salsa::tracked]
@@ -349,28 +830,242 @@ pub fn compile_file(
     db: &dyn salsa::Database,
     file: SourceFile,
 ) -> Result<Arc<Vec<u8>>, Arc<CompileError>> {
-    // Parse and analyze the file first
-    let scope = match analyze_file(db, file) {
-        Ok(scope) => scope,
-        Err(semantic_error) => {
-            return Err(Arc::new(CompileError {
-                message: format!("Semantic error: {}", semantic_error.message),
-            }));
+    compile_file_with_options(db, file, CompileOptions::default())
+}
+
+#[salsa::tracked]
+pub fn compile_file_with_options(
+    db: &dyn salsa::Database,
+    file: SourceFile,
+    options: CompileOptions,
+) -> Result<Arc<Vec<u8>>, Arc<CompileError>> {
+    let (ast, scope) = analyze_program(db, file, &options)?;
+
+    // Generate executable
+    match compile_to_executable_with_options(&ast, &scope, &options.codegen_options()) {
+        Ok(executable) => Ok(Arc::new(executable)),
+        Err(e) => Err(codegen_error(e)),
+    }
+}
+
+/// Warnings about likely mistakes in the file, not counting the stdlib, in
+/// source order. These include recursive calls that could overflow the
+/// stack. A file that doesn't parse has none.
+#[salsa::tracked]
+pub fn lint_file(db: &dyn salsa::Database, file: SourceFile) -> Arc<Vec<SemanticWarning>> {
+    match parse_file(db, file) {
+        Ok(ast) => {
+            let mut warnings = lint_cst(&ast);
+            warnings.extend(recursion_warnings(&ast));
+            warnings.sort_by_key(|warning| warning.span.start);
+            Arc::new(warnings)
         }
+        Err(_) => Arc::new(Vec::new()),
+    }
+}
+
+/// Warnings for `let`s in the file that shadow a binding still in scope. Like
+/// `lint_file`, a file that doesn't parse has none, but these are only
+/// reported when asked for.
+#[salsa::tracked]
+pub fn shadowing_file(db: &dyn salsa::Database, file: SourceFile) -> Arc<Vec<SemanticWarning>> {
+    match parse_file(db, file) {
+        Ok(ast) => Arc::new(shadowing_warnings(&ast)),
+        Err(_) => Arc::new(Vec::new()),
+    }
+}
+
+/// The file's bare machine code, without an ELF wrapper, with `_start` at
+/// offset 0
+#[salsa::tracked]
+pub fn compile_file_to_binary(
+    db: &dyn salsa::Database,
+    file: SourceFile,
+    options: CompileOptions,
+) -> Result<Arc<Vec<u8>>, Arc<CompileError>> {
+    let (ast, scope) = analyze_program(db, file, &options)?;
+
+    match compile_to_machine_code_with_options(&ast, &scope, &options.codegen_options()) {
+        Ok(machine_code) => Ok(Arc::new(machine_code)),
+        Err(e) => Err(codegen_error(e)),
+    }
+}
+
+/// The file's source lines, each followed by the IR generated for it
+#[salsa::tracked]
+pub fn listing_file(
+    db: &dyn salsa::Database,
+    file: SourceFile,
+    options: CompileOptions,
+) -> Result<Arc<String>, Arc<CompileError>> {
+    let (ast, scope) = analyze_program(db, file, &options)?;
+
+    let mut codegen = options.codegen();
+    let instructions = codegen.generate(&ast, &scope).map_err(codegen_error)?;
+
+    // Spans in stdlib functions point into the stdlib's source rather than
+    // this file, so list that code without source lines
+    let program_functions: HashSet<String> = match parse_file(db, file) {
+        Ok(program) => program
+            .items
+            .iter()
+            .filter_map(stdlib::function_name)
+            .map(str::to_string)
+            .collect(),
+        Err(_) => HashSet::new(),
     };
+    let function_labels = codegen.function_labels();
+    let mut spans = codegen.spans().to_vec();
+    let mut in_stdlib = false;
+    for (instr, span) in instructions.iter().zip(&mut spans) {
+        if let Instruction::Label(label) = instr
+            && let Some((name, _)) = function_labels.iter().find(|(_, l)| *l == label)
+        {
+            in_stdlib = !program_functions.contains(name);
+        }
+        if in_stdlib {
+            *span = None;
+        }
+    }
 
+    Ok(Arc::new(render_listing(
+        file.text(db).as_str(),
+        &instructions,
+        &spans,
+    )))
+}
+
+/// The IR for the file's whole program, one function at a time in `order`
+#[salsa::tracked]
+pub fn ir_file(
+    db: &dyn salsa::Database,
+    file: SourceFile,
+    options: CompileOptions,
+    order: IrOrder,
+) -> Result<Arc<String>, Arc<CompileError>> {
+    let (ast, scope) = analyze_program(db, file, &options)?;
+
+    let mut codegen = options.codegen();
+    let instructions = codegen.generate(&ast, &scope).map_err(codegen_error)?;
+    Ok(Arc::new(dump_program_ir(
+        &ast,
+        &instructions,
+        codegen.function_labels(),
+        order,
+    )))
+}
+
+/// The file's program's call graph, including the stdlib when it's part of
+/// the program, in Graphviz's DOT format
+#[salsa::tracked]
+pub fn call_graph_file(
+    db: &dyn salsa::Database,
+    file: SourceFile,
+    options: CompileOptions,
+) -> Result<Arc<String>, Arc<CompileError>> {
+    let (ast, _) = analyze_program(db, file, &options)?;
+    Ok(Arc::new(dump_call_graph(&call_graph(&ast))))
+}
+
+/// A log of the register allocator's spills for the file's program, one per
+/// line, with the IR instruction each happened at
+#[salsa::tracked]
+pub fn regalloc_spills_file(
+    db: &dyn salsa::Database,
+    file: SourceFile,
+    options: CompileOptions,
+) -> Result<Arc<String>, Arc<CompileError>> {
+    let (ast, scope) = analyze_program(db, file, &options)?;
+
+    let mut codegen = options.codegen();
+    let instructions = codegen.generate(&ast, &scope).map_err(codegen_error)?;
+    let mut assembler = Assembler::new();
+    for (name, label) in codegen.function_labels() {
+        assembler.add_function_mapping(name.clone(), *label);
+    }
+    assembler
+        .assemble(instructions.clone())
+        .map_err(codegen_error)?;
+
+    Ok(Arc::new(render_spills(&instructions, assembler.spills())))
+}
+
+/// What the optimizer did to the file's program, at the optimization level
+/// in `options`. At `-O0` nothing is optimized, so every count is 0.
+#[salsa::tracked]
+pub fn opt_report_file(
+    db: &dyn salsa::Database,
+    file: SourceFile,
+    options: CompileOptions,
+) -> Result<OptStats, Arc<CompileError>> {
+    let (ast, scope) = analyze_program(db, file, &options)?;
+
+    let mut codegen = options.codegen();
+    codegen.generate(&ast, &scope).map_err(codegen_error)?;
+    Ok(codegen.opt_stats())
+}
+
+/// Run the file's program in the IR interpreter, without compiling it to an
+/// executable, and return its exit code
+#[salsa::tracked]
+pub fn interpret_file(
+    db: &dyn salsa::Database,
+    file: SourceFile,
+    options: CompileOptions,
+) -> Result<i64, Arc<CompileError>> {
+    let (ast, scope) = analyze_program(db, file, &options)?;
+
+    let mut codegen = options.codegen();
+    let instructions = codegen.generate(&ast, &scope).map_err(codegen_error)?;
+    interpret(&instructions, codegen.function_labels()).map_err(codegen_error)
+}
+
+// Parse and analyze a file along with the stdlib, if it's enabled
+fn analyze_program(
+    db: &dyn salsa::Database,
+    file: SourceFile,
+    options: &CompileOptions,
+) -> Result<(Arc<CstRoot>, rue_semantic::Scope), Arc<CompileError>> {
     let ast = match parse_file(db, file) {
         Ok(ast) => ast,
-        Err(parse_error) => {
-            return Err(Arc::new(CompileError {
-                message: format!("Parse error: {}", parse_error.message),
-            }));
-        }
+        Err(error) => return Err(Arc::new(parse_error(&error))),
     };
 
-    // Generate executable
-    match compile_to_executable(&ast, &scope) {
-        Ok(executable) => Ok(Arc::new(executable)),
-        Err(e) => Err(Arc::new(CompileError { message: e.message })),
+    // The stdlib has to be analyzed along with the program, since the
+    // program may call into it. The entry function has to be in the
+    // program itself, though.
+    let program = if options.stdlib {
+        Arc::new(with_stdlib(&ast))
+    } else {
+        ast.clone()
+    };
+    let entry = options.entry.as_deref().unwrap_or("main");
+
+    match analyze_cst(&program).and_then(|scope| {
+        check_program(&ast, entry)?;
+        Ok(scope)
+    }) {
+        Ok(scope) => Ok((program, scope)),
+        Err(semantic_error) => Err(Arc::new(CompileError {
+            message: format!("Semantic error: {}", semantic_error.message),
+            span: Some(semantic_error.span),
+            related: None,
+        })),
+    }
+}
+
+fn parse_error(error: &ParseError) -> CompileError {
+    CompileError {
+        message: format!("Parse error: {}", error.message),
+        span: Some(error.span),
+        related: error.related.clone(),
     }
 }
+
+fn codegen_error(error: rue_codegen::CodegenError) -> Arc<CompileError> {
+    Arc::new(CompileError {
+        message: error.message,
+        span: None,
+        related: None,
+    })
+}
@@ -0,0 +1,37 @@
+use rue_ast::{CstNode, CstRoot};
+use rue_lexer::TokenKind;
+use std::collections::HashSet;
+
+/// Rue source for the standard library, compiled into every program unless
+/// `CompileOptions::stdlib` is turned off
+pub const STDLIB_SOURCE: &str = include_str!("stdlib.rue");
+
+/// Prepend the standard library's functions to a program. A function the
+/// program defines itself takes the place of the stdlib one of the same name.
+pub fn with_stdlib(ast: &CstRoot) -> CstRoot {
+    let stdlib = rue_parser::parse(rue_lexer::Lexer::new(STDLIB_SOURCE).tokenize())
+        .expect("stdlib should parse");
+
+    let defined: HashSet<&str> = ast.items.iter().filter_map(function_name).collect();
+    let mut items: Vec<CstNode> = stdlib
+        .items
+        .into_iter()
+        .filter(|item| !function_name(item).is_some_and(|name| defined.contains(name)))
+        .collect();
+    items.extend(ast.items.iter().cloned());
+
+    CstRoot {
+        items,
+        trivia: ast.trivia.clone(),
+    }
+}
+
+pub(crate) fn function_name(item: &CstNode) -> Option<&str> {
+    match item {
+        CstNode::Function(func) => match &func.name.kind {
+            TokenKind::Ident(name) => Some(name),
+            _ => None,
+        },
+        _ => None,
+    }
+}
@@ -1,45 +1,88 @@
-#[derive(Debug, Clone, PartialEq, Eq)]
+mod source_map;
+pub use source_map::{LineCol, SourceMap};
+
+#[derive(Debug, Clone, PartialEq)]
 pub enum TokenKind {
     // Literals
     Integer(i64),
+    StringLit(String), // The unescaped value
+    Char(char),
+    Float(f64),
 
     // Keywords
     Fn,
     Let,
+    Const,
     If,
     Else,
     While,
+    Return,
+    For,
+    In,
+    Loop,
+    Break,
+    Continue,
+    True,
+    False,
 
     // Identifiers
     Ident(String),
 
     // Operators
     Plus,
+    /// `-`, both binary subtraction and unary negation: the lexer never
+    /// folds a minus sign into a number, so `-5` is `Minus` then
+    /// `Integer(5)`, and telling the two uses apart is the parser's job
     Minus,
     Star,
+    /// `**`, raising to a power
+    StarStar,
     Slash,
     Percent,
     Assign,
+    PlusAssign,
+    MinusAssign,
+    StarAssign,
+    SlashAssign,
+    PercentAssign,
     Less,
     LessEqual,
     Greater,
     GreaterEqual,
     Equal,
     NotEqual,
+    /// `!`, logical not
+    Bang,
+    AmpAmp,
+    PipePipe,
 
     // Delimiters
     LeftParen,
     RightParen,
     LeftBrace,
     RightBrace,
+    LeftBracket,
+    RightBracket,
     Semicolon,
     Comma,
+    Colon,
+    Dot,
+    /// `..`, the range in a `for` loop
+    DotDot,
+    Arrow,
+
+    // Trivia, only produced by `Lexer::tokenize_with_trivia`
+    Whitespace(String),
+    /// A `//` comment, up to the end of its line, with the slashes
+    Comment(String),
 
     // Special
+    /// Input the lexer couldn't tokenize, with a message describing why
+    Error(String),
     Eof,
 }
 
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct Token {
     pub kind: TokenKind,
     pub span: Span,
@@ -51,146 +94,211 @@ pub struct Span {
     pub end: usize,
 }
 
+impl Span {
+    /// 1-based line and 0-based char column of the start of this span
+    ///
+    /// This scans `source` on every call; build a `SourceMap` when converting
+    /// many offsets in the same file.
+    pub fn line_col(&self, source: &str) -> (u32, u32) {
+        let start = self.start.min(source.len());
+        let before = &source[..start];
+        let line = before.matches('\n').count() + 1;
+        let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+        let column = before[line_start..].chars().count();
+        (line as u32, column as u32)
+    }
+}
+
+impl TokenKind {
+    /// Whether this token is trivia, which carries no meaning for the parser
+    pub fn is_trivia(&self) -> bool {
+        matches!(self, TokenKind::Whitespace(_) | TokenKind::Comment(_))
+    }
+
+    /// Source text for this token
+    ///
+    /// Literals are written in their plainest form, so this isn't always
+    /// the text the token came from: `0x2a` comes back as `42`, and a
+    /// string's escapes are redone with Rust's rules. Errors and the end of
+    /// input have no text.
+    pub fn text(&self) -> String {
+        let text = match self {
+            TokenKind::Integer(value) => return value.to_string(),
+            TokenKind::StringLit(value) => return format!("{:?}", value),
+            TokenKind::Char(value) => return format!("{:?}", value),
+            TokenKind::Float(value) => return format!("{:?}", value),
+            TokenKind::Ident(name) => return name.clone(),
+            TokenKind::Whitespace(text) | TokenKind::Comment(text) => return text.clone(),
+            TokenKind::Fn => "fn",
+            TokenKind::Let => "let",
+            TokenKind::Const => "const",
+            TokenKind::If => "if",
+            TokenKind::Else => "else",
+            TokenKind::While => "while",
+            TokenKind::Return => "return",
+            TokenKind::For => "for",
+            TokenKind::In => "in",
+            TokenKind::Loop => "loop",
+            TokenKind::Break => "break",
+            TokenKind::Continue => "continue",
+            TokenKind::True => "true",
+            TokenKind::False => "false",
+            TokenKind::Plus => "+",
+            TokenKind::Minus => "-",
+            TokenKind::Star => "*",
+            TokenKind::StarStar => "**",
+            TokenKind::Slash => "/",
+            TokenKind::Percent => "%",
+            TokenKind::Assign => "=",
+            TokenKind::PlusAssign => "+=",
+            TokenKind::MinusAssign => "-=",
+            TokenKind::StarAssign => "*=",
+            TokenKind::SlashAssign => "/=",
+            TokenKind::PercentAssign => "%=",
+            TokenKind::Less => "<",
+            TokenKind::LessEqual => "<=",
+            TokenKind::Greater => ">",
+            TokenKind::GreaterEqual => ">=",
+            TokenKind::Equal => "==",
+            TokenKind::NotEqual => "!=",
+            TokenKind::Bang => "!",
+            TokenKind::AmpAmp => "&&",
+            TokenKind::PipePipe => "||",
+            TokenKind::LeftParen => "(",
+            TokenKind::RightParen => ")",
+            TokenKind::LeftBrace => "{",
+            TokenKind::RightBrace => "}",
+            TokenKind::LeftBracket => "[",
+            TokenKind::RightBracket => "]",
+            TokenKind::Semicolon => ";",
+            TokenKind::Comma => ",",
+            TokenKind::Colon => ":",
+            TokenKind::Dot => ".",
+            TokenKind::DotDot => "..",
+            TokenKind::Arrow => "->",
+            TokenKind::Error(_) | TokenKind::Eof => "",
+        };
+        text.to_string()
+    }
+}
+
+/// Lexer over a source string
+///
+/// The lexer is an iterator that produces tokens lazily, ending with a single
+/// `Eof` token. `tokenize` collects them all at once.
 pub struct Lexer<'a> {
     input: &'a str,
     position: usize,
+    emitted_eof: bool,
+    /// Produce trivia tokens instead of skipping them
+    trivia: bool,
 }
 
 impl<'a> Lexer<'a> {
     pub fn new(input: &'a str) -> Self {
-        Self { input, position: 0 }
+        Self {
+            input,
+            position: 0,
+            emitted_eof: false,
+            trivia: false,
+        }
     }
 
     pub fn tokenize(&mut self) -> Vec<Token> {
-        let mut tokens = Vec::new();
-
-        while !self.is_at_end() {
-            self.skip_whitespace();
-            if !self.is_at_end() {
-                tokens.push(self.next_token());
-            }
-        }
-
-        tokens.push(Token {
-            kind: TokenKind::Eof,
-            span: Span {
-                start: self.position,
-                end: self.position,
-            },
-        });
+        self.collect()
+    }
 
-        tokens
+    /// Like `tokenize`, but whitespace and comments are kept as `Whitespace`
+    /// and `Comment` tokens, so the tokens' text concatenates back to the
+    /// original source
+    pub fn tokenize_with_trivia(&mut self) -> Vec<Token> {
+        self.trivia = true;
+        self.collect()
     }
 
     fn next_token(&mut self) -> Token {
         let start = self.position;
 
         match self.current_char() {
-            '+' => self.make_token(TokenKind::Plus, start),
-            '-' => self.make_token(TokenKind::Minus, start),
-            '*' => self.make_token(TokenKind::Star, start),
-            '/' => self.make_token(TokenKind::Slash, start),
-            '%' => self.make_token(TokenKind::Percent, start),
+            '+' => self.match_two_char('=', TokenKind::PlusAssign, TokenKind::Plus),
+            '-' if self.peek_char() == '>' => {
+                self.advance();
+                self.make_token(TokenKind::Arrow, start)
+            }
+            '-' => self.match_two_char('=', TokenKind::MinusAssign, TokenKind::Minus),
+            '*' if self.peek_char() == '*' => {
+                self.advance();
+                self.make_token(TokenKind::StarStar, start)
+            }
+            '*' => self.match_two_char('=', TokenKind::StarAssign, TokenKind::Star),
+            '/' => self.match_two_char('=', TokenKind::SlashAssign, TokenKind::Slash),
+            '%' => self.match_two_char('=', TokenKind::PercentAssign, TokenKind::Percent),
             '(' => self.make_token(TokenKind::LeftParen, start),
             ')' => self.make_token(TokenKind::RightParen, start),
             '{' => self.make_token(TokenKind::LeftBrace, start),
             '}' => self.make_token(TokenKind::RightBrace, start),
+            '[' => self.make_token(TokenKind::LeftBracket, start),
+            ']' => self.make_token(TokenKind::RightBracket, start),
             ';' => self.make_token(TokenKind::Semicolon, start),
             ',' => self.make_token(TokenKind::Comma, start),
-            '=' => {
-                self.advance();
-                if self.current_char() == '=' {
-                    self.advance();
-                    Token {
-                        kind: TokenKind::Equal,
-                        span: Span {
-                            start,
-                            end: self.position,
-                        },
-                    }
-                } else {
-                    Token {
-                        kind: TokenKind::Assign,
-                        span: Span {
-                            start,
-                            end: self.position,
-                        },
-                    }
-                }
-            }
-            '<' => {
-                self.advance();
-                if self.current_char() == '=' {
-                    self.advance();
-                    Token {
-                        kind: TokenKind::LessEqual,
-                        span: Span {
-                            start,
-                            end: self.position,
-                        },
-                    }
-                } else {
-                    Token {
-                        kind: TokenKind::Less,
-                        span: Span {
-                            start,
-                            end: self.position,
-                        },
-                    }
-                }
-            }
-            '>' => {
+            ':' => self.make_token(TokenKind::Colon, start),
+            '.' if self.peek_char() == '.' => {
                 self.advance();
-                if self.current_char() == '=' {
-                    self.advance();
-                    Token {
-                        kind: TokenKind::GreaterEqual,
-                        span: Span {
-                            start,
-                            end: self.position,
-                        },
-                    }
-                } else {
-                    Token {
-                        kind: TokenKind::Greater,
-                        span: Span {
-                            start,
-                            end: self.position,
-                        },
-                    }
-                }
+                self.make_token(TokenKind::DotDot, start)
             }
-            '!' => {
+            '.' => self.make_token(TokenKind::Dot, start),
+            '=' => self.match_two_char('=', TokenKind::Equal, TokenKind::Assign),
+            '<' => self.match_two_char('=', TokenKind::LessEqual, TokenKind::Less),
+            '>' => self.match_two_char('=', TokenKind::GreaterEqual, TokenKind::Greater),
+            '!' => self.match_two_char('=', TokenKind::NotEqual, TokenKind::Bang),
+            '&' => self.lex_pair('&', TokenKind::AmpAmp),
+            '|' => self.lex_pair('|', TokenKind::PipePipe),
+            '"' => self.lex_string(start),
+            '\'' => self.lex_char(start),
+            '0' if self.peek_char() == 'x' => self.lex_hex_number(start),
+            '0'..='9' => self.lex_number(start),
+            'a'..='z' | 'A'..='Z' | '_' => self.lex_ident_or_keyword(start),
+            c => {
                 self.advance();
-                if self.current_char() == '=' {
-                    self.advance();
-                    Token {
-                        kind: TokenKind::NotEqual,
-                        span: Span {
-                            start,
-                            end: self.position,
-                        },
-                    }
-                } else {
-                    panic!("Unexpected character '!' at position {}", start);
+                Token {
+                    kind: TokenKind::Error(format!("Unexpected character '{}'", c)),
+                    span: Span {
+                        start,
+                        end: self.position,
+                    },
                 }
             }
-            '0'..='9' => self.lex_number(start),
-            'a'..='z' | 'A'..='Z' | '_' => self.lex_ident_or_keyword(start),
-            c => panic!("Unexpected character '{}' at position {}", c, start),
         }
     }
 
     fn lex_number(&mut self, start: usize) -> Token {
-        while self.current_char().is_ascii_digit() {
+        self.skip_digits();
+
+        // A `.` is only part of the number if a digit follows it, so `3.`
+        // lexes as `3` followed by a `.`. Extra fractional parts are
+        // consumed so that `1.2.3` is reported as a single error.
+        let mut dots = 0;
+        while self.current_char() == '.' && self.peek_char().is_ascii_digit() {
             self.advance();
+            self.skip_digits();
+            dots += 1;
         }
 
         let text = &self.input[start..self.position];
-        let value = text.parse::<i64>().expect("Invalid number");
+        let kind = match dots {
+            // Only digits were consumed, so parsing can only fail on overflow
+            0 => match text.parse::<i64>() {
+                Ok(value) => TokenKind::Integer(value),
+                Err(_) => {
+                    TokenKind::Error(format!("Integer literal '{}' is too large for i64", text))
+                }
+            },
+            1 => TokenKind::Float(text.parse::<f64>().expect("Invalid float")),
+            _ => TokenKind::Error(format!("Invalid number literal '{}'", text)),
+        };
 
         Token {
-            kind: TokenKind::Integer(value),
+            kind,
             span: Span {
                 start,
                 end: self.position,
@@ -198,6 +306,148 @@ impl<'a> Lexer<'a> {
         }
     }
 
+    // Lex a hex literal like `0xff`. Digits may be either case. The whole
+    // alphanumeric run is consumed, so `0xfg` is one bad literal rather than
+    // `0xf` followed by `g`, and the error points at the first bad digit.
+    fn lex_hex_number(&mut self, start: usize) -> Token {
+        self.advance(); // 0
+        self.advance(); // x
+        let digits_start = self.position;
+
+        let mut invalid = None;
+        while self.current_char().is_alphanumeric() || self.current_char() == '_' {
+            let c = self.current_char();
+            if !c.is_ascii_hexdigit() && invalid.is_none() {
+                invalid = Some((c, self.position));
+            }
+            self.advance();
+        }
+
+        let text = &self.input[start..self.position];
+        let (kind, span) = match invalid {
+            Some((c, at)) => (
+                TokenKind::Error(format!("Invalid digit '{}' in hex literal '{}'", c, text)),
+                Span {
+                    start: at,
+                    end: at + c.len_utf8(),
+                },
+            ),
+            None => {
+                let kind = match i64::from_str_radix(&self.input[digits_start..self.position], 16) {
+                    Ok(value) => TokenKind::Integer(value),
+                    Err(_) if digits_start == self.position => {
+                        TokenKind::Error("Hex literal '0x' has no digits".to_string())
+                    }
+                    Err(_) => {
+                        TokenKind::Error(format!("Integer literal '{}' is too large for i64", text))
+                    }
+                };
+                (
+                    kind,
+                    Span {
+                        start,
+                        end: self.position,
+                    },
+                )
+            }
+        };
+
+        Token { kind, span }
+    }
+
+    fn lex_string(&mut self, start: usize) -> Token {
+        let kind = match self.lex_quoted('"', "string") {
+            Ok(value) => TokenKind::StringLit(value),
+            Err(message) => TokenKind::Error(message),
+        };
+
+        Token {
+            kind,
+            span: Span {
+                start,
+                end: self.position,
+            },
+        }
+    }
+
+    fn lex_char(&mut self, start: usize) -> Token {
+        let kind = match self.lex_quoted('\'', "character") {
+            Ok(value) => {
+                let mut chars = value.chars();
+                match (chars.next(), chars.next()) {
+                    (Some(c), None) => TokenKind::Char(c),
+                    (None, _) => TokenKind::Error("Empty character literal".to_string()),
+                    (Some(_), Some(_)) => TokenKind::Error(format!(
+                        "Character literal '{}' must contain exactly one character",
+                        value
+                    )),
+                }
+            }
+            Err(message) => TokenKind::Error(message),
+        };
+
+        Token {
+            kind,
+            span: Span {
+                start,
+                end: self.position,
+            },
+        }
+    }
+
+    // Lex the contents of a string or character literal up to and including
+    // the closing `quote`, returning the unescaped value. `what` names the
+    // kind of literal for error messages.
+    fn lex_quoted(&mut self, quote: char, what: &str) -> Result<String, String> {
+        self.advance(); // opening quote
+        let mut value = String::new();
+
+        loop {
+            if self.is_at_end() {
+                return Err(format!("Unterminated {} literal", what));
+            }
+
+            match self.current_char() {
+                c if c == quote => {
+                    self.advance();
+                    return Ok(value);
+                }
+                '\\' => {
+                    self.advance();
+                    let escaped = match self.current_char() {
+                        'n' => '\n',
+                        't' => '\t',
+                        '\\' => '\\',
+                        '"' => '"',
+                        '\'' => '\'',
+                        _ if self.is_at_end() => continue,
+                        c => {
+                            // Skip to the closing quote so the rest of the
+                            // literal isn't lexed as code
+                            while !self.is_at_end() && self.current_char() != quote {
+                                self.advance();
+                            }
+                            self.advance();
+                            return Err(format!("Unknown escape sequence '\\{}'", c));
+                        }
+                    };
+                    value.push(escaped);
+                    self.advance();
+                }
+                c => {
+                    value.push(c);
+                    self.advance();
+                }
+            }
+        }
+    }
+
+    fn skip_digits(&mut self) {
+        while self.current_char().is_ascii_digit() {
+            self.advance();
+        }
+    }
+
     fn lex_ident_or_keyword(&mut self, start: usize) -> Token {
         while self.current_char().is_alphanumeric() || self.current_char() == '_' {
             self.advance();
@@ -207,9 +457,18 @@ impl<'a> Lexer<'a> {
         let kind = match text {
             "fn" => TokenKind::Fn,
             "let" => TokenKind::Let,
+            "const" => TokenKind::Const,
             "if" => TokenKind::If,
             "else" => TokenKind::Else,
             "while" => TokenKind::While,
+            "return" => TokenKind::Return,
+            "for" => TokenKind::For,
+            "in" => TokenKind::In,
+            "loop" => TokenKind::Loop,
+            "break" => TokenKind::Break,
+            "continue" => TokenKind::Continue,
+            "true" => TokenKind::True,
+            "false" => TokenKind::False,
             _ => TokenKind::Ident(text.to_string()),
         };
 
@@ -222,6 +481,37 @@ impl<'a> Lexer<'a> {
         }
     }
 
+    // Lex an operator that's one character, or two if the next character is
+    // `second`, like `<` and `<=`. The longest match wins.
+    fn match_two_char(&mut self, second: char, two: TokenKind, one: TokenKind) -> Token {
+        let start = self.position;
+        self.advance();
+        let kind = if self.current_char() == second {
+            self.advance();
+            two
+        } else {
+            one
+        };
+
+        Token {
+            kind,
+            span: Span {
+                start,
+                end: self.position,
+            },
+        }
+    }
+
+    // Lex a doubled character like `&&`. There are no single-character forms
+    // yet, so a lone character is an error.
+    fn lex_pair(&mut self, c: char, kind: TokenKind) -> Token {
+        let lone = TokenKind::Error(format!(
+            "Unexpected character '{}', did you mean '{}{}'?",
+            c, c, c
+        ));
+        self.match_two_char(c, kind, lone)
+    }
+
     fn make_token(&mut self, kind: TokenKind, start: usize) -> Token {
         self.advance();
         Token {
@@ -239,8 +529,39 @@ impl<'a> Lexer<'a> {
         }
     }
 
+    // Whether a comment starts here
+    fn at_comment(&self) -> bool {
+        self.current_char() == '/' && self.peek_char() == '/'
+    }
+
+    // Lex a `//` comment, up to but not including the newline that ends it
+    fn lex_comment(&mut self) -> Token {
+        let start = self.position;
+        while !self.is_at_end() && self.current_char() != '\n' {
+            self.advance();
+        }
+        Token {
+            kind: TokenKind::Comment(self.input[start..self.position].to_string()),
+            span: Span {
+                start,
+                end: self.position,
+            },
+        }
+    }
+
+    // `position` is a byte offset into `input`, so we slice rather than
+    // counting chars; this keeps spans byte-accurate for multi-byte input.
+    // At the end of input this is '\0', which no token continues with, so
+    // lookahead never needs its own bounds check.
     fn current_char(&self) -> char {
-        self.input.chars().nth(self.position).unwrap_or('\0')
+        self.input[self.position..].chars().next().unwrap_or('\0')
+    }
+
+    // The char after the current one
+    fn peek_char(&self) -> char {
+        let mut chars = self.input[self.position..].chars();
+        chars.next();
+        chars.next().unwrap_or('\0')
     }
 
     fn advance(&mut self) {
@@ -254,6 +575,48 @@ impl<'a> Lexer<'a> {
     }
 }
 
+impl Iterator for Lexer<'_> {
+    type Item = Token;
+
+    fn next(&mut self) -> Option<Token> {
+        if self.trivia && self.current_char().is_whitespace() {
+            let start = self.position;
+            self.skip_whitespace();
+            return Some(Token {
+                kind: TokenKind::Whitespace(self.input[start..self.position].to_string()),
+                span: Span {
+                    start,
+                    end: self.position,
+                },
+            });
+        }
+        if self.trivia && self.at_comment() {
+            return Some(self.lex_comment());
+        }
+
+        self.skip_whitespace();
+        while self.at_comment() {
+            self.lex_comment();
+            self.skip_whitespace();
+        }
+        if !self.is_at_end() {
+            return Some(self.next_token());
+        }
+
+        if self.emitted_eof {
+            return None;
+        }
+        self.emitted_eof = true;
+        Some(Token {
+            kind: TokenKind::Eof,
+            span: Span {
+                start: self.position,
+                end: self.position,
+            },
+        })
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -271,6 +634,518 @@ mod tests {
         assert_eq!(tokens[5].kind, TokenKind::Eof);
     }
 
+    #[test]
+    fn test_compound_assignment_tokens() {
+        let mut lexer = Lexer::new("x += 1 -= *= /= %=");
+        let tokens = lexer.tokenize();
+
+        assert_eq!(tokens[0].kind, TokenKind::Ident("x".to_string()));
+        assert_eq!(tokens[1].kind, TokenKind::PlusAssign);
+        assert_eq!(tokens[1].span, Span { start: 2, end: 4 });
+        assert_eq!(tokens[2].kind, TokenKind::Integer(1));
+        assert_eq!(tokens[3].kind, TokenKind::MinusAssign);
+        assert_eq!(tokens[4].kind, TokenKind::StarAssign);
+        assert_eq!(tokens[5].kind, TokenKind::SlashAssign);
+        assert_eq!(tokens[6].kind, TokenKind::PercentAssign);
+        assert_eq!(tokens[7].kind, TokenKind::Eof);
+    }
+
+    #[test]
+    fn test_assign_and_equal_tokens() {
+        let mut lexer = Lexer::new("a = b == c + = d");
+        let tokens = lexer.tokenize();
+
+        assert_eq!(tokens[1].kind, TokenKind::Assign);
+        assert_eq!(tokens[3].kind, TokenKind::Equal);
+        // With whitespace in between, `+ =` is two separate tokens
+        assert_eq!(tokens[5].kind, TokenKind::Plus);
+        assert_eq!(tokens[6].kind, TokenKind::Assign);
+    }
+
+    #[test]
+    fn test_comparison_tokens_longest_match() {
+        let cases = [
+            ("=", TokenKind::Assign),
+            ("==", TokenKind::Equal),
+            ("<", TokenKind::Less),
+            ("<=", TokenKind::LessEqual),
+            (">", TokenKind::Greater),
+            (">=", TokenKind::GreaterEqual),
+            ("!=", TokenKind::NotEqual),
+        ];
+        for (input, kind) in cases {
+            let tokens = Lexer::new(input).tokenize();
+            assert_eq!(tokens.len(), 2, "{}", input);
+            assert_eq!(tokens[0].kind, kind, "{}", input);
+            assert_eq!(
+                tokens[0].span,
+                Span {
+                    start: 0,
+                    end: input.len()
+                }
+            );
+        }
+
+        // Only two characters are taken: `===` is `==` then `=`
+        let tokens = Lexer::new("=== <== !").tokenize();
+        assert_eq!(tokens[0].kind, TokenKind::Equal);
+        assert_eq!(tokens[1].kind, TokenKind::Assign);
+        assert_eq!(tokens[2].kind, TokenKind::LessEqual);
+        assert_eq!(tokens[3].kind, TokenKind::Assign);
+        assert_eq!(tokens[4].kind, TokenKind::Bang);
+
+        // A space splits them
+        let tokens = Lexer::new("< =").tokenize();
+        assert_eq!(tokens[0].kind, TokenKind::Less);
+        assert_eq!(tokens[1].kind, TokenKind::Assign);
+    }
+
+    #[test]
+    fn test_logical_and() {
+        let mut lexer = Lexer::new("a && b");
+        let tokens = lexer.tokenize();
+
+        assert_eq!(tokens[0].kind, TokenKind::Ident("a".to_string()));
+        assert_eq!(tokens[1].kind, TokenKind::AmpAmp);
+        assert_eq!(tokens[1].span, Span { start: 2, end: 4 });
+        assert_eq!(tokens[2].kind, TokenKind::Ident("b".to_string()));
+        assert_eq!(tokens[3].kind, TokenKind::Eof);
+    }
+
+    #[test]
+    fn test_logical_or() {
+        let mut lexer = Lexer::new("a || b");
+        let tokens = lexer.tokenize();
+
+        assert_eq!(tokens[1].kind, TokenKind::PipePipe);
+        assert_eq!(tokens[1].span, Span { start: 2, end: 4 });
+        assert_eq!(tokens[2].kind, TokenKind::Ident("b".to_string()));
+    }
+
+    #[test]
+    fn test_lone_ampersand_is_error() {
+        let mut lexer = Lexer::new("a & b | c");
+        let tokens = lexer.tokenize();
+
+        assert!(matches!(tokens[1].kind, TokenKind::Error(_)));
+        assert_eq!(tokens[1].span, Span { start: 2, end: 3 });
+        // Lexing carries on after the error
+        assert_eq!(tokens[2].kind, TokenKind::Ident("b".to_string()));
+        assert!(matches!(tokens[3].kind, TokenKind::Error(_)));
+        assert_eq!(tokens[5].kind, TokenKind::Eof);
+    }
+
+    #[test]
+    fn test_string_literals() {
+        let mut lexer = Lexer::new(r#""" "hi" "a\nb\t\\\"""#);
+        let tokens = lexer.tokenize();
+
+        assert_eq!(tokens[0].kind, TokenKind::StringLit(String::new()));
+        assert_eq!(tokens[0].span, Span { start: 0, end: 2 });
+        assert_eq!(tokens[1].kind, TokenKind::StringLit("hi".to_string()));
+        assert_eq!(
+            tokens[2].kind,
+            TokenKind::StringLit("a\nb\t\\\"".to_string())
+        );
+        assert_eq!(tokens[3].kind, TokenKind::Eof);
+    }
+
+    #[test]
+    fn test_unterminated_string_is_error() {
+        let mut lexer = Lexer::new("let s = \"abc");
+        let tokens = lexer.tokenize();
+
+        assert_eq!(
+            tokens[3].kind,
+            TokenKind::Error("Unterminated string literal".to_string())
+        );
+        assert_eq!(tokens[3].span, Span { start: 8, end: 12 });
+        assert_eq!(tokens[4].kind, TokenKind::Eof);
+
+        // A trailing backslash doesn't escape the end of input
+        let tokens = Lexer::new("\"abc\\").tokenize();
+        assert!(matches!(tokens[0].kind, TokenKind::Error(_)));
+    }
+
+    #[test]
+    fn test_char_literals() {
+        let tokens = Lexer::new(r"'a' 'é' '\n' '\'' '\\' '\t'").tokenize();
+
+        assert_eq!(tokens[0].kind, TokenKind::Char('a'));
+        assert_eq!(tokens[0].span, Span { start: 0, end: 3 });
+        assert_eq!(tokens[1].kind, TokenKind::Char('é'));
+        assert_eq!(tokens[1].span, Span { start: 4, end: 8 });
+        assert_eq!(tokens[2].kind, TokenKind::Char('\n'));
+        assert_eq!(tokens[3].kind, TokenKind::Char('\''));
+        assert_eq!(tokens[4].kind, TokenKind::Char('\\'));
+        assert_eq!(tokens[5].kind, TokenKind::Char('\t'));
+        assert_eq!(tokens[6].kind, TokenKind::Eof);
+    }
+
+    #[test]
+    fn test_invalid_char_literals() {
+        let tokens = Lexer::new("'ab' '' x 'c").tokenize();
+
+        assert_eq!(
+            tokens[0].kind,
+            TokenKind::Error(
+                "Character literal 'ab' must contain exactly one character".to_string()
+            )
+        );
+        assert_eq!(tokens[0].span, Span { start: 0, end: 4 });
+        assert_eq!(
+            tokens[1].kind,
+            TokenKind::Error("Empty character literal".to_string())
+        );
+        assert_eq!(tokens[2].kind, TokenKind::Ident("x".to_string()));
+        assert_eq!(
+            tokens[3].kind,
+            TokenKind::Error("Unterminated character literal".to_string())
+        );
+    }
+
+    #[test]
+    fn test_unknown_escape_is_error() {
+        let tokens = Lexer::new(r#""a\qb" x"#).tokenize();
+        assert_eq!(
+            tokens[0].kind,
+            TokenKind::Error("Unknown escape sequence '\\q'".to_string())
+        );
+        assert_eq!(tokens[1].kind, TokenKind::Ident("x".to_string()));
+    }
+
+    #[test]
+    fn test_colon_type_annotation() {
+        let mut lexer = Lexer::new("x: i64");
+        let tokens = lexer.tokenize();
+
+        assert_eq!(tokens[0].kind, TokenKind::Ident("x".to_string()));
+        assert_eq!(tokens[1].kind, TokenKind::Colon);
+        assert_eq!(tokens[1].span, Span { start: 1, end: 2 });
+        assert_eq!(tokens[2].kind, TokenKind::Ident("i64".to_string()));
+    }
+
+    #[test]
+    fn test_arrow() {
+        let mut lexer = Lexer::new("-> i64");
+        let tokens = lexer.tokenize();
+
+        assert_eq!(tokens[0].kind, TokenKind::Arrow);
+        assert_eq!(tokens[0].span, Span { start: 0, end: 2 });
+        assert_eq!(tokens[1].kind, TokenKind::Ident("i64".to_string()));
+    }
+
+    #[test]
+    fn test_minus_is_not_arrow() {
+        let mut lexer = Lexer::new("a - b - >");
+        let tokens = lexer.tokenize();
+
+        assert_eq!(tokens[0].kind, TokenKind::Ident("a".to_string()));
+        assert_eq!(tokens[1].kind, TokenKind::Minus);
+        assert_eq!(tokens[2].kind, TokenKind::Ident("b".to_string()));
+        assert_eq!(tokens[3].kind, TokenKind::Minus);
+        assert_eq!(tokens[4].kind, TokenKind::Greater);
+    }
+
+    #[test]
+    fn test_negative_literal_is_minus_then_integer() {
+        let kinds = |input| -> Vec<TokenKind> {
+            Lexer::new(input)
+                .tokenize()
+                .into_iter()
+                .map(|token| token.kind)
+                .collect()
+        };
+
+        // Unary and binary minus are the same token
+        assert_eq!(
+            kinds("-5"),
+            vec![TokenKind::Minus, TokenKind::Integer(5), TokenKind::Eof]
+        );
+        assert_eq!(
+            kinds("a - 5"),
+            vec![
+                TokenKind::Ident("a".to_string()),
+                TokenKind::Minus,
+                TokenKind::Integer(5),
+                TokenKind::Eof
+            ]
+        );
+        assert_eq!(kinds("a -5"), kinds("a - 5"));
+        assert_eq!(kinds("a-5"), kinds("a - 5"));
+        assert_eq!(
+            kinds("--5"),
+            vec![
+                TokenKind::Minus,
+                TokenKind::Minus,
+                TokenKind::Integer(5),
+                TokenKind::Eof
+            ]
+        );
+    }
+
+    #[test]
+    fn test_negative_literal_spans() {
+        let tokens = Lexer::new("x = -42").tokenize();
+
+        assert_eq!(tokens[2].kind, TokenKind::Minus);
+        assert_eq!(tokens[2].span, Span { start: 4, end: 5 });
+        assert_eq!(tokens[3].kind, TokenKind::Integer(42));
+        assert_eq!(tokens[3].span, Span { start: 5, end: 7 });
+    }
+
+    #[test]
+    fn test_most_negative_integer_is_not_a_literal() {
+        // Since the sign isn't part of the literal, i64::MIN's digits on
+        // their own are out of range
+        let tokens = Lexer::new("-9223372036854775808").tokenize();
+
+        assert_eq!(tokens[0].kind, TokenKind::Minus);
+        assert!(matches!(tokens[1].kind, TokenKind::Error(_)));
+    }
+
+    #[test]
+    fn test_iterator_matches_tokenize() {
+        let input = "fn main() {\n    let x = 1;\n    x += 2;\n    x\n}  ";
+
+        let mut lexer = Lexer::new(input);
+        let first = lexer.next().unwrap();
+        assert_eq!(first.kind, TokenKind::Fn);
+        let rest: Vec<Token> = lexer.by_ref().collect();
+        assert_eq!(rest.last().unwrap().kind, TokenKind::Eof);
+        assert_eq!(lexer.next(), None);
+
+        let mut tokens = vec![first];
+        tokens.extend(rest);
+        assert_eq!(tokens, Lexer::new(input).tokenize());
+    }
+
+    #[test]
+    fn test_tokens_at_end_of_input() {
+        let last_two = |input: &str| -> Vec<Token> {
+            let tokens = Lexer::new(input).tokenize();
+            tokens[tokens.len() - 2..].to_vec()
+        };
+        let eof = |at| Token {
+            kind: TokenKind::Eof,
+            span: Span { start: at, end: at },
+        };
+
+        // Each input ends mid-token, with no trailing newline
+        let cases = [
+            ("x = 42", TokenKind::Integer(42), 4),
+            ("x = 4", TokenKind::Integer(4), 4),
+            ("x = 1.5", TokenKind::Float(1.5), 4),
+            ("let x = y", TokenKind::Ident("y".to_string()), 8),
+            ("return", TokenKind::Return, 0),
+            ("x +", TokenKind::Plus, 2),
+            ("x <=", TokenKind::LessEqual, 2),
+            ("x <", TokenKind::Less, 2),
+            ("x ->", TokenKind::Arrow, 2),
+            ("x -", TokenKind::Minus, 2),
+        ];
+        for (input, kind, start) in cases {
+            assert_eq!(
+                last_two(input),
+                vec![
+                    Token {
+                        kind,
+                        span: Span {
+                            start,
+                            end: input.len()
+                        },
+                    },
+                    eof(input.len()),
+                ],
+                "{}",
+                input
+            );
+        }
+
+        // A trailing `.` isn't part of the number
+        let tokens = Lexer::new("3.").tokenize();
+        assert_eq!(tokens[0].kind, TokenKind::Integer(3));
+        assert_eq!(tokens[1].kind, TokenKind::Dot);
+        assert_eq!(tokens[2], eof(2));
+    }
+
+    #[test]
+    fn test_iterator_on_empty_input() {
+        let mut lexer = Lexer::new("   ");
+        assert_eq!(
+            lexer.next(),
+            Some(Token {
+                kind: TokenKind::Eof,
+                span: Span { start: 3, end: 3 },
+            })
+        );
+        assert_eq!(lexer.next(), None);
+    }
+
+    #[test]
+    #[allow(clippy::approx_constant)]
+    fn test_float_literal() {
+        let mut lexer = Lexer::new("3.14");
+        let tokens = lexer.tokenize();
+
+        assert_eq!(tokens[0].kind, TokenKind::Float(3.14));
+        assert_eq!(tokens[0].span, Span { start: 0, end: 4 });
+        assert_eq!(tokens[1].kind, TokenKind::Eof);
+    }
+
+    #[test]
+    fn test_integer_vs_float() {
+        let mut lexer = Lexer::new("3 3.0 30");
+        let tokens = lexer.tokenize();
+
+        assert_eq!(tokens[0].kind, TokenKind::Integer(3));
+        assert_eq!(tokens[1].kind, TokenKind::Float(3.0));
+        assert_eq!(tokens[2].kind, TokenKind::Integer(30));
+    }
+
+    #[test]
+    fn test_dot_without_digits_is_not_float() {
+        // Neither `3.` nor `.5` is a float, and the `.` isn't swallowed
+        let mut lexer = Lexer::new("3. .5");
+        let tokens = lexer.tokenize();
+
+        assert_eq!(tokens[0].kind, TokenKind::Integer(3));
+        assert_eq!(tokens[0].span, Span { start: 0, end: 1 });
+        assert_eq!(tokens[1].kind, TokenKind::Dot);
+        assert_eq!(tokens[1].span, Span { start: 1, end: 2 });
+        assert_eq!(tokens[2].kind, TokenKind::Dot);
+        assert_eq!(tokens[3].kind, TokenKind::Integer(5));
+    }
+
+    #[test]
+    fn test_field_access_dot() {
+        let mut lexer = Lexer::new("foo.bar 3.14 x.y.z 3.max");
+        let tokens = lexer.tokenize();
+
+        let kinds: Vec<_> = tokens.iter().map(|t| t.kind.clone()).collect();
+        assert_eq!(
+            kinds,
+            vec![
+                TokenKind::Ident("foo".to_string()),
+                TokenKind::Dot,
+                TokenKind::Ident("bar".to_string()),
+                #[allow(clippy::approx_constant)]
+                TokenKind::Float(3.14),
+                TokenKind::Ident("x".to_string()),
+                TokenKind::Dot,
+                TokenKind::Ident("y".to_string()),
+                TokenKind::Dot,
+                TokenKind::Ident("z".to_string()),
+                TokenKind::Integer(3),
+                TokenKind::Dot,
+                TokenKind::Ident("max".to_string()),
+                TokenKind::Eof,
+            ]
+        );
+        assert_eq!(tokens[1].span, Span { start: 3, end: 4 });
+    }
+
+    #[test]
+    fn test_token_text_lexes_back() {
+        let source = "fn let if else while return for loop break continue x 42 \
+                      + - * / % = += -= *= /= %= < <= > >= == != ! && || \
+                      ( ) { } [ ] ; , : . ->";
+        for token in Lexer::new(source).tokenize() {
+            if token.kind == TokenKind::Eof {
+                continue;
+            }
+            let text = token.kind.text();
+            assert_eq!(&source[token.span.start..token.span.end], text);
+            assert_eq!(Lexer::new(&text).tokenize()[0].kind, token.kind);
+        }
+
+        assert_eq!(TokenKind::Integer(0x2a).text(), "42");
+        assert_eq!(TokenKind::Eof.text(), "");
+    }
+
+    #[test]
+    fn test_hex_literals() {
+        let tokens = Lexer::new("0xAB 0xab 0xaB 0x0 0x7fffffffffffffff 0").tokenize();
+
+        assert_eq!(tokens[0].kind, TokenKind::Integer(0xab));
+        assert_eq!(tokens[0].span, Span { start: 0, end: 4 });
+        assert_eq!(tokens[1].kind, TokenKind::Integer(0xab));
+        assert_eq!(tokens[2].kind, TokenKind::Integer(0xab));
+        assert_eq!(tokens[3].kind, TokenKind::Integer(0));
+        assert_eq!(tokens[4].kind, TokenKind::Integer(i64::MAX));
+        assert_eq!(tokens[5].kind, TokenKind::Integer(0));
+        assert_eq!(tokens[6].kind, TokenKind::Eof);
+    }
+
+    #[test]
+    fn test_invalid_hex_literals() {
+        // The error points at the bad digit
+        let tokens = Lexer::new("x = 0xG;").tokenize();
+        assert_eq!(
+            tokens[2].kind,
+            TokenKind::Error("Invalid digit 'G' in hex literal '0xG'".to_string())
+        );
+        assert_eq!(tokens[2].span, Span { start: 6, end: 7 });
+        assert_eq!(tokens[3].kind, TokenKind::Semicolon);
+
+        let tokens = Lexer::new("0xaBzq").tokenize();
+        assert_eq!(
+            tokens[0].kind,
+            TokenKind::Error("Invalid digit 'z' in hex literal '0xaBzq'".to_string())
+        );
+        assert_eq!(tokens[0].span, Span { start: 4, end: 5 });
+        assert_eq!(tokens[1].kind, TokenKind::Eof);
+
+        let tokens = Lexer::new("0x").tokenize();
+        assert_eq!(
+            tokens[0].kind,
+            TokenKind::Error("Hex literal '0x' has no digits".to_string())
+        );
+
+        let tokens = Lexer::new("0x8000000000000000").tokenize();
+        assert_eq!(
+            tokens[0].kind,
+            TokenKind::Error(
+                "Integer literal '0x8000000000000000' is too large for i64".to_string()
+            )
+        );
+
+        // Hex digits outside a hex literal are just an identifier
+        let tokens = Lexer::new("1ab").tokenize();
+        assert_eq!(tokens[0].kind, TokenKind::Integer(1));
+        assert_eq!(tokens[1].kind, TokenKind::Ident("ab".to_string()));
+    }
+
+    #[test]
+    fn test_integer_literal_limits() {
+        let mut lexer = Lexer::new("9223372036854775807 9223372036854775808 99999999999999999999");
+        let tokens = lexer.tokenize();
+
+        assert_eq!(tokens[0].kind, TokenKind::Integer(i64::MAX));
+        assert_eq!(
+            tokens[1].kind,
+            TokenKind::Error(
+                "Integer literal '9223372036854775808' is too large for i64".to_string()
+            )
+        );
+        assert_eq!(tokens[1].span, Span { start: 20, end: 39 });
+        assert!(matches!(tokens[2].kind, TokenKind::Error(_)));
+        assert_eq!(tokens[3].kind, TokenKind::Eof);
+    }
+
+    #[test]
+    fn test_float_with_two_dots_is_error() {
+        let mut lexer = Lexer::new("1.2.3 + 1");
+        let tokens = lexer.tokenize();
+
+        assert_eq!(
+            tokens[0].kind,
+            TokenKind::Error("Invalid number literal '1.2.3'".to_string())
+        );
+        assert_eq!(tokens[0].span, Span { start: 0, end: 5 });
+        assert_eq!(tokens[1].kind, TokenKind::Plus);
+    }
+
     #[test]
     fn test_factorial() {
         let input = r#"
@@ -299,4 +1174,187 @@ fn factorial(n) {
         assert_eq!(tokens[0].kind, TokenKind::While);
         assert_eq!(tokens[1].kind, TokenKind::Eof);
     }
+
+    #[test]
+    fn test_brackets() {
+        let mut lexer = Lexer::new("[1, 2, 3]");
+        let tokens = lexer.tokenize();
+
+        let kinds: Vec<_> = tokens.iter().map(|t| t.kind.clone()).collect();
+        assert_eq!(
+            kinds,
+            vec![
+                TokenKind::LeftBracket,
+                TokenKind::Integer(1),
+                TokenKind::Comma,
+                TokenKind::Integer(2),
+                TokenKind::Comma,
+                TokenKind::Integer(3),
+                TokenKind::RightBracket,
+                TokenKind::Eof,
+            ]
+        );
+        assert_eq!(tokens[0].span, Span { start: 0, end: 1 });
+        assert_eq!(tokens[6].span, Span { start: 8, end: 9 });
+    }
+
+    #[test]
+    fn test_tokenize_with_trivia_round_trips() {
+        let input = "fn main() {\n    let x = 1;\t\n\n  x + 2 }\n";
+        let tokens = Lexer::new(input).tokenize_with_trivia();
+
+        let text: String = tokens
+            .iter()
+            .map(|token| &input[token.span.start..token.span.end])
+            .collect();
+        assert_eq!(text, input);
+
+        assert_eq!(tokens[1].kind, TokenKind::Whitespace(" ".to_string()));
+        assert!(tokens[1].kind.is_trivia());
+        assert_eq!(tokens[7].kind, TokenKind::Whitespace("\n    ".to_string()));
+        assert_eq!(tokens.last().unwrap().kind, TokenKind::Eof);
+
+        // The default mode still skips whitespace
+        let tokens = Lexer::new(input).tokenize();
+        assert!(!tokens.iter().any(|token| token.kind.is_trivia()));
+    }
+
+    #[test]
+    fn test_comments() {
+        let input = "// leading\nx / 2 // trailing\n//\n";
+        let kinds: Vec<TokenKind> = Lexer::new(input)
+            .tokenize_with_trivia()
+            .into_iter()
+            .map(|token| token.kind)
+            .filter(|kind| !matches!(kind, TokenKind::Whitespace(_)))
+            .collect();
+        assert_eq!(
+            kinds,
+            vec![
+                TokenKind::Comment("// leading".to_string()),
+                TokenKind::Ident("x".to_string()),
+                TokenKind::Slash,
+                TokenKind::Integer(2),
+                TokenKind::Comment("// trailing".to_string()),
+                TokenKind::Comment("//".to_string()),
+                TokenKind::Eof,
+            ]
+        );
+        assert!(TokenKind::Comment(String::new()).is_trivia());
+
+        // The default mode skips them, even at the very end of the input
+        let kinds: Vec<TokenKind> = Lexer::new("x // no newline")
+            .tokenize()
+            .into_iter()
+            .map(|token| token.kind)
+            .collect();
+        assert_eq!(
+            kinds,
+            vec![TokenKind::Ident("x".to_string()), TokenKind::Eof]
+        );
+    }
+
+    #[test]
+    fn test_loop_keywords() {
+        let mut lexer = Lexer::new("for loop break continue forever looping breaks continued");
+        let tokens = lexer.tokenize();
+
+        assert_eq!(tokens[0].kind, TokenKind::For);
+        assert_eq!(tokens[1].kind, TokenKind::Loop);
+        assert_eq!(tokens[2].kind, TokenKind::Break);
+        assert_eq!(tokens[3].kind, TokenKind::Continue);
+        assert_eq!(tokens[4].kind, TokenKind::Ident("forever".to_string()));
+        assert_eq!(tokens[5].kind, TokenKind::Ident("looping".to_string()));
+        assert_eq!(tokens[6].kind, TokenKind::Ident("breaks".to_string()));
+        assert_eq!(tokens[7].kind, TokenKind::Ident("continued".to_string()));
+    }
+
+    #[test]
+    fn test_boolean_keywords() {
+        let mut lexer = Lexer::new("true false truest");
+        let tokens = lexer.tokenize();
+
+        assert_eq!(tokens[0].kind, TokenKind::True);
+        assert_eq!(tokens[1].kind, TokenKind::False);
+        assert_eq!(tokens[2].kind, TokenKind::Ident("truest".to_string()));
+    }
+
+    #[test]
+    fn test_range() {
+        let mut lexer = Lexer::new("for i in 0..n inside");
+        let tokens = lexer.tokenize();
+
+        let kinds: Vec<_> = tokens.iter().map(|t| t.kind.clone()).collect();
+        assert_eq!(
+            kinds,
+            vec![
+                TokenKind::For,
+                TokenKind::Ident("i".to_string()),
+                TokenKind::In,
+                TokenKind::Integer(0),
+                TokenKind::DotDot,
+                TokenKind::Ident("n".to_string()),
+                TokenKind::Ident("inside".to_string()),
+                TokenKind::Eof,
+            ]
+        );
+        assert_eq!(tokens[4].span, Span { start: 10, end: 12 });
+    }
+
+    #[test]
+    fn test_return_keyword() {
+        let mut lexer = Lexer::new("return returned");
+        let tokens = lexer.tokenize();
+
+        assert_eq!(tokens[0].kind, TokenKind::Return);
+        assert_eq!(tokens[1].kind, TokenKind::Ident("returned".to_string()));
+    }
+
+    #[test]
+    fn test_const_keyword() {
+        let mut lexer = Lexer::new("const constant");
+        let tokens = lexer.tokenize();
+
+        assert_eq!(tokens[0].kind, TokenKind::Const);
+        assert_eq!(tokens[1].kind, TokenKind::Ident("constant".to_string()));
+    }
+
+    #[test]
+    fn test_multibyte_spans() {
+        // 'ö' is two bytes in UTF-8, so `föö` occupies bytes 0..5
+        let mut lexer = Lexer::new("föö + 1");
+        let tokens = lexer.tokenize();
+
+        assert_eq!(tokens[0].kind, TokenKind::Ident("föö".to_string()));
+        assert_eq!(tokens[0].span, Span { start: 0, end: 5 });
+        assert_eq!(tokens[1].kind, TokenKind::Plus);
+        assert_eq!(tokens[1].span, Span { start: 6, end: 7 });
+        assert_eq!(tokens[2].kind, TokenKind::Integer(1));
+        assert_eq!(tokens[2].span, Span { start: 8, end: 9 });
+        assert_eq!(tokens[3].span, Span { start: 9, end: 9 });
+    }
+
+    #[test]
+    fn test_span_line_col() {
+        let input = "fn main() {\n    let x = 1;\n  föö + x\n}";
+        let tokens = Lexer::new(input).tokenize();
+
+        assert_eq!(tokens[0].span.line_col(input), (1, 0));
+
+        let let_token = tokens.iter().find(|t| t.kind == TokenKind::Let).unwrap();
+        assert_eq!(let_token.span.line_col(input), (2, 4));
+
+        let foo = tokens
+            .iter()
+            .find(|t| t.kind == TokenKind::Ident("föö".to_string()))
+            .unwrap();
+        assert_eq!(foo.span.line_col(input), (3, 2));
+
+        // Columns count chars, not bytes, so 'ö' only moves `+` by one each
+        let plus = tokens.iter().find(|t| t.kind == TokenKind::Plus).unwrap();
+        assert_eq!(plus.span.line_col(input), (3, 6));
+
+        let eof = tokens.last().unwrap();
+        assert_eq!(eof.span.line_col(input), (4, 1));
+    }
 }
@@ -0,0 +1,211 @@
+use std::ops::Range;
+
+/// A zero-based line and column. What a column counts (chars or UTF-16 code
+/// units) depends on the `SourceMap` method that produced it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LineCol {
+    pub line: usize,
+    pub column: usize,
+}
+
+/// Precomputed offset tables for a source file
+///
+/// Spans are byte offsets, but tooling wants other units: error rendering
+/// wants char columns and the LSP wants UTF-16 code units. The tables are
+/// built once per file so every conversion is a binary search.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SourceMap {
+    /// Byte offset of the start of each line
+    line_starts: Vec<usize>,
+    /// Byte offset of each char, plus a trailing entry for the end of input
+    char_bytes: Vec<usize>,
+    /// UTF-16 offset of each char, plus a trailing entry for the end of input
+    char_utf16: Vec<usize>,
+}
+
+impl SourceMap {
+    pub fn new(source: &str) -> Self {
+        let mut line_starts = vec![0];
+        let mut char_bytes = Vec::with_capacity(source.len() + 1);
+        let mut char_utf16 = Vec::with_capacity(source.len() + 1);
+        let mut utf16 = 0;
+
+        for (byte, c) in source.char_indices() {
+            char_bytes.push(byte);
+            char_utf16.push(utf16);
+            utf16 += c.len_utf16();
+            if c == '\n' {
+                line_starts.push(byte + 1);
+            }
+        }
+        char_bytes.push(source.len());
+        char_utf16.push(utf16);
+
+        Self {
+            line_starts,
+            char_bytes,
+            char_utf16,
+        }
+    }
+
+    /// Length of the source in bytes
+    pub fn len(&self) -> usize {
+        *self.char_bytes.last().unwrap()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn line_count(&self) -> usize {
+        self.line_starts.len()
+    }
+
+    /// Byte range of a line, excluding its trailing newline
+    pub fn line_range(&self, line: usize) -> Range<usize> {
+        let start = self.line_starts[line];
+        let end = match self.line_starts.get(line + 1) {
+            Some(next) => next - 1,
+            None => self.len(),
+        };
+        start..end
+    }
+
+    /// Char index of the char containing `byte`
+    pub fn byte_to_char(&self, byte: usize) -> usize {
+        let byte = byte.min(self.len());
+        self.char_bytes.partition_point(|&b| b <= byte) - 1
+    }
+
+    pub fn char_to_byte(&self, char_index: usize) -> usize {
+        self.char_bytes[char_index.min(self.char_bytes.len() - 1)]
+    }
+
+    pub fn byte_to_utf16(&self, byte: usize) -> usize {
+        self.char_utf16[self.byte_to_char(byte)]
+    }
+
+    /// Byte offset for a UTF-16 offset. A UTF-16 offset that falls between
+    /// the two halves of a surrogate pair maps to the start of that char.
+    pub fn utf16_to_byte(&self, utf16: usize) -> usize {
+        let index = self.char_utf16.partition_point(|&u| u <= utf16) - 1;
+        self.char_bytes[index]
+    }
+
+    pub fn byte_to_line(&self, byte: usize) -> usize {
+        let byte = byte.min(self.len());
+        self.line_starts.partition_point(|&start| start <= byte) - 1
+    }
+
+    /// Line and char column of a byte offset
+    pub fn line_col(&self, byte: usize) -> LineCol {
+        let line = self.byte_to_line(byte);
+        let line_start = self.line_starts[line];
+        LineCol {
+            line,
+            column: self.byte_to_char(byte) - self.byte_to_char(line_start),
+        }
+    }
+
+    /// Line and UTF-16 column of a byte offset, as used by LSP positions
+    pub fn line_col_utf16(&self, byte: usize) -> LineCol {
+        let line = self.byte_to_line(byte);
+        let line_start = self.line_starts[line];
+        LineCol {
+            line,
+            column: self.byte_to_utf16(byte) - self.byte_to_utf16(line_start),
+        }
+    }
+
+    /// Byte offset of a line and char column, clamped to the end of the line
+    pub fn byte_offset(&self, position: LineCol) -> usize {
+        let Some(range) = self.line_range_checked(position.line) else {
+            return self.len();
+        };
+        let char_index = self.byte_to_char(range.start) + position.column;
+        self.char_to_byte(char_index).min(range.end)
+    }
+
+    /// Byte offset of a line and UTF-16 column, clamped to the end of the line
+    pub fn byte_offset_utf16(&self, position: LineCol) -> usize {
+        let Some(range) = self.line_range_checked(position.line) else {
+            return self.len();
+        };
+        let utf16 = self.byte_to_utf16(range.start) + position.column;
+        self.utf16_to_byte(utf16).min(range.end)
+    }
+
+    fn line_range_checked(&self, line: usize) -> Option<Range<usize>> {
+        (line < self.line_count()).then(|| self.line_range(line))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // "é" is 2 bytes / 1 UTF-16 unit, "🦀" is 4 bytes / 2 UTF-16 units
+    const SOURCE: &str = "fn é() {\n    🦀 + x\n}";
+
+    #[test]
+    fn test_byte_char_conversions() {
+        let map = SourceMap::new(SOURCE);
+
+        assert_eq!(map.len(), SOURCE.len());
+        assert_eq!(map.byte_to_char(3), 3); // 'é'
+        assert_eq!(map.byte_to_char(4), 3); // second byte of 'é'
+        assert_eq!(map.byte_to_char(5), 4); // '('
+        assert_eq!(map.char_to_byte(4), 5);
+
+        let crab = SOURCE.find('🦀').unwrap();
+        let plus = SOURCE.find('+').unwrap();
+        assert_eq!(map.char_to_byte(map.byte_to_char(crab)), crab);
+        assert_eq!(map.byte_to_char(plus), map.byte_to_char(crab) + 2);
+        assert_eq!(map.byte_to_char(SOURCE.len()), SOURCE.chars().count());
+    }
+
+    #[test]
+    fn test_utf16_conversions() {
+        let map = SourceMap::new(SOURCE);
+
+        let crab = SOURCE.find('🦀').unwrap();
+        let plus = SOURCE.find('+').unwrap();
+
+        // 'é' counts as one UTF-16 unit, so '(' is at UTF-16 offset 4
+        assert_eq!(map.byte_to_utf16(5), 4);
+        // The crab takes two UTF-16 units, then a space
+        assert_eq!(map.byte_to_utf16(plus), map.byte_to_utf16(crab) + 3);
+        assert_eq!(map.utf16_to_byte(map.byte_to_utf16(plus)), plus);
+        // The middle of a surrogate pair maps back to the start of the char
+        assert_eq!(map.utf16_to_byte(map.byte_to_utf16(crab) + 1), crab);
+    }
+
+    #[test]
+    fn test_line_col_conversions() {
+        let map = SourceMap::new(SOURCE);
+        assert_eq!(map.line_count(), 3);
+
+        let crab = SOURCE.find('🦀').unwrap();
+        let plus = SOURCE.find('+').unwrap();
+        let x = SOURCE.find('x').unwrap();
+
+        assert_eq!(map.line_col(0), LineCol { line: 0, column: 0 });
+        assert_eq!(map.line_col(5), LineCol { line: 0, column: 4 });
+        assert_eq!(map.line_col(crab), LineCol { line: 1, column: 4 });
+        assert_eq!(map.line_col(plus), LineCol { line: 1, column: 6 });
+        assert_eq!(map.line_col_utf16(plus), LineCol { line: 1, column: 7 });
+        assert_eq!(map.line_col(SOURCE.len()), LineCol { line: 2, column: 1 });
+
+        assert_eq!(map.byte_offset(LineCol { line: 1, column: 6 }), plus);
+        assert_eq!(map.byte_offset_utf16(LineCol { line: 1, column: 9 }), x);
+        // Columns past the end of a line clamp to the line's end
+        assert_eq!(
+            map.byte_offset(LineCol {
+                line: 0,
+                column: 100
+            }),
+            map.line_range(0).end
+        );
+        assert_eq!(&SOURCE[map.line_range(1)], "    🦀 + x");
+    }
+}
@@ -1,11 +1,22 @@
-use rue_lexer::Lexer;
-use rue_parser::{parse, ParseError};
+use rue_ast::{CstNode, FunctionNode};
+use rue_codegen::{dump_ir, Codegen};
+use rue_compiler::{interpret_file, CompileOptions, RueDatabase, SourceFile};
+use rue_lexer::{Lexer, SourceMap, Span, TokenKind};
+use rue_parser::{parse, parse_with_recovery, ParseError};
 use std::collections::HashMap;
 use tokio::sync::RwLock;
 use tower_lsp::jsonrpc::Result;
 use tower_lsp::lsp_types::*;
 use tower_lsp::{Client, LanguageServer, LspService, Server};
 
+/// Command behind the "Run" code lens, which takes the document's URI
+pub const RUN_COMMAND: &str = "rue.run";
+
+/// Command that returns the type of the expression at a position, as a
+/// string like `"i64"`, or null if there isn't one. Its argument is a
+/// `TextDocumentPositionParams`.
+pub const TYPEOF_COMMAND: &str = "rue/typeof";
+
 #[derive(Debug)]
 pub struct RueLanguageServer {
     client: Client,
@@ -20,40 +31,16 @@ impl RueLanguageServer {
         }
     }
 
-    async fn parse_document(&self, _uri: &Url, text: &str) -> Vec<Diagnostic> {
+    async fn parse_document(&self, uri: &Url, text: &str) -> Vec<Diagnostic> {
         let mut lexer = Lexer::new(text);
         let tokens = lexer.tokenize();
 
-        match parse(tokens) {
-            Ok(_) => Vec::new(), // No errors
-            Err(error) => vec![self.parse_error_to_diagnostic(error)],
-        }
-    }
-
-    fn parse_error_to_diagnostic(&self, error: ParseError) -> Diagnostic {
-        // For now, just use character offsets. We could convert to line/column later.
-        let range = Range {
-            start: Position {
-                line: 0,
-                character: error.span.start as u32,
-            },
-            end: Position {
-                line: 0,
-                character: error.span.end as u32,
-            },
-        };
-
-        Diagnostic {
-            range,
-            severity: Some(DiagnosticSeverity::ERROR),
-            code: None,
-            code_description: None,
-            source: Some("rue-lsp".to_string()),
-            message: error.message,
-            related_information: None,
-            tags: None,
-            data: None,
-        }
+        // Report every error the parser can find, not just the first
+        let (_, errors) = parse_with_recovery(tokens);
+        errors
+            .into_iter()
+            .map(|error| parse_error_to_diagnostic(uri, text, error))
+            .collect()
     }
 }
 
@@ -65,6 +52,18 @@ impl LanguageServer for RueLanguageServer {
                 text_document_sync: Some(TextDocumentSyncCapability::Kind(
                     TextDocumentSyncKind::FULL,
                 )),
+                hover_provider: Some(HoverProviderCapability::Simple(true)),
+                code_lens_provider: Some(CodeLensOptions {
+                    resolve_provider: Some(false),
+                }),
+                execute_command_provider: Some(ExecuteCommandOptions {
+                    commands: vec![RUN_COMMAND.to_string(), TYPEOF_COMMAND.to_string()],
+                    ..Default::default()
+                }),
+                rename_provider: Some(OneOf::Right(RenameOptions {
+                    prepare_provider: Some(true),
+                    work_done_progress_options: Default::default(),
+                })),
                 ..Default::default()
             },
             server_info: Some(ServerInfo {
@@ -120,6 +119,120 @@ impl LanguageServer for RueLanguageServer {
         }
     }
 
+    async fn hover(&self, params: HoverParams) -> Result<Option<Hover>> {
+        let position = params.text_document_position_params.position;
+        let documents = self.documents.read().await;
+        let Some(text) = documents.get(&params.text_document_position_params.text_document.uri)
+        else {
+            return Ok(None);
+        };
+
+        let source_map = SourceMap::new(text);
+        let offset = position_to_offset(&source_map, position);
+
+        Ok(function_ir_hover(text, offset).map(|(ir, span)| Hover {
+            contents: HoverContents::Markup(MarkupContent {
+                kind: MarkupKind::Markdown,
+                value: format!("```\n{}```", ir),
+            }),
+            range: Some(span_to_range(&source_map, span)),
+        }))
+    }
+
+    async fn prepare_rename(
+        &self,
+        params: TextDocumentPositionParams,
+    ) -> Result<Option<PrepareRenameResponse>> {
+        let documents = self.documents.read().await;
+        let Some(text) = documents.get(&params.text_document.uri) else {
+            return Ok(None);
+        };
+
+        let source_map = SourceMap::new(text);
+        let offset = position_to_offset(&source_map, params.position);
+        match symbol_at(text, offset) {
+            Ok(symbol) => Ok(Some(PrepareRenameResponse::Range(span_to_range(
+                &source_map,
+                symbol.span,
+            )))),
+            Err(message) => Err(tower_lsp::jsonrpc::Error::invalid_params(message)),
+        }
+    }
+
+    async fn rename(&self, params: RenameParams) -> Result<Option<WorkspaceEdit>> {
+        let uri = params.text_document_position.text_document.uri;
+        let documents = self.documents.read().await;
+        let Some(text) = documents.get(&uri) else {
+            return Ok(None);
+        };
+
+        let source_map = SourceMap::new(text);
+        let offset = position_to_offset(&source_map, params.text_document_position.position);
+        let spans = rename_spans(text, offset, &params.new_name)
+            .map_err(tower_lsp::jsonrpc::Error::invalid_params)?;
+        let edits = spans
+            .into_iter()
+            .map(|span| TextEdit {
+                range: span_to_range(&source_map, span),
+                new_text: params.new_name.clone(),
+            })
+            .collect();
+        Ok(Some(WorkspaceEdit {
+            changes: Some(HashMap::from([(uri.clone(), edits)])),
+            ..Default::default()
+        }))
+    }
+
+    async fn code_lens(&self, params: CodeLensParams) -> Result<Option<Vec<CodeLens>>> {
+        let uri = params.text_document.uri;
+        let documents = self.documents.read().await;
+        let Some(text) = documents.get(&uri) else {
+            return Ok(None);
+        };
+
+        Ok(run_code_lens(&uri, text).map(|lens| vec![lens]))
+    }
+
+    async fn execute_command(
+        &self,
+        params: ExecuteCommandParams,
+    ) -> Result<Option<serde_json::Value>> {
+        if params.command == TYPEOF_COMMAND {
+            let Some(position) = params.arguments.into_iter().next().and_then(|argument| {
+                serde_json::from_value::<TextDocumentPositionParams>(argument).ok()
+            }) else {
+                return Err(tower_lsp::jsonrpc::Error::invalid_params(
+                    "rue/typeof takes a document and position",
+                ));
+            };
+            let documents = self.documents.read().await;
+            let ty = documents
+                .get(&position.text_document.uri)
+                .and_then(|text| type_at(text, position.position));
+            return Ok(Some(ty.map_or(serde_json::Value::Null, |ty| {
+                serde_json::Value::String(ty)
+            })));
+        }
+        if params.command != RUN_COMMAND {
+            return Ok(None);
+        }
+        let Some(uri) = params
+            .arguments
+            .first()
+            .and_then(|argument| argument.as_str())
+            .and_then(|uri| Url::parse(uri).ok())
+        else {
+            return Ok(None);
+        };
+
+        let message = match self.documents.read().await.get(&uri) {
+            Some(text) => run_program(uri.path(), text),
+            None => format!("{} isn't open", uri),
+        };
+        self.client.show_message(MessageType::INFO, message).await;
+        Ok(None)
+    }
+
     async fn did_close(&self, params: DidCloseTextDocumentParams) {
         // Remove document from storage
         self.documents
@@ -134,6 +247,263 @@ impl LanguageServer for RueLanguageServer {
     }
 }
 
+fn parse_error_to_diagnostic(uri: &Url, text: &str, error: ParseError) -> Diagnostic {
+    let source_map = SourceMap::new(text);
+    let related_information = error.related.map(|(message, span)| {
+        vec![DiagnosticRelatedInformation {
+            location: Location {
+                uri: uri.clone(),
+                range: span_to_range(&source_map, span),
+            },
+            message,
+        }]
+    });
+
+    Diagnostic {
+        range: span_to_range(&source_map, error.span),
+        severity: Some(DiagnosticSeverity::ERROR),
+        code: None,
+        code_description: None,
+        source: Some("rue-lsp".to_string()),
+        message: error.message,
+        related_information,
+        tags: None,
+        data: None,
+    }
+}
+
+/// The IR for the function whose name is at `offset`, along with the span of
+/// the name. Returns None if there's no function there or the program
+/// doesn't compile.
+fn function_ir_hover(text: &str, offset: usize) -> Option<(String, Span)> {
+    let ast = parse(Lexer::new(text).tokenize()).ok()?;
+    let (name, span) = ast.items.iter().find_map(|item| match item {
+        CstNode::Function(func)
+            if func.name.span.start <= offset && offset <= func.name.span.end =>
+        {
+            match &func.name.kind {
+                TokenKind::Ident(name) => Some((name.clone(), func.name.span)),
+                _ => None,
+            }
+        }
+        _ => None,
+    })?;
+
+    let ast = rue_compiler::with_stdlib(&ast);
+    let scope = rue_semantic::analyze_cst(&ast).ok()?;
+    let instructions = Codegen::new()
+        .generate_single_function(&ast, &scope, &name)
+        .ok()?;
+    Some((dump_ir(&instructions), span))
+}
+
+/// The type of the innermost expression at `position`, for the
+/// `rue/typeof` command. Only expressions in functions have one.
+fn type_at(text: &str, position: Position) -> Option<String> {
+    let offset = position_to_offset(&SourceMap::new(text), position);
+    let ast = parse(Lexer::new(text).tokenize()).ok()?;
+    let name = ast.items.iter().find_map(|item| match item {
+        CstNode::Function(func) if func.span().start <= offset && offset <= func.span().end => {
+            match &func.name.kind {
+                TokenKind::Ident(name) => Some(name.clone()),
+                _ => None,
+            }
+        }
+        _ => None,
+    })?;
+
+    let ast = rue_compiler::with_stdlib(&ast);
+    let (ty, _) = rue_semantic::type_at(&ast, &name, offset)?;
+    Some(ty.to_string())
+}
+
+/// A "Run" lens above `fn main`, which interprets the program when clicked
+fn run_code_lens(uri: &Url, text: &str) -> Option<CodeLens> {
+    let ast = parse(Lexer::new(text).tokenize()).ok()?;
+    let main = ast.items.iter().find_map(|item| match item {
+        CstNode::Function(func) if func.name.kind == TokenKind::Ident("main".to_string()) => {
+            Some(func)
+        }
+        _ => None,
+    })?;
+
+    Some(CodeLens {
+        range: span_to_range(&SourceMap::new(text), main.name.span),
+        command: Some(Command {
+            title: "Run".to_string(),
+            command: RUN_COMMAND.to_string(),
+            arguments: Some(vec![serde_json::Value::String(uri.to_string())]),
+        }),
+        data: None,
+    })
+}
+
+/// Interpret a program and describe how it went, for the "Run" command.
+/// Interpreting needs no native toolchain, so this works on any platform.
+fn run_program(path: &str, text: &str) -> String {
+    let db = RueDatabase::default();
+    let file = SourceFile::new(&db, path.to_string(), text.to_string());
+    match interpret_file(&db, file, CompileOptions::default()) {
+        Ok(result) => format!("main returned {}", result),
+        Err(error) => format!("Run failed: {}", error.message),
+    }
+}
+
+/// A name that can be renamed: a function defined in the document, or a
+/// function's parameter or local variable
+#[derive(Debug, PartialEq)]
+struct Symbol {
+    /// The occurrence the lookup found
+    span: Span,
+    /// Every occurrence, including that one, in source order
+    occurrences: Vec<Span>,
+}
+
+/// The symbol whose name is at `offset`, or why there isn't one
+///
+/// Resolution is by name: a variable's occurrences are the identifiers with
+/// its name in the function that declares it, and a function's are those
+/// with its name everywhere else. Keywords, literals, type names and names
+/// that aren't defined in the document, like the standard library's, can't
+/// be renamed.
+fn symbol_at(text: &str, offset: usize) -> std::result::Result<Symbol, String> {
+    let tokens = Lexer::new(text).tokenize();
+    let ast = parse(tokens.clone()).map_err(|_| "Fix the syntax errors to rename".to_string())?;
+    let not_renameable = || "Only functions and variables can be renamed".to_string();
+
+    let index = tokens
+        .iter()
+        .position(|token| {
+            matches!(token.kind, TokenKind::Ident(_))
+                && token.span.start <= offset
+                && offset <= token.span.end
+        })
+        .ok_or_else(not_renameable)?;
+    let target = &tokens[index];
+
+    let functions: Vec<&FunctionNode> = ast
+        .items
+        .iter()
+        .filter_map(|item| match item {
+            CstNode::Function(func) => Some(&**func),
+            _ => None,
+        })
+        .collect();
+    let is_type_name = |span: Span| {
+        functions.iter().any(|func| {
+            func.return_type.as_ref().is_some_and(|ty| ty.span == span)
+                || func
+                    .param_list
+                    .params
+                    .iter()
+                    .any(|param| param.ty.as_ref().is_some_and(|ty| ty.span == span))
+        })
+    };
+    if is_type_name(target.span) {
+        return Err(not_renameable());
+    }
+
+    let function_range = |func: &FunctionNode| func.span().start..func.span().end;
+    // Whether a function has a parameter or variable with the target's name
+    let declares = |func: &FunctionNode| {
+        let range = function_range(func);
+        func.param_list
+            .params
+            .iter()
+            .any(|param| param.name.kind == target.kind)
+            || tokens.windows(2).any(|pair| {
+                matches!(pair[0].kind, TokenKind::Let | TokenKind::For)
+                    && pair[1].kind == target.kind
+                    && range.contains(&pair[1].span.start)
+            })
+    };
+    let occurrences = |include: &dyn Fn(usize) -> bool| {
+        tokens
+            .iter()
+            .filter(|token| {
+                token.kind == target.kind && include(token.span.start) && !is_type_name(token.span)
+            })
+            .map(|token| token.span)
+            .collect()
+    };
+
+    let variable_in = functions.iter().find(|func| {
+        function_range(func).contains(&target.span.start)
+            && declares(func)
+            && func.name.span != target.span
+    });
+    if let Some(func) = variable_in {
+        let range = function_range(func);
+        return Ok(Symbol {
+            span: target.span,
+            occurrences: occurrences(&|start| range.contains(&start)),
+        });
+    }
+
+    if functions.iter().any(|func| func.name.kind == target.kind) {
+        // Skip the functions where a variable hides it
+        let hidden: Vec<_> = functions
+            .iter()
+            .filter(|func| declares(func))
+            .map(|func| function_range(func))
+            .collect();
+        return Ok(Symbol {
+            span: target.span,
+            occurrences: occurrences(&|start| {
+                hidden.iter().all(|range| !range.contains(&start))
+                    || functions.iter().any(|func| func.name.span.start == start)
+            }),
+        });
+    }
+
+    Err(not_renameable())
+}
+
+/// Where to write `new_name` to rename the symbol at `offset`
+fn rename_spans(
+    text: &str,
+    offset: usize,
+    new_name: &str,
+) -> std::result::Result<Vec<Span>, String> {
+    let tokens = Lexer::new(new_name).tokenize();
+    if !matches!(
+        tokens.as_slice(),
+        [
+            rue_lexer::Token {
+                kind: TokenKind::Ident(name),
+                ..
+            },
+            _eof,
+        ] if name == new_name
+    ) {
+        return Err(format!("`{}` isn't a valid name", new_name));
+    }
+    Ok(symbol_at(text, offset)?.occurrences)
+}
+
+fn position_to_offset(source_map: &SourceMap, position: Position) -> usize {
+    source_map.byte_offset_utf16(rue_lexer::LineCol {
+        line: position.line as usize,
+        column: position.character as usize,
+    })
+}
+
+/// Convert a byte span into an LSP range, which counts UTF-16 code units
+fn span_to_range(source_map: &SourceMap, span: Span) -> Range {
+    let position = |byte| {
+        let position = source_map.line_col_utf16(byte);
+        Position {
+            line: position.line as u32,
+            character: position.column as u32,
+        }
+    };
+
+    Range {
+        start: position(span.start),
+        end: position(span.end),
+    }
+}
+
 pub async fn run_server() {
     let stdin = tokio::io::stdin();
     let stdout = tokio::io::stdout();
@@ -144,8 +514,7 @@ pub async fn run_server() {
 
 #[cfg(test)]
 mod tests {
-    use rue_lexer::Lexer;
-    use rue_parser::parse;
+    use super::*;
 
     #[test]
     fn test_while_loop_parsing() {
@@ -187,6 +556,10 @@ fn test_invalid() {
             result.is_err(),
             "Invalid while syntax should produce errors"
         );
+        assert_eq!(
+            result.unwrap_err().message,
+            "expected a condition expression before the block"
+        );
     }
 
     #[test]
@@ -209,4 +582,168 @@ fn main() {
 
         assert!(result.is_ok(), "Assignment should parse without errors");
     }
+
+    #[test]
+    fn test_span_to_range_uses_lines() {
+        let text = "fn main() {\n    let xé = 1;\n    xé +\n}";
+        let error = parse(Lexer::new(text).tokenize()).unwrap_err();
+        let range = span_to_range(&SourceMap::new(text), error.span);
+
+        // The error is reported at the closing brace on the fourth line
+        assert_eq!(
+            range.start,
+            Position {
+                line: 3,
+                character: 0
+            }
+        );
+        assert_eq!(
+            range.end,
+            Position {
+                line: 3,
+                character: 1
+            }
+        );
+
+        let plus = text.find('+').unwrap();
+        let range = span_to_range(
+            &SourceMap::new(text),
+            Span {
+                start: plus,
+                end: plus + 1,
+            },
+        );
+        assert_eq!(
+            range.start,
+            Position {
+                line: 2,
+                character: 7
+            }
+        );
+    }
+
+    #[test]
+    fn test_hover_shows_function_ir() {
+        let text = r#"
+fn factorial(n) {
+    if n <= 1 {
+        1
+    } else {
+        n * factorial(n - 1)
+    }
+}
+
+fn main() {
+    factorial(5)
+}
+"#;
+
+        let offset = text.find("factorial").unwrap() + 2;
+        let (ir, span) = function_ir_hover(text, offset).expect("hover should show IR");
+        assert!(ir.contains("Call"), "IR should contain a call: {}", ir);
+        assert_eq!(&text[span.start..span.end], "factorial");
+
+        // Hovering anywhere other than a function name shows nothing
+        assert!(function_ir_hover(text, text.find("<=").unwrap()).is_none());
+    }
+
+    #[test]
+    fn test_prepare_rename() {
+        let text =
+            "fn double(x: i64) {\n    let y = x;\n    y + y\n}\n\nfn main() {\n    double(21)\n}\n";
+        let at = |needle: &str| text.find(needle).unwrap();
+
+        // A variable's span is just its name, wherever in it the cursor is
+        let symbol = symbol_at(text, at("y =") + 1).unwrap();
+        assert_eq!(&text[symbol.span.start..symbol.span.end], "y");
+        assert_eq!(symbol.span.start, at("y ="));
+
+        // Keywords, literals and type names can't be renamed
+        let error = symbol_at(text, at("let") + 1).unwrap_err();
+        assert_eq!(error, "Only functions and variables can be renamed");
+        assert!(symbol_at(text, at("21")).is_err());
+        assert!(symbol_at(text, at("i64")).is_err());
+    }
+
+    #[test]
+    fn test_rename_occurrences() {
+        let text = "fn f(n) { n + 1 }\nfn g(f) { f * 2 }\nfn main() { let n = f(1); g(n) }";
+        let starts =
+            |spans: Vec<Span>| -> Vec<usize> { spans.iter().map(|span| span.start).collect() };
+
+        // A variable is renamed only in its own function
+        let spans = rename_spans(text, text.find("n)").unwrap(), "m").unwrap();
+        assert_eq!(starts(spans), vec![5, 10]);
+
+        // A function is renamed everywhere it isn't hidden by a variable
+        let spans = rename_spans(text, text.find("f(1)").unwrap(), "h").unwrap();
+        assert_eq!(starts(spans), vec![3, text.find("f(1)").unwrap()]);
+
+        let error = rename_spans(text, 3, "let").unwrap_err();
+        assert_eq!(error, "`let` isn't a valid name");
+        assert!(rename_spans(text, 3, "a b").is_err());
+    }
+
+    #[test]
+    fn test_run_code_lens_is_above_main() {
+        let uri = Url::parse("file:///test.rue").unwrap();
+        let text = "fn helper() { 1 }\n\nfn main() {\n    helper()\n}\n";
+        let lens = run_code_lens(&uri, text).expect("main should have a lens");
+
+        assert_eq!(
+            lens.range.start,
+            Position {
+                line: 2,
+                character: 3
+            }
+        );
+        let command = lens.command.unwrap();
+        assert_eq!(command.title, "Run");
+        assert_eq!(command.command, RUN_COMMAND);
+        assert_eq!(
+            command.arguments,
+            Some(vec![serde_json::Value::String(uri.to_string())])
+        );
+
+        assert!(run_code_lens(&uri, "fn helper() { 1 }").is_none());
+    }
+
+    #[test]
+    fn test_typeof_command() {
+        let text = "fn main() {\n    let b = 1 <= 2;\n    abs(-3)\n}\n";
+        let at = |line, character| type_at(text, Position { line, character });
+
+        assert_eq!(at(1, 15), Some("bool".to_string()));
+        // Calls into the standard library are known too
+        assert_eq!(at(2, 5), Some("i64".to_string()));
+        assert_eq!(at(0, 0), None);
+        assert_eq!(at(1, 5), None);
+    }
+
+    #[test]
+    fn test_run_command_reports_main_result() {
+        let text = include_str!("../../../samples/factorial.rue");
+        assert_eq!(run_program("factorial.rue", text), "main returned 120");
+
+        let message = run_program("broken.rue", "fn main() { missing() }");
+        assert!(message.starts_with("Run failed: "), "{}", message);
+    }
+
+    #[test]
+    fn test_unclosed_block_diagnostic_has_related_location() {
+        let uri = Url::parse("file:///test.rue").unwrap();
+        let text = "fn main() {\n    1\n";
+        let error = parse(Lexer::new(text).tokenize()).unwrap_err();
+        let diagnostic = parse_error_to_diagnostic(&uri, text, error);
+
+        let related = diagnostic.related_information.unwrap();
+        assert_eq!(related[0].message, "block opened here");
+        assert_eq!(
+            related[0].location.range.start,
+            Position {
+                line: 0,
+                character: 10
+            }
+        );
+    }
 }
@@ -0,0 +1,484 @@
+use rue_ast::*;
+use rue_lexer::TokenKind;
+
+/// Format a program in the standard style: four-space indentation, one
+/// statement per line, and a blank line around each function
+///
+/// Parentheses the program wrote are kept as they are. Others are only
+/// added where the tree's structure couldn't be read back from the text
+/// without them, as for a tree built by hand, so the output always parses to
+/// the same meaning. Literals are written in their plainest form; see
+/// `TokenKind::text`.
+pub fn format_cst(root: &CstRoot) -> String {
+    let mut formatter = Formatter::default();
+    let mut previous: Option<&CstNode> = None;
+    for item in &root.items {
+        if let Some(previous) = previous
+            && (matches!(previous, CstNode::Function(_)) || matches!(item, CstNode::Function(_)))
+        {
+            formatter.out.push('\n');
+        }
+        formatter.item(item);
+        previous = Some(item);
+    }
+    formatter.out
+}
+
+/// Format a single expression, as `format_cst` would
+pub fn format_expression(expr: &ExpressionNode) -> String {
+    let mut formatter = Formatter::default();
+    formatter.expression(expr);
+    formatter.out
+}
+
+// Binding power of each level of operator, from loosest to tightest
+const ASSIGNMENT: u8 = 0;
+const OR: u8 = 1;
+const AND: u8 = 2;
+const COMPARISON: u8 = 3;
+const ADDITIVE: u8 = 4;
+const MULTIPLICATIVE: u8 = 5;
+const UNARY: u8 = 6;
+const POWER: u8 = 7;
+const PRIMARY: u8 = 8;
+
+// How tightly an expression's outermost operator binds. Anything that isn't
+// an operator can go anywhere without parentheses.
+fn precedence(expr: &ExpressionNode) -> u8 {
+    match expr {
+        ExpressionNode::Binary(binary) => match binary.operator.kind {
+            TokenKind::PipePipe => OR,
+            TokenKind::AmpAmp => AND,
+            TokenKind::Plus | TokenKind::Minus => ADDITIVE,
+            TokenKind::Star | TokenKind::Slash | TokenKind::Percent => MULTIPLICATIVE,
+            TokenKind::StarStar => POWER,
+            _ => COMPARISON,
+        },
+        ExpressionNode::Unary(_) => UNARY,
+        ExpressionNode::Assign(_) => ASSIGNMENT,
+        _ => PRIMARY,
+    }
+}
+
+#[derive(Default)]
+struct Formatter {
+    out: String,
+    indent: usize,
+}
+
+impl Formatter {
+    fn item(&mut self, item: &CstNode) {
+        match item {
+            CstNode::Function(func) => self.function(func),
+            CstNode::Statement(stmt) => self.statement(stmt),
+            CstNode::Expression(expr) => {
+                self.expression(expr);
+                self.out.push('\n');
+            }
+            CstNode::Token(token) => {
+                self.out.push_str(&token.kind.text());
+                self.out.push('\n');
+            }
+            // Code that didn't parse is kept, just spaced out
+            CstNode::Error(error) => {
+                let tokens: Vec<String> = error.tokens.iter().map(|t| t.kind.text()).collect();
+                self.out.push_str(&tokens.join(" "));
+                self.out.push('\n');
+            }
+        }
+    }
+
+    fn function(&mut self, func: &FunctionNode) {
+        self.out.push_str("fn ");
+        self.out.push_str(&func.name.kind.text());
+        self.out.push('(');
+        for (i, param) in func.param_list.params.iter().enumerate() {
+            if i > 0 {
+                self.out.push_str(", ");
+            }
+            self.out.push_str(&param.name.kind.text());
+            if let Some(ty) = &param.ty {
+                self.out.push_str(": ");
+                self.out.push_str(&ty.kind.text());
+            }
+        }
+        self.out.push(')');
+        if let Some(return_type) = &func.return_type {
+            self.out.push_str(" -> ");
+            self.out.push_str(&return_type.kind.text());
+        }
+        self.out.push(' ');
+        self.block(&func.body);
+        self.out.push('\n');
+    }
+
+    // A block, starting where the cursor is and ending after its `}`
+    fn block(&mut self, block: &BlockNode) {
+        if block.statements.is_empty() && block.final_expr.is_none() {
+            self.out.push_str("{}");
+            return;
+        }
+
+        self.out.push_str("{\n");
+        self.indent += 1;
+        for stmt in &block.statements {
+            self.statement(stmt);
+        }
+        if let Some(final_expr) = &block.final_expr {
+            self.start_line();
+            self.expression(final_expr);
+            self.out.push('\n');
+        }
+        self.indent -= 1;
+        self.start_line();
+        self.out.push('}');
+    }
+
+    // A statement on its own line
+    fn statement(&mut self, stmt: &StatementNode) {
+        self.start_line();
+        match stmt {
+            StatementNode::Let(let_stmt) => {
+                self.out.push_str("let ");
+                self.out.push_str(&let_stmt.name.kind.text());
+                self.out.push_str(" = ");
+                self.expression(&let_stmt.value);
+            }
+            StatementNode::Const(const_stmt) => {
+                self.out.push_str("const ");
+                self.out.push_str(&const_stmt.name.kind.text());
+                self.out.push_str(" = ");
+                self.expression(&const_stmt.value);
+            }
+            StatementNode::Assign(assign_stmt) => {
+                self.out.push_str(&assign_stmt.name.kind.text());
+                self.out.push(' ');
+                self.out.push_str(&assign_stmt.operator.kind.text());
+                self.out.push(' ');
+                self.expression(&assign_stmt.value);
+            }
+            StatementNode::IndexAssign(assign_stmt) => {
+                self.index(&assign_stmt.target);
+                self.out.push(' ');
+                self.out.push_str(&assign_stmt.operator.kind.text());
+                self.out.push(' ');
+                self.expression(&assign_stmt.value);
+            }
+            StatementNode::Expression(expr_stmt) => self.expression(&expr_stmt.expression),
+            StatementNode::Return(return_stmt) => {
+                self.out.push_str("return");
+                if let Some(value) = &return_stmt.value {
+                    self.out.push(' ');
+                    self.expression(value);
+                }
+            }
+            StatementNode::Break(_) => self.out.push_str("break"),
+            StatementNode::Continue(_) => self.out.push_str("continue"),
+            // Kept like an item that didn't parse, with its own `;` if it
+            // got as far as one
+            StatementNode::Error(error) => {
+                let tokens: Vec<String> = error.tokens.iter().map(|t| t.kind.text()).collect();
+                self.out.push_str(&tokens.join(" "));
+                self.out.push('\n');
+                return;
+            }
+        }
+        self.out.push_str(";\n");
+    }
+
+    fn expression(&mut self, expr: &ExpressionNode) {
+        match expr {
+            ExpressionNode::Binary(binary) => {
+                // Operators are left-associative, so a right operand at the
+                // same level needs parentheses to stay grouped: `a - (b - c)`.
+                // Comparisons don't chain at all, so need them on either side.
+                // `**` is the other way around, and its exponent can be a
+                // unary expression.
+                let level = precedence(expr);
+                let (left_level, right_level) = match level {
+                    COMPARISON => (level + 1, level + 1),
+                    POWER => (level + 1, UNARY),
+                    _ => (level, level + 1),
+                };
+                self.operand(&binary.left, left_level);
+                self.out.push(' ');
+                self.out.push_str(&binary.operator.kind.text());
+                self.out.push(' ');
+                self.operand(&binary.right, right_level);
+            }
+            ExpressionNode::Unary(unary) => {
+                self.out.push_str(&unary.operator.kind.text());
+                self.operand(&unary.operand, UNARY);
+            }
+            ExpressionNode::Paren(paren) => {
+                self.out.push('(');
+                self.expression(&paren.inner);
+                self.out.push(')');
+            }
+            ExpressionNode::Call(call) => {
+                self.operand(&call.function, PRIMARY);
+                self.out.push('(');
+                for (i, arg) in call.args.iter().enumerate() {
+                    if i > 0 {
+                        self.out.push_str(", ");
+                    }
+                    self.expression(arg);
+                }
+                self.out.push(')');
+            }
+            ExpressionNode::ArrayLit(array) => {
+                self.out.push('[');
+                for (i, element) in array.elements.iter().enumerate() {
+                    if i > 0 {
+                        self.out.push_str(", ");
+                    }
+                    self.expression(element);
+                }
+                self.out.push(']');
+            }
+            ExpressionNode::Index(index_expr) => self.index(index_expr),
+            ExpressionNode::Assign(assign_expr) => {
+                self.out.push_str(&assign_expr.name.kind.text());
+                self.out.push(' ');
+                self.out.push_str(&assign_expr.operator.kind.text());
+                self.out.push(' ');
+                self.expression(&assign_expr.value);
+            }
+            ExpressionNode::If(if_stmt) => self.if_expression(if_stmt),
+            ExpressionNode::Block(block) => self.block(block),
+            ExpressionNode::While(while_stmt) => {
+                self.out.push_str("while ");
+                self.expression(&while_stmt.condition);
+                self.out.push(' ');
+                self.block(&while_stmt.body);
+            }
+            ExpressionNode::For(for_stmt) => {
+                self.out.push_str("for ");
+                self.out.push_str(&for_stmt.variable.kind.text());
+                self.out.push_str(" in ");
+                self.expression(&for_stmt.start);
+                self.out.push_str("..");
+                self.expression(&for_stmt.end);
+                self.out.push(' ');
+                self.block(&for_stmt.body);
+            }
+            ExpressionNode::Identifier(token) | ExpressionNode::Literal(token) => {
+                self.out.push_str(&token.kind.text())
+            }
+        }
+    }
+
+    // An operand of an operator that binds at `level`, in parentheses if
+    // it binds more loosely than that
+    fn operand(&mut self, expr: &ExpressionNode, level: u8) {
+        if precedence(expr) < level {
+            self.out.push('(');
+            self.expression(expr);
+            self.out.push(')');
+        } else {
+            self.expression(expr);
+        }
+    }
+
+    fn index(&mut self, index_expr: &IndexExprNode) {
+        self.operand(&index_expr.array, PRIMARY);
+        self.out.push('[');
+        self.expression(&index_expr.index);
+        self.out.push(']');
+    }
+
+    fn if_expression(&mut self, if_stmt: &IfStatementNode) {
+        self.out.push_str("if ");
+        self.expression(&if_stmt.condition);
+        self.out.push(' ');
+        self.block(&if_stmt.then_block);
+        if let Some(else_clause) = &if_stmt.else_clause {
+            self.out.push_str(" else ");
+            match &else_clause.body {
+                ElseBodyNode::Block(block) => self.block(block),
+                ElseBodyNode::If(nested_if) => self.if_expression(nested_if),
+            }
+        }
+    }
+
+    fn start_line(&mut self) {
+        self.out.push_str(&"    ".repeat(self.indent));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rue_lexer::{Lexer, Span, Token};
+
+    fn format_source(source: &str) -> String {
+        format_cst(&crate::parse(Lexer::new(source).tokenize()).unwrap())
+    }
+
+    fn token(kind: TokenKind) -> Token {
+        Token {
+            kind,
+            span: Span { start: 0, end: 0 },
+        }
+    }
+
+    fn binary(left: ExpressionNode, operator: TokenKind, right: ExpressionNode) -> ExpressionNode {
+        ExpressionNode::Binary(BinaryExprNode {
+            left: Box::new(left),
+            operator: token(operator),
+            right: Box::new(right),
+            trivia: Trivia::default(),
+        })
+    }
+
+    fn int(value: i64) -> ExpressionNode {
+        ExpressionNode::Literal(token(TokenKind::Integer(value)))
+    }
+
+    #[test]
+    fn test_precedence_round_trips() {
+        // No parentheses are added where precedence already groups things,
+        // and the ones written are kept
+        for source in [
+            "2 + 3 * 4",
+            "(2 + 3) * 4",
+            "2 * 3 + 4",
+            "((2))",
+            "-(1 + 2)",
+            "2 ** 3 ** 2",
+            "-2 ** -1",
+        ] {
+            let program = format!("fn main() {{\n    {}\n}}\n", source);
+            assert_eq!(format_source(&program), program);
+        }
+    }
+
+    #[test]
+    fn test_parentheses_added_where_needed() {
+        // `(2 + 3) * 4` with no parenthesized node in the tree
+        let expr = binary(
+            binary(int(2), TokenKind::Plus, int(3)),
+            TokenKind::Star,
+            int(4),
+        );
+        assert_eq!(format_expression(&expr), "(2 + 3) * 4");
+
+        // ...and `2 + 3 * 4` needs none
+        let expr = binary(
+            int(2),
+            TokenKind::Plus,
+            binary(int(3), TokenKind::Star, int(4)),
+        );
+        assert_eq!(format_expression(&expr), "2 + 3 * 4");
+
+        // Same-level operators on the right need them, on the left they don't
+        let expr = binary(
+            int(1),
+            TokenKind::Minus,
+            binary(int(2), TokenKind::Minus, int(3)),
+        );
+        assert_eq!(format_expression(&expr), "1 - (2 - 3)");
+        let expr = binary(
+            binary(int(1), TokenKind::Minus, int(2)),
+            TokenKind::Minus,
+            int(3),
+        );
+        assert_eq!(format_expression(&expr), "1 - 2 - 3");
+
+        // Comparisons can't be chained, so either side needs them
+        let expr = binary(
+            binary(int(1), TokenKind::Less, int(2)),
+            TokenKind::Equal,
+            int(3),
+        );
+        assert_eq!(format_expression(&expr), "(1 < 2) == 3");
+
+        // `**` is right-associative, so it's the other way around
+        let expr = binary(
+            binary(int(2), TokenKind::StarStar, int(3)),
+            TokenKind::StarStar,
+            int(2),
+        );
+        assert_eq!(format_expression(&expr), "(2 ** 3) ** 2");
+
+        // Unary operators bind tighter than any binary one but `**`
+        let negated = |operand| {
+            ExpressionNode::Unary(UnaryExprNode {
+                operator: token(TokenKind::Minus),
+                operand: Box::new(operand),
+                trivia: Trivia::default(),
+            })
+        };
+        let expr = binary(negated(int(2)), TokenKind::StarStar, int(2));
+        assert_eq!(format_expression(&expr), "(-2) ** 2");
+        let expr = ExpressionNode::Unary(UnaryExprNode {
+            operator: token(TokenKind::Minus),
+            operand: Box::new(binary(int(1), TokenKind::Plus, int(2))),
+            trivia: Trivia::default(),
+        });
+        assert_eq!(format_expression(&expr), "-(1 + 2)");
+
+        let expr = binary(
+            binary(int(1), TokenKind::PipePipe, int(2)),
+            TokenKind::AmpAmp,
+            binary(int(3), TokenKind::Less, int(4)),
+        );
+        assert_eq!(format_expression(&expr), "(1 || 2) && 3 < 4");
+
+        // Assignment binds loosest of all
+        let assign = ExpressionNode::Assign(AssignExprNode {
+            name: token(TokenKind::Ident("x".to_string())),
+            operator: token(TokenKind::Assign),
+            value: Box::new(int(1)),
+            trivia: Trivia::default(),
+        });
+        assert_eq!(format_expression(&assign), "x = 1");
+        let expr = binary(assign, TokenKind::Plus, int(2));
+        assert_eq!(format_expression(&expr), "(x = 1) + 2");
+    }
+
+    #[test]
+    fn test_format_program() {
+        let source = "const N=1+2; fn f(x: i64)->i64{let y=x*2;if y>10{return y;};y=y+1;y*=2;let a=[y,2];a[0]+=1;a[1]} \
+                      fn main(){if f(1)==3{1}else if 0<1{2}else{while 0>1{};for i in 0..3{if i>1{break;};continue;};3}}";
+        assert_eq!(
+            format_source(source),
+            "\
+const N = 1 + 2;
+
+fn f(x: i64) -> i64 {
+    let y = x * 2;
+    if y > 10 {
+        return y;
+    };
+    y = y + 1;
+    y *= 2;
+    let a = [y, 2];
+    a[0] += 1;
+    a[1]
+}
+
+fn main() {
+    if f(1) == 3 {
+        1
+    } else if 0 < 1 {
+        2
+    } else {
+        while 0 > 1 {};
+        for i in 0..3 {
+            if i > 1 {
+                break;
+            };
+            continue;
+        };
+        3
+    }
+}
+"
+        );
+
+        // Formatting is stable
+        let formatted = format_source(source);
+        assert_eq!(format_source(&formatted), formatted);
+    }
+}
@@ -1,9 +1,18 @@
 use rue_ast::*;
 use rue_lexer::{Span, TokenKind};
 
+mod format;
+pub use format::{format_cst, format_expression};
+
 pub struct Parser {
     tokens: Vec<TokenNode>,
+    /// The comments just before each token, each with whether there was a
+    /// line break before it since the last token or comment
+    comments: Vec<Vec<(TokenNode, bool)>>,
     current: usize,
+    /// Errors parsing carried on from without giving up on the item they're
+    /// in, like a missing `;`
+    errors: Vec<ParseError>,
 }
 
 pub type ParseResult<T> = Result<T, ParseError>;
@@ -12,28 +21,177 @@ pub type ParseResult<T> = Result<T, ParseError>;
 pub struct ParseError {
     pub message: String,
     pub span: Span,
+    /// The tokens just before and after the failure point
+    pub context: Vec<TokenNode>,
+    /// Another location that explains the error, like the opening brace of a
+    /// block that was never closed
+    pub related: Option<(String, Span)>,
 }
 
+// Number of tokens kept on either side of an error for context
+const CONTEXT_TOKENS: usize = 2;
+
 impl Parser {
+    /// A parser over tokens from the lexer, which can include trivia, as from
+    /// `Lexer::tokenize_with_trivia`. Comments become the trivia of the nodes
+    /// around them, and whitespace is only used to tell which.
     pub fn new(tokens: Vec<TokenNode>) -> Self {
-        Self { tokens, current: 0 }
+        let mut kept = Vec::new();
+        let mut comments = Vec::new();
+        let mut pending = Vec::new();
+        let mut line_break = true;
+        for token in tokens {
+            match &token.kind {
+                TokenKind::Whitespace(text) => line_break |= text.contains('\n'),
+                TokenKind::Comment(_) => {
+                    pending.push((token, line_break));
+                    line_break = false;
+                }
+                _ => {
+                    kept.push(token);
+                    comments.push(std::mem::take(&mut pending));
+                    line_break = false;
+                }
+            }
+        }
+
+        Self {
+            tokens: kept,
+            comments,
+            current: 0,
+            errors: Vec::new(),
+        }
     }
 
-    pub fn parse(mut self) -> ParseResult<CstRoot> {
+    /// Parse the tokens, failing with the first error
+    pub fn parse(self) -> ParseResult<CstRoot> {
+        let (root, mut errors) = self.parse_with_recovery();
+        if errors.is_empty() {
+            Ok(root)
+        } else {
+            Err(errors.swap_remove(0))
+        }
+    }
+
+    /// Parse the tokens, carrying on past errors to find as many as possible
+    ///
+    /// After an error in a block, the rest of the statement it's in is
+    /// skipped and kept in the tree as a `StatementNode::Error`; see
+    /// `recover_statement`. Anywhere else, the rest of the item is skipped
+    /// and kept as an `ErrorNode`; see `recover`. A missing `;` before
+    /// another statement is reported without skipping anything. The errors
+    /// are returned in source order, one for each place, so a token the
+    /// lexer couldn't make sense of is reported as the lexer's error rather
+    /// than the parser's.
+    pub fn parse_with_recovery(mut self) -> (CstRoot, Vec<ParseError>) {
+        let mut errors: Vec<ParseError> = self
+            .tokens
+            .iter()
+            .enumerate()
+            .filter_map(|(index, token)| match &token.kind {
+                TokenKind::Error(message) => {
+                    Some(self.error_at(index, message.clone(), token.span))
+                }
+                _ => None,
+            })
+            .collect();
+
         let mut items = Vec::new();
-        let leading_trivia = self.consume_trivia();
 
         while !self.is_at_end() {
-            items.push(self.parse_item()?);
+            let start = self.current;
+            match self.parse_item() {
+                Ok(item) => items.push(item),
+                Err(error) => {
+                    items.push(CstNode::Error(self.recover(start, &error)));
+                    errors.push(error);
+                }
+            }
         }
 
-        Ok(CstRoot {
+        errors.append(&mut self.errors);
+        errors.sort_by_key(|error| error.span.start);
+        errors.dedup_by_key(|error| error.span);
+
+        // Comments before the first item are that item's, so the root only
+        // has the ones after the last
+        let root = CstRoot {
             items,
             trivia: Trivia {
-                leading: leading_trivia,
-                trailing: vec![],
+                leading: vec![],
+                trailing: self.consume_trivia(),
             },
-        })
+        };
+        (root, errors)
+    }
+
+    // Skip the rest of an item that failed to parse, starting from its first
+    // token at `start`, up to the next statement boundary after the error:
+    // a top-level `;` or the `}` that closes the item, which are skipped
+    // too, or the `fn` that begins the next function
+    fn recover(&mut self, start: usize, error: &ParseError) -> ErrorNode {
+        let failed_at = self.current;
+        self.current = start;
+
+        let mut depth = 0i32;
+        let mut tokens = Vec::new();
+        while !self.is_at_end() {
+            let past_error = self.current >= failed_at;
+            match self.peek().kind {
+                TokenKind::Fn if self.current > start && past_error => break,
+                TokenKind::LeftBrace => depth += 1,
+                TokenKind::RightBrace => depth -= 1,
+                _ => {}
+            }
+
+            let token = self.advance();
+            let boundary = matches!(token.kind, TokenKind::Semicolon | TokenKind::RightBrace);
+            tokens.push(token);
+            if past_error && depth <= 0 && boundary {
+                break;
+            }
+        }
+
+        ErrorNode {
+            tokens,
+            message: error.message.clone(),
+            trivia: Trivia::default(),
+        }
+    }
+
+    // Skip the rest of a statement in a block that failed to parse, starting
+    // from its first token at `start`, up to the `;` that ends it, which is
+    // skipped too, or the `}` that closes the block, or the `fn` that begins
+    // the next function if the block is never closed
+    fn recover_statement(&mut self, start: usize, error: &ParseError) -> ErrorNode {
+        let failed_at = self.current;
+        self.current = start;
+
+        let mut depth = 0i32;
+        let mut tokens = Vec::new();
+        while !self.is_at_end() {
+            let past_error = self.current >= failed_at;
+            match self.peek().kind {
+                TokenKind::Fn if self.current > start && past_error => break,
+                TokenKind::RightBrace if depth == 0 && self.current > start => break,
+                TokenKind::LeftBrace => depth += 1,
+                TokenKind::RightBrace => depth -= 1,
+                _ => {}
+            }
+
+            let token = self.advance();
+            let semicolon = matches!(token.kind, TokenKind::Semicolon);
+            tokens.push(token);
+            if past_error && depth == 0 && semicolon {
+                break;
+            }
+        }
+
+        ErrorNode {
+            tokens,
+            message: error.message.clone(),
+            trivia: Trivia::default(),
+        }
     }
 
     fn parse_item(&mut self) -> ParseResult<CstNode> {
@@ -51,16 +209,24 @@ impl Parser {
         let fn_token = self.expect_kind(&TokenKind::Fn)?;
         let name = self.expect_ident()?;
         let param_list = self.parse_param_list()?;
+        let (arrow, return_type) = if self.check_kind(&TokenKind::Arrow) {
+            let arrow = self.advance();
+            (Some(arrow), Some(self.expect_ident()?))
+        } else {
+            (None, None)
+        };
         let body = self.parse_block()?;
 
         Ok(FunctionNode {
             fn_token,
             name,
             param_list,
+            arrow,
+            return_type,
             body,
             trivia: Trivia {
                 leading: leading_trivia,
-                trailing: self.consume_trivia(),
+                trailing: self.consume_trailing_trivia(),
             },
         })
     }
@@ -69,10 +235,19 @@ impl Parser {
         let leading_trivia = self.consume_trivia();
         let open_paren = self.expect_kind(&TokenKind::LeftParen)?;
 
+        // Parameters are separated by commas, and can have one trailing
+        // comma, but a comma alone isn't a parameter list
         let mut params = Vec::new();
+        let mut commas = Vec::new();
         if !self.check_kind(&TokenKind::RightParen) {
-            params.push(self.expect_ident()?);
-            // TODO: Handle multiple parameters with commas
+            params.push(self.parse_param()?);
+            while self.check_kind(&TokenKind::Comma) {
+                commas.push(self.advance());
+                if self.check_kind(&TokenKind::RightParen) {
+                    break;
+                }
+                params.push(self.parse_param()?);
+            }
         }
 
         let close_paren = self.expect_kind(&TokenKind::RightParen)?;
@@ -80,10 +255,33 @@ impl Parser {
         Ok(ParamListNode {
             open_paren,
             params,
+            commas,
             close_paren,
             trivia: Trivia {
                 leading: leading_trivia,
-                trailing: self.consume_trivia(),
+                trailing: self.consume_trailing_trivia(),
+            },
+        })
+    }
+
+    // A parameter name, optionally annotated with its type: `x` or `x: i64`
+    fn parse_param(&mut self) -> ParseResult<ParamNode> {
+        let leading_trivia = self.consume_trivia();
+        let name = self.expect_ident()?;
+        let (colon, ty) = if self.check_kind(&TokenKind::Colon) {
+            let colon = self.advance();
+            (Some(colon), Some(self.expect_ident()?))
+        } else {
+            (None, None)
+        };
+
+        Ok(ParamNode {
+            name,
+            colon,
+            ty,
+            trivia: Trivia {
+                leading: leading_trivia,
+                trailing: self.consume_trailing_trivia(),
             },
         })
     }
@@ -92,37 +290,35 @@ impl Parser {
         let leading_trivia = self.consume_trivia();
         let open_brace = self.expect_kind(&TokenKind::LeftBrace)?;
 
+        // A statement that fails to parse is skipped up to the next
+        // statement boundary, and parsing carries on from there. A `fn` ends
+        // a block that was never closed.
         let mut statements = Vec::new();
         let mut final_expr = None;
-
-        while !self.check_kind(&TokenKind::RightBrace) && !self.is_at_end() {
-            // Try to parse as statement first
-            if self.is_statement_start() {
-                statements.push(self.parse_statement()?);
-            } else {
-                // Parse as potential final expression
-                let expr = self.parse_expression()?;
-
-                // If followed by semicolon, it's an expression statement
-                if self.check_kind(&TokenKind::Semicolon) {
-                    let semicolon = self.advance();
-                    statements.push(StatementNode::Expression(ExpressionStatementNode {
-                        expression: expr,
-                        semicolon,
-                        trivia: Trivia {
-                            leading: vec![],
-                            trailing: self.consume_trivia(),
-                        },
-                    }));
-                } else {
-                    // No semicolon - this is the final expression
+        while !self.check_kind(&TokenKind::RightBrace)
+            && !self.check_kind(&TokenKind::Fn)
+            && !self.is_at_end()
+        {
+            let start = self.current;
+            match self.parse_block_entry(&mut statements) {
+                Ok(Some(expr)) => {
                     final_expr = Some(expr);
                     break;
                 }
+                Ok(None) => {}
+                Err(error) => {
+                    statements.push(StatementNode::Error(self.recover_statement(start, &error)));
+                    self.errors.push(error);
+                }
             }
         }
 
-        let close_brace = self.expect_kind(&TokenKind::RightBrace)?;
+        let close_brace = self
+            .expect_kind(&TokenKind::RightBrace)
+            .map_err(|error| ParseError {
+                related: Some(("block opened here".to_string(), open_brace.span)),
+                ..error
+            })?;
 
         Ok(BlockNode {
             open_brace,
@@ -131,21 +327,89 @@ impl Parser {
             close_brace,
             trivia: Trivia {
                 leading: leading_trivia,
-                trailing: self.consume_trivia(),
+                trailing: self.consume_trailing_trivia(),
             },
         })
     }
 
+    // Parse the next statement in a block onto `statements`, or the block's
+    // final expression, which is returned
+    fn parse_block_entry(
+        &mut self,
+        statements: &mut Vec<StatementNode>,
+    ) -> ParseResult<Option<ExpressionNode>> {
+        // Try to parse as statement first
+        if self.is_statement_start() {
+            statements.push(self.parse_statement()?);
+            return Ok(None);
+        }
+
+        // Parse as potential final expression, unless it's the element an
+        // assignment is to
+        let leading_trivia = self.consume_trivia();
+        let mut expr = match self.parse_expression()? {
+            ExpressionNode::Index(target) if is_assignment_operator(&self.peek().kind) => {
+                statements.push(self.parse_index_assignment(target, leading_trivia)?);
+                return Ok(None);
+            }
+            expr => expr,
+        };
+
+        // If followed by semicolon, it's an expression statement
+        if self.check_kind(&TokenKind::Semicolon) {
+            let semicolon = self.advance();
+            statements.push(StatementNode::Expression(ExpressionStatementNode {
+                expression: expr,
+                semicolon,
+                trivia: Trivia {
+                    leading: leading_trivia,
+                    trailing: self.consume_trailing_trivia(),
+                },
+            }));
+            Ok(None)
+        } else if self.is_missing_semicolon() || self.starts_operand() {
+            // Another statement, or another expression, follows, so this one
+            // can't be the final expression, and must have been meant to end
+            // with a `;`
+            let semicolon = self.missing_semicolon();
+            statements.push(StatementNode::Expression(ExpressionStatementNode {
+                expression: expr,
+                semicolon,
+                trivia: Trivia {
+                    leading: leading_trivia,
+                    trailing: vec![],
+                },
+            }));
+            Ok(None)
+        } else {
+            // Comments before it are its own, if it has trivia to keep them
+            // in, which a lone name or number doesn't
+            if let Some(trivia) = expr.trivia_mut() {
+                trivia.leading.splice(0..0, leading_trivia);
+            }
+            // No semicolon - this is the final expression, so only the
+            // closing brace can follow it
+            if !self.check_kind(&TokenKind::RightBrace) && !self.is_at_end() {
+                return Err(self.error(
+                    "unexpected token after block's final expression; \
+                     did you forget a semicolon?"
+                        .to_string(),
+                ));
+            }
+            Ok(Some(expr))
+        }
+    }
+
     fn is_statement_start(&self) -> bool {
         match self.peek().kind {
-            TokenKind::Let => true,
-            TokenKind::Ident(_) => {
-                // Check if this is an assignment statement (identifier = expression)
-                if self.current + 1 < self.tokens.len() {
-                    matches!(self.tokens[self.current + 1].kind, TokenKind::Assign)
-                } else {
-                    false
-                }
+            TokenKind::Let
+            | TokenKind::Const
+            | TokenKind::Return
+            | TokenKind::Break
+            | TokenKind::Continue => true,
+            // Check if this is an assignment statement (identifier = expression)
+            TokenKind::Ident(_) if self.current + 1 < self.tokens.len() => {
+                is_assignment_operator(&self.tokens[self.current + 1].kind)
             }
             _ => false,
         }
@@ -154,44 +418,78 @@ impl Parser {
     fn parse_statement(&mut self) -> ParseResult<StatementNode> {
         match self.peek().kind {
             TokenKind::Let => Ok(StatementNode::Let(self.parse_let_statement()?)),
+            TokenKind::Const => Ok(StatementNode::Const(self.parse_const_statement()?)),
+            TokenKind::Return => Ok(StatementNode::Return(self.parse_return_statement()?)),
+            TokenKind::Break => {
+                let leading_trivia = self.consume_trivia();
+                let break_token = self.advance();
+                let semicolon = self.expect_semicolon()?;
+                Ok(StatementNode::Break(BreakStatementNode {
+                    break_token,
+                    semicolon,
+                    trivia: Trivia {
+                        leading: leading_trivia,
+                        trailing: self.consume_trailing_trivia(),
+                    },
+                }))
+            }
+            TokenKind::Continue => {
+                let leading_trivia = self.consume_trivia();
+                let continue_token = self.advance();
+                let semicolon = self.expect_semicolon()?;
+                Ok(StatementNode::Continue(ContinueStatementNode {
+                    continue_token,
+                    semicolon,
+                    trivia: Trivia {
+                        leading: leading_trivia,
+                        trailing: self.consume_trailing_trivia(),
+                    },
+                }))
+            }
             TokenKind::Ident(_) => {
                 // Look ahead to see if this is an assignment (identifier = expression)
                 if self.current + 1 < self.tokens.len() {
                     match &self.tokens[self.current + 1].kind {
-                        TokenKind::Assign => {
+                        kind if is_assignment_operator(kind) => {
                             Ok(StatementNode::Assign(self.parse_assign_statement()?))
                         }
                         _ => {
                             // This is an expression statement - parse expression + semicolon
-                            let expr = self.parse_expression()?;
-                            let semicolon = self.expect_kind(&TokenKind::Semicolon)?;
+                            let leading_trivia = self.consume_trivia();
+                            let expr = match self.parse_expression()? {
+                                ExpressionNode::Index(target)
+                                    if is_assignment_operator(&self.peek().kind) =>
+                                {
+                                    return self.parse_index_assignment(target, leading_trivia);
+                                }
+                                expr => expr,
+                            };
+                            let semicolon = self.expect_semicolon()?;
                             Ok(StatementNode::Expression(ExpressionStatementNode {
                                 expression: expr,
                                 semicolon,
                                 trivia: Trivia {
-                                    leading: vec![],
-                                    trailing: self.consume_trivia(),
+                                    leading: leading_trivia,
+                                    trailing: self.consume_trailing_trivia(),
                                 },
                             }))
                         }
                     }
                 } else {
-                    Err(ParseError {
-                        message: "Unexpected end of input".to_string(),
-                        span: self.peek().span,
-                    })
+                    Err(self.error("Unexpected end of input".to_string()))
                 }
             }
             _ => {
                 // Expression statement
+                let leading_trivia = self.consume_trivia();
                 let expr = self.parse_expression()?;
-                let semicolon = self.expect_kind(&TokenKind::Semicolon)?;
+                let semicolon = self.expect_semicolon()?;
                 Ok(StatementNode::Expression(ExpressionStatementNode {
                     expression: expr,
                     semicolon,
                     trivia: Trivia {
-                        leading: vec![],
-                        trailing: self.consume_trivia(),
+                        leading: leading_trivia,
+                        trailing: self.consume_trailing_trivia(),
                     },
                 }))
             }
@@ -204,7 +502,7 @@ impl Parser {
         let name = self.expect_ident()?;
         let equals = self.expect_kind(&TokenKind::Assign)?;
         let value = self.parse_expression()?;
-        let semicolon = self.expect_kind(&TokenKind::Semicolon)?;
+        let semicolon = self.expect_semicolon()?;
 
         Ok(LetStatementNode {
             let_token,
@@ -214,26 +512,92 @@ impl Parser {
             semicolon,
             trivia: Trivia {
                 leading: leading_trivia,
-                trailing: self.consume_trivia(),
+                trailing: self.consume_trailing_trivia(),
             },
         })
     }
 
-    fn parse_assign_statement(&mut self) -> ParseResult<AssignStatementNode> {
+    fn parse_const_statement(&mut self) -> ParseResult<ConstStatementNode> {
         let leading_trivia = self.consume_trivia();
+        let const_token = self.expect_kind(&TokenKind::Const)?;
         let name = self.expect_ident()?;
         let equals = self.expect_kind(&TokenKind::Assign)?;
         let value = self.parse_expression()?;
-        let semicolon = self.expect_kind(&TokenKind::Semicolon)?;
+        let semicolon = self.expect_semicolon()?;
 
-        Ok(AssignStatementNode {
+        Ok(ConstStatementNode {
+            const_token,
             name,
             equals,
             value,
             semicolon,
             trivia: Trivia {
                 leading: leading_trivia,
-                trailing: self.consume_trivia(),
+                trailing: self.consume_trailing_trivia(),
+            },
+        })
+    }
+
+    fn parse_assign_statement(&mut self) -> ParseResult<AssignStatementNode> {
+        let leading_trivia = self.consume_trivia();
+        let name = self.expect_ident()?;
+        // Only called once the operator has been seen
+        let operator = self.advance();
+        let value = self.parse_expression()?;
+        let semicolon = self.expect_semicolon()?;
+
+        Ok(AssignStatementNode {
+            name,
+            operator,
+            value,
+            semicolon,
+            trivia: Trivia {
+                leading: leading_trivia,
+                trailing: self.consume_trailing_trivia(),
+            },
+        })
+    }
+
+    // The rest of `array[index] = value;`, after the element, which came
+    // after `leading_trivia`
+    fn parse_index_assignment(
+        &mut self,
+        target: IndexExprNode,
+        leading_trivia: Vec<TokenNode>,
+    ) -> ParseResult<StatementNode> {
+        let operator = self.advance();
+        let value = self.parse_expression()?;
+        let semicolon = self.expect_semicolon()?;
+
+        Ok(StatementNode::IndexAssign(IndexAssignStatementNode {
+            target,
+            operator,
+            value,
+            semicolon,
+            trivia: Trivia {
+                leading: leading_trivia,
+                trailing: self.consume_trailing_trivia(),
+            },
+        }))
+    }
+
+    fn parse_return_statement(&mut self) -> ParseResult<ReturnStatementNode> {
+        let leading_trivia = self.consume_trivia();
+        let return_token = self.expect_kind(&TokenKind::Return)?;
+        let value = if self.check_kind(&TokenKind::Semicolon) {
+            None
+        } else {
+            Some(self.parse_expression()?)
+        };
+        let semicolon = self.expect_semicolon()?;
+
+        Ok(ReturnStatementNode {
+            return_token,
+            value,
+            semicolon,
+            trivia: Trivia {
+                leading: leading_trivia,
+                trailing: self.consume_trailing_trivia(),
             },
         })
     }
@@ -241,7 +605,7 @@ impl Parser {
     fn parse_if_statement(&mut self) -> ParseResult<IfStatementNode> {
         let leading_trivia = self.consume_trivia();
         let if_token = self.expect_kind(&TokenKind::If)?;
-        let condition = self.parse_expression()?;
+        let condition = self.parse_condition()?;
         let then_block = self.parse_block()?;
 
         let else_clause = if self.check_kind(&TokenKind::Else) {
@@ -257,7 +621,7 @@ impl Parser {
             else_clause,
             trivia: Trivia {
                 leading: leading_trivia,
-                trailing: self.consume_trivia(),
+                trailing: self.consume_trailing_trivia(),
             },
         })
     }
@@ -277,15 +641,29 @@ impl Parser {
             body,
             trivia: Trivia {
                 leading: leading_trivia,
-                trailing: self.consume_trivia(),
+                trailing: self.consume_trailing_trivia(),
             },
         })
     }
 
+    // The condition of an `if` or `while`. A block is an expression, so
+    // without this `if { ... }` would take the block as its condition and
+    // then complain about whatever follows it.
+    fn parse_condition(&mut self) -> ParseResult<ExpressionNode> {
+        if self.check_kind(&TokenKind::LeftBrace) {
+            return Err(self.error("expected a condition expression before the block".to_string()));
+        }
+        self.parse_expression()
+    }
+
     fn parse_while_statement(&mut self) -> ParseResult<WhileStatementNode> {
         let leading_trivia = self.consume_trivia();
         let while_token = self.expect_kind(&TokenKind::While)?;
-        let condition = self.parse_expression()?;
+        let condition = self.parse_condition()?;
+        // C-style single statement bodies aren't allowed
+        if !self.check_kind(&TokenKind::LeftBrace) {
+            return Err(self.error("while body must be a block `{ ... }`".to_string()));
+        }
         let body = self.parse_block()?;
 
         Ok(WhileStatementNode {
@@ -294,25 +672,120 @@ impl Parser {
             body,
             trivia: Trivia {
                 leading: leading_trivia,
-                trailing: self.consume_trivia(),
+                trailing: self.consume_trailing_trivia(),
+            },
+        })
+    }
+
+    fn parse_for_statement(&mut self) -> ParseResult<ForStatementNode> {
+        let leading_trivia = self.consume_trivia();
+        let for_token = self.expect_kind(&TokenKind::For)?;
+        let variable = self.expect_ident()?;
+        let in_token = self.expect_kind(&TokenKind::In)?;
+        let start = self.parse_expression()?;
+        let dot_dot = self.expect_kind(&TokenKind::DotDot)?;
+        let end = self.parse_expression()?;
+        if !self.check_kind(&TokenKind::LeftBrace) {
+            return Err(self.error("for body must be a block `{ ... }`".to_string()));
+        }
+        let body = self.parse_block()?;
+
+        Ok(ForStatementNode {
+            for_token,
+            variable,
+            in_token,
+            start,
+            dot_dot,
+            end,
+            body,
+            trivia: Trivia {
+                leading: leading_trivia,
+                trailing: self.consume_trailing_trivia(),
             },
         })
     }
 
     fn parse_expression(&mut self) -> ParseResult<ExpressionNode> {
-        self.parse_comparison()
+        // Assignment binds loosest of all, and to the right, so `x = y = 1`
+        // assigns 1 to both. `==` is its own token, so it's never mistaken
+        // for one.
+        if matches!(self.peek().kind, TokenKind::Ident(_))
+            && self
+                .tokens
+                .get(self.current + 1)
+                .is_some_and(|next| is_assignment_operator(&next.kind))
+        {
+            return self.parse_assign_expression();
+        }
+        self.parse_logical_or()
+    }
+
+    fn parse_assign_expression(&mut self) -> ParseResult<ExpressionNode> {
+        let leading_trivia = self.consume_trivia();
+        let name = self.expect_ident()?;
+        // Only called once the operator has been seen
+        let operator = self.advance();
+        let value = self.parse_expression()?;
+
+        Ok(ExpressionNode::Assign(AssignExprNode {
+            name,
+            operator,
+            value: Box::new(value),
+            trivia: Trivia {
+                leading: leading_trivia,
+                trailing: self.consume_trailing_trivia(),
+            },
+        }))
+    }
+
+    fn parse_logical_or(&mut self) -> ParseResult<ExpressionNode> {
+        let mut expr = self.parse_logical_and()?;
+
+        while self.check_kind(&TokenKind::PipePipe) {
+            let leading_trivia = self.consume_trivia();
+            let operator = self.advance();
+            let right = self.parse_logical_and()?;
+            expr = ExpressionNode::Binary(BinaryExprNode {
+                left: Box::new(expr),
+                operator,
+                right: Box::new(right),
+                trivia: Trivia {
+                    leading: leading_trivia,
+                    trailing: self.consume_trailing_trivia(),
+                },
+            });
+        }
+
+        Ok(expr)
+    }
+
+    fn parse_logical_and(&mut self) -> ParseResult<ExpressionNode> {
+        let mut expr = self.parse_comparison()?;
+
+        while self.check_kind(&TokenKind::AmpAmp) {
+            let leading_trivia = self.consume_trivia();
+            let operator = self.advance();
+            let right = self.parse_comparison()?;
+            expr = ExpressionNode::Binary(BinaryExprNode {
+                left: Box::new(expr),
+                operator,
+                right: Box::new(right),
+                trivia: Trivia {
+                    leading: leading_trivia,
+                    trailing: self.consume_trailing_trivia(),
+                },
+            });
+        }
+
+        Ok(expr)
     }
 
+    // Comparisons don't chain: `a < b < c` would compare the result of
+    // `a < b` with `c`, which is almost never what was meant
     fn parse_comparison(&mut self) -> ParseResult<ExpressionNode> {
         let mut expr = self.parse_addition()?;
 
-        while self.check_kind(&TokenKind::LessEqual)
-            || self.check_kind(&TokenKind::Less)
-            || self.check_kind(&TokenKind::Greater)
-            || self.check_kind(&TokenKind::GreaterEqual)
-            || self.check_kind(&TokenKind::Equal)
-            || self.check_kind(&TokenKind::NotEqual)
-        {
+        if self.check_comparison() {
             let leading_trivia = self.consume_trivia();
             let operator = self.advance();
             let right = self.parse_addition()?;
@@ -322,14 +795,31 @@ impl Parser {
                 right: Box::new(right),
                 trivia: Trivia {
                     leading: leading_trivia,
-                    trailing: self.consume_trivia(),
+                    trailing: self.consume_trailing_trivia(),
                 },
             });
+
+            if self.check_comparison() {
+                return Err(self.error(
+                    "Comparison operators can't be chained: use parentheses, or `&&` \
+                     as in `a < b && b < c`"
+                        .to_string(),
+                ));
+            }
         }
 
         Ok(expr)
     }
 
+    fn check_comparison(&self) -> bool {
+        self.check_kind(&TokenKind::LessEqual)
+            || self.check_kind(&TokenKind::Less)
+            || self.check_kind(&TokenKind::Greater)
+            || self.check_kind(&TokenKind::GreaterEqual)
+            || self.check_kind(&TokenKind::Equal)
+            || self.check_kind(&TokenKind::NotEqual)
+    }
+
     fn parse_addition(&mut self) -> ParseResult<ExpressionNode> {
         let mut expr = self.parse_multiplication()?;
 
@@ -343,7 +833,7 @@ impl Parser {
                 right: Box::new(right),
                 trivia: Trivia {
                     leading: leading_trivia,
-                    trailing: self.consume_trivia(),
+                    trailing: self.consume_trailing_trivia(),
                 },
             });
         }
@@ -352,7 +842,7 @@ impl Parser {
     }
 
     fn parse_multiplication(&mut self) -> ParseResult<ExpressionNode> {
-        let mut expr = self.parse_call()?;
+        let mut expr = self.parse_unary()?;
 
         while self.check_kind(&TokenKind::Star)
             || self.check_kind(&TokenKind::Slash)
@@ -360,14 +850,14 @@ impl Parser {
         {
             let leading_trivia = self.consume_trivia();
             let operator = self.advance();
-            let right = self.parse_call()?;
+            let right = self.parse_unary()?;
             expr = ExpressionNode::Binary(BinaryExprNode {
                 left: Box::new(expr),
                 operator,
                 right: Box::new(right),
                 trivia: Trivia {
                     leading: leading_trivia,
-                    trailing: self.consume_trivia(),
+                    trailing: self.consume_trailing_trivia(),
                 },
             });
         }
@@ -375,17 +865,90 @@ impl Parser {
         Ok(expr)
     }
 
+    // A prefix operator binds tighter than any binary one but `**`, so
+    // `3 * -2` is `3 * (-2)`, `-f(x)` negates the call's result, and `-2 ** 2`
+    // is `-(2 ** 2)`
+    fn parse_unary(&mut self) -> ParseResult<ExpressionNode> {
+        if !self.check_kind(&TokenKind::Minus) && !self.check_kind(&TokenKind::Bang) {
+            return self.parse_power();
+        }
+
+        let leading_trivia = self.consume_trivia();
+        let operator = self.advance();
+        let operand = self.parse_unary()?;
+        Ok(ExpressionNode::Unary(UnaryExprNode {
+            operator,
+            operand: Box::new(operand),
+            trivia: Trivia {
+                leading: leading_trivia,
+                trailing: self.consume_trailing_trivia(),
+            },
+        }))
+    }
+
+    // `**` is right-associative, so `2 ** 3 ** 2` is `2 ** (3 ** 2)`. Its
+    // exponent can be negated, as in `2 ** -1`, but not its base.
+    fn parse_power(&mut self) -> ParseResult<ExpressionNode> {
+        let base = self.parse_call()?;
+        if !self.check_kind(&TokenKind::StarStar) {
+            return Ok(base);
+        }
+
+        let leading_trivia = self.consume_trivia();
+        let operator = self.advance();
+        let exponent = self.parse_unary()?;
+        Ok(ExpressionNode::Binary(BinaryExprNode {
+            left: Box::new(base),
+            operator,
+            right: Box::new(exponent),
+            trivia: Trivia {
+                leading: leading_trivia,
+                trailing: self.consume_trailing_trivia(),
+            },
+        }))
+    }
+
+    // Calls and indexing, which can follow each other, as in `f(x)[0]`
     fn parse_call(&mut self) -> ParseResult<ExpressionNode> {
         let mut expr = self.parse_primary()?;
 
-        while self.check_kind(&TokenKind::LeftParen) {
+        loop {
+            if self.check_kind(&TokenKind::LeftBracket) {
+                let leading_trivia = self.consume_trivia();
+                let open_bracket = self.advance();
+                let index = self.parse_expression()?;
+                let close_bracket = self.expect_kind(&TokenKind::RightBracket)?;
+                expr = ExpressionNode::Index(IndexExprNode {
+                    array: Box::new(expr),
+                    open_bracket,
+                    index: Box::new(index),
+                    close_bracket,
+                    trivia: Trivia {
+                        leading: leading_trivia,
+                        trailing: self.consume_trailing_trivia(),
+                    },
+                });
+                continue;
+            }
+            if !self.check_kind(&TokenKind::LeftParen) {
+                break;
+            }
+
             let leading_trivia = self.consume_trivia();
             let open_paren = self.advance();
 
+            // Like parameters, arguments can have one trailing comma
             let mut args = Vec::new();
+            let mut commas = Vec::new();
             if !self.check_kind(&TokenKind::RightParen) {
                 args.push(self.parse_expression()?);
-                // TODO: Handle multiple arguments with commas
+                while self.check_kind(&TokenKind::Comma) {
+                    commas.push(self.advance());
+                    if self.check_kind(&TokenKind::RightParen) {
+                        break;
+                    }
+                    args.push(self.parse_expression()?);
+                }
             }
 
             let close_paren = self.expect_kind(&TokenKind::RightParen)?;
@@ -394,10 +957,11 @@ impl Parser {
                 function: Box::new(expr),
                 open_paren,
                 args,
+                commas,
                 close_paren,
                 trivia: Trivia {
                     leading: leading_trivia,
-                    trailing: self.consume_trivia(),
+                    trailing: self.consume_trailing_trivia(),
                 },
             });
         }
@@ -407,26 +971,90 @@ impl Parser {
 
     fn parse_primary(&mut self) -> ParseResult<ExpressionNode> {
         match &self.peek().kind {
-            TokenKind::Integer(_) => Ok(ExpressionNode::Literal(self.advance())),
+            TokenKind::Integer(_) | TokenKind::True | TokenKind::False => {
+                Ok(ExpressionNode::Literal(self.advance()))
+            }
             TokenKind::Ident(_) => Ok(ExpressionNode::Identifier(self.advance())),
             TokenKind::If => Ok(ExpressionNode::If(Box::new(self.parse_if_statement()?))),
             TokenKind::While => Ok(ExpressionNode::While(Box::new(
                 self.parse_while_statement()?,
             ))),
+            TokenKind::For => Ok(ExpressionNode::For(Box::new(self.parse_for_statement()?))),
+            TokenKind::LeftBrace => Ok(ExpressionNode::Block(Box::new(self.parse_block()?))),
+            TokenKind::LeftBracket => {
+                let leading_trivia = self.consume_trivia();
+                let open_bracket = self.advance();
+
+                // Unlike arguments, elements have no trailing comma
+                let mut elements = Vec::new();
+                let mut commas = Vec::new();
+                if !self.check_kind(&TokenKind::RightBracket) {
+                    elements.push(self.parse_expression()?);
+                    while self.check_kind(&TokenKind::Comma) {
+                        commas.push(self.advance());
+                        elements.push(self.parse_expression()?);
+                    }
+                }
+
+                let close_bracket = self.expect_kind(&TokenKind::RightBracket)?;
+                Ok(ExpressionNode::ArrayLit(ArrayLitNode {
+                    open_bracket,
+                    elements,
+                    commas,
+                    close_bracket,
+                    trivia: Trivia {
+                        leading: leading_trivia,
+                        trailing: self.consume_trailing_trivia(),
+                    },
+                }))
+            }
             TokenKind::LeftParen => {
-                self.advance(); // consume '('
-                let expr = self.parse_expression()?;
-                self.expect_kind(&TokenKind::RightParen)?;
-                Ok(expr)
+                let leading_trivia = self.consume_trivia();
+                let open_paren = self.advance();
+                let inner = self.parse_expression()?;
+                let close_paren = self.expect_kind(&TokenKind::RightParen)?;
+                Ok(ExpressionNode::Paren(ParenExprNode {
+                    open_paren,
+                    inner: Box::new(inner),
+                    close_paren,
+                    trivia: Trivia {
+                        leading: leading_trivia,
+                        trailing: self.consume_trailing_trivia(),
+                    },
+                }))
             }
-            _ => Err(ParseError {
-                message: format!("Unexpected token: {:?}", self.peek().kind),
-                span: self.peek().span,
-            }),
+            _ => Err(self.error(format!("Unexpected token: {:?}", self.peek().kind))),
         }
     }
 
     // Helper methods
+
+    // An error at the current token
+    fn error(&self, message: String) -> ParseError {
+        self.error_at(self.current, message, self.error_span())
+    }
+
+    // Span to report an error at the current token with. The end of input
+    // has no text to highlight, so errors there point at the last real token
+    // instead, which is the construct left unfinished.
+    fn error_span(&self) -> Span {
+        match self.current.checked_sub(1).and_then(|i| self.tokens.get(i)) {
+            Some(last) if self.peek().kind == TokenKind::Eof => last.span,
+            _ => self.peek().span,
+        }
+    }
+
+    fn error_at(&self, index: usize, message: String, span: Span) -> ParseError {
+        let start = index.saturating_sub(CONTEXT_TOKENS);
+        let end = (index + CONTEXT_TOKENS + 1).min(self.tokens.len());
+        ParseError {
+            message,
+            span,
+            context: self.tokens[start.min(end)..end].to_vec(),
+            related: None,
+        }
+    }
+
     fn peek(&self) -> &TokenNode {
         self.tokens.get(self.current).unwrap_or(&TokenNode {
             kind: TokenKind::Eof,
@@ -449,20 +1077,61 @@ impl Parser {
         if self.check_kind(kind) {
             Ok(self.advance())
         } else {
-            Err(ParseError {
-                message: format!("Expected {:?}, found {:?}", kind, self.peek().kind),
-                span: self.peek().span,
-            })
+            Err(self.error(format!("Expected {:?}, found {:?}", kind, self.peek().kind)))
+        }
+    }
+
+    // The `;` that ends a statement. When another statement follows instead,
+    // the `;` was most likely forgotten, so that's reported, pointing just
+    // after the statement, and parsing carries on as if it were there.
+    fn expect_semicolon(&mut self) -> ParseResult<TokenNode> {
+        if !self.is_missing_semicolon() {
+            return self.expect_kind(&TokenKind::Semicolon);
+        }
+        Ok(self.missing_semicolon())
+    }
+
+    // Report a `;` missing before the current token, and stand in for it with
+    // an empty one, so parsing can carry on
+    fn missing_semicolon(&mut self) -> TokenNode {
+        let end = self.tokens[self.current - 1].span.end;
+        let span = Span { start: end, end };
+        let error = self.error_at(self.current, "missing `;`".to_string(), span);
+        self.errors.push(error);
+        TokenNode {
+            kind: TokenKind::Semicolon,
+            span,
         }
     }
 
+    // Whether the current token can only start a new expression, rather
+    // than continue one just parsed, the way `(` or `-` can
+    fn starts_operand(&self) -> bool {
+        matches!(
+            self.peek().kind,
+            TokenKind::Ident(_)
+                | TokenKind::Integer(_)
+                | TokenKind::True
+                | TokenKind::False
+                | TokenKind::Bang
+        )
+    }
+
+    // Whether the current token begins a statement where a `;` should have
+    // ended the one before it
+    fn is_missing_semicolon(&self) -> bool {
+        let starts_statement = self.is_statement_start()
+            || matches!(
+                self.peek().kind,
+                TokenKind::Fn | TokenKind::If | TokenKind::While | TokenKind::For
+            );
+        self.current > 0 && starts_statement
+    }
+
     fn expect_ident(&mut self) -> ParseResult<TokenNode> {
         match &self.peek().kind {
             TokenKind::Ident(_) => Ok(self.advance()),
-            _ => Err(ParseError {
-                message: format!("Expected identifier, found {:?}", self.peek().kind),
-                span: self.peek().span,
-            }),
+            _ => Err(self.error(format!("Expected identifier, found {:?}", self.peek().kind))),
         }
     }
 
@@ -470,17 +1139,54 @@ impl Parser {
         self.current >= self.tokens.len() || self.peek().kind == TokenKind::Eof
     }
 
+    // The comments before the current token, as the leading trivia of a node
+    // that starts with it. Only the outermost node starting there gets them.
+    // Comments where no node starts or ends, like just after an operator,
+    // aren't kept anywhere yet.
     fn consume_trivia(&mut self) -> Vec<TokenNode> {
-        // Note: lexer already skips whitespace, so no trivia to consume for now
-        // TODO: Handle comments when lexer supports them
-        Vec::new()
+        match self.comments.get_mut(self.current) {
+            Some(comments) => std::mem::take(comments)
+                .into_iter()
+                .map(|(comment, _)| comment)
+                .collect(),
+            None => Vec::new(),
+        }
+    }
+
+    // A comment on the same line just after the last token, as the trailing
+    // trivia of a node that ends with it. The innermost node ending there
+    // gets it, and any comments after it are left for the next node.
+    fn consume_trailing_trivia(&mut self) -> Vec<TokenNode> {
+        match self.comments.get_mut(self.current) {
+            Some(comments) if matches!(comments.first(), Some((_, false))) && self.current > 0 => {
+                vec![comments.remove(0).0]
+            }
+            _ => Vec::new(),
+        }
     }
 }
 
+// `=`, or a compound assignment like `+=`
+fn is_assignment_operator(kind: &TokenKind) -> bool {
+    matches!(
+        kind,
+        TokenKind::Assign
+            | TokenKind::PlusAssign
+            | TokenKind::MinusAssign
+            | TokenKind::StarAssign
+            | TokenKind::SlashAssign
+            | TokenKind::PercentAssign
+    )
+}
+
 pub fn parse(tokens: Vec<TokenNode>) -> ParseResult<CstRoot> {
     Parser::new(tokens).parse()
 }
 
+pub fn parse_with_recovery(tokens: Vec<TokenNode>) -> (CstRoot, Vec<ParseError>) {
+    Parser::new(tokens).parse_with_recovery()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -614,6 +1320,302 @@ mod tests {
         }
     }
 
+    // The call in a program that's a single expression statement
+    fn parse_call(source: &str) -> CallExprNode {
+        let cst = lex_and_parse(source).unwrap();
+        match &cst.items[0] {
+            CstNode::Statement(stmt) => match &**stmt {
+                StatementNode::Expression(expr_stmt) => match &expr_stmt.expression {
+                    ExpressionNode::Call(call) => call.clone(),
+                    _ => panic!("Expected function call"),
+                },
+                _ => panic!("Expected expression statement with function call"),
+            },
+            _ => panic!("Expected statement"),
+        }
+    }
+
+    #[test]
+    fn test_call_arguments() {
+        for (source, count) in [
+            ("f();", 0),
+            ("f(1);", 1),
+            ("f(1, 2);", 2),
+            ("f(a, b, c);", 3),
+        ] {
+            let call = parse_call(source);
+            assert_eq!(call.args.len(), count, "{}", source);
+            assert_eq!(call.commas.len(), count.saturating_sub(1), "{}", source);
+        }
+
+        // Arguments are full expressions
+        let call = parse_call("f(1 + 2, x * 3);");
+        assert!(matches!(call.args[0], ExpressionNode::Binary(_)));
+        assert!(matches!(call.args[1], ExpressionNode::Binary(_)));
+    }
+
+    #[test]
+    fn test_nested_call_arguments() {
+        let call = parse_call("f(g(1), 2);");
+        assert_eq!(call.args.len(), 2);
+        match &call.args[0] {
+            ExpressionNode::Call(inner) => assert_eq!(inner.args.len(), 1),
+            _ => panic!("Expected nested call"),
+        }
+        assert!(matches!(call.args[1], ExpressionNode::Literal(_)));
+    }
+
+    #[test]
+    fn test_invalid_call_arguments() {
+        // A comma alone isn't an argument list, and only one can trail
+        let error = lex_and_parse("f(,);").unwrap_err();
+        assert_eq!(error.message, "Unexpected token: Comma");
+        assert!(lex_and_parse("f(1,,);").is_err());
+
+        assert!(lex_and_parse("f(1 2);").is_err());
+    }
+
+    #[test]
+    fn test_trailing_commas() {
+        let call = parse_call("f(a, b,);");
+        assert_eq!(call.args.len(), 2);
+        assert_eq!(call.commas.len(), 2);
+        assert_eq!(parse_call("f(1,);").args.len(), 1);
+
+        let cst = lex_and_parse("fn g(x, y,) { x }").unwrap();
+        let CstNode::Function(func) = &cst.items[0] else {
+            panic!("Expected function");
+        };
+        assert_eq!(func.param_list.params.len(), 2);
+        assert_eq!(func.param_list.commas.len(), 2);
+    }
+
+    // The expression in `fn main() { <source> }`
+    fn parse_main_expression(source: &str) -> ExpressionNode {
+        let cst = lex_and_parse(&format!("fn main() {{ {} }}", source)).unwrap();
+        match &cst.items[0] {
+            CstNode::Function(func) => func.body.final_expr.clone().unwrap(),
+            _ => panic!("Expected function"),
+        }
+    }
+
+    #[test]
+    fn test_unary_negation() {
+        match parse_main_expression("-5") {
+            ExpressionNode::Unary(unary) => {
+                assert_eq!(unary.operator.kind, TokenKind::Minus);
+                assert!(matches!(*unary.operand, ExpressionNode::Literal(_)));
+            }
+            _ => panic!("Expected unary expression"),
+        }
+
+        match parse_main_expression("-x") {
+            ExpressionNode::Unary(unary) => {
+                assert!(matches!(*unary.operand, ExpressionNode::Identifier(_)));
+            }
+            _ => panic!("Expected unary expression"),
+        }
+
+        // Negation binds tighter than `*`
+        match parse_main_expression("3 * -2") {
+            ExpressionNode::Binary(binary) => {
+                assert_eq!(binary.operator.kind, TokenKind::Star);
+                assert!(matches!(*binary.left, ExpressionNode::Literal(_)));
+                assert!(matches!(*binary.right, ExpressionNode::Unary(_)));
+            }
+            _ => panic!("Expected binary expression"),
+        }
+
+        // ...and looser than a call
+        match parse_main_expression("-f(1)") {
+            ExpressionNode::Unary(unary) => {
+                assert!(matches!(*unary.operand, ExpressionNode::Call(_)));
+            }
+            _ => panic!("Expected unary expression"),
+        }
+
+        // A binary minus is still a binary minus
+        assert!(matches!(
+            parse_main_expression("x - 1"),
+            ExpressionNode::Binary(_)
+        ));
+        match parse_main_expression("--x") {
+            ExpressionNode::Unary(unary) => {
+                assert!(matches!(*unary.operand, ExpressionNode::Unary(_)));
+            }
+            _ => panic!("Expected unary expression"),
+        }
+    }
+
+    // The operator of a binary expression, or None for anything else
+    fn binary_operator(expr: &ExpressionNode) -> Option<&TokenKind> {
+        match expr {
+            ExpressionNode::Binary(binary) => Some(&binary.operator.kind),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn test_logical_operator_precedence() {
+        // `((a < b) && (c < d)) || e`
+        match parse_main_expression("a < b && c < d || e") {
+            ExpressionNode::Binary(or) => {
+                assert_eq!(or.operator.kind, TokenKind::PipePipe);
+                assert!(matches!(*or.right, ExpressionNode::Identifier(_)));
+                match *or.left {
+                    ExpressionNode::Binary(and) => {
+                        assert_eq!(and.operator.kind, TokenKind::AmpAmp);
+                        assert_eq!(binary_operator(&and.left), Some(&TokenKind::Less));
+                        assert_eq!(binary_operator(&and.right), Some(&TokenKind::Less));
+                    }
+                    _ => panic!("Expected && on the left"),
+                }
+            }
+            _ => panic!("Expected binary expression"),
+        }
+
+        // `a || (b && c)`
+        match parse_main_expression("a || b && c") {
+            ExpressionNode::Binary(or) => {
+                assert_eq!(or.operator.kind, TokenKind::PipePipe);
+                assert!(matches!(*or.left, ExpressionNode::Identifier(_)));
+                assert_eq!(binary_operator(&or.right), Some(&TokenKind::AmpAmp));
+            }
+            _ => panic!("Expected binary expression"),
+        }
+
+        // Both are left-associative: `(a || b) || c`
+        match parse_main_expression("a || b || c") {
+            ExpressionNode::Binary(or) => {
+                assert_eq!(binary_operator(&or.left), Some(&TokenKind::PipePipe));
+                assert!(matches!(*or.right, ExpressionNode::Identifier(_)));
+            }
+            _ => panic!("Expected binary expression"),
+        }
+        match parse_main_expression("a && b && c") {
+            ExpressionNode::Binary(and) => {
+                assert_eq!(binary_operator(&and.left), Some(&TokenKind::AmpAmp));
+            }
+            _ => panic!("Expected binary expression"),
+        }
+
+        // Arithmetic binds tighter still: `(x + 1 == y) && !z`
+        match parse_main_expression("x + 1 == y && !z") {
+            ExpressionNode::Binary(and) => {
+                assert_eq!(and.operator.kind, TokenKind::AmpAmp);
+                assert_eq!(binary_operator(&and.left), Some(&TokenKind::Equal));
+                assert!(matches!(*and.right, ExpressionNode::Unary(_)));
+            }
+            _ => panic!("Expected binary expression"),
+        }
+    }
+
+    #[test]
+    fn test_parenthesized_expression() {
+        match parse_main_expression("(1 + 2)") {
+            ExpressionNode::Paren(paren) => {
+                assert_eq!(paren.open_paren.kind, TokenKind::LeftParen);
+                assert_eq!(binary_operator(&paren.inner), Some(&TokenKind::Plus));
+                assert_eq!(paren.close_paren.kind, TokenKind::RightParen);
+            }
+            _ => panic!("Expected parenthesized expression"),
+        }
+
+        // The parentheses still group: `(1 + 2) * 3`, not `1 + (2 * 3)`
+        match parse_main_expression("(1 + 2) * 3") {
+            ExpressionNode::Binary(binary) => {
+                assert_eq!(binary.operator.kind, TokenKind::Star);
+                match *binary.left {
+                    ExpressionNode::Paren(paren) => {
+                        assert_eq!(binary_operator(&paren.inner), Some(&TokenKind::Plus))
+                    }
+                    _ => panic!("Expected parenthesized left operand"),
+                }
+                assert!(matches!(*binary.right, ExpressionNode::Literal(_)));
+            }
+            _ => panic!("Expected binary expression"),
+        }
+
+        // Nested parentheses are each kept
+        match parse_main_expression("((x))") {
+            ExpressionNode::Paren(outer) => {
+                assert!(matches!(*outer.inner, ExpressionNode::Paren(_)))
+            }
+            _ => panic!("Expected parenthesized expression"),
+        }
+
+        let error = lex_and_parse("fn main() { (1 + 2 }").unwrap_err();
+        assert_eq!(error.message, "Expected RightParen, found RightBrace");
+    }
+
+    #[test]
+    fn test_power_operator() {
+        // Right-associative: `2 ** (3 ** 2)`
+        let ExpressionNode::Binary(outer) = parse_main_expression("2 ** 3 ** 2") else {
+            panic!("Expected binary expression");
+        };
+        assert_eq!(outer.operator.kind, TokenKind::StarStar);
+        assert!(matches!(*outer.left, ExpressionNode::Literal(_)));
+        assert_eq!(binary_operator(&outer.right), Some(&TokenKind::StarStar));
+
+        // Tighter than `*` and unary minus, which can negate the exponent
+        let expr = parse_main_expression("2 * 3 ** 2");
+        let ExpressionNode::Binary(product) = &expr else {
+            panic!("Expected binary expression");
+        };
+        assert_eq!(product.operator.kind, TokenKind::Star);
+        assert_eq!(binary_operator(&product.right), Some(&TokenKind::StarStar));
+
+        let ExpressionNode::Unary(negation) = parse_main_expression("-2 ** 2") else {
+            panic!("Expected unary expression");
+        };
+        assert_eq!(
+            binary_operator(&negation.operand),
+            Some(&TokenKind::StarStar)
+        );
+
+        let ExpressionNode::Binary(power) = parse_main_expression("2 ** -1") else {
+            panic!("Expected binary expression");
+        };
+        assert!(matches!(*power.right, ExpressionNode::Unary(_)));
+    }
+
+    #[test]
+    fn test_logical_not() {
+        match parse_main_expression("!cond") {
+            ExpressionNode::Unary(unary) => {
+                assert_eq!(unary.operator.kind, TokenKind::Bang);
+                assert!(matches!(*unary.operand, ExpressionNode::Identifier(_)));
+            }
+            _ => panic!("Expected unary expression"),
+        }
+
+        match parse_main_expression("!(a < b)") {
+            ExpressionNode::Unary(unary) => match *unary.operand {
+                ExpressionNode::Paren(paren) => {
+                    assert_eq!(binary_operator(&paren.inner), Some(&TokenKind::Less))
+                }
+                _ => panic!("Expected parenthesized operand"),
+            },
+            _ => panic!("Expected unary expression"),
+        }
+
+        // Like negation, `!` binds tighter than comparisons
+        match parse_main_expression("!a == b") {
+            ExpressionNode::Binary(binary) => {
+                assert_eq!(binary.operator.kind, TokenKind::Equal);
+                assert!(matches!(*binary.left, ExpressionNode::Unary(_)));
+            }
+            _ => panic!("Expected binary expression"),
+        }
+
+        // `!=` is still one token
+        assert!(matches!(
+            parse_main_expression("a != b"),
+            ExpressionNode::Binary(_)
+        ));
+    }
+
     #[test]
     fn test_let_statement() {
         let result = lex_and_parse("let x = 42;");
@@ -645,6 +1647,26 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_const_statement() {
+        let cst = lex_and_parse("const MAX = 10 * 10; fn main() { MAX }").unwrap();
+        assert_eq!(cst.items.len(), 2);
+
+        match &cst.items[0] {
+            CstNode::Statement(stmt) => match &**stmt {
+                StatementNode::Const(const_stmt) => {
+                    assert_eq!(const_stmt.name.kind, TokenKind::Ident("MAX".to_string()));
+                    assert_eq!(binary_operator(&const_stmt.value), Some(&TokenKind::Star));
+                }
+                _ => panic!("Expected const statement"),
+            },
+            _ => panic!("Expected statement"),
+        }
+
+        assert!(lex_and_parse("const = 1;").is_err());
+        assert!(lex_and_parse("const MAX 1;").is_err());
+    }
+
     #[test]
     fn test_simple_function() {
         let result = lex_and_parse("fn test(x) { x }");
@@ -662,7 +1684,7 @@ mod tests {
 
                 // Check parameter
                 assert_eq!(func.param_list.params.len(), 1);
-                match &func.param_list.params[0].kind {
+                match &func.param_list.params[0].name.kind {
                     TokenKind::Ident(name) => assert_eq!(name, "x"),
                     _ => panic!("Expected identifier token for parameter"),
                 }
@@ -674,6 +1696,109 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_parameter_lists() {
+        let param_names = |source: &str| -> Vec<String> {
+            let cst = lex_and_parse(source).unwrap();
+            match &cst.items[0] {
+                CstNode::Function(func) => {
+                    assert_eq!(
+                        func.param_list.commas.len(),
+                        func.param_list.params.len().saturating_sub(1)
+                    );
+                    func.param_list
+                        .params
+                        .iter()
+                        .map(|param| match &param.name.kind {
+                            TokenKind::Ident(name) => name.clone(),
+                            _ => panic!("Expected identifier token for parameter"),
+                        })
+                        .collect()
+                }
+                _ => panic!("Expected function"),
+            }
+        };
+
+        assert_eq!(param_names("fn f() { 0 }"), Vec::<String>::new());
+        assert_eq!(param_names("fn f(a) { a }"), vec!["a"]);
+        assert_eq!(param_names("fn f(a, b) { a }"), vec!["a", "b"]);
+        assert_eq!(param_names("fn f(a, b, c) { a }"), vec!["a", "b", "c"]);
+    }
+
+    #[test]
+    fn test_invalid_parameter_lists() {
+        // A comma alone isn't a parameter list, and only one can trail
+        let error = lex_and_parse("fn f(,) { 0 }").unwrap_err();
+        assert_eq!(error.message, "Expected identifier, found Comma");
+        assert!(lex_and_parse("fn f(a,,) { a }").is_err());
+
+        assert!(lex_and_parse("fn f(a b) { a }").is_err());
+        assert!(lex_and_parse("fn f(a,, b) { a }").is_err());
+    }
+
+    #[test]
+    fn test_return_type_annotations() {
+        let function = |source: &str| -> FunctionNode {
+            match &lex_and_parse(source).unwrap().items[0] {
+                CstNode::Function(func) => (**func).clone(),
+                _ => panic!("Expected function"),
+            }
+        };
+
+        let annotated = function("fn f(x: i64) -> i64 { x }");
+        assert_eq!(annotated.arrow.unwrap().kind, TokenKind::Arrow);
+        assert_eq!(
+            annotated.return_type.unwrap().kind,
+            TokenKind::Ident("i64".to_string())
+        );
+
+        let bare = function("fn f() { 0 }");
+        assert!(bare.arrow.is_none());
+        assert!(bare.return_type.is_none());
+
+        let error = lex_and_parse("fn f() -> { 0 }").unwrap_err();
+        assert_eq!(error.message, "Expected identifier, found LeftBrace");
+    }
+
+    #[test]
+    fn test_parameter_type_annotations() {
+        let params = |source: &str| -> Vec<ParamNode> {
+            match &lex_and_parse(source).unwrap().items[0] {
+                CstNode::Function(func) => func.param_list.params.clone(),
+                _ => panic!("Expected function"),
+            }
+        };
+
+        let annotated = params("fn f(x: i64) { x }");
+        assert_eq!(annotated[0].name.kind, TokenKind::Ident("x".to_string()));
+        assert_eq!(annotated[0].colon.as_ref().unwrap().kind, TokenKind::Colon);
+        assert_eq!(
+            annotated[0].ty.as_ref().unwrap().kind,
+            TokenKind::Ident("i64".to_string())
+        );
+
+        // The annotation is optional
+        let bare = params("fn f(x) { x }");
+        assert!(bare[0].colon.is_none());
+        assert!(bare[0].ty.is_none());
+
+        // Any identifier parses as a type; semantic analysis checks it
+        let bogus = params("fn f(x: bogus) { x }");
+        assert_eq!(
+            bogus[0].ty.as_ref().unwrap().kind,
+            TokenKind::Ident("bogus".to_string())
+        );
+
+        // Annotated and bare parameters can be mixed
+        let mixed = params("fn f(a: i64, b) { a }");
+        assert!(mixed[0].ty.is_some());
+        assert!(mixed[1].ty.is_none());
+
+        let error = lex_and_parse("fn f(x:) { x }").unwrap_err();
+        assert_eq!(error.message, "Expected identifier, found RightParen");
+        assert!(lex_and_parse("fn f(x: 5) { x }").is_err());
+    }
+
     #[test]
     fn test_factorial_example() {
         let source = r#"
@@ -788,6 +1913,98 @@ fn main() {
         }
     }
 
+    #[test]
+    fn test_for_statement() {
+        let cst =
+            lex_and_parse("fn main() { let sum = 0; for i in 1..n + 1 { sum = sum + i; }; sum }")
+                .unwrap();
+        let CstNode::Function(func) = &cst.items[0] else {
+            panic!("Expected function");
+        };
+        let StatementNode::Expression(expr_stmt) = &func.body.statements[1] else {
+            panic!("Expected expression statement");
+        };
+        let ExpressionNode::For(for_stmt) = &expr_stmt.expression else {
+            panic!("Expected for expression");
+        };
+
+        assert_eq!(for_stmt.variable.kind, TokenKind::Ident("i".to_string()));
+        assert!(matches!(
+            for_stmt.start,
+            ExpressionNode::Literal(rue_lexer::Token {
+                kind: TokenKind::Integer(1),
+                ..
+            })
+        ));
+        // The end of the range is a full expression
+        assert_eq!(binary_operator(&for_stmt.end), Some(&TokenKind::Plus));
+        assert!(matches!(
+            for_stmt.body.statements[..],
+            [StatementNode::Assign(_)]
+        ));
+    }
+
+    #[test]
+    fn test_block_expression() {
+        let cst = lex_and_parse("fn main() { let y = { let a = 1; a + 1 }; { y } }").unwrap();
+        let CstNode::Function(func) = &cst.items[0] else {
+            panic!("Expected function");
+        };
+        let StatementNode::Let(let_stmt) = &func.body.statements[0] else {
+            panic!("Expected let statement");
+        };
+        let ExpressionNode::Block(block) = &let_stmt.value else {
+            panic!("Expected block expression");
+        };
+        assert!(matches!(block.statements[..], [StatementNode::Let(_)]));
+        assert_eq!(
+            block.final_expr.as_ref().and_then(binary_operator),
+            Some(&TokenKind::Plus)
+        );
+
+        // A block can be the final expression too
+        assert!(matches!(
+            func.body.final_expr,
+            Some(ExpressionNode::Block(_))
+        ));
+    }
+
+    #[test]
+    fn test_break_and_continue() {
+        let cst = lex_and_parse("while x < 10 { if x > 5 { break; }; continue; };").unwrap();
+        let CstNode::Statement(stmt) = &cst.items[0] else {
+            panic!("Expected statement");
+        };
+        let StatementNode::Expression(ExpressionStatementNode {
+            expression: ExpressionNode::While(while_stmt),
+            ..
+        }) = &**stmt
+        else {
+            panic!("Expected while expression");
+        };
+        assert!(matches!(
+            while_stmt.body.statements[..],
+            [StatementNode::Expression(_), StatementNode::Continue(_)]
+        ));
+
+        // They're statements, so they need a semicolon
+        let error = lex_and_parse("while x < 10 { break };").unwrap_err();
+        assert!(
+            error.message.contains("Expected Semicolon"),
+            "{}",
+            error.message
+        );
+    }
+
+    #[test]
+    fn test_for_requires_range_and_block() {
+        let error = lex_and_parse("for i in 10 { i };").unwrap_err();
+        assert!(error.message.contains("DotDot"), "{}", error.message);
+
+        let error = lex_and_parse("for i in 0..10 i;").unwrap_err();
+        assert_eq!(error.message, "for body must be a block `{ ... }`");
+    }
+
     #[test]
     fn test_assign_statement() {
         let result = lex_and_parse("x = 42;");
@@ -818,4 +2035,660 @@ fn main() {
             _ => panic!("Expected statement"),
         }
     }
+
+    #[test]
+    fn test_compound_assign_statement() {
+        for (source, operator) in [
+            ("x += 1;", TokenKind::PlusAssign),
+            ("x -= 1;", TokenKind::MinusAssign),
+            ("x *= 1 + 2;", TokenKind::StarAssign),
+        ] {
+            let cst = lex_and_parse(source).unwrap();
+            match &cst.items[0] {
+                CstNode::Statement(stmt) => match &**stmt {
+                    StatementNode::Assign(assign_stmt) => {
+                        assert_eq!(assign_stmt.operator.kind, operator)
+                    }
+                    _ => panic!("Expected assign statement"),
+                },
+                _ => panic!("Expected statement"),
+            }
+        }
+
+        // `x *= 1 + 2` is short for `x * (1 + 2)`
+        let cst = lex_and_parse("x *= 1 + 2;").unwrap();
+        let CstNode::Statement(stmt) = &cst.items[0] else {
+            panic!("Expected statement");
+        };
+        let StatementNode::Assign(assign_stmt) = &**stmt else {
+            panic!("Expected assign statement");
+        };
+        match assign_stmt.assigned_value() {
+            ExpressionNode::Binary(binary) => {
+                assert_eq!(binary.operator.kind, TokenKind::Star);
+                assert!(matches!(*binary.left, ExpressionNode::Identifier(_)));
+                assert_eq!(binary_operator(&binary.right), Some(&TokenKind::Plus));
+            }
+            _ => panic!("Expected binary expression"),
+        }
+    }
+
+    #[test]
+    fn test_empty_input() {
+        // Whether a program needs a `main` is up to the compiler, not the
+        // parser
+        for source in ["", "   \n  ", "\t\n\n"] {
+            let cst = lex_and_parse(source).unwrap();
+            assert!(cst.items.is_empty(), "{:?}", source);
+        }
+
+        // Nor do comments, which are kept in the root's trivia
+        assert!(
+            lex_and_parse("// just a comment\n")
+                .unwrap()
+                .items
+                .is_empty()
+        );
+        let cst = parse_with_trivia("// just a comment\n").unwrap();
+        assert!(cst.items.is_empty());
+        assert_eq!(
+            comment_texts(&cst.trivia.trailing),
+            vec!["// just a comment"]
+        );
+    }
+
+    fn parse_with_trivia(source: &str) -> ParseResult<CstRoot> {
+        parse(Lexer::new(source).tokenize_with_trivia())
+    }
+
+    fn comment_texts(comments: &[TokenNode]) -> Vec<String> {
+        comments.iter().map(|comment| comment.kind.text()).collect()
+    }
+
+    #[test]
+    fn test_to_source_round_trip() {
+        let source = "// Adds two\nfn add_two(x: i64) -> i64 { x + 2 } // helper\n\
+                      fn main() { // start\nlet a = [1, 2]; a[0] += 1; \
+                      if a[0] > 1 { return add_two(-a[1] ** 2,); } else if a[1] == 2 { 0 } \
+                      else { for i in 0..3 { break; }; 1 } } // done";
+        assert_eq!(parse_with_trivia(source).unwrap().to_source(), source);
+
+        // Only whitespace and literals' spelling change
+        let messy = "fn main()   {\n    let x = 0x2a;  // the answer\n    x\n\n}\n// done\n";
+        assert_eq!(
+            parse_with_trivia(messy).unwrap().to_source(),
+            "fn main() { let x = 42; // the answer\nx } // done"
+        );
+    }
+
+    #[test]
+    fn test_node_spans() {
+        let source = "// Not part of it\nfn main(x: i64) {\n    let y = -x;\n    y + f(1) * 2\n}\n";
+        let cst = parse_with_trivia(source).unwrap();
+        let CstNode::Function(func) = &cst.items[0] else {
+            panic!("Expected function");
+        };
+        let text = |span: Span| &source[span.start..span.end];
+
+        // From `fn` to the closing `}`, without the comment before it
+        assert!(text(func.span()).starts_with("fn main"));
+        assert!(text(func.span()).ends_with('}'));
+        assert_eq!(func.span().end, source.len() - 1);
+
+        assert_eq!(text(func.param_list.span()), "(x: i64)");
+        assert_eq!(text(func.param_list.params[0].span()), "x: i64");
+        assert_eq!(text(func.body.statements[0].span()), "let y = -x;");
+        let final_expr = func.body.final_expr.as_ref().unwrap();
+        assert_eq!(text(final_expr.span()), "y + f(1) * 2");
+
+        let source = "fn f(a) { if a { 1 } else if a { a[0] } else { 3 } }";
+        let cst = lex_and_parse(source).unwrap();
+        let CstNode::Function(func) = &cst.items[0] else {
+            panic!("Expected function");
+        };
+        let if_expr = func.body.final_expr.as_ref().unwrap();
+        assert_eq!(
+            &source[if_expr.span().start..if_expr.span().end],
+            "if a { 1 } else if a { a[0] } else { 3 }"
+        );
+    }
+
+    #[test]
+    fn test_comment_trivia() {
+        let source = "// The entry point\n// Returns 3\nfn main() { // opening\n    let x = 1; // one\n    // then two more\n    x + 2\n}\n";
+        let cst = parse_with_trivia(source).unwrap();
+        let CstNode::Function(func) = &cst.items[0] else {
+            panic!("Expected function");
+        };
+        assert_eq!(
+            comment_texts(&func.trivia.leading),
+            vec!["// The entry point", "// Returns 3"]
+        );
+
+        // A comment after an opening brace is the first statement's
+        let StatementNode::Let(let_stmt) = &func.body.statements[0] else {
+            panic!("Expected let statement");
+        };
+        assert_eq!(comment_texts(&let_stmt.trivia.leading), vec!["// opening"]);
+        assert_eq!(comment_texts(&let_stmt.trivia.trailing), vec!["// one"]);
+        let Some(ExpressionNode::Binary(binary)) = &func.body.final_expr else {
+            panic!("Expected binary expression");
+        };
+        assert_eq!(
+            comment_texts(&binary.trivia.leading),
+            vec!["// then two more"]
+        );
+
+        // Comments can go anywhere whitespace can, even where there's no
+        // node for them, where they aren't kept
+        let source = "fn main() { 1 + // more to come\n 2 }";
+        assert_eq!(
+            format_cst(&parse_with_trivia(source).unwrap()),
+            format_cst(&lex_and_parse("fn main() { 1 + 2 }").unwrap())
+        );
+    }
+
+    #[test]
+    fn test_assignment_expression() {
+        // `x = 1` assigns, `x == 1` compares
+        let cst = lex_and_parse("let y = x = 1;").unwrap();
+        let CstNode::Statement(stmt) = &cst.items[0] else {
+            panic!("Expected statement");
+        };
+        let StatementNode::Let(let_stmt) = &**stmt else {
+            panic!("Expected let statement");
+        };
+        match &let_stmt.value {
+            ExpressionNode::Assign(assign_expr) => {
+                assert_eq!(assign_expr.operator.kind, TokenKind::Assign);
+                assert!(matches!(*assign_expr.value, ExpressionNode::Literal(_)));
+            }
+            _ => panic!("Expected assignment expression"),
+        }
+
+        let cst = lex_and_parse("let y = x == 1;").unwrap();
+        let CstNode::Statement(stmt) = &cst.items[0] else {
+            panic!("Expected statement");
+        };
+        let StatementNode::Let(let_stmt) = &**stmt else {
+            panic!("Expected let statement");
+        };
+        assert_eq!(binary_operator(&let_stmt.value), Some(&TokenKind::Equal));
+
+        // Assignment binds loosest, and groups to the right
+        let cst = lex_and_parse("fn main() { f(x = y += 1 + 2) }").unwrap();
+        let CstNode::Function(func) = &cst.items[0] else {
+            panic!("Expected function");
+        };
+        let Some(ExpressionNode::Call(call)) = &func.body.final_expr else {
+            panic!("Expected call");
+        };
+        let ExpressionNode::Assign(outer) = &call.args[0] else {
+            panic!("Expected assignment expression");
+        };
+        match &*outer.value {
+            ExpressionNode::Assign(inner) => {
+                assert_eq!(inner.operator.kind, TokenKind::PlusAssign);
+                assert_eq!(binary_operator(&inner.value), Some(&TokenKind::Plus));
+            }
+            _ => panic!("Expected nested assignment expression"),
+        }
+
+        // Only a variable can be assigned
+        assert!(lex_and_parse("fn main() { let y = 1 + x = 2; y }").is_err());
+    }
+
+    #[test]
+    fn test_array_literal_and_index() {
+        let cst = lex_and_parse("let a = [1, 2 + 3, f(4)];").unwrap();
+        let CstNode::Statement(stmt) = &cst.items[0] else {
+            panic!("Expected statement");
+        };
+        let StatementNode::Let(let_stmt) = &**stmt else {
+            panic!("Expected let statement");
+        };
+        match &let_stmt.value {
+            ExpressionNode::ArrayLit(array) => {
+                assert_eq!(array.elements.len(), 3);
+                assert_eq!(array.commas.len(), 2);
+                assert_eq!(binary_operator(&array.elements[1]), Some(&TokenKind::Plus));
+                assert!(matches!(array.elements[2], ExpressionNode::Call(_)));
+            }
+            _ => panic!("Expected array literal"),
+        }
+
+        // Indexing binds tighter than any operator
+        let cst = lex_and_parse("fn main() { -a[i + 1] * 2 }").unwrap();
+        let CstNode::Function(func) = &cst.items[0] else {
+            panic!("Expected function");
+        };
+        let Some(ExpressionNode::Binary(binary)) = &func.body.final_expr else {
+            panic!("Expected binary expression");
+        };
+        let ExpressionNode::Unary(unary) = &*binary.left else {
+            panic!("Expected unary expression");
+        };
+        match &*unary.operand {
+            ExpressionNode::Index(index_expr) => {
+                assert!(matches!(*index_expr.array, ExpressionNode::Identifier(_)));
+                assert_eq!(binary_operator(&index_expr.index), Some(&TokenKind::Plus));
+            }
+            _ => panic!("Expected index expression"),
+        }
+
+        assert!(lex_and_parse("fn main() { [] }").is_ok());
+        assert!(lex_and_parse("fn main() { [1, 2,] }").is_err());
+        assert!(lex_and_parse("fn main() { a[1 }").is_err());
+    }
+
+    #[test]
+    fn test_index_assign_statement() {
+        for source in ["a[0] = 1;", "fn main() { a[0] += 1; a[1] }"] {
+            let cst = lex_and_parse(source).unwrap();
+            let stmt = match &cst.items[0] {
+                CstNode::Statement(stmt) => &**stmt,
+                CstNode::Function(func) => &func.body.statements[0],
+                _ => panic!("Expected statement"),
+            };
+            match stmt {
+                StatementNode::IndexAssign(assign_stmt) => {
+                    assert!(matches!(
+                        *assign_stmt.target.index,
+                        ExpressionNode::Literal(_)
+                    ))
+                }
+                _ => panic!("Expected index assignment in {}", source),
+            }
+        }
+    }
+
+    #[test]
+    fn test_lexer_error_is_parse_error() {
+        let error = lex_and_parse("fn main() { 1 & 2 }").unwrap_err();
+        assert!(error.message.contains("'&'"));
+        assert_eq!(error.span, Span { start: 14, end: 15 });
+    }
+
+    #[test]
+    fn test_chained_comparison_is_error() {
+        let source = "fn main() { a < b < c }";
+        let error = lex_and_parse(source).unwrap_err();
+        assert_eq!(
+            error.message,
+            "Comparison operators can't be chained: use parentheses, or `&&` as in `a < b && b < c`"
+        );
+        let second = source.rfind('<').unwrap();
+        assert_eq!(
+            error.span,
+            Span {
+                start: second,
+                end: second + 1
+            }
+        );
+
+        assert!(lex_and_parse("fn main() { a == b != c }").is_err());
+
+        // Grouping makes it clear what's meant
+        assert!(lex_and_parse("fn main() { (a < b) < c }").is_ok());
+        assert!(lex_and_parse("fn main() { a + 1 < b * 2 }").is_ok());
+    }
+
+    #[test]
+    fn test_block_statements_then_final_expression() {
+        let cst = lex_and_parse("fn main() { f(1); g(2); f(3) + 1 }").unwrap();
+        let CstNode::Function(func) = &cst.items[0] else {
+            panic!("Expected function");
+        };
+        assert!(matches!(
+            func.body.statements[..],
+            [StatementNode::Expression(_), StatementNode::Expression(_)]
+        ));
+        assert!(matches!(
+            func.body.final_expr,
+            Some(ExpressionNode::Binary(_))
+        ));
+    }
+
+    #[test]
+    fn test_token_after_final_expression() {
+        let source = "fn main() { 1 ) }";
+        let error = lex_and_parse(source).unwrap_err();
+
+        assert_eq!(
+            error.message,
+            "unexpected token after block's final expression; did you forget a semicolon?"
+        );
+        assert_eq!(error.span, Span { start: 14, end: 15 });
+
+        // Another expression can't follow it either, so it was a statement
+        // missing its `;`
+        let error = lex_and_parse("fn main() { 1 2 }").unwrap_err();
+        assert_eq!(error.message, "missing `;`");
+        assert_eq!(error.span, Span { start: 13, end: 13 });
+
+        // With the semicolon, `2` is the final expression
+        assert!(lex_and_parse("fn main() { 1; 2 }").is_ok());
+    }
+
+    #[test]
+    fn test_missing_condition() {
+        for source in [
+            "fn main() { if { 1 } else { 2 } }",
+            "fn main() { while { 1 } }",
+        ] {
+            let error = lex_and_parse(source).unwrap_err();
+            assert_eq!(
+                error.message,
+                "expected a condition expression before the block"
+            );
+            let block = source.find("{ 1").unwrap();
+            assert_eq!(
+                error.span,
+                Span {
+                    start: block,
+                    end: block + 1
+                }
+            );
+        }
+
+        // A parenthesized block is still a condition
+        assert!(lex_and_parse("fn main() { if ({ 1 }) { 2 } else { 3 } }").is_ok());
+    }
+
+    #[test]
+    fn test_visitor_counts_calls() {
+        #[derive(Default)]
+        struct CountCalls {
+            calls: usize,
+        }
+
+        impl Visitor for CountCalls {
+            fn visit_expression(&mut self, expr: &ExpressionNode) {
+                if let ExpressionNode::Call(_) = expr {
+                    self.calls += 1;
+                }
+                rue_ast::visit::walk_expression(self, expr);
+            }
+        }
+
+        // Calls nested in calls, conditions, else-if chains, loops, index
+        // assignments and top-level statements all count
+        let source = "fn f(x) { x } \
+                      fn main() { \
+                          let a = [f(1), 2]; \
+                          a[f(0)] = f(f(2)); \
+                          if f(1) > 0 { 1 } else if 0 > 1 { f(3) } else { 0 }; \
+                          while f(0) > 0 { }; \
+                          for i in 0..f(2) { return f(i); }; \
+                          f(a[0]) \
+                      } \
+                      f(4);";
+        let cst = lex_and_parse(source).unwrap();
+        let mut counter = CountCalls::default();
+        rue_ast::visit::walk_root(&mut counter, &cst);
+        assert_eq!(counter.calls, 11);
+    }
+
+    #[test]
+    fn test_braceless_while_body() {
+        let source = "fn main() { let i = 0; while (i < 3) i = i + 1; i }";
+        let error = lex_and_parse(source).unwrap_err();
+
+        assert_eq!(error.message, "while body must be a block `{ ... }`");
+        let body = source.find(") i").unwrap() + 2;
+        assert_eq!(error.span.start, body);
+    }
+
+    #[test]
+    fn test_return_statement() {
+        let cst = lex_and_parse("fn f(x) { if x < 0 { return 0; }; return; }").unwrap();
+        let CstNode::Function(func) = &cst.items[0] else {
+            panic!("Expected function");
+        };
+
+        match &func.body.statements[1] {
+            StatementNode::Return(return_stmt) => assert!(return_stmt.value.is_none()),
+            _ => panic!("Expected bare return statement"),
+        }
+
+        let StatementNode::Expression(if_stmt) = &func.body.statements[0] else {
+            panic!("Expected if expression statement");
+        };
+        let ExpressionNode::If(if_expr) = &if_stmt.expression else {
+            panic!("Expected if expression");
+        };
+        match &if_expr.then_block.statements[0] {
+            StatementNode::Return(return_stmt) => assert!(matches!(
+                return_stmt.value,
+                Some(ExpressionNode::Literal(_))
+            )),
+            _ => panic!("Expected return statement with value"),
+        }
+    }
+
+    #[test]
+    fn test_return_requires_semicolon() {
+        assert!(lex_and_parse("fn main() { return 1 }").is_err());
+    }
+
+    #[test]
+    fn test_unclosed_block_points_at_open_brace() {
+        let source = "fn main()\n\n{\n    let x = 1;\n    if x < 2 {\n        x\n    }\n";
+        let error = lex_and_parse(source).unwrap_err();
+
+        assert!(error.message.contains("Expected RightBrace"));
+        let (note, open_brace) = error.related.expect("error should point at the open brace");
+        assert_eq!(note, "block opened here");
+        assert_eq!(open_brace.line_col(source), (3, 0));
+    }
+
+    #[test]
+    fn test_end_of_input_error_points_at_last_token() {
+        let error = lex_and_parse("fn main() {").unwrap_err();
+        assert_eq!(error.span, Span { start: 10, end: 11 });
+
+        let error = lex_and_parse("fn main() { let x =").unwrap_err();
+        assert_eq!(error.span, Span { start: 18, end: 19 });
+
+        // Errors before the end still point at the offending token
+        let error = lex_and_parse("fn main() { let = 1; }").unwrap_err();
+        assert_eq!(error.span, Span { start: 16, end: 17 });
+    }
+
+    #[test]
+    fn test_error_context_tokens() {
+        let error = lex_and_parse("fn main() { let = 1; }").unwrap_err();
+        let kinds: Vec<_> = error.context.iter().map(|t| t.kind.clone()).collect();
+
+        // Two tokens either side of the unexpected `=`
+        assert_eq!(
+            kinds,
+            vec![
+                TokenKind::LeftBrace,
+                TokenKind::Let,
+                TokenKind::Assign,
+                TokenKind::Integer(1),
+                TokenKind::Semicolon,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_recovery_reports_every_error() {
+        let source = "fn main() { let x = ; x }\nfn helper() { 1 + }\nfn ok() { 2 }";
+        let (root, errors) = parse_with_recovery(Lexer::new(source).tokenize());
+
+        assert_eq!(errors.len(), 2);
+        assert_eq!(errors[0].message, "Unexpected token: Semicolon");
+        assert_eq!(&source[errors[0].span.start..errors[0].span.end], ";");
+        assert_eq!(errors[1].message, "Unexpected token: RightBrace");
+        assert_eq!(errors[1].span.start, source.find("}\nfn ok").unwrap());
+
+        // Each broken statement becomes an error node, up to its `;` or
+        // the closing brace, and parsing carries on with the next one
+        assert_eq!(root.items.len(), 3);
+        let CstNode::Function(main) = &root.items[0] else {
+            panic!("Expected function");
+        };
+        match &main.body.statements[..] {
+            [StatementNode::Error(error)] => {
+                assert_eq!(error.message, errors[0].message);
+                assert_eq!(error.tokens.first().unwrap().kind, TokenKind::Let);
+                assert_eq!(error.tokens.last().unwrap().kind, TokenKind::Semicolon);
+                assert_eq!(error.tokens.len(), 4);
+            }
+            _ => panic!("Expected an error statement"),
+        }
+        assert!(main.body.final_expr.is_some());
+        let CstNode::Function(helper) = &root.items[1] else {
+            panic!("Expected function");
+        };
+        assert!(matches!(
+            helper.body.statements[..],
+            [StatementNode::Error(_)]
+        ));
+        assert!(matches!(root.items[2], CstNode::Function(_)));
+
+        // Plain parsing stops at the first one
+        let error = lex_and_parse(source).unwrap_err();
+        assert_eq!(error, errors[0]);
+    }
+
+    #[test]
+    fn test_recovery_in_one_body() {
+        let source = "fn main() { let = 1; let y = ; 0 }";
+        let (root, errors) = parse_with_recovery(Lexer::new(source).tokenize());
+        let messages: Vec<&str> = errors.iter().map(|error| error.message.as_str()).collect();
+        assert_eq!(
+            messages,
+            vec![
+                "Expected identifier, found Assign",
+                "Unexpected token: Semicolon"
+            ]
+        );
+        assert_eq!(errors[0].span.start, source.find('=').unwrap());
+        assert_eq!(errors[1].span.start, source.find("; 0").unwrap());
+
+        let CstNode::Function(func) = &root.items[0] else {
+            panic!("Expected function");
+        };
+        assert!(matches!(
+            func.body.statements[..],
+            [StatementNode::Error(_), StatementNode::Error(_)]
+        ));
+        assert!(func.body.final_expr.is_some());
+
+        // Nested blocks recover on their own, and the source is all kept
+        let source = "fn main() { if x { 1 + } else { 2 }; let = 3; 4 }\nfn f() { 5 }";
+        let (root, errors) = parse_with_recovery(Lexer::new(source).tokenize());
+        assert_eq!(errors.len(), 2);
+        assert_eq!(root.to_source(), source.replace('\n', " "));
+        assert!(matches!(root.items[1], CstNode::Function(_)));
+    }
+
+    #[test]
+    fn test_lexer_errors_keep_the_tree() {
+        let source = "fn main() { let x = 1 @ 2; x }\nfn f() { 3 }";
+        let (root, errors) = parse_with_recovery(Lexer::new(source).tokenize());
+
+        // The lexer's error is reported once, and not again by the parser
+        assert_eq!(errors.len(), 1);
+        assert!(errors[0].message.contains("'@'"), "{}", errors[0].message);
+        assert_eq!(errors[0].span.start, source.find('@').unwrap());
+
+        // The rest of the tree is still there
+        assert_eq!(root.items.len(), 2);
+        let CstNode::Function(main) = &root.items[0] else {
+            panic!("Expected function");
+        };
+        assert!(matches!(
+            main.body.statements[..],
+            [StatementNode::Error(_)]
+        ));
+        assert!(main.body.final_expr.is_some());
+        assert!(matches!(root.items[1], CstNode::Function(_)));
+    }
+
+    #[test]
+    fn test_recovery_at_statement_boundaries() {
+        // A top-level statement resumes after its semicolon
+        let (root, errors) = parse_with_recovery(Lexer::new("let = 1; let y = 2;").tokenize());
+        assert_eq!(errors.len(), 1);
+        assert_eq!(root.items.len(), 2);
+        assert!(matches!(root.items[1], CstNode::Statement(_)));
+
+        // An unclosed block resumes at the next function
+        let source = "fn main() { let x = 1;\nfn f() { 2 }";
+        let (root, errors) = parse_with_recovery(Lexer::new(source).tokenize());
+        assert_eq!(errors.len(), 1);
+        assert!(matches!(root.items[1], CstNode::Function(_)));
+
+        // A stray closing brace is skipped on its own
+        let (root, errors) = parse_with_recovery(Lexer::new("} fn main() { 1 }").tokenize());
+        assert_eq!(errors.len(), 1);
+        assert_eq!(root.items.len(), 2);
+
+        // Without errors, recovery changes nothing
+        let source = "fn main() { 1 }";
+        let (root, errors) = parse_with_recovery(Lexer::new(source).tokenize());
+        assert!(errors.is_empty());
+        assert_eq!(root, lex_and_parse(source).unwrap());
+    }
+
+    #[test]
+    fn test_missing_semicolon() {
+        let source = "let x = 1 let y = 2;";
+        let (root, errors) = parse_with_recovery(Lexer::new(source).tokenize());
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].message, "missing `;`");
+        let end = source.find("1").unwrap() + 1;
+        assert_eq!(errors[0].span, Span { start: end, end });
+
+        // Both statements are still parsed
+        assert_eq!(root.items.len(), 2);
+        for item in &root.items {
+            let CstNode::Statement(stmt) = item else {
+                panic!("Expected statement");
+            };
+            assert!(matches!(**stmt, StatementNode::Let(_)));
+        }
+
+        // Inside a block, and for the statements after an expression
+        let source = "fn main() { f() x = 2 let y = x; y }";
+        let (root, errors) = parse_with_recovery(Lexer::new(source).tokenize());
+        let ends: Vec<usize> = errors.iter().map(|error| error.span.start).collect();
+        assert_eq!(
+            ends,
+            vec![source.find(" x =").unwrap(), source.find(" let").unwrap()]
+        );
+        let CstNode::Function(func) = &root.items[0] else {
+            panic!("Expected function");
+        };
+        assert!(matches!(
+            func.body.statements[..],
+            [
+                StatementNode::Expression(_),
+                StatementNode::Assign(_),
+                StatementNode::Let(_)
+            ]
+        ));
+
+        // A call can't be a block's final expression if anything but the
+        // closing brace follows it
+        let source = "fn main() { print(x) print(y); z }";
+        let (root, errors) = parse_with_recovery(Lexer::new(source).tokenize());
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].message, "missing `;`");
+        let end = source.find(" print(y)").unwrap();
+        assert_eq!(errors[0].span, Span { start: end, end });
+        let CstNode::Function(func) = &root.items[0] else {
+            panic!("Expected function");
+        };
+        assert_eq!(func.body.statements.len(), 2);
+        assert!(func.body.final_expr.is_some());
+
+        // Anything else is still an unexpected token
+        let error = lex_and_parse("fn main() { let x = 1 2; x }").unwrap_err();
+        assert_eq!(error.message, "Expected Semicolon, found Integer(2)");
+    }
 }
@@ -0,0 +1,311 @@
+use rue_ast::{
+    BlockNode, CstNode, CstRoot, ElseBodyNode, ExpressionNode, IfStatementNode, StatementNode,
+    TokenNode, Trivia, UnaryExprNode,
+};
+use rue_lexer::{Span, TokenKind};
+use std::collections::HashMap;
+
+/// Evaluate an expression at compile time, given the values of the names
+/// known to be constant: `const` declarations, and variables the optimizer
+/// has propagated
+///
+/// Booleans evaluate to 1 for true and 0 for false, the way they're
+/// represented at runtime. Returns None if the expression depends on
+/// anything else, or if
+/// evaluating it would overflow, so that overflow still happens (and traps,
+/// with overflow checks) at runtime. Division is never folded, since it can
+/// fail, and neither is `**` with a negative exponent.
+pub fn fold(expr: &ExpressionNode, constants: &HashMap<String, i64>) -> Option<i64> {
+    match expr {
+        ExpressionNode::Literal(token) => match token.kind {
+            TokenKind::Integer(value) => Some(value),
+            TokenKind::True => Some(1),
+            TokenKind::False => Some(0),
+            _ => None,
+        },
+        ExpressionNode::Identifier(token) => match &token.kind {
+            TokenKind::Ident(name) => constants.get(name).copied(),
+            _ => None,
+        },
+        ExpressionNode::Binary(binary) => {
+            let lhs = fold(&binary.left, constants)?;
+            let rhs = fold(&binary.right, constants)?;
+            match binary.operator.kind {
+                TokenKind::Plus => lhs.checked_add(rhs),
+                TokenKind::Minus => lhs.checked_sub(rhs),
+                TokenKind::Star => lhs.checked_mul(rhs),
+                TokenKind::StarStar => lhs.checked_pow(u32::try_from(rhs).ok()?),
+                TokenKind::Less => Some((lhs < rhs) as i64),
+                TokenKind::LessEqual => Some((lhs <= rhs) as i64),
+                TokenKind::Greater => Some((lhs > rhs) as i64),
+                TokenKind::GreaterEqual => Some((lhs >= rhs) as i64),
+                TokenKind::Equal => Some((lhs == rhs) as i64),
+                TokenKind::NotEqual => Some((lhs != rhs) as i64),
+                TokenKind::AmpAmp => Some((lhs != 0 && rhs != 0) as i64),
+                TokenKind::PipePipe => Some((lhs != 0 || rhs != 0) as i64),
+                _ => None,
+            }
+        }
+        ExpressionNode::Paren(paren) => fold(&paren.inner, constants),
+        ExpressionNode::Unary(unary) => match unary.operator.kind {
+            TokenKind::Minus => fold(&unary.operand, constants)?.checked_neg(),
+            TokenKind::Bang => Some((fold(&unary.operand, constants)? == 0) as i64),
+            _ => None,
+        },
+        ExpressionNode::Call(_)
+        | ExpressionNode::ArrayLit(_)
+        | ExpressionNode::Index(_)
+        | ExpressionNode::Assign(_)
+        | ExpressionNode::If(_)
+        | ExpressionNode::While(_)
+        | ExpressionNode::For(_)
+        | ExpressionNode::Block(_) => None,
+    }
+}
+
+/// Replace each integer expression `fold` can evaluate in a program's
+/// functions with its value, like `2 + 3 * 4` with `14`
+///
+/// Anything `fold` can't evaluate is left as it was, apart from the parts
+/// of it that it can. Comparisons aren't replaced, since their value is a
+/// bool rather than an integer literal, though their operands can be. A
+/// local variable or parameter with a constant's name shadows it here too.
+///
+/// Returns how many expressions were replaced.
+pub fn fold_program(ast: &mut CstRoot, constants: &HashMap<String, i64>) -> usize {
+    let mut folds = 0;
+    for item in &mut ast.items {
+        if let CstNode::Function(func) = item {
+            let mut constants = constants.clone();
+            for param in &func.param_list.params {
+                shadow(&param.name, &mut constants);
+            }
+            folds += fold_block(&mut func.body, &constants);
+        }
+    }
+    folds
+}
+
+/// Fold the constant parts of an expression, as `fold_program` does, and
+/// return how many were replaced
+pub fn fold_expression(expr: &mut ExpressionNode, constants: &HashMap<String, i64>) -> usize {
+    if is_integer_valued(expr)
+        && !matches!(
+            expr,
+            ExpressionNode::Literal(_) | ExpressionNode::Identifier(_)
+        )
+        && let Some(folded) = fold(expr, constants).and_then(|value| literal(value, expr.span()))
+    {
+        *expr = folded;
+        return 1;
+    }
+
+    match expr {
+        ExpressionNode::Binary(binary) => {
+            fold_expression(&mut binary.left, constants)
+                + fold_expression(&mut binary.right, constants)
+        }
+        ExpressionNode::Unary(unary) => fold_expression(&mut unary.operand, constants),
+        ExpressionNode::Paren(paren) => fold_expression(&mut paren.inner, constants),
+        ExpressionNode::Call(call) => call
+            .args
+            .iter_mut()
+            .map(|arg| fold_expression(arg, constants))
+            .sum(),
+        ExpressionNode::ArrayLit(array) => array
+            .elements
+            .iter_mut()
+            .map(|element| fold_expression(element, constants))
+            .sum(),
+        ExpressionNode::Index(index_expr) => fold_expression(&mut index_expr.index, constants),
+        ExpressionNode::Assign(assign_expr) => fold_expression(&mut assign_expr.value, constants),
+        ExpressionNode::If(if_expr) => fold_if(if_expr, constants),
+        ExpressionNode::While(while_expr) => {
+            fold_expression(&mut while_expr.condition, constants)
+                + fold_block(&mut while_expr.body, constants)
+        }
+        ExpressionNode::For(for_expr) => {
+            let bounds = fold_expression(&mut for_expr.start, constants)
+                + fold_expression(&mut for_expr.end, constants);
+            let mut constants = constants.clone();
+            shadow(&for_expr.variable, &mut constants);
+            bounds + fold_block(&mut for_expr.body, &constants)
+        }
+        ExpressionNode::Block(block) => fold_block(block, constants),
+        ExpressionNode::Identifier(_) | ExpressionNode::Literal(_) => 0,
+    }
+}
+
+fn fold_if(if_expr: &mut IfStatementNode, constants: &HashMap<String, i64>) -> usize {
+    let mut folds = fold_expression(&mut if_expr.condition, constants)
+        + fold_block(&mut if_expr.then_block, constants);
+    if let Some(else_clause) = &mut if_expr.else_clause {
+        folds += match &mut else_clause.body {
+            ElseBodyNode::Block(block) => fold_block(block, constants),
+            ElseBodyNode::If(nested_if) => fold_if(nested_if, constants),
+        };
+    }
+    folds
+}
+
+// A `let` shadows a constant from the statement after it to the end of the
+// block
+fn fold_block(block: &mut BlockNode, constants: &HashMap<String, i64>) -> usize {
+    let mut constants = constants.clone();
+    let mut folds = 0;
+    for stmt in &mut block.statements {
+        folds += match stmt {
+            StatementNode::Let(let_stmt) => {
+                let folds = fold_expression(&mut let_stmt.value, &constants);
+                shadow(&let_stmt.name, &mut constants);
+                folds
+            }
+            StatementNode::Const(const_stmt) => fold_expression(&mut const_stmt.value, &constants),
+            StatementNode::Assign(assign_stmt) => {
+                fold_expression(&mut assign_stmt.value, &constants)
+            }
+            StatementNode::IndexAssign(assign_stmt) => {
+                fold_expression(&mut assign_stmt.target.index, &constants)
+                    + fold_expression(&mut assign_stmt.value, &constants)
+            }
+            StatementNode::Expression(expr_stmt) => {
+                fold_expression(&mut expr_stmt.expression, &constants)
+            }
+            StatementNode::Return(return_stmt) => match &mut return_stmt.value {
+                Some(value) => fold_expression(value, &constants),
+                None => 0,
+            },
+            StatementNode::Break(_) | StatementNode::Continue(_) | StatementNode::Error(_) => 0,
+        };
+    }
+    if let Some(final_expr) = &mut block.final_expr {
+        folds += fold_expression(final_expr, &constants);
+    }
+    folds
+}
+
+fn shadow(name: &TokenNode, constants: &mut HashMap<String, i64>) {
+    if let TokenKind::Ident(name) = &name.kind {
+        constants.remove(name);
+    }
+}
+
+// Whether an expression's value is an integer, whatever it's made of, so
+// replacing it with a literal keeps its type
+fn is_integer_valued(expr: &ExpressionNode) -> bool {
+    match expr {
+        ExpressionNode::Literal(token) => matches!(token.kind, TokenKind::Integer(_)),
+        // Only constants fold, and they're all integers
+        ExpressionNode::Identifier(_) => true,
+        ExpressionNode::Binary(binary) => matches!(
+            binary.operator.kind,
+            TokenKind::Plus
+                | TokenKind::Minus
+                | TokenKind::Star
+                | TokenKind::StarStar
+                | TokenKind::Slash
+                | TokenKind::Percent
+        ),
+        ExpressionNode::Unary(unary) => unary.operator.kind == TokenKind::Minus,
+        ExpressionNode::Paren(paren) => is_integer_valued(&paren.inner),
+        _ => false,
+    }
+}
+
+// An expression for `value`, covering `span`. Literals have no sign, so a
+// negative value is a negated literal, and the most negative one, which
+// has no positive counterpart, can't be written at all.
+fn literal(value: i64, span: Span) -> Option<ExpressionNode> {
+    let token = |kind| TokenNode { kind, span };
+    if value >= 0 {
+        return Some(ExpressionNode::Literal(token(TokenKind::Integer(value))));
+    }
+    Some(ExpressionNode::Unary(UnaryExprNode {
+        operator: token(TokenKind::Minus),
+        operand: Box::new(ExpressionNode::Literal(token(TokenKind::Integer(
+            value.checked_neg()?,
+        )))),
+        trivia: Trivia::default(),
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rue_lexer::Lexer;
+
+    // The value of `main` in `source`, after folding with `constants`
+    fn folded_main(source: &str, constants: &[(&str, i64)]) -> ExpressionNode {
+        let mut ast = rue_parser::parse(Lexer::new(source).tokenize()).unwrap();
+        let constants = constants
+            .iter()
+            .map(|(name, value)| (name.to_string(), *value))
+            .collect();
+        fold_program(&mut ast, &constants);
+        let Some(CstNode::Function(func)) = ast.items.last() else {
+            panic!("expected main last");
+        };
+        func.body.final_expr.clone().unwrap()
+    }
+
+    fn unfolded_main(source: &str) -> ExpressionNode {
+        let ast = rue_parser::parse(Lexer::new(source).tokenize()).unwrap();
+        let Some(CstNode::Function(func)) = ast.items.last() else {
+            panic!("expected main last");
+        };
+        func.body.final_expr.clone().unwrap()
+    }
+
+    fn integer(expr: &ExpressionNode) -> Option<i64> {
+        match expr {
+            ExpressionNode::Literal(TokenNode {
+                kind: TokenKind::Integer(value),
+                ..
+            }) => Some(*value),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn test_fold_program() {
+        let expr = folded_main("fn main() { 2 + 3 * 4 }", &[]);
+        assert_eq!(integer(&expr), Some(14));
+        let source = "fn main() { if 1 < 1 + 1 { 2 * 3 } else { 0 } }";
+        let mut ast = rue_parser::parse(Lexer::new(source).tokenize()).unwrap();
+        // `1 + 1` and `2 * 3`, but not the comparison
+        assert_eq!(fold_program(&mut ast, &HashMap::new()), 2);
+        assert_eq!(expr.span(), rue_lexer::Span { start: 12, end: 21 });
+
+        // Only the constant parts of the rest
+        let source = "fn main(x) { x + 1 }";
+        assert_eq!(folded_main(source, &[]), unfolded_main(source));
+        let ExpressionNode::Binary(binary) = folded_main("fn main(x) { x * (N - 1) }", &[("N", 4)])
+        else {
+            panic!("expected a binary expression");
+        };
+        assert_eq!(integer(&binary.right), Some(3));
+
+        // A comparison stays one, so it's still a bool
+        let ExpressionNode::Binary(binary) = folded_main("fn main() { 1 + 1 < 3 }", &[]) else {
+            panic!("expected a binary expression");
+        };
+        assert_eq!(integer(&binary.left), Some(2));
+
+        // Negative values are negated literals
+        let ExpressionNode::Unary(unary) = folded_main("fn main() { 2 - 5 }", &[]) else {
+            panic!("expected a unary expression");
+        };
+        assert_eq!(integer(&unary.operand), Some(3));
+
+        // Division is never folded, and variables shadow constants
+        let source = "fn main() { 6 / 2 }";
+        assert_eq!(folded_main(source, &[]), unfolded_main(source));
+        let source = "fn main() { let N = 1; N + 1 }";
+        assert_eq!(folded_main(source, &[("N", 4)]), unfolded_main(source));
+        let expr = folded_main("fn main() { { N + 1 } }", &[("N", 4)]);
+        let ExpressionNode::Block(block) = expr else {
+            panic!("expected a block");
+        };
+        assert_eq!(integer(block.final_expr.as_ref().unwrap()), Some(5));
+    }
+}
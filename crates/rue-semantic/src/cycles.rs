@@ -0,0 +1,125 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+/// Find the cycles in a dependency graph, where each name maps to the names
+/// it depends on
+///
+/// Each cycle is a strongly connected component that loops back on itself:
+/// either several names that all depend on each other, or one name that
+/// depends on itself directly. Names within a cycle are sorted, and cycles
+/// are ordered by their first name, so results are stable. Dependencies with
+/// no entry of their own in the graph are treated as leaves.
+///
+/// The call graph uses this to find recursion, and it's what detects
+/// definitions that refer to themselves, like `const A = B; const B = A;`.
+pub fn find_cycles(graph: &BTreeMap<String, BTreeSet<String>>) -> Vec<Vec<String>> {
+    let mut tarjan = Tarjan {
+        graph,
+        index: BTreeMap::new(),
+        lowlink: BTreeMap::new(),
+        stack: Vec::new(),
+        on_stack: BTreeSet::new(),
+        components: Vec::new(),
+    };
+    for name in graph.keys() {
+        if !tarjan.index.contains_key(name.as_str()) {
+            tarjan.visit(name);
+        }
+    }
+
+    let mut cycles: Vec<Vec<String>> = tarjan
+        .components
+        .into_iter()
+        .filter(|component| {
+            component.len() > 1 || graph[component[0].as_str()].contains(&component[0])
+        })
+        .map(|mut component| {
+            component.sort();
+            component
+        })
+        .collect();
+    cycles.sort();
+    cycles
+}
+
+// Tarjan's strongly connected components algorithm
+struct Tarjan<'a> {
+    graph: &'a BTreeMap<String, BTreeSet<String>>,
+    index: BTreeMap<&'a str, usize>,
+    lowlink: BTreeMap<&'a str, usize>,
+    stack: Vec<&'a str>,
+    on_stack: BTreeSet<&'a str>,
+    components: Vec<Vec<String>>,
+}
+
+impl<'a> Tarjan<'a> {
+    fn visit(&mut self, name: &'a str) {
+        let index = self.index.len();
+        self.index.insert(name, index);
+        self.lowlink.insert(name, index);
+        self.stack.push(name);
+        self.on_stack.insert(name);
+
+        let graph = self.graph;
+        for dependency in graph[name].iter() {
+            let Some((dependency, _)) = graph.get_key_value(dependency) else {
+                continue;
+            };
+            let dependency = dependency.as_str();
+            if !self.index.contains_key(dependency) {
+                self.visit(dependency);
+                let low = self.lowlink[name].min(self.lowlink[dependency]);
+                self.lowlink.insert(name, low);
+            } else if self.on_stack.contains(dependency) {
+                let low = self.lowlink[name].min(self.index[dependency]);
+                self.lowlink.insert(name, low);
+            }
+        }
+
+        // `name` is the root of a component: pop everything above it
+        if self.lowlink[name] == self.index[name] {
+            let mut component = Vec::new();
+            while let Some(member) = self.stack.pop() {
+                self.on_stack.remove(member);
+                component.push(member.to_string());
+                if member == name {
+                    break;
+                }
+            }
+            self.components.push(component);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn graph(edges: &[(&str, &[&str])]) -> BTreeMap<String, BTreeSet<String>> {
+        edges
+            .iter()
+            .map(|(name, deps)| {
+                let deps = deps.iter().map(|dep| dep.to_string()).collect();
+                (name.to_string(), deps)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_two_name_cycle() {
+        // Like `const A = B + 1; const B = A;`
+        let cycles = find_cycles(&graph(&[("A", &["B"]), ("B", &["A"]), ("C", &["A"])]));
+        assert_eq!(cycles, vec![vec!["A".to_string(), "B".to_string()]]);
+    }
+
+    #[test]
+    fn test_self_cycle() {
+        let cycles = find_cycles(&graph(&[("A", &["A"]), ("B", &[])]));
+        assert_eq!(cycles, vec![vec!["A".to_string()]]);
+    }
+
+    #[test]
+    fn test_acyclic_graph() {
+        let acyclic = graph(&[("A", &["B", "C"]), ("B", &["C"]), ("C", &["undefined"])]);
+        assert!(find_cycles(&acyclic).is_empty());
+    }
+}
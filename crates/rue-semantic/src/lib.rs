@@ -1,5 +1,18 @@
-use rue_ast::{CstRoot, ExpressionNode, FunctionNode, StatementNode};
-use std::collections::HashMap;
+use rue_ast::visit::walk_statement;
+use rue_ast::{
+    BlockNode, CallExprNode, ConstStatementNode, CstNode, CstRoot, ExpressionNode, FunctionNode,
+    StatementNode, Visitor,
+};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+mod const_eval;
+mod cycles;
+mod lint;
+mod query;
+pub use const_eval::{fold, fold_expression, fold_program};
+pub use cycles::find_cycles;
+pub use lint::{SemanticWarning, lint_cst, shadowing_warnings};
+pub use query::type_at;
 
 // Semantic analysis types
 #[derive(Debug, Clone, PartialEq)]
@@ -11,13 +24,111 @@ pub struct SemanticError {
 #[derive(Debug, Clone, PartialEq)]
 pub enum RueType {
     I64,
+    /// The type of comparisons and conditions, kept as 1 or 0 at runtime
+    Bool,
+    /// The type of a block with no final expression
+    Unit,
+    /// A fixed-size array of i64s, of this length
+    Array(usize),
     Unknown,
 }
 
+impl RueType {
+    /// The type a type name in source refers to, like `i64`
+    pub fn from_name(name: &str) -> Option<RueType> {
+        match name {
+            "i64" => Some(RueType::I64),
+            "bool" => Some(RueType::Bool),
+            _ => None,
+        }
+    }
+
+    /// Size of a value of this type in bytes, or None if it has no layout
+    pub fn size(&self) -> Option<i64> {
+        match self {
+            RueType::I64 | RueType::Bool => Some(8),
+            RueType::Unit => Some(0),
+            RueType::Array(len) => Some(8 * *len as i64),
+            RueType::Unknown => None,
+        }
+    }
+}
+
+impl std::fmt::Display for RueType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RueType::I64 => write!(f, "i64"),
+            RueType::Bool => write!(f, "bool"),
+            RueType::Unit => write!(f, "()"),
+            RueType::Array(len) => write!(f, "[i64; {}]", len),
+            RueType::Unknown => write!(f, "unknown"),
+        }
+    }
+}
+
+/// The type two types agree on, or None if they're incompatible
+///
+/// `Unknown` is compatible with anything and resolves to the other type, so
+/// an expression whose type isn't inferred yet doesn't cause a spurious
+/// mismatch. Use this rather than `==` to check types against each other.
+pub fn unify(a: &RueType, b: &RueType) -> Option<RueType> {
+    match (a, b) {
+        (RueType::Unknown, other) | (other, RueType::Unknown) => Some(other.clone()),
+        _ if a == b => Some(a.clone()),
+        _ => None,
+    }
+}
+
+/// Name of the compile-time intrinsic `size_of(Type)`, which evaluates to
+/// the size of `Type` in bytes. A program can define its own function with
+/// this name, which replaces the intrinsic.
+pub const SIZE_OF: &str = "size_of";
+
+/// Name of the intrinsic `assert(condition)`, which exits the program with
+/// code 101 if `condition` is false. Like `size_of`, a program's own function
+/// with this name replaces it.
+pub const ASSERT: &str = "assert";
+
+/// Name of the intrinsic `read_int()`, which reads a decimal integer from
+/// stdin. Like the other intrinsics, a program's own function with this name
+/// replaces it.
+pub const READ_INT: &str = "read_int";
+
+/// Most parameters a function can have, one for each register arguments are
+/// passed in
+pub const MAX_PARAMS: usize = 5;
+
+/// The type whose size a call to `size_of` asks for
+pub fn size_of_type(call: &CallExprNode) -> Result<RueType, SemanticError> {
+    match call.args.as_slice() {
+        [
+            ExpressionNode::Identifier(rue_lexer::Token {
+                kind: rue_lexer::TokenKind::Ident(name),
+                span,
+            }),
+        ] => RueType::from_name(name).ok_or_else(|| SemanticError {
+            message: format!("Unknown type in size_of: {}", name),
+            span: *span,
+        }),
+        _ => Err(SemanticError {
+            message: "size_of expects a single type name, like size_of(i64)".to_string(),
+            span: call.open_paren.span,
+        }),
+    }
+}
+
 #[derive(Debug, Clone, PartialEq, Default)]
 pub struct Scope {
-    pub variables: HashMap<String, RueType>,
-    pub functions: HashMap<String, FunctionSignature>,
+    // Ordered by name, so anything that goes through them, like looking for
+    // a name to suggest, does so the same way every time
+    pub variables: BTreeMap<String, RueType>,
+    pub functions: BTreeMap<String, FunctionSignature>,
+    /// Values of the program's `const` declarations, which can't be assigned
+    pub constants: HashMap<String, i64>,
+    /// Type `return` statements must produce, or None outside a function
+    pub return_type: Option<RueType>,
+    /// Whether this is a loop body, where `break` and `continue` can be used
+    pub in_loop: bool,
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -28,14 +139,19 @@ pub struct FunctionSignature {
 
 // Semantic analysis functions
 pub fn analyze_cst(ast: &CstRoot) -> Result<Scope, SemanticError> {
-    let mut scope = Scope::default();
+    let mut scope = Scope {
+        constants: evaluate_constants(ast)?,
+        ..Scope::default()
+    };
 
     for item in &ast.items {
         match item {
-            rue_ast::CstNode::Function(func) => {
+            CstNode::Function(func) => {
                 analyze_function(&mut scope, func)?;
             }
-            rue_ast::CstNode::Statement(stmt) => {
+            // Already evaluated, up front
+            CstNode::Statement(stmt) if matches!(**stmt, StatementNode::Const(_)) => {}
+            CstNode::Statement(stmt) => {
                 analyze_statement(&mut scope, stmt)?;
             }
             _ => {} // Skip other node types for now
@@ -45,8 +161,170 @@ pub fn analyze_cst(ast: &CstRoot) -> Result<Scope, SemanticError> {
     Ok(scope)
 }
 
+/// Check that a program has a function to start in, `entry`, which is
+/// usually `main`
+///
+/// This is separate from `analyze_cst`, since a file of functions can be
+/// analyzed without being a whole program. The entry function is passed
+/// `argc` if it takes a parameter, so it can take one at most. A missing
+/// entry function is reported across the whole file.
+pub fn check_program(ast: &CstRoot, entry: &str) -> Result<(), SemanticError> {
+    let entry_func = ast.items.iter().find_map(|item| match item {
+        CstNode::Function(func)
+            if func.name.kind == rue_lexer::TokenKind::Ident(entry.to_string()) =>
+        {
+            Some(func)
+        }
+        _ => None,
+    });
+    match entry_func {
+        None => Err(SemanticError {
+            message: format!("No `{}` function found", entry),
+            span: ast.span(),
+        }),
+        Some(func) if func.param_list.params.len() > 1 => Err(SemanticError {
+            message: format!("`{}` can only take one parameter, argc", entry),
+            span: func.param_list.span(),
+        }),
+        Some(_) => Ok(()),
+    }
+}
+
+// Evaluate the top-level `const` declarations. They can refer to each other
+// in any order, as long as none depends on itself.
+fn evaluate_constants(ast: &CstRoot) -> Result<HashMap<String, i64>, SemanticError> {
+    let mut declarations: BTreeMap<String, &ConstStatementNode> = BTreeMap::new();
+    for item in &ast.items {
+        if let CstNode::Statement(stmt) = item
+            && let StatementNode::Const(const_stmt) = &**stmt
+            && let rue_lexer::TokenKind::Ident(name) = &const_stmt.name.kind
+            && declarations.insert(name.clone(), const_stmt).is_some()
+        {
+            return Err(SemanticError {
+                message: format!("Duplicate constant: {}", name),
+                span: const_stmt.name.span,
+            });
+        }
+    }
+
+    let dependencies: BTreeMap<String, BTreeSet<String>> = declarations
+        .iter()
+        .map(|(name, const_stmt)| {
+            let mut names = BTreeSet::new();
+            referenced_names(&const_stmt.value, &mut names);
+            names.retain(|name| declarations.contains_key(name));
+            (name.clone(), names)
+        })
+        .collect();
+    if let Some(cycle) = find_cycles(&dependencies).first() {
+        return Err(SemanticError {
+            message: format!("Constant depends on itself: {}", cycle.join(", ")),
+            span: declarations[&cycle[0]].name.span,
+        });
+    }
+
+    // Without cycles, every pass evaluates at least one more constant
+    let mut values = HashMap::new();
+    while values.len() < declarations.len() {
+        for (name, const_stmt) in &declarations {
+            if values.contains_key(name)
+                || !dependencies[name]
+                    .iter()
+                    .all(|dep| values.contains_key(dep))
+            {
+                continue;
+            }
+            let not_constant = || SemanticError {
+                message: format!(
+                    "Constant {} must be an integer expression known at compile time",
+                    name
+                ),
+                span: const_stmt.name.span,
+            };
+            let value = fold(&const_stmt.value, &values).ok_or_else(not_constant)?;
+            // `fold` gives booleans as 1 or 0, but constants are all i64s
+            let known = Scope {
+                constants: values.clone(),
+                ..Scope::default()
+            };
+            if analyze_expression(&known, &const_stmt.value)? != RueType::I64 {
+                return Err(not_constant());
+            }
+            values.insert(name.clone(), value);
+        }
+    }
+    Ok(values)
+}
+
+// Names an expression could read as constants. `fold` can only evaluate
+// operators on literals and names, so nothing else needs searching.
+fn referenced_names(expr: &ExpressionNode, names: &mut BTreeSet<String>) {
+    match expr {
+        ExpressionNode::Identifier(token) => {
+            if let rue_lexer::TokenKind::Ident(name) = &token.kind {
+                names.insert(name.clone());
+            }
+        }
+        ExpressionNode::Binary(binary) => {
+            referenced_names(&binary.left, names);
+            referenced_names(&binary.right, names);
+        }
+        ExpressionNode::Unary(unary) => referenced_names(&unary.operand, names),
+        ExpressionNode::Paren(paren) => referenced_names(&paren.inner, names),
+        _ => {}
+    }
+}
+
 // Helper functions for semantic analysis
 fn analyze_function(scope: &mut Scope, func: &FunctionNode) -> Result<(), SemanticError> {
+    let (mut local_scope, declared_return_type) = function_scope(scope, func)?;
+
+    // Analyze function body statements
+    for stmt in &func.body.statements {
+        analyze_statement(&mut local_scope, stmt)?;
+    }
+
+    // Analyze final expression if it exists. A body that ends in a `return`
+    // instead has already been checked by it.
+    let body_type = match &func.body.final_expr {
+        Some(final_expr) => analyze_expression(&local_scope, final_expr)?,
+        None if matches!(func.body.statements.last(), Some(StatementNode::Return(_))) => {
+            RueType::Unknown
+        }
+        None => RueType::Unit,
+    };
+
+    // The body has to produce the declared return type
+    if let (Some(declared), Some(ty)) = (&declared_return_type, &func.return_type)
+        && unify(declared, &body_type).is_none()
+    {
+        return Err(SemanticError {
+            message: format!(
+                "Mismatched return type: expected {}, found {}",
+                declared, body_type
+            ),
+            span: ty.span,
+        });
+    }
+
+    // Without an annotation, a function whose body is a bool returns one
+    if declared_return_type.is_none()
+        && body_type == RueType::Bool
+        && let rue_lexer::TokenKind::Ident(name) = &func.name.kind
+        && let Some(signature) = scope.functions.get_mut(name)
+    {
+        signature.return_type = RueType::Bool;
+    }
+
+    Ok(())
+}
+
+// Register a function in `scope`, and make the scope its body is analyzed
+// in. Also returns the return type it declares, if any.
+fn function_scope(
+    scope: &mut Scope,
+    func: &FunctionNode,
+) -> Result<(Scope, Option<RueType>), SemanticError> {
     // Extract function name
     let func_name = match &func.name.kind {
         rue_lexer::TokenKind::Ident(name) => name.clone(),
@@ -58,92 +336,246 @@ fn analyze_function(scope: &mut Scope, func: &FunctionNode) -> Result<(), Semant
         }
     };
 
-    // Check parameter count (rue only supports single parameter for now)
+    if scope.functions.contains_key(&func_name) {
+        return Err(SemanticError {
+            message: format!("function `{}` is already defined", func_name),
+            span: func.name.span,
+        });
+    }
+
+    // Arguments are only passed in registers, so there's a limit to them
     let param_count = func.param_list.params.len();
-    if param_count > 1 {
+    if let Some(param) = func.param_list.params.get(MAX_PARAMS) {
         return Err(SemanticError {
-            message: "Functions can only have at most one parameter".to_string(),
-            span: func.param_list.open_paren.span,
+            message: format!(
+                "Too many parameters: a function can have at most {}",
+                MAX_PARAMS
+            ),
+            span: param.name.span,
         });
     }
 
+    // Each parameter needs a name of its own
+    let mut param_names = BTreeSet::new();
+    for param in &func.param_list.params {
+        if let rue_lexer::TokenKind::Ident(name) = &param.name.kind
+            && !param_names.insert(name)
+        {
+            return Err(SemanticError {
+                message: format!("Duplicate parameter: {}", name),
+                span: param.name.span,
+            });
+        }
+    }
+
+    // Parameter and return types can be written out, but they have to be
+    // ones that exist. Parameters without one are i64s.
+    let mut param_types = Vec::new();
+    for param in &func.param_list.params {
+        param_types.push(match &param.ty {
+            Some(ty) => annotated_type(ty)?,
+            None => RueType::I64,
+        });
+    }
+    let declared_return_type = match &func.return_type {
+        Some(ty) => Some(annotated_type(ty)?),
+        None => None,
+    };
+
     // Register function in scope
     scope.functions.insert(
         func_name,
         FunctionSignature {
             param_count,
-            return_type: RueType::I64, // All functions return i64
+            // Without an annotation, functions return i64, unless their body
+            // turns out to be a bool
+            return_type: declared_return_type.clone().unwrap_or(RueType::I64),
         },
     );
 
-    // Create local scope for function body
+    // Create local scope for function body. Without an annotation, a
+    // function returns i64 if it produces a value, either from a final
+    // expression or a `return`, and unit if it never does, so that either
+    // all of its `return`s have a value or none do.
     let mut local_scope = scope.clone();
+    local_scope.return_type = Some(match (&declared_return_type, &func.body.final_expr) {
+        (Some(declared), _) => declared.clone(),
+        (None, Some(_)) => RueType::I64,
+        (None, None) if returns_value(&func.body) => RueType::I64,
+        (None, None) => RueType::Unit,
+    });
 
-    // Add parameter to local scope if it exists
-    if let Some(param) = func.param_list.params.first() {
-        if let rue_lexer::TokenKind::Ident(param_name) = &param.kind {
-            local_scope
-                .variables
-                .insert(param_name.clone(), RueType::I64);
+    // Add the parameters to the local scope
+    for (param, param_type) in func.param_list.params.iter().zip(param_types) {
+        if let rue_lexer::TokenKind::Ident(param_name) = &param.name.kind {
+            local_scope.variables.insert(param_name.clone(), param_type);
         }
     }
 
-    // Analyze function body statements
-    for stmt in &func.body.statements {
-        analyze_statement(&mut local_scope, stmt)?;
-    }
+    Ok((local_scope, declared_return_type))
+}
 
-    // Analyze final expression if it exists
-    if let Some(final_expr) = &func.body.final_expr {
-        analyze_expression(&mut local_scope, final_expr)?;
+// Whether any `return` in a function body has a value
+fn returns_value(body: &BlockNode) -> bool {
+    struct Returns {
+        with_value: bool,
+    }
+    impl Visitor for Returns {
+        fn visit_statement(&mut self, stmt: &StatementNode) {
+            if let StatementNode::Return(return_stmt) = stmt {
+                self.with_value |= return_stmt.value.is_some();
+            }
+            walk_statement(self, stmt);
+        }
     }
 
-    Ok(())
+    let mut returns = Returns { with_value: false };
+    returns.visit_block(body);
+    returns.with_value
+}
+
+// The type a type annotation names
+fn annotated_type(ty: &rue_ast::TokenNode) -> Result<RueType, SemanticError> {
+    let name = match &ty.kind {
+        rue_lexer::TokenKind::Ident(name) => name,
+        _ => {
+            return Err(SemanticError {
+                message: "Expected a type name".to_string(),
+                span: ty.span,
+            });
+        }
+    };
+    RueType::from_name(name).ok_or_else(|| SemanticError {
+        message: format!("Unknown type: {}", name),
+        span: ty.span,
+    })
 }
 
 fn analyze_statement(scope: &mut Scope, stmt: &StatementNode) -> Result<(), SemanticError> {
     match stmt {
         StatementNode::Let(let_stmt) => {
-            // Analyze the value expression
-            analyze_expression(scope, &let_stmt.value)?;
+            // Analyze the value expression. An array literal can only be
+            // the value of a `let`, for now.
+            let var_type = match &let_stmt.value {
+                ExpressionNode::ArrayLit(array) => analyze_array_literal(scope, array)?,
+                // A unit value is kept as an i64, for now
+                value => match analyze_expression(scope, value)? {
+                    RueType::Bool => RueType::Bool,
+                    _ => RueType::I64,
+                },
+            };
 
             // Add variable to scope
             if let rue_lexer::TokenKind::Ident(var_name) = &let_stmt.name.kind {
-                scope.variables.insert(var_name.clone(), RueType::I64);
+                scope.variables.insert(var_name.clone(), var_type);
             }
         }
         StatementNode::Assign(assign_stmt) => {
-            // Analyze the value expression
-            analyze_expression(scope, &assign_stmt.value)?;
+            analyze_assignment(scope, &assign_stmt.name, &assign_stmt.assigned_value())?;
+        }
+        // Top-level ones are evaluated before anything else is analyzed
+        StatementNode::Const(const_stmt) => {
+            return Err(SemanticError {
+                message: "Constants can only be declared at the top level".to_string(),
+                span: const_stmt.const_token.span,
+            });
+        }
+        StatementNode::IndexAssign(assign_stmt) => {
+            // The element has to exist, and what's stored in it be an i64
+            analyze_expression(scope, &ExpressionNode::Index(assign_stmt.target.clone()))?;
+            let value_type = analyze_expression(scope, &assign_stmt.assigned_value())?;
+            if unify(&value_type, &RueType::I64).is_none() {
+                return Err(SemanticError {
+                    message: "Array elements must be i64".to_string(),
+                    span: assign_stmt.operator.span,
+                });
+            }
+        }
+        StatementNode::Expression(expr_stmt) => {
+            analyze_discardable(scope, &expr_stmt.expression, false)?;
+        }
+        StatementNode::Break(break_stmt) => {
+            if !scope.in_loop {
+                return Err(SemanticError {
+                    message: "Cannot break outside of a loop".to_string(),
+                    span: break_stmt.break_token.span,
+                });
+            }
+        }
+        StatementNode::Continue(continue_stmt) => {
+            if !scope.in_loop {
+                return Err(SemanticError {
+                    message: "Cannot continue outside of a loop".to_string(),
+                    span: continue_stmt.continue_token.span,
+                });
+            }
+        }
+        // Only a tree parsed with recovery has these
+        StatementNode::Error(error) => {
+            return Err(SemanticError {
+                message: error.message.clone(),
+                span: stmt.span(),
+            });
+        }
+        StatementNode::Return(return_stmt) => {
+            let value_type = match &return_stmt.value {
+                Some(value) => analyze_expression(scope, value)?,
+                None => RueType::Unit,
+            };
 
-            // Check that variable exists in scope
-            if let rue_lexer::TokenKind::Ident(var_name) = &assign_stmt.name.kind {
-                if !scope.variables.contains_key(var_name) {
+            match &scope.return_type {
+                None => {
+                    return Err(SemanticError {
+                        message: "Cannot return outside of a function".to_string(),
+                        span: return_stmt.return_token.span,
+                    });
+                }
+                Some(expected) if unify(expected, &value_type).is_none() => {
                     return Err(SemanticError {
-                        message: format!("Cannot assign to undefined variable: {}", var_name),
-                        span: assign_stmt.name.span,
+                        message: format!(
+                            "Mismatched return type: expected {}, found {}",
+                            expected, value_type
+                        ),
+                        span: return_stmt.return_token.span,
                     });
                 }
-                // Variable already exists, assignment is valid
+                Some(_) => {}
             }
         }
-        StatementNode::Expression(expr_stmt) => {
-            analyze_expression(scope, &expr_stmt.expression)?;
-        }
     }
     Ok(())
 }
 
-fn analyze_expression(scope: &mut Scope, expr: &ExpressionNode) -> Result<RueType, SemanticError> {
+fn analyze_expression(scope: &Scope, expr: &ExpressionNode) -> Result<RueType, SemanticError> {
     match expr {
-        ExpressionNode::Literal(_) => Ok(RueType::I64), // All literals are i64
+        ExpressionNode::Literal(token) => match token.kind {
+            rue_lexer::TokenKind::True | rue_lexer::TokenKind::False => Ok(RueType::Bool),
+            _ => Ok(RueType::I64),
+        },
         ExpressionNode::Identifier(token) => {
             if let rue_lexer::TokenKind::Ident(name) = &token.kind {
-                if scope.variables.contains_key(name) {
+                if let Some(RueType::Array(_)) = scope.variables.get(name) {
+                    Err(SemanticError {
+                        message: format!("Arrays can only be indexed, like {}[0]", name),
+                        span: token.span,
+                    })
+                } else if let Some(var_type) = scope.variables.get(name) {
+                    Ok(var_type.clone())
+                } else if scope.constants.contains_key(name) {
                     Ok(RueType::I64)
                 } else {
+                    // Either a variable or a constant could have been meant
+                    let candidates: BTreeSet<&String> = scope
+                        .variables
+                        .keys()
+                        .chain(scope.constants.keys())
+                        .collect();
                     Err(SemanticError {
-                        message: format!("Undefined variable: {}", name),
+                        message: with_suggestion(
+                            format!("Undefined variable: {}", name),
+                            name,
+                            candidates,
+                        ),
                         span: token.span,
                     })
                 }
@@ -159,13 +591,99 @@ fn analyze_expression(scope: &mut Scope, expr: &ExpressionNode) -> Result<RueTyp
             let left_type = analyze_expression(scope, &binary_expr.left)?;
             let right_type = analyze_expression(scope, &binary_expr.right)?;
 
-            // Both operands must be i64
-            if left_type == RueType::I64 && right_type == RueType::I64 {
+            let error = |message: &str| SemanticError {
+                message: message.to_string(),
+                span: binary_expr.operator.span,
+            };
+            let both =
+                |ty: &RueType| unify(&left_type, ty).is_some() && unify(&right_type, ty).is_some();
+            match binary_expr.operator.kind {
+                // `&&` and `||` take and produce booleans
+                rue_lexer::TokenKind::AmpAmp | rue_lexer::TokenKind::PipePipe => {
+                    return if both(&RueType::Bool) {
+                        Ok(RueType::Bool)
+                    } else {
+                        Err(error("Logical operators require boolean operands"))
+                    };
+                }
+                // Comparing two values of the same type gives a boolean,
+                // though only i64s are ordered
+                rue_lexer::TokenKind::Equal | rue_lexer::TokenKind::NotEqual => {
+                    return match unify(&left_type, &right_type) {
+                        Some(RueType::I64 | RueType::Bool | RueType::Unknown) => Ok(RueType::Bool),
+                        _ => Err(error("Equality requires two i64 or two bool operands")),
+                    };
+                }
+                rue_lexer::TokenKind::Less
+                | rue_lexer::TokenKind::LessEqual
+                | rue_lexer::TokenKind::Greater
+                | rue_lexer::TokenKind::GreaterEqual => {
+                    return if both(&RueType::I64) {
+                        Ok(RueType::Bool)
+                    } else {
+                        Err(error("Comparison operators require i64 operands"))
+                    };
+                }
+                _ => {}
+            }
+
+            // A negative exponent would trap at runtime, so catch the ones
+            // known now
+            if binary_expr.operator.kind == rue_lexer::TokenKind::StarStar
+                && fold(&binary_expr.right, &visible_constants(scope)).is_some_and(|e| e < 0)
+            {
+                return Err(SemanticError {
+                    message: "Exponents can't be negative".to_string(),
+                    span: binary_expr.operator.span,
+                });
+            }
+
+            // So would dividing by zero
+            if matches!(
+                binary_expr.operator.kind,
+                rue_lexer::TokenKind::Slash | rue_lexer::TokenKind::Percent
+            ) && fold(&binary_expr.right, &visible_constants(scope)) == Some(0)
+            {
+                return Err(SemanticError {
+                    message: "Can't divide by zero".to_string(),
+                    span: binary_expr.operator.span,
+                });
+            }
+
+            // Arithmetic takes and produces i64s
+            match [&left_type, &right_type]
+                .into_iter()
+                .find(|ty| unify(ty, &RueType::I64).is_none())
+            {
+                None => Ok(RueType::I64),
+                Some(found) => Err(error(&format!(
+                    "arithmetic requires i64 operands, found {}",
+                    found
+                ))),
+            }
+        }
+        ExpressionNode::Paren(paren_expr) => analyze_expression(scope, &paren_expr.inner),
+        ExpressionNode::Unary(unary_expr) => {
+            // `!` takes and produces a boolean
+            let operand_type = analyze_expression(scope, &unary_expr.operand)?;
+            if unary_expr.operator.kind == rue_lexer::TokenKind::Bang {
+                return if unify(&operand_type, &RueType::Bool).is_some() {
+                    Ok(RueType::Bool)
+                } else {
+                    Err(SemanticError {
+                        message: "Logical not requires a boolean operand".to_string(),
+                        span: unary_expr.operator.span,
+                    })
+                };
+            }
+
+            // Negation takes and produces an i64
+            if unify(&operand_type, &RueType::I64).is_some() {
                 Ok(RueType::I64)
             } else {
                 Err(SemanticError {
-                    message: "Binary operators require i64 operands".to_string(),
-                    span: binary_expr.operator.span,
+                    message: "Unary operators require an i64 operand".to_string(),
+                    span: unary_expr.operator.span,
                 })
             }
         }
@@ -173,6 +691,23 @@ fn analyze_expression(scope: &mut Scope, expr: &ExpressionNode) -> Result<RueTyp
             // Get function name
             if let ExpressionNode::Identifier(func_token) = &*call_expr.function {
                 if let rue_lexer::TokenKind::Ident(func_name) = &func_token.kind {
+                    if func_name == SIZE_OF && !scope.functions.contains_key(func_name) {
+                        size_of_type(call_expr)?;
+                        return Ok(RueType::I64);
+                    }
+                    if func_name == ASSERT && !scope.functions.contains_key(func_name) {
+                        return analyze_assert(scope, call_expr);
+                    }
+                    if func_name == READ_INT && !scope.functions.contains_key(func_name) {
+                        if !call_expr.args.is_empty() {
+                            return Err(SemanticError {
+                                message: "read_int takes no arguments".to_string(),
+                                span: call_expr.open_paren.span,
+                            });
+                        }
+                        return Ok(RueType::I64);
+                    }
+
                     // Check if function exists
                     if let Some(signature) = scope.functions.get(func_name).cloned() {
                         // Check argument count
@@ -196,7 +731,11 @@ fn analyze_expression(scope: &mut Scope, expr: &ExpressionNode) -> Result<RueTyp
                         Ok(signature.return_type)
                     } else {
                         Err(SemanticError {
-                            message: format!("Undefined function: {}", func_name),
+                            message: with_suggestion(
+                                format!("Undefined function: {}", func_name),
+                                func_name,
+                                scope.functions.keys(),
+                            ),
                             span: func_token.span,
                         })
                     }
@@ -213,67 +752,364 @@ fn analyze_expression(scope: &mut Scope, expr: &ExpressionNode) -> Result<RueTyp
                 })
             }
         }
-        ExpressionNode::If(if_stmt) => {
-            // Analyze condition
-            analyze_expression(scope, &if_stmt.condition)?;
+        ExpressionNode::ArrayLit(array) => Err(SemanticError {
+            message: "Array literals can only be the value of a `let`, for now".to_string(),
+            span: array.open_bracket.span,
+        }),
+        ExpressionNode::Index(index_expr) => {
+            analyze_index(scope, index_expr)?;
+            Ok(RueType::I64)
+        }
+        ExpressionNode::Assign(assign_expr) => {
+            analyze_assignment(scope, &assign_expr.name, &assign_expr.assigned_value())
+        }
+        ExpressionNode::If(if_stmt) => analyze_if(scope, if_stmt, true),
+        ExpressionNode::While(while_stmt) => {
+            analyze_condition(scope, "While", &while_stmt.condition)?;
 
-            // Analyze then block
-            for stmt in &if_stmt.then_block.statements {
-                analyze_statement(scope, stmt)?;
-            }
-            let then_type = if let Some(final_expr) = &if_stmt.then_block.final_expr {
-                analyze_expression(scope, final_expr)?
-            } else {
-                RueType::I64 // blocks without final expression return i64(0)
-            };
+            // Analyze body
+            let mut body_scope = scope.clone();
+            body_scope.in_loop = true;
+            analyze_block(&mut body_scope, &while_stmt.body, false)?;
 
-            // Analyze else block if it exists
-            let else_type = if let Some(else_clause) = &if_stmt.else_clause {
-                match &else_clause.body {
-                    rue_ast::ElseBodyNode::Block(block) => {
-                        for stmt in &block.statements {
-                            analyze_statement(scope, stmt)?;
-                        }
-                        if let Some(final_expr) = &block.final_expr {
-                            analyze_expression(scope, final_expr)?
-                        } else {
-                            RueType::I64
-                        }
-                    }
-                    rue_ast::ElseBodyNode::If(nested_if) => {
-                        analyze_expression(scope, &ExpressionNode::If(nested_if.clone()))?
-                    }
+            // While expressions always return i64(0)
+            Ok(RueType::I64)
+        }
+        ExpressionNode::Block(block) => {
+            // Variables declared in the block are only in scope inside it
+            let mut block_scope = scope.clone();
+            analyze_block(&mut block_scope, block, true)
+        }
+        ExpressionNode::For(for_stmt) => {
+            // The range is evaluated outside the loop
+            for bound in [&for_stmt.start, &for_stmt.end] {
+                let bound_type = analyze_expression(scope, bound)?;
+                if unify(&bound_type, &RueType::I64).is_none() {
+                    return Err(SemanticError {
+                        message: "For loop ranges require i64 bounds".to_string(),
+                        span: for_stmt.dot_dot.span,
+                    });
                 }
+            }
+
+            // The loop variable is only in scope in the body
+            let mut body_scope = scope.clone();
+            if let rue_lexer::TokenKind::Ident(var_name) = &for_stmt.variable.kind {
+                body_scope.variables.insert(var_name.clone(), RueType::I64);
+            }
+            body_scope.in_loop = true;
+            analyze_block(&mut body_scope, &for_stmt.body, false)?;
+
+            // Like while expressions, for expressions always return i64(0)
+            Ok(RueType::I64)
+        }
+    }
+}
+
+// An `if` expression, whose value is `used` unless it's a statement or the
+// end of a block whose value isn't. Only an `if` whose value is used needs
+// an `else`, since one without would have nothing to produce when its
+// condition is false.
+fn analyze_if(
+    scope: &Scope,
+    if_stmt: &rue_ast::IfStatementNode,
+    used: bool,
+) -> Result<RueType, SemanticError> {
+    analyze_condition(scope, "If", &if_stmt.condition)?;
+
+    // Analyze then block
+    let then_type = analyze_block(&mut scope.clone(), &if_stmt.then_block, used)?;
+
+    // Analyze else block if it exists
+    let else_type = match &if_stmt.else_clause {
+        Some(else_clause) => match &else_clause.body {
+            rue_ast::ElseBodyNode::Block(block) => analyze_block(&mut scope.clone(), block, used)?,
+            rue_ast::ElseBodyNode::If(nested_if) => analyze_if(scope, nested_if, used)?,
+        },
+        None if used && unify(&then_type, &RueType::Unit).is_none() => {
+            return Err(SemanticError {
+                message: "`if` used as a value needs an `else`".to_string(),
+                span: if_stmt.if_token.span,
+            });
+        }
+        None => then_type.clone(),
+    };
+
+    // Both branches must have same type
+    unify(&then_type, &else_type).ok_or_else(|| SemanticError {
+        message: "If expression branches must have the same type".to_string(),
+        span: if_stmt.if_token.span,
+    })
+}
+
+// An `if` or `while` condition, which has to be a bool, like a comparison.
+// `what` starts the error message.
+fn analyze_condition(
+    scope: &Scope,
+    what: &str,
+    condition: &ExpressionNode,
+) -> Result<(), SemanticError> {
+    let condition_type = analyze_expression(scope, condition)?;
+    if unify(&condition_type, &RueType::Bool).is_none() {
+        return Err(SemanticError {
+            message: format!("{} conditions must be bool, found {}", what, condition_type),
+            span: condition.span(),
+        });
+    }
+    Ok(())
+}
+
+// Check an assignment of `assigned_value` to the variable `name`, returning
+// the variable's type, which is also the type of an assignment expression
+fn analyze_assignment(
+    scope: &Scope,
+    name: &rue_lexer::Token,
+    assigned_value: &ExpressionNode,
+) -> Result<RueType, SemanticError> {
+    let rue_lexer::TokenKind::Ident(var_name) = &name.kind else {
+        return Err(SemanticError {
+            message: "Expected variable name".to_string(),
+            span: name.span,
+        });
+    };
+
+    // Check that variable exists in scope
+    let var_type = match scope.variables.get(var_name) {
+        Some(RueType::Array(_)) => {
+            return Err(SemanticError {
+                message: format!(
+                    "Cannot assign to array {}: assign to its elements instead",
+                    var_name
+                ),
+                span: name.span,
+            });
+        }
+        Some(var_type) => var_type.clone(),
+        None => {
+            let message = if scope.constants.contains_key(var_name) {
+                format!("Cannot assign to constant: {}", var_name)
             } else {
-                RueType::I64 // missing else defaults to i64(0)
+                with_suggestion(
+                    format!("Cannot assign to undefined variable: {}", var_name),
+                    var_name,
+                    scope.variables.keys(),
+                )
             };
+            return Err(SemanticError {
+                message,
+                span: name.span,
+            });
+        }
+    };
 
-            // Both branches must have same type
-            if then_type == else_type {
-                Ok(then_type)
-            } else {
-                Err(SemanticError {
-                    message: "If expression branches must have the same type".to_string(),
-                    span: if_stmt.if_token.span,
-                })
-            }
+    // Analyze the value expression. A compound assignment like `+=` reads
+    // the variable too, and is checked as that operator.
+    let value_type = analyze_expression(scope, assigned_value)?;
+    if unify(&value_type, &var_type).is_none() {
+        return Err(SemanticError {
+            message: format!(
+                "Cannot assign a value of type {} to {}, which is {}",
+                value_type, var_name, var_type
+            ),
+            span: name.span,
+        });
+    }
+    Ok(var_type)
+}
+
+// An array literal's elements are all i64s
+fn analyze_array_literal(
+    scope: &Scope,
+    array: &rue_ast::ArrayLitNode,
+) -> Result<RueType, SemanticError> {
+    for element in &array.elements {
+        if unify(&analyze_expression(scope, element)?, &RueType::I64).is_none() {
+            return Err(SemanticError {
+                message: "Array elements must be i64".to_string(),
+                span: array.open_bracket.span,
+            });
         }
-        ExpressionNode::While(while_stmt) => {
-            // Analyze condition
-            analyze_expression(scope, &while_stmt.condition)?;
+    }
+    Ok(RueType::Array(array.elements.len()))
+}
 
-            // Analyze body
-            for stmt in &while_stmt.body.statements {
-                analyze_statement(scope, stmt)?;
-            }
-            if let Some(final_expr) = &while_stmt.body.final_expr {
-                analyze_expression(scope, final_expr)?;
+/// Which element of an array variable `array[index]` is, when it's a valid
+/// one. Indices have to be known at compile time, for now, so they're checked
+/// against the array's length here rather than at runtime.
+fn array_index(scope: &Scope, index_expr: &rue_ast::IndexExprNode) -> Option<(String, usize)> {
+    let ExpressionNode::Identifier(rue_lexer::Token {
+        kind: rue_lexer::TokenKind::Ident(name),
+        ..
+    }) = index_expr.array.as_ref()
+    else {
+        return None;
+    };
+    let RueType::Array(len) = scope.variables.get(name)? else {
+        return None;
+    };
+
+    let index = usize::try_from(fold(&index_expr.index, &visible_constants(scope))?).ok()?;
+    (index < *len).then(|| (name.clone(), index))
+}
+
+// The constants in scope: the ones a local variable shadows can't be used
+fn visible_constants(scope: &Scope) -> HashMap<String, i64> {
+    scope
+        .constants
+        .iter()
+        .filter(|(name, _)| !scope.variables.contains_key(*name))
+        .map(|(name, value)| (name.clone(), *value))
+        .collect()
+}
+
+fn analyze_index(scope: &Scope, index_expr: &rue_ast::IndexExprNode) -> Result<(), SemanticError> {
+    let error = |message: String| SemanticError {
+        message,
+        span: index_expr.open_bracket.span,
+    };
+
+    let len = match index_expr.array.as_ref() {
+        ExpressionNode::Identifier(rue_lexer::Token {
+            kind: rue_lexer::TokenKind::Ident(name),
+            ..
+        }) => match scope.variables.get(name) {
+            Some(RueType::Array(len)) => *len,
+            _ => {
+                return Err(error(format!(
+                    "Cannot index {}, which isn't an array",
+                    name
+                )));
             }
+        },
+        _ => return Err(error("Only array variables can be indexed".to_string())),
+    };
 
-            // While expressions always return i64(0)
-            Ok(RueType::I64)
+    if unify(
+        &analyze_expression(scope, &index_expr.index)?,
+        &RueType::I64,
+    )
+    .is_none()
+    {
+        return Err(error("Array indices must be i64".to_string()));
+    }
+    if array_index(scope, index_expr).is_none() {
+        return Err(match fold(&index_expr.index, &visible_constants(scope)) {
+            Some(index) => error(format!(
+                "Index {} is out of bounds for an array of length {}",
+                index, len
+            )),
+            _ => error("Array indices must be known at compile time, for now".to_string()),
+        });
+    }
+    Ok(())
+}
+
+// An assert takes a single condition, a bool like an `if` condition.
+// Asserting has no value.
+fn analyze_assert(scope: &Scope, call: &CallExprNode) -> Result<RueType, SemanticError> {
+    let [condition] = call.args.as_slice() else {
+        return Err(SemanticError {
+            message: "assert expects a single condition, like assert(x < 10)".to_string(),
+            span: call.open_paren.span,
+        });
+    };
+    let condition_type = analyze_expression(scope, condition)?;
+    if unify(&condition_type, &RueType::Bool).is_none() {
+        return Err(SemanticError {
+            message: "assert requires a bool condition".to_string(),
+            span: call.open_paren.span,
+        });
+    }
+    Ok(RueType::Unit)
+}
+
+/// The type of `expr` in `scope`, which is only read
+///
+/// Statements in blocks inside `expr` are analyzed in copies of `scope`, like
+/// any other block's, so they can't change it.
+pub fn type_of(scope: &Scope, expr: &ExpressionNode) -> Result<RueType, SemanticError> {
+    match expr {
+        ExpressionNode::ArrayLit(array) => analyze_array_literal(scope, array),
+        _ => analyze_expression(scope, expr),
+    }
+}
+
+// An error about `name` not being defined, with a "did you mean" hint when
+// it's close to one of the names that are
+fn with_suggestion<'a>(
+    message: String,
+    name: &str,
+    candidates: impl IntoIterator<Item = &'a String>,
+) -> String {
+    match closest_name(name, candidates) {
+        Some(suggestion) => format!("{}; did you mean `{}`?", message, suggestion),
+        None => message,
+    }
+}
+
+// The candidate closest to `name`, if one is close enough that `name` is
+// likely a typo of it. Ties go to the first, so the candidates should come in
+// a stable order.
+fn closest_name<'a>(
+    name: &str,
+    candidates: impl IntoIterator<Item = &'a String>,
+) -> Option<&'a str> {
+    // Up to two edits, but never so many that all of a short name changes
+    let max_distance = name.chars().count().saturating_sub(1).min(2);
+    candidates
+        .into_iter()
+        .map(|candidate| (edit_distance(name, candidate), candidate))
+        .filter(|(distance, _)| *distance <= max_distance)
+        .min_by_key(|(distance, _)| *distance)
+        .map(|(_, candidate)| candidate.as_str())
+}
+
+// The Levenshtein distance between two strings: how many characters have to
+// be inserted, deleted or replaced to turn one into the other
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    // Distances from the prefix of `a` so far to each prefix of `b`
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+    for (i, a_char) in a.chars().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+        for (j, b_char) in b.iter().enumerate() {
+            let replaced = diagonal + usize::from(a_char != *b_char);
+            diagonal = row[j + 1];
+            row[j + 1] = replaced.min(row[j] + 1).min(diagonal + 1);
         }
     }
+    row[b.len()]
+}
+
+// Analyze a block's statements and return the type of its value, which is
+// unit if it has no final expression, and whether that value is `used`
+fn analyze_block(
+    scope: &mut Scope,
+    block: &BlockNode,
+    used: bool,
+) -> Result<RueType, SemanticError> {
+    for stmt in &block.statements {
+        analyze_statement(scope, stmt)?;
+    }
+    match &block.final_expr {
+        Some(final_expr) => analyze_discardable(scope, final_expr, used),
+        None => Ok(RueType::Unit),
+    }
+}
+
+// Analyze an expression whose value might not be `used`, which only matters
+// to an `if`, or a block that ends in one
+fn analyze_discardable(
+    scope: &Scope,
+    expr: &ExpressionNode,
+    used: bool,
+) -> Result<RueType, SemanticError> {
+    match expr {
+        ExpressionNode::If(if_stmt) => analyze_if(scope, if_stmt, used),
+        ExpressionNode::Block(block) => analyze_block(&mut scope.clone(), block, used),
+        _ => analyze_expression(scope, expr),
+    }
 }
 
 #[cfg(test)]
@@ -358,6 +1194,133 @@ fn main() {
         assert!(error.message.contains("Undefined function: undefined_func"));
     }
 
+    #[test]
+    fn test_undefined_name_suggestions() {
+        let error = parse_and_analyze(
+            "fn factorial(n) { if n <= 1 { 1 } else { n * factorial(n - 1) } } \
+             fn main() { factorail(5) }",
+        )
+        .unwrap_err();
+        assert_eq!(
+            error.message,
+            "Undefined function: factorail; did you mean `factorial`?"
+        );
+
+        let error = parse_and_analyze("fn main() { let count = 1; coutn + 1 }").unwrap_err();
+        assert_eq!(
+            error.message,
+            "Undefined variable: coutn; did you mean `count`?"
+        );
+        let error = parse_and_analyze("const LIMIT = 10; fn main() { LIMTI }").unwrap_err();
+        assert_eq!(
+            error.message,
+            "Undefined variable: LIMTI; did you mean `LIMIT`?"
+        );
+        let error = parse_and_analyze("fn main() { let total = 0; totl = 1; total }").unwrap_err();
+        assert_eq!(
+            error.message,
+            "Cannot assign to undefined variable: totl; did you mean `total`?"
+        );
+
+        // Nothing is suggested for a name that isn't close to any
+        let error = parse_and_analyze("fn factorial(n) { n } fn main() { zebra(5) }").unwrap_err();
+        assert_eq!(error.message, "Undefined function: zebra");
+        let error = parse_and_analyze("fn main() { let count = 1; quokka }").unwrap_err();
+        assert_eq!(error.message, "Undefined variable: quokka");
+    }
+
+    #[test]
+    fn test_undefined_function_suggestion() {
+        let error = parse_and_analyze("fn fact(n) { n } fn fast(n) { n } fn main() { facts(1) }")
+            .unwrap_err();
+        assert_eq!(
+            error.message,
+            "Undefined function: facts; did you mean `fact`?"
+        );
+
+        // Between names that are equally close, it's the one first by name,
+        // whatever order they're defined in
+        for source in [
+            "fn fao() { 0 } fn fab() { 0 } fn main() { fa() }",
+            "fn fab() { 0 } fn fao() { 0 } fn main() { fa() }",
+        ] {
+            let error = parse_and_analyze(source).unwrap_err();
+            assert_eq!(error.message, "Undefined function: fa; did you mean `fab`?");
+        }
+    }
+
+    #[test]
+    fn test_check_program() {
+        let check = |source: &str, entry: &str| {
+            let ast = rue_parser::parse(Lexer::new(source).tokenize()).unwrap();
+            check_program(&ast, entry)
+        };
+        assert!(check("fn main() { 0 }", "main").is_ok());
+        assert!(check("fn main(argc) { argc }", "main").is_ok());
+        assert!(check("fn start() { 0 }", "start").is_ok());
+
+        let source = "const A = 1;\nfn helper() { A }\n";
+        let error = check(source, "main").unwrap_err();
+        assert_eq!(error.message, "No `main` function found");
+        assert_eq!(error.span, rue_lexer::Span { start: 0, end: 30 });
+        let error = check("fn main() { 0 }", "start").unwrap_err();
+        assert_eq!(error.message, "No `start` function found");
+
+        // Only argc can be passed to it
+        let error = check("fn main(a, b) { a }", "main").unwrap_err();
+        assert_eq!(error.message, "`main` can only take one parameter, argc");
+        assert_eq!(error.span, rue_lexer::Span { start: 7, end: 13 });
+    }
+
+    #[test]
+    fn test_duplicate_function() {
+        let source = "fn foo() { 1 } fn main() { foo() } fn foo() { 2 }";
+        let error = parse_and_analyze(source).unwrap_err();
+        assert_eq!(error.message, "function `foo` is already defined");
+        assert_eq!(error.span, rue_lexer::Span { start: 38, end: 41 });
+
+        assert!(
+            parse_and_analyze("fn foo() { 1 } fn bar() { 2 } fn main() { foo() + bar() }").is_ok()
+        );
+    }
+
+    #[test]
+    fn test_multiple_parameters() {
+        let source = "fn sub(a, b) { a - b } fn main() { sub(5, 3) }";
+        let scope = parse_and_analyze(source).unwrap();
+        assert_eq!(scope.functions["sub"].param_count, 2);
+
+        let error = parse_and_analyze("fn sub(a, b) { a - b } fn main() { sub(5) }").unwrap_err();
+        assert_eq!(error.message, "Function 'sub' expects 2 arguments, got 1");
+        let error =
+            parse_and_analyze("fn sub(a, b) { a - b } fn main() { sub(5, 3, 1) }").unwrap_err();
+        assert_eq!(error.message, "Function 'sub' expects 2 arguments, got 3");
+
+        // Every parameter is in scope, with its own type
+        let source = "fn pick(take: bool, x, y) { if take { x } else { y } } \
+                      fn main() { pick(true, 1, 2) }";
+        assert!(parse_and_analyze(source).is_ok());
+        let error = parse_and_analyze("fn f(a, b: bool) { a + b }").unwrap_err();
+        assert_eq!(
+            error.message,
+            "arithmetic requires i64 operands, found bool"
+        );
+
+        let error = parse_and_analyze("fn f(a, a) { a }").unwrap_err();
+        assert_eq!(error.message, "Duplicate parameter: a");
+        assert_eq!(error.span, rue_lexer::Span { start: 8, end: 9 });
+
+        // The sixth parameter is one too many
+        assert!(parse_and_analyze("fn f(a, b, c, d, e) { a + e }").is_ok());
+        let source = "fn f(a, b, c, d, e, g) { a + g }";
+        let error = parse_and_analyze(source).unwrap_err();
+        assert_eq!(
+            error.message,
+            "Too many parameters: a function can have at most 5"
+        );
+        assert_eq!(error.span.start, source.find('g').unwrap());
+    }
+
     #[test]
     fn test_semantic_analysis_wrong_argument_count() {
         let result = parse_and_analyze(
@@ -421,6 +1384,55 @@ fn main() {
         assert!(error.message.contains("Undefined variable: undefined_var"));
     }
 
+    #[test]
+    fn test_semantic_analysis_for_loop() {
+        let result =
+            parse_and_analyze("fn main() { let sum = 0; for i in 0..10 { sum = sum + i; }; sum }");
+        assert!(result.is_ok());
+
+        // The loop variable isn't in scope after the loop
+        let error = parse_and_analyze("fn main() { for i in 0..10 { i; }; i }").unwrap_err();
+        assert_eq!(error.message, "Undefined variable: i");
+
+        // ...or in its own range
+        let error = parse_and_analyze("fn main() { for i in 0..i { }; 0 }").unwrap_err();
+        assert_eq!(error.message, "Undefined variable: i");
+    }
+
+    #[test]
+    fn test_semantic_analysis_block_expression() {
+        let result = parse_and_analyze("fn main() { let y = { let a = 1; a + 1 }; y }");
+        assert!(result.is_ok());
+
+        // Its variables aren't in scope after it, but outer ones are inside
+        let error = parse_and_analyze("fn main() { let y = { let a = 1; a }; a }").unwrap_err();
+        assert_eq!(error.message, "Undefined variable: a");
+        let result = parse_and_analyze("fn main() { let a = 1; let y = { a = 2; a }; y }");
+        assert!(result.is_ok());
+
+        // Without a final expression, a block has no value
+        let error = parse_and_analyze("fn main() -> i64 { { 1; } }").unwrap_err();
+        assert!(error.message.contains("Mismatched return type"));
+    }
+
+    #[test]
+    fn test_semantic_analysis_break_and_continue() {
+        let source = "fn main() { while 1 > 0 { if 2 > 1 { break; }; continue; }; \
+                      for i in 0..3 { continue; }; 0 }";
+        assert!(parse_and_analyze(source).is_ok());
+
+        let error = parse_and_analyze("break;").unwrap_err();
+        assert_eq!(error.message, "Cannot break outside of a loop");
+        assert_eq!(error.span, rue_lexer::Span { start: 0, end: 5 });
+
+        let error = parse_and_analyze("fn main() { continue; 0 }").unwrap_err();
+        assert_eq!(error.message, "Cannot continue outside of a loop");
+
+        // The loop ends at its closing brace
+        let error = parse_and_analyze("fn main() { while 1 > 0 { }; break; 0 }").unwrap_err();
+        assert_eq!(error.message, "Cannot break outside of a loop");
+    }
+
     #[test]
     fn test_semantic_analysis_assignment_valid() {
         let result = parse_and_analyze(
@@ -468,4 +1480,490 @@ fn main() {
         );
         assert!(result.is_ok());
     }
+
+    #[test]
+    fn test_semantic_analysis_compound_assignment() {
+        assert!(parse_and_analyze("fn main() { let x = 1; x += 2; x -= 1; x *= 3; x }").is_ok());
+
+        let error = parse_and_analyze("fn main() { y += 1; 0 }").unwrap_err();
+        assert_eq!(error.message, "Cannot assign to undefined variable: y");
+
+        let error = parse_and_analyze("fn main() { let x = 1; x += {}; x }").unwrap_err();
+        assert!(error.message.contains("i64"), "{}", error.message);
+    }
+
+    #[test]
+    fn test_semantic_analysis_assignment_expression() {
+        assert!(parse_and_analyze("fn main() { let x = 1; let y = (x = 5); x + y }").is_ok());
+        assert!(parse_and_analyze("fn main() { let x = 1; let y = 2; x = y += 1; x }").is_ok());
+
+        let error = parse_and_analyze("fn main() { let y = (x = 5); y }").unwrap_err();
+        assert_eq!(error.message, "Cannot assign to undefined variable: x");
+        let error = parse_and_analyze("fn main() { let x = 1; (x = {}); x }").unwrap_err();
+        assert_eq!(
+            error.message,
+            "Cannot assign a value of type () to x, which is i64"
+        );
+    }
+
+    #[test]
+    fn test_semantic_analysis_arrays() {
+        assert!(
+            parse_and_analyze(
+                "const N = 1; fn main() { let a = [1, 2, 3]; a[N + 1] = a[0]; a[2] *= 2; a[N] }"
+            )
+            .is_ok()
+        );
+
+        for (source, message) in [
+            (
+                "fn main() { let a = [1, 2, 3]; a[3] }",
+                "Index 3 is out of bounds for an array of length 3",
+            ),
+            (
+                "fn main() { let a = [1]; a[0 - 1] }",
+                "Index -1 is out of bounds for an array of length 1",
+            ),
+            (
+                "fn f(i) { let a = [1, 2]; a[i] }",
+                "Array indices must be known at compile time, for now",
+            ),
+            (
+                "const I = 0; fn f(I) { let a = [1, 2]; a[I] }",
+                "Array indices must be known at compile time, for now",
+            ),
+            (
+                "fn main() { let x = 1; x[0] }",
+                "Cannot index x, which isn't an array",
+            ),
+            (
+                "fn main() { let a = [1]; a }",
+                "Arrays can only be indexed, like a[0]",
+            ),
+            (
+                "fn main() { let a = [1]; a = 2; 0 }",
+                "Cannot assign to array a: assign to its elements instead",
+            ),
+            (
+                "fn main() { [1, 2][0] }",
+                "Only array variables can be indexed",
+            ),
+            (
+                "fn main() { let a = [1, {}]; 0 }",
+                "Array elements must be i64",
+            ),
+        ] {
+            let error = parse_and_analyze(source).unwrap_err();
+            assert_eq!(error.message, message, "{}", source);
+        }
+    }
+
+    #[test]
+    fn test_type_of_leaves_scope_alone() {
+        let scope = parse_and_analyze("fn f(n) { n }").unwrap();
+        let source = "if f(1) > 0 { let x = 1; x } else { while 1 > 0 { let y = 2; break; }; 0 }";
+        let ast = rue_parser::parse(Lexer::new(&format!("{};", source)).tokenize()).unwrap();
+        let CstNode::Statement(stmt) = &ast.items[0] else {
+            panic!("expected a statement");
+        };
+        let StatementNode::Expression(expr_stmt) = stmt.as_ref() else {
+            panic!("expected an expression statement");
+        };
+
+        let before = scope.clone();
+        assert_eq!(type_of(&scope, &expr_stmt.expression), Ok(RueType::I64));
+        assert_eq!(scope, before);
+    }
+
+    #[test]
+    fn test_empty_if_block_is_unit() {
+        let tokens = Lexer::new("fn main() { if 1 > 0 {}; 0 }").tokenize();
+        let ast = rue_parser::parse(tokens).unwrap();
+        let rue_ast::CstNode::Function(func) = &ast.items[0] else {
+            panic!("Expected function");
+        };
+        let StatementNode::Expression(expr_stmt) = &func.body.statements[0] else {
+            panic!("Expected expression statement");
+        };
+
+        let scope = Scope::default();
+        let if_type = analyze_expression(&scope, &expr_stmt.expression);
+        assert_eq!(if_type, Ok(RueType::Unit));
+    }
+
+    #[test]
+    fn test_unit_and_i64_branches_mismatch() {
+        let result = parse_and_analyze("fn main() { if 1 > 0 {} else { 5 } }");
+        let error = result.unwrap_err();
+        assert!(
+            error
+                .message
+                .contains("If expression branches must have the same type")
+        );
+    }
+
+    #[test]
+    fn test_if_value_needs_else() {
+        let error = parse_and_analyze("fn main(c) { let x = if c > 0 { 1 }; x }").unwrap_err();
+        assert_eq!(error.message, "`if` used as a value needs an `else`");
+        assert_eq!(error.span, rue_lexer::Span { start: 21, end: 23 });
+        let error =
+            parse_and_analyze("fn main(c) { if c > 0 { 1 } else if c < 0 { 2 } }").unwrap_err();
+        assert_eq!(error.message, "`if` used as a value needs an `else`");
+
+        assert!(parse_and_analyze("fn main(c) { let x = if c > 0 { 1 } else { 2 }; x }").is_ok());
+
+        // As a statement, or at the end of a block whose value is dropped,
+        // nothing uses the value
+        assert!(parse_and_analyze("fn main(c) { if c > 0 { 1 }; 0 }").is_ok());
+        assert!(parse_and_analyze("fn main(c) { while c > 0 { if c > 1 { 1 } }; 0 }").is_ok());
+        assert!(parse_and_analyze("fn main(c) { { if c > 0 { 1 } }; 0 }").is_ok());
+        // An `if` with no value doesn't need one either
+        assert!(parse_and_analyze("fn main(c) { let x = 0; if c > 0 { x = 1; } }").is_ok());
+    }
+
+    #[test]
+    fn test_unit_in_arithmetic_is_error() {
+        let result = parse_and_analyze("fn main() { (if 1 > 0 {}) + 1 }");
+        let error = result.unwrap_err();
+        assert!(
+            error
+                .message
+                .contains("arithmetic requires i64 operands, found ()")
+        );
+    }
+
+    #[test]
+    fn test_comparisons_are_bool() {
+        let source =
+            "fn main() { let x = 3; while x > 0 { x = x - 1; }; if x == 0 { 1 } else { 2 } }";
+        assert!(parse_and_analyze(source).is_ok());
+        let scope = parse_and_analyze("fn is_zero(n) { n == 0 } fn main() { is_zero(0) }").unwrap();
+        assert_eq!(scope.functions["is_zero"].return_type, RueType::Bool);
+
+        let error =
+            parse_and_analyze("fn main() { let x = 1; if x { 1 } else { 2 } }").unwrap_err();
+        assert_eq!(error.message, "If conditions must be bool, found i64");
+        assert_eq!(error.span, rue_lexer::Span { start: 26, end: 27 });
+        let error = parse_and_analyze("fn main() { while 1 { }; 0 }").unwrap_err();
+        assert_eq!(error.message, "While conditions must be bool, found i64");
+
+        let error = parse_and_analyze("fn main() { true + 1 }").unwrap_err();
+        assert_eq!(
+            error.message,
+            "arithmetic requires i64 operands, found bool"
+        );
+        let error = parse_and_analyze("fn main() { (1 < 2) * 3 }").unwrap_err();
+        assert_eq!(
+            error.message,
+            "arithmetic requires i64 operands, found bool"
+        );
+        assert_eq!(error.span, rue_lexer::Span { start: 20, end: 21 });
+        let error = parse_and_analyze("fn main() { let x = 1; x += 1 == 1; x }").unwrap_err();
+        assert_eq!(
+            error.message,
+            "arithmetic requires i64 operands, found bool"
+        );
+        let error = parse_and_analyze("fn main() { (1 < 2) < 3 }").unwrap_err();
+        assert_eq!(error.message, "Comparison operators require i64 operands");
+        let error = parse_and_analyze("fn main() { true == 1 }").unwrap_err();
+        assert_eq!(
+            error.message,
+            "Equality requires two i64 or two bool operands"
+        );
+        let error = parse_and_analyze("fn main() { let b = true; b = 1; 0 }").unwrap_err();
+        assert_eq!(
+            error.message,
+            "Cannot assign a value of type i64 to b, which is bool"
+        );
+        let error = parse_and_analyze("const C = 1 < 2; fn main() { C }").unwrap_err();
+        assert_eq!(
+            error.message,
+            "Constant C must be an integer expression known at compile time"
+        );
+    }
+
+    #[test]
+    fn test_block_variables_dont_leak() {
+        let error =
+            parse_and_analyze("fn main() { if 1 > 0 { let inner = 1; }; inner }").unwrap_err();
+        assert_eq!(error.message, "Undefined variable: inner");
+
+        for source in [
+            "fn main() { if 1 > 0 { 0 } else { let inner = 1; 0 }; inner }",
+            "fn main() { while 1 > 0 { let inner = 1; }; inner }",
+            "fn main() { for i in 0..3 { let inner = i; }; inner }",
+            "fn main() { { let inner = 1; }; inner }",
+        ] {
+            let error = parse_and_analyze(source).unwrap_err();
+            assert_eq!(error.message, "Undefined variable: inner", "{}", source);
+        }
+
+        // But outer variables stay visible inside
+        assert!(parse_and_analyze("fn main() { let x = 1; if x > 0 { x } else { 0 } }").is_ok());
+    }
+
+    #[test]
+    fn test_negative_constant_exponent_is_error() {
+        let error = parse_and_analyze("fn main() { 2 ** -1 }").unwrap_err();
+        assert_eq!(error.message, "Exponents can't be negative");
+
+        let error = parse_and_analyze("const E = 0 - 3; fn main() { 2 ** E }").unwrap_err();
+        assert_eq!(error.message, "Exponents can't be negative");
+
+        assert!(parse_and_analyze("fn main() { 2 ** 0 }").is_ok());
+        assert!(parse_and_analyze("fn f(e) { 2 ** e }").is_ok());
+    }
+
+    #[test]
+    fn test_constant_division_by_zero_is_error() {
+        let error = parse_and_analyze("fn main() { 10 / 0 }").unwrap_err();
+        assert_eq!(error.message, "Can't divide by zero");
+        assert_eq!(error.span, rue_lexer::Span { start: 15, end: 16 });
+
+        let error = parse_and_analyze("fn main(x) { x % (2 - 2) }").unwrap_err();
+        assert_eq!(error.message, "Can't divide by zero");
+        assert_eq!(error.span, rue_lexer::Span { start: 15, end: 16 });
+        let error =
+            parse_and_analyze("const Z = 0; fn main() { let x = 1; x /= Z; x }").unwrap_err();
+        assert_eq!(error.message, "Can't divide by zero");
+
+        // Whether it's zero is only known at runtime
+        assert!(parse_and_analyze("fn main(x) { 10 / x }").is_ok());
+        assert!(parse_and_analyze("fn main(x) { 10 / (x - x) }").is_ok());
+        assert!(parse_and_analyze("const Z = 0; fn main(Z) { 10 / Z }").is_ok());
+    }
+
+    #[test]
+    fn test_return_type_annotations() {
+        let source = "fn f(x: i64) -> i64 { x }";
+        let ast = rue_parser::parse(rue_lexer::Lexer::new(source).tokenize()).unwrap();
+        let scope = analyze_cst(&ast).unwrap();
+        assert_eq!(scope.functions["f"].return_type, RueType::I64);
+
+        // A body ending in `return` produces its value that way
+        assert!(parse_and_analyze("fn f(x) -> i64 { return x; }").is_ok());
+        assert!(parse_and_analyze("fn f(x) -> i64 { if x < 0 { return 0; }; x }").is_ok());
+
+        // A body with no value doesn't produce an i64
+        let source = "fn f() -> i64 { let x = 1; }";
+        let error = parse_and_analyze(source).unwrap_err();
+        assert_eq!(
+            error.message,
+            "Mismatched return type: expected i64, found ()"
+        );
+        assert_eq!(error.span.start, source.find("i64").unwrap());
+        let error = parse_and_analyze("fn f() -> i64 { 1 < 2 }").unwrap_err();
+        assert_eq!(
+            error.message,
+            "Mismatched return type: expected i64, found bool"
+        );
+
+        // ...and neither does `return` without a value
+        let error = parse_and_analyze("fn f() -> i64 { return; }").unwrap_err();
+        assert!(error.message.starts_with("Mismatched return type"));
+
+        let error = parse_and_analyze("fn f() -> bogus { 0 }").unwrap_err();
+        assert_eq!(error.message, "Unknown type: bogus");
+    }
+
+    #[test]
+    fn test_no_return_type_annotation_defaults_to_i64() {
+        let ast =
+            rue_parser::parse(rue_lexer::Lexer::new("fn f() { 1 } fn g() { f() }").tokenize())
+                .unwrap();
+        let scope = analyze_cst(&ast).unwrap();
+        assert_eq!(scope.functions["f"].return_type, RueType::I64);
+    }
+
+    #[test]
+    fn test_parameter_type_annotations() {
+        assert!(parse_and_analyze("fn f(x: i64) { x }").is_ok());
+        assert!(parse_and_analyze("fn f(x) { x }").is_ok());
+
+        let source = "fn f(x: bogus) { x }";
+        let error = parse_and_analyze(source).unwrap_err();
+        assert_eq!(error.message, "Unknown type: bogus");
+        assert_eq!(&source[error.span.start..error.span.end], "bogus");
+    }
+
+    #[test]
+    fn test_unary_negation() {
+        assert!(parse_and_analyze("fn main() { let x = 5; 3 * -2 + -x }").is_ok());
+
+        let error = parse_and_analyze("fn main() { -(if 1 > 0 {}) }").unwrap_err();
+        assert_eq!(error.message, "Unary operators require an i64 operand");
+
+        let error = parse_and_analyze("fn main() { -y }").unwrap_err();
+        assert!(error.message.contains("Undefined variable"));
+    }
+
+    #[test]
+    fn test_logical_not_requires_boolean() {
+        let error = parse_and_analyze("fn main() { !5 }").unwrap_err();
+        assert_eq!(error.message, "Logical not requires a boolean operand");
+        assert_eq!(error.span, rue_lexer::Span { start: 12, end: 13 });
+
+        // The operand is still checked first
+        let error = parse_and_analyze("fn main() { !y }").unwrap_err();
+        assert!(error.message.contains("Undefined variable"));
+
+        assert!(parse_and_analyze("fn main() { !(1 < 2) }").is_ok());
+        assert!(parse_and_analyze("fn main() { 1 < 2 && 3 < 4 || !true }").is_ok());
+        let error = parse_and_analyze("fn main() { 1 && 3 < 4 }").unwrap_err();
+        assert_eq!(error.message, "Logical operators require boolean operands");
+    }
+
+    #[test]
+    fn test_early_return() {
+        let result = parse_and_analyze(
+            r#"
+fn check(x) {
+    if x < 0 {
+        return 0;
+    };
+    x
+}
+"#,
+        );
+        assert!(result.is_ok());
+    }
+
+    #[test]
+    fn test_bare_return_in_unit_function() {
+        assert!(parse_and_analyze("fn f() { return; }").is_ok());
+    }
+
+    #[test]
+    fn test_return_only_function_returns_i64() {
+        let source = "fn f(x) { if x < 0 { return 0; }; return x; } fn main() { f(5) + 1 }";
+        assert!(parse_and_analyze(source).is_ok());
+
+        // Its `return`s still have to agree
+        let error = parse_and_analyze("fn f(x) { if x < 0 { return; }; return x; }").unwrap_err();
+        assert_eq!(
+            error.message,
+            "Mismatched return type: expected i64, found ()"
+        );
+    }
+
+    #[test]
+    fn test_return_type_mismatch() {
+        let error = parse_and_analyze("fn f(x) { if x < 0 { return; }; x }").unwrap_err();
+        assert!(
+            error
+                .message
+                .contains("Mismatched return type: expected i64, found ()")
+        );
+
+        // A `return` with a value makes the function return i64
+        assert!(parse_and_analyze("fn f() { return 1; }").is_ok());
+    }
+
+    #[test]
+    fn test_return_outside_function() {
+        let error = parse_and_analyze("return 1;").unwrap_err();
+        assert!(
+            error
+                .message
+                .contains("Cannot return outside of a function")
+        );
+    }
+
+    #[test]
+    fn test_type_layout() {
+        assert_eq!(RueType::from_name("i64"), Some(RueType::I64));
+        assert_eq!(RueType::from_name("foo"), None);
+        assert_eq!(RueType::I64.size(), Some(8));
+        assert_eq!(RueType::Unit.size(), Some(0));
+        assert_eq!(RueType::Unknown.size(), None);
+    }
+
+    #[test]
+    fn test_unify() {
+        use RueType::*;
+
+        assert_eq!(unify(&I64, &I64), Some(I64));
+        assert_eq!(unify(&Unit, &Unit), Some(Unit));
+        assert_eq!(unify(&I64, &Unit), None);
+        assert_eq!(unify(&Unit, &I64), None);
+
+        // Unknown resolves to whatever it's unified with, on either side
+        assert_eq!(unify(&Unknown, &I64), Some(I64));
+        assert_eq!(unify(&I64, &Unknown), Some(I64));
+        assert_eq!(unify(&Unknown, &Unit), Some(Unit));
+        assert_eq!(unify(&Unknown, &Unknown), Some(Unknown));
+    }
+
+    #[test]
+    fn test_size_of() {
+        assert!(parse_and_analyze("fn main() { size_of(i64) + 1 }").is_ok());
+
+        let error = parse_and_analyze("fn main() { size_of(foo) }").unwrap_err();
+        assert_eq!(error.message, "Unknown type in size_of: foo");
+
+        let error = parse_and_analyze("fn main() { size_of(1) }").unwrap_err();
+        assert!(error.message.contains("size_of expects a single type name"));
+
+        // A program's own size_of is an ordinary function
+        assert!(parse_and_analyze("fn size_of(x) { x } fn main() { size_of(2) }").is_ok());
+    }
+
+    #[test]
+    fn test_assert() {
+        assert!(parse_and_analyze("fn main() { assert(1 <= 2); 0 }").is_ok());
+
+        let error = parse_and_analyze("fn main() { assert(1, 2); 0 }").unwrap_err();
+        assert!(error.message.contains("assert expects a single condition"));
+
+        // Asserting has no value
+        let error = parse_and_analyze("fn main() { assert(assert(1 < 2)); 0 }").unwrap_err();
+        assert_eq!(error.message, "assert requires a bool condition");
+        let error = parse_and_analyze("fn main() { assert(1); 0 }").unwrap_err();
+        assert_eq!(error.message, "assert requires a bool condition");
+        let error = parse_and_analyze("fn main() -> i64 { assert(1 < 2) }").unwrap_err();
+        assert!(error.message.contains("Mismatched return type"));
+    }
+
+    #[test]
+    fn test_read_int() {
+        assert!(parse_and_analyze("fn main() { read_int() * 6 }").is_ok());
+
+        let error = parse_and_analyze("fn main() { read_int(0) }").unwrap_err();
+        assert_eq!(error.message, "read_int takes no arguments");
+
+        // A program's own read_int replaces the intrinsic
+        assert!(parse_and_analyze("fn read_int(x) { x } fn main() { read_int(1) }").is_ok());
+    }
+
+    #[test]
+    fn test_const_declarations() {
+        let scope = parse_and_analyze("const MAX = 100; fn main() { MAX + 1 }").unwrap();
+        assert_eq!(scope.constants.get("MAX"), Some(&100));
+
+        // Constants can refer to ones declared later
+        let scope = parse_and_analyze("const A = B * 2; const B = -3; fn main() { A }").unwrap();
+        assert_eq!(scope.constants.get("A"), Some(&-6));
+
+        let error = parse_and_analyze("const MAX = 100; fn main() { MAX = 1; 0 }").unwrap_err();
+        assert_eq!(error.message, "Cannot assign to constant: MAX");
+
+        let error = parse_and_analyze("const A = B; const B = A + 1; fn main() { 0 }").unwrap_err();
+        assert_eq!(error.message, "Constant depends on itself: A, B");
+
+        let error = parse_and_analyze("fn f() { 1 } const A = f(); fn main() { A }").unwrap_err();
+        assert_eq!(
+            error.message,
+            "Constant A must be an integer expression known at compile time"
+        );
+
+        let error = parse_and_analyze("const A = 1; const A = 2; fn main() { A }").unwrap_err();
+        assert_eq!(error.message, "Duplicate constant: A");
+
+        let error = parse_and_analyze("fn main() { const A = 1; A }").unwrap_err();
+        assert_eq!(
+            error.message,
+            "Constants can only be declared at the top level"
+        );
+    }
 }
@@ -0,0 +1,225 @@
+use rue_ast::visit::{walk_block, walk_expression, walk_function, walk_root, walk_statement};
+use rue_ast::{BlockNode, CstRoot, ExpressionNode, FunctionNode, StatementNode, Visitor};
+use rue_lexer::{Span, TokenKind};
+use std::collections::BTreeSet;
+
+/// Something suspicious about a program that still compiles
+#[derive(Debug, Clone, PartialEq)]
+pub struct SemanticWarning {
+    pub message: String,
+    pub span: Span,
+}
+
+/// Check a program for likely mistakes, in source order
+///
+/// Unlike `analyze_cst`, this never fails: it's meant to be run on programs
+/// that already analyze cleanly, and only reports warnings.
+pub fn lint_cst(ast: &CstRoot) -> Vec<SemanticWarning> {
+    let mut linter = Linter {
+        warnings: Vec::new(),
+    };
+    walk_root(&mut linter, ast);
+    linter.warnings
+}
+
+struct Linter {
+    warnings: Vec<SemanticWarning>,
+}
+
+impl Visitor for Linter {
+    fn visit_expression(&mut self, expr: &ExpressionNode) {
+        if let ExpressionNode::Binary(binary) = expr {
+            lint_self_comparison(binary, &mut self.warnings);
+        }
+        walk_expression(self, expr);
+    }
+}
+
+/// Warnings for `let`s that reuse the name of a variable or parameter that's
+/// still in scope, in source order
+///
+/// Shadowing is allowed, so these aren't part of `lint_cst`, and have to be
+/// asked for. Constants aren't counted, since a `let` is meant to be able to
+/// reuse a constant's name.
+pub fn shadowing_warnings(ast: &CstRoot) -> Vec<SemanticWarning> {
+    let mut shadowing = Shadowing {
+        scopes: vec![BTreeSet::new()],
+        warnings: Vec::new(),
+    };
+    walk_root(&mut shadowing, ast);
+    shadowing.warnings
+}
+
+// The names bound in each scope, innermost last
+struct Shadowing {
+    scopes: Vec<BTreeSet<String>>,
+    warnings: Vec<SemanticWarning>,
+}
+
+impl Shadowing {
+    fn in_scope<F: FnOnce(&mut Self)>(&mut self, names: BTreeSet<String>, walk: F) {
+        self.scopes.push(names);
+        walk(self);
+        self.scopes.pop();
+    }
+}
+
+impl Visitor for Shadowing {
+    fn visit_function(&mut self, func: &FunctionNode) {
+        let params = func
+            .param_list
+            .params
+            .iter()
+            .filter_map(|param| match &param.name.kind {
+                TokenKind::Ident(name) => Some(name.clone()),
+                _ => None,
+            })
+            .collect();
+        self.in_scope(params, |this| walk_function(this, func));
+    }
+
+    fn visit_block(&mut self, block: &BlockNode) {
+        self.in_scope(BTreeSet::new(), |this| walk_block(this, block));
+    }
+
+    // The name is bound after the value is evaluated, so `let x = x + 1;`
+    // reads the old `x` but still shadows it
+    fn visit_statement(&mut self, stmt: &StatementNode) {
+        walk_statement(self, stmt);
+        if let StatementNode::Let(let_stmt) = stmt
+            && let TokenKind::Ident(name) = &let_stmt.name.kind
+        {
+            if self.scopes.iter().any(|scope| scope.contains(name)) {
+                self.warnings.push(SemanticWarning {
+                    message: format!("`{}` shadows an existing binding", name),
+                    span: let_stmt.name.span,
+                });
+            }
+            if let Some(scope) = self.scopes.last_mut() {
+                scope.insert(name.clone());
+            }
+        }
+    }
+
+    fn visit_expression(&mut self, expr: &ExpressionNode) {
+        let ExpressionNode::For(for_expr) = expr else {
+            return walk_expression(self, expr);
+        };
+        // The loop variable is only bound in the body
+        self.visit_expression(&for_expr.start);
+        self.visit_expression(&for_expr.end);
+        let variable = match &for_expr.variable.kind {
+            TokenKind::Ident(name) => BTreeSet::from([name.clone()]),
+            _ => BTreeSet::new(),
+        };
+        self.in_scope(variable, |this| this.visit_block(&for_expr.body));
+    }
+}
+
+// `x == x` and the like always give the same answer, which usually means
+// one side was copied and not edited
+fn lint_self_comparison(binary: &rue_ast::BinaryExprNode, warnings: &mut Vec<SemanticWarning>) {
+    let always = match binary.operator.kind {
+        TokenKind::Equal | TokenKind::LessEqual | TokenKind::GreaterEqual => true,
+        TokenKind::NotEqual | TokenKind::Less | TokenKind::Greater => false,
+        _ => return,
+    };
+    if same_identifier(&binary.left, &binary.right) {
+        warnings.push(SemanticWarning {
+            message: format!("comparison is always {}", always),
+            span: binary.operator.span,
+        });
+    }
+}
+
+// Whether two expressions are the same variable. Only plain identifiers
+// count: anything more could have side effects or differ between
+// evaluations, like a call.
+fn same_identifier(a: &ExpressionNode, b: &ExpressionNode) -> bool {
+    match (a, b) {
+        (ExpressionNode::Identifier(a), ExpressionNode::Identifier(b)) => a.kind == b.kind,
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rue_lexer::Lexer;
+
+    fn lint(source: &str) -> Vec<SemanticWarning> {
+        lint_cst(&rue_parser::parse(Lexer::new(source).tokenize()).unwrap())
+    }
+
+    fn shadowing(source: &str) -> Vec<SemanticWarning> {
+        shadowing_warnings(&rue_parser::parse(Lexer::new(source).tokenize()).unwrap())
+    }
+
+    #[test]
+    fn test_self_comparison() {
+        let source = "fn main() { let x = 1; x == x }";
+        let warnings = lint(source);
+        assert_eq!(
+            warnings,
+            vec![SemanticWarning {
+                message: "comparison is always true".to_string(),
+                span: Span {
+                    start: source.find("==").unwrap(),
+                    end: source.find("==").unwrap() + 2,
+                },
+            }]
+        );
+
+        let warnings = lint("fn f(x) { if x < x { 1 } else { 2 } }");
+        assert_eq!(warnings.len(), 1);
+        assert_eq!(warnings[0].message, "comparison is always false");
+    }
+
+    #[test]
+    fn test_shadowing() {
+        assert_eq!(
+            shadowing("fn f(x) { let x = x + 1; x }"),
+            vec![SemanticWarning {
+                message: "`x` shadows an existing binding".to_string(),
+                span: Span { start: 14, end: 15 },
+            }]
+        );
+
+        let messages = |source: &str| {
+            shadowing(source)
+                .into_iter()
+                .map(|warning| warning.message)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            messages("fn f() { let y = 1; let y = 2; y }"),
+            vec!["`y` shadows an existing binding"]
+        );
+        // Bindings from enclosing blocks and loops are still in scope
+        assert_eq!(
+            messages("fn f() { let y = 1; if y > 0 { let y = 2; }; 0 }"),
+            vec!["`y` shadows an existing binding"]
+        );
+        assert_eq!(
+            messages("fn f() { for i in 0..3 { let i = 2; }; 0 }"),
+            vec!["`i` shadows an existing binding"]
+        );
+
+        // Fresh names, ones whose block has ended, and constants don't count
+        assert!(messages("fn f(x) { let y = x; let z = y; z }").is_empty());
+        assert!(messages("fn f() { { let y = 1; }; let y = 2; y }").is_empty());
+        assert!(messages("fn f(x) { x } fn g() { let x = 1; x }").is_empty());
+        assert!(messages("const N = 1; fn f() { let N = 2; N }").is_empty());
+
+        // Shadowing is only a warning
+        assert!(lint("fn f() { let y = 1; let y = 2; y }").is_empty());
+    }
+
+    #[test]
+    fn test_self_comparison_needs_identical_identifiers() {
+        assert!(lint("fn f(x) { let y = x; x == y }").is_empty());
+        assert!(lint("fn f(x) { x + 1 == x + 1 }").is_empty());
+        assert!(lint("fn f(x) { f(x) == f(x) }").is_empty());
+        assert!(lint("fn f(x) { x - x }").is_empty());
+    }
+}
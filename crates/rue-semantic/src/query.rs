@@ -0,0 +1,165 @@
+use crate::{
+    RueType, Scope, analyze_function, analyze_statement, evaluate_constants, function_scope,
+    type_of,
+};
+use rue_ast::{BlockNode, CstNode, CstRoot, ElseBodyNode, ExpressionNode, StatementNode};
+use rue_lexer::{Span, TokenKind};
+
+/// The type of the innermost expression at `offset` in the function named
+/// `function`, along with that expression's span
+///
+/// The program is analyzed as far as that expression, so everything before
+/// it has to analyze cleanly, but nothing after it does. Returns None if
+/// there's no expression at `offset`, or analysis fails first.
+pub fn type_at(ast: &CstRoot, function: &str, offset: usize) -> Option<(RueType, Span)> {
+    let mut scope = Scope {
+        constants: evaluate_constants(ast).ok()?,
+        ..Scope::default()
+    };
+
+    for item in &ast.items {
+        match item {
+            CstNode::Function(func) if func.name.kind == TokenKind::Ident(function.to_string()) => {
+                let (mut local_scope, _) = function_scope(&mut scope, func).ok()?;
+                return block_type_at(&mut local_scope, &func.body, offset);
+            }
+            CstNode::Function(func) => analyze_function(&mut scope, func).ok()?,
+            CstNode::Statement(stmt) if matches!(**stmt, StatementNode::Const(_)) => {}
+            CstNode::Statement(stmt) => analyze_statement(&mut scope, stmt).ok()?,
+            _ => {}
+        }
+    }
+    None
+}
+
+// Look for `offset` in a block, analyzing the statements before it as they
+// would be
+fn block_type_at(scope: &mut Scope, block: &BlockNode, offset: usize) -> Option<(RueType, Span)> {
+    for stmt in &block.statements {
+        let value = match stmt {
+            StatementNode::Let(let_stmt) => Some(&let_stmt.value),
+            StatementNode::Const(const_stmt) => Some(&const_stmt.value),
+            StatementNode::Assign(assign_stmt) => Some(&assign_stmt.value),
+            StatementNode::IndexAssign(assign_stmt) => Some(&assign_stmt.value),
+            StatementNode::Expression(expr_stmt) => Some(&expr_stmt.expression),
+            StatementNode::Return(return_stmt) => return_stmt.value.as_ref(),
+            StatementNode::Break(_) | StatementNode::Continue(_) | StatementNode::Error(_) => None,
+        };
+        if let Some(value) = value
+            && contains(value.span(), offset)
+        {
+            return expression_type_at(scope, value, offset);
+        }
+        analyze_statement(scope, stmt).ok()?;
+    }
+    expression_type_at(scope, block.final_expr.as_ref()?, offset)
+}
+
+fn expression_type_at(
+    scope: &Scope,
+    expr: &ExpressionNode,
+    offset: usize,
+) -> Option<(RueType, Span)> {
+    let extent = expr.span();
+    if !contains(extent, offset) {
+        return None;
+    }
+
+    // The innermost expression wins, so look in the ones this is made of
+    // first, in scopes set up as `analyze_expression` sets them up
+    let inner = match expr {
+        ExpressionNode::Binary(binary) => expression_type_at(scope, &binary.left, offset)
+            .or_else(|| expression_type_at(scope, &binary.right, offset)),
+        ExpressionNode::Unary(unary) => expression_type_at(scope, &unary.operand, offset),
+        ExpressionNode::Paren(paren) => expression_type_at(scope, &paren.inner, offset),
+        ExpressionNode::Call(call) => call
+            .args
+            .iter()
+            .find_map(|arg| expression_type_at(scope, arg, offset)),
+        ExpressionNode::ArrayLit(array) => array
+            .elements
+            .iter()
+            .find_map(|element| expression_type_at(scope, element, offset)),
+        ExpressionNode::Index(index_expr) => expression_type_at(scope, &index_expr.index, offset),
+        ExpressionNode::Assign(assign_expr) => {
+            expression_type_at(scope, &assign_expr.value, offset)
+        }
+        ExpressionNode::If(if_expr) => expression_type_at(scope, &if_expr.condition, offset)
+            .or_else(|| {
+                contains(if_expr.then_block.span(), offset)
+                    .then(|| block_type_at(&mut scope.clone(), &if_expr.then_block, offset))?
+            })
+            .or_else(|| match &if_expr.else_clause.as_ref()?.body {
+                ElseBodyNode::Block(block) => contains(block.span(), offset)
+                    .then(|| block_type_at(&mut scope.clone(), block, offset))?,
+                ElseBodyNode::If(nested_if) => {
+                    expression_type_at(scope, &ExpressionNode::If(nested_if.clone()), offset)
+                }
+            }),
+        ExpressionNode::While(while_expr) => {
+            expression_type_at(scope, &while_expr.condition, offset).or_else(|| {
+                let mut body_scope = scope.clone();
+                body_scope.in_loop = true;
+                block_type_at(&mut body_scope, &while_expr.body, offset)
+            })
+        }
+        ExpressionNode::For(for_expr) => expression_type_at(scope, &for_expr.start, offset)
+            .or_else(|| expression_type_at(scope, &for_expr.end, offset))
+            .or_else(|| {
+                let mut body_scope = scope.clone();
+                if let TokenKind::Ident(var_name) = &for_expr.variable.kind {
+                    body_scope.variables.insert(var_name.clone(), RueType::I64);
+                }
+                body_scope.in_loop = true;
+                block_type_at(&mut body_scope, &for_expr.body, offset)
+            }),
+        ExpressionNode::Block(block) => block_type_at(&mut scope.clone(), block, offset),
+        ExpressionNode::Identifier(_) | ExpressionNode::Literal(_) => None,
+    };
+
+    inner.or_else(|| Some((type_of(scope, expr).ok()?, extent)))
+}
+
+// Whether `offset` is in `span`, counting either end, so a cursor just
+// after a name is still on it
+fn contains(span: Span, offset: usize) -> bool {
+    span.start <= offset && offset <= span.end
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rue_lexer::Lexer;
+
+    fn type_in_main(source: &str, at: &str) -> Option<(RueType, Span)> {
+        let ast = rue_parser::parse(Lexer::new(source).tokenize()).unwrap();
+        type_at(&ast, "main", source.find(at).unwrap())
+    }
+
+    #[test]
+    fn test_type_at() {
+        let source = "fn main() { let x = 1 <= 2; x }";
+        let (ty, span) = type_in_main(source, "<=").unwrap();
+        assert_eq!(ty, RueType::Bool);
+        assert_eq!(&source[span.start..span.end], "1 <= 2");
+
+        // The innermost expression
+        let source = "fn main() { let y = 3; if y > 1 { y * 2 } else { 0 }; {} }";
+        let (_, span) = type_in_main(source, "* 2").unwrap();
+        assert_eq!(&source[span.start..span.end], "y * 2");
+        let (ty, span) = type_in_main(source, "{}").unwrap();
+        assert_eq!(ty, RueType::Unit);
+        assert_eq!(&source[span.start..span.end], "{}");
+
+        // Arrays
+        let source = "fn main() { let a = [1, 2]; a[1] }";
+        let (ty, _) = type_in_main(source, "[1").unwrap();
+        assert_eq!(ty, RueType::Array(2));
+        let (ty, span) = type_in_main(source, "a[1]").unwrap();
+        assert_eq!(ty, RueType::I64);
+        assert_eq!(&source[span.start..span.end], "a[1]");
+
+        // Not inside any expression
+        assert!(type_in_main("fn main() { let x = 1; x }", "let").is_none());
+    }
+}
@@ -0,0 +1,242 @@
+use rue_lexer::{SourceMap, Span};
+use std::io::IsTerminal;
+
+const RED: &str = "\x1b[1;31m";
+const YELLOW: &str = "\x1b[1;33m";
+const BLUE: &str = "\x1b[1;34m";
+const BOLD: &str = "\x1b[1m";
+const RESET: &str = "\x1b[0m";
+
+/// When to emit ANSI color codes in diagnostics
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ColorMode {
+    #[default]
+    Auto,
+    Always,
+    Never,
+}
+
+impl ColorMode {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "auto" => Some(ColorMode::Auto),
+            "always" => Some(ColorMode::Always),
+            "never" => Some(ColorMode::Never),
+            _ => None,
+        }
+    }
+
+    /// Resolve `Auto` by checking whether stderr is a terminal
+    pub fn enabled(self) -> bool {
+        match self {
+            ColorMode::Auto => std::io::stderr().is_terminal(),
+            ColorMode::Always => true,
+            ColorMode::Never => false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+/// Render a diagnostic with the offending source line and a caret underline,
+/// followed by a note for a related location if there is one
+pub fn render(
+    severity: Severity,
+    message: &str,
+    path: &str,
+    source: &str,
+    span: Option<Span>,
+    related: Option<&(String, Span)>,
+    color: bool,
+) -> String {
+    let (label, label_color) = match severity {
+        Severity::Error => ("error", RED),
+        Severity::Warning => ("warning", YELLOW),
+    };
+
+    let mut out = format!(
+        "{}{}\n",
+        paint(color, label_color, label),
+        paint(color, BOLD, &format!(": {}", message))
+    );
+
+    let Some(span) = span else {
+        out.push_str(&format!("  --> {}\n", path));
+        return out;
+    };
+
+    let source_map = SourceMap::new(source);
+    render_snippet(&mut out, &source_map, path, source, span, color);
+
+    if let Some((note, related_span)) = related {
+        out.push_str(&format!(
+            "{}{}\n",
+            paint(color, BOLD, "note"),
+            paint(color, BOLD, &format!(": {}", note))
+        ));
+        render_snippet(&mut out, &source_map, path, source, *related_span, color);
+    }
+
+    out
+}
+
+fn paint(color: bool, code: &str, text: &str) -> String {
+    if color {
+        format!("{}{}{}", code, text, RESET)
+    } else {
+        text.to_string()
+    }
+}
+
+// Render the location of a span and its source line with a caret underline
+fn render_snippet(
+    out: &mut String,
+    source_map: &SourceMap,
+    path: &str,
+    source: &str,
+    span: Span,
+    color: bool,
+) {
+    // Find the line containing the start of the span
+    let start = span.start.min(source.len());
+    let position = source_map.line_col(start);
+    let line = source_map.line_range(position.line);
+    let line_number = position.line + 1;
+    let line_text = &source[line.clone()];
+    let column = position.column;
+
+    // Underline at least one column, and never past the end of the line
+    let end = span.end.clamp(start, line.end);
+    let width = (source_map.byte_to_char(end) - source_map.byte_to_char(start)).max(1);
+
+    let gutter = " ".repeat(line_number.to_string().len());
+    out.push_str(&format!(
+        "{}{} {}:{}:{}\n",
+        gutter,
+        paint(color, BLUE, "-->"),
+        path,
+        line_number,
+        column + 1
+    ));
+    out.push_str(&format!("{} {}\n", gutter, paint(color, BLUE, "|")));
+    out.push_str(&format!(
+        "{} {}",
+        paint(color, BLUE, &line_number.to_string()),
+        paint(color, BLUE, "|")
+    ));
+    out.push_str(&format!(" {}\n", line_text));
+    out.push_str(&format!(
+        "{} {}\n",
+        gutter,
+        paint(
+            color,
+            BLUE,
+            &format!("| {}{}", " ".repeat(column), "^".repeat(width))
+        )
+    ));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SOURCE: &str = "fn main() {\n    undefined_var\n}\n";
+
+    fn span() -> Option<Span> {
+        Some(Span { start: 16, end: 29 })
+    }
+
+    #[test]
+    fn test_render_points_at_span() {
+        let out = render(
+            Severity::Error,
+            "Undefined variable: undefined_var",
+            "test.rue",
+            SOURCE,
+            span(),
+            None,
+            false,
+        );
+
+        assert!(out.starts_with("error: Undefined variable: undefined_var\n"));
+        assert!(out.contains("--> test.rue:2:5\n"));
+        assert!(out.contains("2 |     undefined_var\n"));
+        assert!(out.contains("  |     ^^^^^^^^^^^^^\n"));
+    }
+
+    #[test]
+    fn test_render_without_color_has_no_escapes() {
+        let out = render(
+            Severity::Error,
+            "oops",
+            "test.rue",
+            SOURCE,
+            span(),
+            None,
+            false,
+        );
+        assert!(!out.contains('\x1b'));
+    }
+
+    #[test]
+    fn test_render_with_color_has_escapes() {
+        let out = render(
+            Severity::Error,
+            "oops",
+            "test.rue",
+            SOURCE,
+            span(),
+            None,
+            true,
+        );
+        assert!(out.contains(RED));
+        assert!(out.contains(BLUE));
+
+        let out = render(
+            Severity::Warning,
+            "hmm",
+            "test.rue",
+            SOURCE,
+            span(),
+            None,
+            true,
+        );
+        assert!(out.contains(YELLOW));
+    }
+
+    #[test]
+    fn test_render_related_note() {
+        let related = (
+            "variable declared here".to_string(),
+            Span { start: 0, end: 2 },
+        );
+        let out = render(
+            Severity::Error,
+            "oops",
+            "test.rue",
+            SOURCE,
+            span(),
+            Some(&related),
+            false,
+        );
+
+        let note = out.find("note: variable declared here\n").unwrap();
+        assert!(out[note..].contains("--> test.rue:1:1\n"));
+        assert!(out[note..].contains("1 | fn main() {\n"));
+        assert!(out[note..].contains("  | ^^\n"));
+    }
+
+    #[test]
+    fn test_parse_color_mode() {
+        assert_eq!(ColorMode::parse("auto"), Some(ColorMode::Auto));
+        assert_eq!(ColorMode::parse("always"), Some(ColorMode::Always));
+        assert_eq!(ColorMode::parse("never"), Some(ColorMode::Never));
+        assert_eq!(ColorMode::parse("sometimes"), None);
+        assert!(ColorMode::Always.enabled());
+        assert!(!ColorMode::Never.enabled());
+    }
+}
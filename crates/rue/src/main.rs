@@ -1,23 +1,193 @@
-use rue_compiler::{RueDatabase, SourceFile, compile_file};
+use rue_codegen::IrOrder;
+use rue_compiler::{
+    CompileError, CompileOptions, RueDatabase, SourceFile, call_graph_file, compile_file_to_binary,
+    compile_file_with_options, ir_file, lint_file, listing_file, opt_report_file, parse_errors,
+    regalloc_spills_file, shadowing_file,
+};
 use std::env;
 use std::fs;
 use std::path::PathBuf;
 
-fn main() {
-    let args: Vec<String> = env::args().collect();
+mod diagnostic;
+
+use diagnostic::{ColorMode, Severity};
+
+const USAGE: &str = "Usage: rue [--color=auto|always|never] [--overflow-checks] [--no-stdlib] \
+                     [-O0|-O1] [--emit=exe|bin|listing|ir|callgraph] [--ir-order=source|callgraph] \
+                     [--entry=function] [--parse-only] [--dump-regalloc] [--opt-report] \
+                     [--warn-shadowing] \
+                     <input.rue> [output]";
+
+/// What the compiler should produce
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Emit {
+    /// An executable, written to the output path
+    Executable,
+    /// Bare machine code with no ELF header, starting at `_start`, written
+    /// to the output path
+    Binary,
+    /// Source lines interleaved with their generated IR, printed to stdout
+    Listing,
+    /// The program's IR, function by function, printed to stdout
+    Ir,
+    /// The program's call graph in Graphviz's DOT format, printed to stdout
+    CallGraph,
+}
+
+impl Emit {
+    fn parse(value: &str) -> Option<Self> {
+        match value {
+            "exe" => Some(Emit::Executable),
+            "bin" => Some(Emit::Binary),
+            "listing" => Some(Emit::Listing),
+            "ir" => Some(Emit::Ir),
+            "callgraph" => Some(Emit::CallGraph),
+            _ => None,
+        }
+    }
+}
 
-    if args.len() < 2 {
-        eprintln!("Usage: {} <input.rue> [output]", args[0]);
-        std::process::exit(1);
+fn parse_ir_order(value: &str) -> Option<IrOrder> {
+    match value {
+        "source" => Some(IrOrder::Source),
+        "callgraph" => Some(IrOrder::CallGraph),
+        _ => None,
     }
+}
 
-    let input_path = PathBuf::from(&args[1]);
-    let output_path = if args.len() > 2 {
-        PathBuf::from(&args[2])
-    } else {
-        input_path.with_extension("")
+// Command-line options
+struct Options {
+    input_path: PathBuf,
+    output_path: Option<PathBuf>,
+    color: ColorMode,
+    compile: CompileOptions,
+    emit: Emit,
+    /// How `--emit=ir` orders functions
+    ir_order: IrOrder,
+    /// Only check that the input parses, exiting with 0 if it does and 1
+    /// if it doesn't
+    parse_only: bool,
+    /// Print the register allocator's spills to stderr while compiling
+    dump_regalloc: bool,
+    /// Print what the optimizer did to stderr while compiling
+    opt_report: bool,
+    /// Also warn about `let`s that shadow a binding still in scope
+    warn_shadowing: bool,
+}
+
+// The value of an option given as either `--name=value` or `--name value`,
+// or None if `arg` isn't that option
+fn option_value<'a>(
+    name: &str,
+    arg: &'a str,
+    rest: &mut impl Iterator<Item = &'a String>,
+) -> Result<Option<&'a str>, String> {
+    match arg.strip_prefix(name) {
+        Some("") => match rest.next() {
+            Some(value) => Ok(Some(value)),
+            None => Err(format!("Missing value for {}", name)),
+        },
+        Some(value) => Ok(value.strip_prefix('=')),
+        None => Ok(None),
+    }
+}
+
+fn parse_args(args: &[String]) -> Result<Options, String> {
+    let mut positional = Vec::new();
+    let mut color = ColorMode::default();
+    let mut compile = CompileOptions::default();
+    let mut emit = Emit::Executable;
+    let mut ir_order = IrOrder::default();
+    let mut parse_only = false;
+    let mut dump_regalloc = false;
+    let mut opt_report = false;
+    let mut warn_shadowing = false;
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        if let Some(value) = arg.strip_prefix("--color=") {
+            color = ColorMode::parse(value)
+                .ok_or_else(|| format!("Invalid value for --color: '{}'", value))?;
+        } else if let Some(value) = option_value("--emit", arg, &mut args)? {
+            emit = Emit::parse(value)
+                .ok_or_else(|| format!("Invalid value for --emit: '{}'", value))?;
+        } else if let Some(value) = option_value("--ir-order", arg, &mut args)? {
+            ir_order = parse_ir_order(value)
+                .ok_or_else(|| format!("Invalid value for --ir-order: '{}'", value))?;
+        } else if let Some(value) = option_value("--entry", arg, &mut args)? {
+            compile.entry = Some(value.to_string());
+        } else if arg == "--parse-only" {
+            parse_only = true;
+        } else if arg == "--dump-regalloc" {
+            dump_regalloc = true;
+        } else if arg == "--opt-report" {
+            opt_report = true;
+        } else if arg == "--warn-shadowing" {
+            warn_shadowing = true;
+        } else if arg == "--overflow-checks" {
+            compile.overflow_checks = true;
+        } else if arg == "--stdlib" {
+            compile.stdlib = true;
+        } else if arg == "--no-stdlib" {
+            compile.stdlib = false;
+        } else if let Some(level) = arg.strip_prefix("-O") {
+            compile.opt_level = match level {
+                "0" => 0,
+                "1" => 1,
+                _ => return Err(format!("Invalid optimization level: '{}'", arg)),
+            };
+        } else if arg.starts_with("--") {
+            return Err(format!("Unknown option: '{}'", arg));
+        } else {
+            positional.push(arg);
+        }
+    }
+
+    match positional.as_slice() {
+        [input] => Ok(Options {
+            input_path: PathBuf::from(input),
+            output_path: None,
+            color,
+            compile,
+            emit,
+            ir_order,
+            parse_only,
+            dump_regalloc,
+            opt_report,
+            warn_shadowing,
+        }),
+        [input, output] => Ok(Options {
+            input_path: PathBuf::from(input),
+            output_path: Some(PathBuf::from(output)),
+            color,
+            compile,
+            emit,
+            ir_order,
+            parse_only,
+            dump_regalloc,
+            opt_report,
+            warn_shadowing,
+        }),
+        _ => Err(USAGE.to_string()),
+    }
+}
+
+fn main() {
+    let args: Vec<String> = env::args().collect();
+
+    let options = match parse_args(&args[1..]) {
+        Ok(options) => options,
+        Err(message) => {
+            eprintln!("{}", message);
+            std::process::exit(1);
+        }
     };
 
+    let input_path = options.input_path;
+    let output_path = options
+        .output_path
+        .unwrap_or_else(|| input_path.with_extension(""));
+
     // Read source file
     let source = match fs::read_to_string(&input_path) {
         Ok(content) => content,
@@ -29,16 +199,98 @@ fn main() {
 
     // Set up Salsa database
     let db = RueDatabase::default();
-    let file = SourceFile::new(&db, input_path.to_string_lossy().to_string(), source);
+    let path = input_path.to_string_lossy().to_string();
+    let file = SourceFile::new(&db, path.clone(), source.clone());
+
+    if options.parse_only {
+        let errors = parse_errors(&db, file);
+        for error in errors.iter() {
+            eprint!(
+                "{}",
+                diagnostic::render(
+                    Severity::Error,
+                    &error.message,
+                    &path,
+                    &source,
+                    error.span,
+                    error.related.as_ref(),
+                    options.color.enabled(),
+                )
+            );
+        }
+        std::process::exit(if errors.is_empty() { 0 } else { 1 });
+    }
+
+    let mut warnings = lint_file(&db, file).to_vec();
+    if options.warn_shadowing {
+        warnings.extend(shadowing_file(&db, file).iter().cloned());
+        warnings.sort_by_key(|warning| warning.span.start);
+    }
+    for warning in &warnings {
+        eprint!(
+            "{}",
+            diagnostic::render(
+                Severity::Warning,
+                &warning.message,
+                &path,
+                &source,
+                Some(warning.span),
+                None,
+                options.color.enabled(),
+            )
+        );
+    }
+
+    if options.dump_regalloc
+        && let Ok(spills) = regalloc_spills_file(&db, file, options.compile.clone())
+    {
+        eprint!("{}", spills);
+    }
+
+    if options.opt_report
+        && let Ok(stats) = opt_report_file(&db, file, options.compile.clone())
+    {
+        eprint!("{}", stats);
+    }
+
+    if options.emit == Emit::Listing {
+        match listing_file(&db, file, options.compile) {
+            Ok(listing) => print!("{}", listing),
+            Err(error) => report_compile_error(&error, &path, &source, options.color),
+        }
+        return;
+    }
+
+    if options.emit == Emit::Ir {
+        match ir_file(&db, file, options.compile, options.ir_order) {
+            Ok(ir) => print!("{}", ir),
+            Err(error) => report_compile_error(&error, &path, &source, options.color),
+        }
+        return;
+    }
+
+    if options.emit == Emit::CallGraph {
+        match call_graph_file(&db, file, options.compile) {
+            Ok(dot) => print!("{}", dot),
+            Err(error) => report_compile_error(&error, &path, &source, options.color),
+        }
+        return;
+    }
 
     // Compile
-    match compile_file(&db, file) {
-        Ok(executable) => {
-            match fs::write(&output_path, &*executable) {
+    let output = if options.emit == Emit::Binary {
+        compile_file_to_binary(&db, file, options.compile)
+    } else {
+        compile_file_with_options(&db, file, options.compile)
+    };
+    match output {
+        Ok(output) => {
+            match fs::write(&output_path, &*output) {
                 Ok(()) => {
-                    // Make executable on Unix systems
+                    // Make executable on Unix systems. A flat binary can't
+                    // be run directly.
                     #[cfg(unix)]
-                    {
+                    if options.emit == Emit::Executable {
                         use std::os::unix::fs::PermissionsExt;
                         let mut perms = fs::metadata(&output_path).unwrap().permissions();
                         perms.set_mode(0o755);
@@ -57,9 +309,22 @@ fn main() {
                 }
             }
         }
-        Err(error) => {
-            eprintln!("Compilation failed: {}", error.message);
-            std::process::exit(1);
-        }
+        Err(error) => report_compile_error(&error, &path, &source, options.color),
     }
 }
+
+fn report_compile_error(error: &CompileError, path: &str, source: &str, color: ColorMode) -> ! {
+    eprint!(
+        "{}",
+        diagnostic::render(
+            Severity::Error,
+            &format!("Compilation failed: {}", error.message),
+            path,
+            source,
+            error.span,
+            error.related.as_ref(),
+            color.enabled(),
+        )
+    );
+    std::process::exit(1);
+}
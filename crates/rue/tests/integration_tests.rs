@@ -1,6 +1,9 @@
+use std::ffi::OsStr;
 use std::fs;
-use std::path::Path;
-use std::process::Command;
+use std::io::Write;
+use std::os::unix::process::ExitStatusExt;
+use std::path::{Path, PathBuf};
+use std::process::{Command, ExitStatus, Output, Stdio};
 
 /// Get the project root directory, compatible with both Cargo and Buck2
 fn get_project_root() -> &'static Path {
@@ -49,6 +52,89 @@ fn get_project_root() -> &'static Path {
     }
 }
 
+/// Run the rue compiler with the given arguments
+fn run_rue(args: &[&OsStr]) -> Output {
+    let project_root = get_project_root();
+
+    // Try Buck2 first, fall back to Cargo
+    if std::env::var("CARGO_MANIFEST_DIR").is_err() {
+        // Buck2 build environment
+        Command::new("buck2")
+            .args(["run", "//crates/rue:rue", "--"])
+            .args(args)
+            .current_dir(project_root)
+            .output()
+            .expect("Failed to execute rue compiler via Buck2")
+    } else {
+        // Cargo build environment
+        Command::new("cargo")
+            .args(["run", "-q", "-p", "rue", "--"])
+            .args(args)
+            .current_dir(project_root)
+            .output()
+            .expect("Failed to execute rue compiler via Cargo")
+    }
+}
+
+/// Write a throwaway source file for tests that don't belong in samples/
+fn write_temp_source(name: &str, source: &str) -> PathBuf {
+    let path = std::env::temp_dir().join(format!("rue_{}_{}.rue", name, std::process::id()));
+    fs::write(&path, source).expect("Failed to write temporary source file");
+    path
+}
+
+/// Compile a throwaway program with extra compiler flags and run it
+fn compile_and_run(name: &str, source: &str, flags: &[&str]) -> ExitStatus {
+    compile_and_run_with_input(name, source, flags, None)
+}
+
+/// Like `compile_and_run`, but with `input` piped to the program's stdin
+fn compile_and_run_with_input(
+    name: &str,
+    source: &str,
+    flags: &[&str],
+    input: Option<&[u8]>,
+) -> ExitStatus {
+    let source_path = write_temp_source(name, source);
+    let executable_path = source_path.with_extension("");
+
+    let mut args: Vec<&OsStr> = flags.iter().map(OsStr::new).collect();
+    args.push(source_path.as_os_str());
+    args.push(executable_path.as_os_str());
+    let compile_output = run_rue(&args);
+    fs::remove_file(&source_path).ok();
+
+    assert!(
+        compile_output.status.success(),
+        "Compilation failed for {}:\nstdout: {}\nstderr: {}",
+        name,
+        String::from_utf8_lossy(&compile_output.stdout),
+        String::from_utf8_lossy(&compile_output.stderr)
+    );
+
+    let mut command = Command::new(&executable_path);
+    let status = match input {
+        Some(input) => {
+            let mut child = command
+                .stdin(Stdio::piped())
+                .spawn()
+                .expect("Failed to execute compiled program");
+            child
+                .stdin
+                .take()
+                .unwrap()
+                .write_all(input)
+                .expect("Failed to write the program's input");
+            child.wait().expect("Failed to wait for compiled program")
+        }
+        None => command
+            .status()
+            .expect("Failed to execute compiled program"),
+    };
+    fs::remove_file(&executable_path).ok();
+    status
+}
+
 /// Test that compiles and runs a .rue program, verifying the exit code
 fn test_rue_program(sample_name: &str, expected_exit_code: i32) {
     let project_root = get_project_root();
@@ -71,24 +157,7 @@ fn test_rue_program(sample_name: &str, expected_exit_code: i32) {
     }
 
     // Compile the rue program using the rue compiler
-    // Try Buck2 first, fall back to Cargo
-    let compile_output = if std::env::var("CARGO_MANIFEST_DIR").is_err() {
-        // Buck2 build environment
-        Command::new("buck2")
-            .args(["run", "//crates/rue:rue", "--"])
-            .arg(&sample_path)
-            .current_dir(project_root)
-            .output()
-            .expect("Failed to execute rue compiler via Buck2")
-    } else {
-        // Cargo build environment
-        Command::new("cargo")
-            .args(["run", "-p", "rue", "--"])
-            .arg(&sample_path)
-            .current_dir(project_root)
-            .output()
-            .expect("Failed to execute rue compiler via Cargo")
-    };
+    let compile_output = run_rue(&[sample_path.as_os_str()]);
 
     if !compile_output.status.success() {
         panic!(
@@ -144,6 +213,16 @@ fn test_while_loop_program() {
     test_rue_program("countdown", 42);
 }
 
+#[test]
+fn test_zero_arg_call_program() {
+    test_rue_program("zero_arg_call", 7);
+}
+
+#[test]
+fn test_early_return_program() {
+    test_rue_program("early_return", 42);
+}
+
 #[test]
 fn test_all_samples_compile() {
     let project_root = get_project_root();
@@ -180,23 +259,7 @@ fn test_all_samples_compile() {
         }
 
         // Compile the rue program
-        let compile_output = if std::env::var("CARGO_MANIFEST_DIR").is_err() {
-            // Buck2 build environment
-            Command::new("buck2")
-                .args(["run", "//crates/rue:rue", "--"])
-                .arg(&sample_path)
-                .current_dir(project_root)
-                .output()
-                .expect("Failed to execute rue compiler via Buck2")
-        } else {
-            // Cargo build environment
-            Command::new("cargo")
-                .args(["run", "-p", "rue", "--"])
-                .arg(&sample_path)
-                .current_dir(project_root)
-                .output()
-                .expect("Failed to execute rue compiler via Cargo")
-        };
+        let compile_output = run_rue(&[sample_path.as_os_str()]);
 
         assert!(
             compile_output.status.success(),
@@ -218,3 +281,776 @@ fn test_all_samples_compile() {
         fs::remove_file(&executable_path).expect("Failed to remove executable after test");
     }
 }
+
+#[test]
+fn test_color_never_has_no_escape_codes() {
+    let source_path = write_temp_source("color_never", "fn main() {\n    undefined_var\n}\n");
+    let output = run_rue(&["--color=never".as_ref(), source_path.as_os_str()]);
+    fs::remove_file(&source_path).ok();
+
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("Undefined variable: undefined_var"));
+    assert!(stderr.contains(":2:5"));
+    assert!(
+        !stderr.contains('\x1b'),
+        "unexpected escape codes: {:?}",
+        stderr
+    );
+}
+
+#[test]
+fn test_color_always_has_escape_codes() {
+    let source_path = write_temp_source("color_always", "fn main() {\n    undefined_var\n}\n");
+    let output = run_rue(&["--color=always".as_ref(), source_path.as_os_str()]);
+    fs::remove_file(&source_path).ok();
+
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("\x1b[1;31m"),
+        "missing red error label: {:?}",
+        stderr
+    );
+    assert!(
+        stderr.contains("\x1b[1;34m"),
+        "missing blue caret line: {:?}",
+        stderr
+    );
+}
+
+// i64::MAX + 43 wraps around to i64::MIN + 42, which exits with 42
+const OVERFLOWING_ADD: &str = "fn main() {\n    9223372036854775807 + 43\n}\n";
+
+#[test]
+fn test_overflow_wraps_by_default() {
+    let status = compile_and_run("overflow_wraps", OVERFLOWING_ADD, &[]);
+    assert_eq!(status.code(), Some(42));
+}
+
+#[test]
+fn test_overflow_checks_trap() {
+    let status = compile_and_run("overflow_traps", OVERFLOWING_ADD, &["--overflow-checks"]);
+    // ud2 raises SIGILL
+    assert_eq!(
+        status.signal(),
+        Some(4),
+        "expected a trap, got {:?}",
+        status
+    );
+}
+
+#[test]
+fn test_overflow_checks_allow_in_range_arithmetic() {
+    let status = compile_and_run(
+        "overflow_in_range",
+        "fn main() {\n    6 * 7 - 2 + 2\n}\n",
+        &["--overflow-checks"],
+    );
+    assert_eq!(status.code(), Some(42));
+}
+
+#[test]
+fn test_stdlib_abs() {
+    let status = compile_and_run("stdlib_abs", "fn main() {\n    abs(0 - 42)\n}\n", &[]);
+    assert_eq!(status.code(), Some(42));
+}
+
+#[test]
+fn test_size_of_program() {
+    let status = compile_and_run("size_of", "fn main() {\n    size_of(i64) * 5\n}\n", &[]);
+    assert_eq!(status.code(), Some(40));
+}
+
+#[test]
+fn test_block_expression_program() {
+    let source =
+        "fn main() {\n    let y = {\n        let a = 40;\n        a + 2\n    };\n    y\n}\n";
+    let status = compile_and_run("block_expression", source, &[]);
+    assert_eq!(status.code(), Some(42));
+}
+
+#[test]
+fn test_assert_program() {
+    let status = compile_and_run(
+        "assert_pass",
+        "fn main() {\n    assert(1 <= 2);\n    0\n}\n",
+        &[],
+    );
+    assert_eq!(status.code(), Some(0));
+
+    let status = compile_and_run(
+        "assert_fail",
+        "fn main() {\n    assert(2 <= 1);\n    0\n}\n",
+        &[],
+    );
+    assert_eq!(status.code(), Some(101));
+}
+
+#[test]
+fn test_const_program() {
+    let source = "const LIMIT = SCALE * 3;\nconst SCALE = 7;\n\nfn main() {\n    LIMIT + 1\n}\n";
+    let status = compile_and_run("const_program", source, &[]);
+    assert_eq!(status.code(), Some(22));
+}
+
+#[test]
+fn test_read_int() {
+    let source = "fn main() {\n    read_int() * 6\n}\n";
+    let status = compile_and_run_with_input("read_int", source, &[], Some(b"7\n"));
+    assert_eq!(status.code(), Some(42));
+
+    // Each call reads up to the end of a number, and a number can be negative
+    let source = "fn main() {\n    let a = read_int();\n    let b = read_int();\n    a - b\n}\n";
+    let status = compile_and_run_with_input("read_int_twice", source, &[], Some(b"-8\n-50"));
+    assert_eq!(status.code(), Some(42));
+
+    // With nothing to read, the result is 0
+    let status = compile_and_run_with_input(
+        "read_int_empty",
+        "fn main() { read_int() + 3 }",
+        &[],
+        Some(b""),
+    );
+    assert_eq!(status.code(), Some(3));
+}
+
+#[test]
+fn test_array_program() {
+    let source = r#"
+const LAST = 2;
+
+fn double(x) {
+    x * 2
+}
+
+fn main() {
+    let a = [1, 2, 3];
+    a[1] = 5;
+    a[LAST] += double(a[0]);
+    a[1] + a[LAST]
+}
+"#;
+    let status = compile_and_run("array_program", source, &[]);
+    assert_eq!(status.code(), Some(10));
+}
+
+#[test]
+fn test_comments() {
+    let source = r#"
+// Comments are skipped wherever they are
+fn main() { // even here
+    let x = 40; // or here
+    // or on their own line
+    x + // or mid-expression
+        2
+}
+// and at the very end"#;
+    let status = compile_and_run("comments", source, &[]);
+    assert_eq!(status.code(), Some(42));
+}
+
+#[test]
+fn test_power() {
+    let source = r#"
+fn two_to(exponent) {
+    2 ** exponent
+}
+
+fn main() {
+    if two_to(10) == 1024 {
+        3 ** two_to(2)
+    } else {
+        0
+    }
+}
+"#;
+    let status = compile_and_run("power", source, &[]);
+    assert_eq!(status.code(), Some(81));
+}
+
+#[test]
+fn test_two_parameters() {
+    let source = r#"
+fn sub(a, b) {
+    a - b
+}
+
+fn main() {
+    sub(50, 8)
+}
+"#;
+    let status = compile_and_run("two_parameters", source, &[]);
+    assert_eq!(status.code(), Some(42));
+}
+
+#[test]
+fn test_four_parameters() {
+    let source = r#"
+fn add4(a, b, c, d) {
+    a + b + c + d
+}
+
+fn main() {
+    add4(1, 2, 3, 36)
+}
+"#;
+    let status = compile_and_run("four_parameters", source, &[]);
+    assert_eq!(status.code(), Some(42));
+}
+
+#[test]
+fn test_five_parameters() {
+    // The fifth argument is passed in r8
+    let source = r#"
+fn weigh(a, b, c, d, e) {
+    a + 2 * b + 3 * c + 4 * d + 5 * e
+}
+
+fn main() {
+    let x = 3;
+    weigh(1, 1, 1, 1, 1) + weigh(x, 0, 0, 0, 1) + x
+}
+"#;
+    let status = compile_and_run("five_parameters", source, &[]);
+    assert_eq!(status.code(), Some(26));
+}
+
+#[test]
+fn test_loop_carried_variables() {
+    let source = r#"
+fn main() {
+    let s = 0;
+    let i = 0;
+    while i < 10 {
+        s = s + i;
+        i = i + 1;
+    };
+    s
+}
+"#;
+    let status = compile_and_run("loop_carried_variables", source, &[]);
+    assert_eq!(status.code(), Some(45));
+}
+
+#[test]
+fn test_for_loop() {
+    let source = r#"
+fn sum_below(n) {
+    let total = 0;
+    for i in 0..n {
+        total = total + i;
+    };
+    total
+}
+
+fn main() {
+    sum_below(10)
+}
+"#;
+    let status = compile_and_run("for_loop", source, &[]);
+    assert_eq!(status.code(), Some(45));
+}
+
+#[test]
+fn test_nested_for_loops() {
+    let source = r#"
+fn main() {
+    let count = 0;
+    for i in 0..5 {
+        for j in 0..5 {
+            count = count + 1;
+        };
+    };
+    count
+}
+"#;
+    let status = compile_and_run("nested_for_loops", source, &[]);
+    assert_eq!(status.code(), Some(25));
+}
+
+#[test]
+fn test_nested_loops() {
+    // Enough loop variables live at once that some are spilled, and updated
+    // in place on the stack
+    let source = r#"
+fn main() {
+    let total = 0;
+    for i in 0..4 {
+        let j = 0;
+        while j < i {
+            total = total + j;
+            j = j + 1;
+        };
+        for k in 0..3 {
+            total = total + k;
+        };
+    };
+    total
+}
+"#;
+    let status = compile_and_run("nested_loops", source, &[]);
+    assert_eq!(status.code(), Some(16));
+}
+
+#[test]
+fn test_spilled_values() {
+    // More values live at once than there are registers, some of them
+    // across calls and around a loop
+    let source = r#"
+fn add4(a, b, c, d) {
+    let x = a * 2;
+    let y = b * 3;
+    let z = c * 4;
+    let w = d * 5;
+    x + y + z + w + a + b + c + d
+}
+
+fn main() {
+    let a = 1;
+    let b = 2;
+    let c = 3;
+    let d = 4;
+    let e = 5;
+    let f = 6;
+    let i = 0;
+    while i < 3 {
+        a = a + b;
+        b = b + c;
+        c = c + add4(0, 0, 0, d);
+        d = d + e;
+        e = e + f;
+        i = i + 1;
+    };
+    a + b + c + d + e + f + add4(1, 2, 3, 4)
+}
+"#;
+    let status = compile_and_run("spilled_values", source, &[]);
+    assert_eq!(status.code(), Some(214));
+}
+
+#[test]
+fn test_booleans() {
+    let source = r#"
+fn is_two(n) {
+    n == 2
+}
+
+fn main(argc) {
+    let small = argc < 10;
+    if small && !is_two(argc) || false {
+        7
+    } else {
+        3
+    }
+}
+"#;
+    let status = compile_and_run("booleans", source, &[]);
+    assert_eq!(status.code(), Some(7));
+}
+
+#[test]
+fn test_negative_exponent_traps() {
+    let source = "fn main(argc) {\n    2 ** (argc - 2)\n}\n";
+    let status = compile_and_run("negative_exponent", source, &[]);
+    assert_eq!(
+        status.signal(),
+        Some(4),
+        "expected a trap, got {:?}",
+        status
+    );
+}
+
+#[test]
+fn test_unit_function_returns_zero() {
+    // The call to seven leaves 7 in rax, which mustn't leak out as the
+    // unit function's result
+    let source = r#"
+fn seven() {
+    7
+}
+
+fn countdown(n) {
+    seven();
+    while n > 0 {
+        n - 1
+    };
+}
+
+fn stop() {
+    seven();
+    return;
+}
+
+fn main() {
+    countdown(0) + stop()
+}
+"#;
+    let status = compile_and_run("unit_function", source, &[]);
+    assert_eq!(status.code(), Some(0));
+}
+
+#[test]
+fn test_unit_final_expression_returns_zero() {
+    // Each body ends in an expression with no value, after a call that
+    // leaves 7 in rax
+    let source = r#"
+fn seven() {
+    7
+}
+
+fn branches(n) {
+    seven();
+    if n > 0 { } else { }
+}
+
+fn no_else(n) {
+    seven();
+    if n > 0 { }
+}
+
+fn block() {
+    { seven(); }
+}
+
+fn nested() {
+    branches(1)
+}
+
+fn main() {
+    branches(1) + branches(0) + no_else(1) + block() + nested()
+}
+"#;
+    let status = compile_and_run("unit_final_expression", source, &[]);
+    assert_eq!(status.code(), Some(0));
+}
+
+#[test]
+fn test_negation_program() {
+    let source = r#"
+fn negate(x) {
+    -x
+}
+
+fn main() {
+    let x = 5;
+    negate(-x + 3 * -2 - 31)
+}
+"#;
+    let status = compile_and_run("negation", source, &[]);
+    assert_eq!(status.code(), Some(42));
+}
+
+#[test]
+fn test_hex_literal_program() {
+    let status = compile_and_run("hex_literal", "fn main() {\n    0x20 + 0xA\n}\n", &[]);
+    assert_eq!(status.code(), Some(42));
+}
+
+#[test]
+fn test_integer_literal_overflow_is_reported() {
+    let source_path = write_temp_source(
+        "literal_overflow",
+        "fn main() {\n    99999999999999999999\n}\n",
+    );
+    let output = run_rue(&[source_path.as_os_str()]);
+    fs::remove_file(&source_path).ok();
+
+    assert_eq!(output.status.code(), Some(1));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("too large for i64"), "{}", stderr);
+    assert!(stderr.contains(":2:5"), "{}", stderr);
+}
+
+#[test]
+fn test_no_stdlib() {
+    let source_path = write_temp_source("no_stdlib", "fn main() {\n    abs(0 - 42)\n}\n");
+    let output = run_rue(&["--no-stdlib".as_ref(), source_path.as_os_str()]);
+    fs::remove_file(&source_path).ok();
+
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("Undefined function: abs"), "{}", stderr);
+}
+
+#[test]
+fn test_opt_level_one_program() {
+    let source = r#"
+fn unused(x) {
+    x * 2
+}
+
+fn add_one(x) {
+    x + 1
+}
+
+fn main() {
+    abs(0 - add_one(41))
+}
+"#;
+    let status = compile_and_run("opt_level_one", source, &["-O1"]);
+    assert_eq!(status.code(), Some(42));
+}
+
+#[test]
+fn test_constant_propagation_program() {
+    let source = r#"
+fn main() {
+    let x = 5;
+    let y = x * 8;
+    let z = 0;
+    z = y + 2;
+    z
+}
+"#;
+    let status = compile_and_run("constant_propagation", source, &["-O1"]);
+    assert_eq!(status.code(), Some(42));
+}
+
+#[test]
+fn test_invalid_opt_level() {
+    let output = run_rue(&["-O3".as_ref(), "missing.rue".as_ref()]);
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("Invalid optimization level: '-O3'"),
+        "{}",
+        stderr
+    );
+}
+
+#[test]
+fn test_emit_listing() {
+    let sample_path = get_project_root().join("samples").join("factorial.rue");
+    let output = run_rue(&[
+        "--emit".as_ref(),
+        "listing".as_ref(),
+        sample_path.as_os_str(),
+    ]);
+    assert!(output.status.success());
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let header = stdout.find(" 5 |         n * factorial(n - 1)\n").unwrap();
+    assert!(stdout[header..].contains("op: Mul"), "{}", stdout);
+    // No executable is written
+    assert!(
+        !get_project_root()
+            .join("samples")
+            .join("factorial")
+            .exists()
+    );
+}
+
+#[test]
+fn test_emit_ir() {
+    let sample_path = get_project_root().join("samples").join("factorial.rue");
+    let output = run_rue(&[
+        "--emit=ir".as_ref(),
+        "--ir-order=callgraph".as_ref(),
+        sample_path.as_os_str(),
+    ]);
+    assert!(output.status.success());
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let factorial = stdout.find("; factorial\n").unwrap();
+    let main = stdout.find("; main\n").unwrap();
+    assert!(factorial < main, "{}", stdout);
+
+    let output = run_rue(&[
+        "--emit=ir".as_ref(),
+        "--ir-order=random".as_ref(),
+        sample_path.as_os_str(),
+    ]);
+    assert!(!output.status.success());
+}
+
+#[test]
+fn test_emit_callgraph() {
+    let sample_path = get_project_root().join("samples").join("factorial.rue");
+    let output = run_rue(&["--emit=callgraph".as_ref(), sample_path.as_os_str()]);
+    assert!(output.status.success());
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.starts_with("digraph calls {\n"), "{}", stdout);
+    assert!(stdout.contains("factorial -> factorial;"), "{}", stdout);
+    assert!(stdout.contains("main -> factorial;"), "{}", stdout);
+}
+
+#[test]
+fn test_dump_regalloc() {
+    let source = "fn main() {\n    let a = 1;\n    let b = 2;\n    let c = 3;\n    let d = 4;\n    \
+                  let e = 5;\n    let f = 6;\n    a + b + c + d + e + f\n}\n";
+    let source_path = write_temp_source("dump_regalloc", source);
+    let executable_path = source_path.with_extension("");
+    let output = run_rue(&[
+        "--dump-regalloc".as_ref(),
+        source_path.as_os_str(),
+        executable_path.as_os_str(),
+    ]);
+    fs::remove_file(&source_path).ok();
+    fs::remove_file(&executable_path).ok();
+
+    assert!(output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("spill VReg("), "{}", stderr);
+    assert!(stderr.contains(" at instruction "), "{}", stderr);
+}
+
+#[test]
+fn test_opt_report() {
+    let source = "fn unused(x) {\n    x + 1\n}\n\nfn main() {\n    2 * 3\n}\n";
+    let source_path = write_temp_source("opt_report", source);
+    let executable_path = source_path.with_extension("");
+    let output = run_rue(&[
+        "-O1".as_ref(),
+        "--opt-report".as_ref(),
+        source_path.as_os_str(),
+        executable_path.as_os_str(),
+    ]);
+    fs::remove_file(&source_path).ok();
+    fs::remove_file(&executable_path).ok();
+
+    assert!(output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("constant folds: 1\n"), "{}", stderr);
+    // `unused`, and `abs` and `max` from the stdlib
+    assert!(stderr.contains("functions pruned: 3\n"), "{}", stderr);
+}
+
+#[test]
+fn test_parse_only() {
+    // Parses, even though `y` is undefined
+    let source_path = write_temp_source("parse_only_ok", "fn main() {\n    y\n}\n");
+    let output = run_rue(&["--parse-only".as_ref(), source_path.as_os_str()]);
+    fs::remove_file(&source_path).ok();
+    assert_eq!(output.status.code(), Some(0));
+    assert!(
+        output.stderr.is_empty(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    // Nothing is compiled
+    assert!(!source_path.with_extension("").exists());
+
+    // Every syntax error is reported
+    let source_path = write_temp_source(
+        "parse_only_errors",
+        "fn main() {\n    1 +\n}\n\nfn f() {\n    let = 2;\n}\n",
+    );
+    let output = run_rue(&["--parse-only".as_ref(), source_path.as_os_str()]);
+    fs::remove_file(&source_path).ok();
+    assert_eq!(output.status.code(), Some(1));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert_eq!(
+        stderr.matches("error: Parse error").count(),
+        2,
+        "{}",
+        stderr
+    );
+}
+
+#[test]
+fn test_self_comparison_warning() {
+    let source_path = write_temp_source(
+        "self_comparison",
+        "fn main() {\n    let x = 1;\n    x == x\n}\n",
+    );
+    let executable_path = source_path.with_extension("");
+    let output = run_rue(&[source_path.as_os_str(), executable_path.as_os_str()]);
+    fs::remove_file(&source_path).ok();
+    fs::remove_file(&executable_path).ok();
+
+    assert!(output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("warning: comparison is always true"),
+        "{}",
+        stderr
+    );
+    assert!(stderr.contains("3 |     x == x"), "{}", stderr);
+}
+
+#[test]
+fn test_shadowing_warning_is_opt_in() {
+    let source_path = write_temp_source(
+        "shadowing",
+        "fn main(argc) {\n    let argc = 3;\n    argc\n}\n",
+    );
+    let executable_path = source_path.with_extension("");
+    let output = run_rue(&[source_path.as_os_str(), executable_path.as_os_str()]);
+    assert!(output.status.success());
+    assert!(!String::from_utf8_lossy(&output.stderr).contains("shadows"));
+
+    let output = run_rue(&[
+        "--warn-shadowing".as_ref(),
+        source_path.as_os_str(),
+        executable_path.as_os_str(),
+    ]);
+    fs::remove_file(&source_path).ok();
+    fs::remove_file(&executable_path).ok();
+
+    assert!(output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("warning: `argc` shadows an existing binding"),
+        "{}",
+        stderr
+    );
+}
+
+#[test]
+fn test_emit_bin() {
+    let source_path = write_temp_source("emit_bin", "fn main() {\n    42\n}\n");
+    let executable_path = source_path.with_extension("");
+    let binary_path = source_path.with_extension("bin");
+
+    let output = run_rue(&[source_path.as_os_str(), executable_path.as_os_str()]);
+    assert!(output.status.success());
+    let output = run_rue(&[
+        "--emit".as_ref(),
+        "bin".as_ref(),
+        source_path.as_os_str(),
+        binary_path.as_os_str(),
+    ]);
+    assert!(output.status.success());
+
+    let executable = fs::read(&executable_path).unwrap();
+    let binary = fs::read(&binary_path).unwrap();
+    fs::remove_file(&source_path).ok();
+    fs::remove_file(&executable_path).ok();
+    fs::remove_file(&binary_path).ok();
+
+    // The machine code follows the ELF header and single program header
+    assert_eq!(binary, &executable[0x78..]);
+}
+
+#[test]
+fn test_entry_function() {
+    let status = compile_and_run(
+        "entry",
+        "fn answer() {\n    42\n}\n",
+        &["--entry", "answer"],
+    );
+    assert_eq!(status.code(), Some(42));
+}
+
+#[test]
+fn test_main_takes_argc() {
+    let source_path = write_temp_source("main_argc", "fn main(argc) {\n    argc\n}\n");
+    let executable_path = source_path.with_extension("");
+    let output = run_rue(&[source_path.as_os_str(), executable_path.as_os_str()]);
+    fs::remove_file(&source_path).ok();
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+
+    // The program's own name counts as an argument
+    let status = Command::new(&executable_path)
+        .args(["one", "two"])
+        .status()
+        .expect("Failed to execute compiled program");
+    fs::remove_file(&executable_path).ok();
+    assert_eq!(status.code(), Some(3));
+}